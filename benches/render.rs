@@ -0,0 +1,54 @@
+// cargo bench --bench render [--features parallel]
+use music_sample_machine::sample::{Composition, MultiChannel, Sample, SineWave};
+
+use std::time::Instant;
+
+const RATE: usize = 44100;
+
+// three minutes of 32 stereo tracks each placed every few seconds
+fn song() -> Composition {
+    let mut comp = Composition::new();
+    for track in 0..32 {
+        let tone = SineWave::new(110.0 * (track + 1) as f32, 2 * RATE, 0.02);
+        let stereo = MultiChannel::new_dual(&tone, &tone).unwrap();
+        let id = comp.add_track(&stereo, track * RATE / 8).unwrap();
+        let mut start = track * RATE / 8;
+        while start < 178 * RATE {
+            start += 4 * RATE;
+            comp.add_track_id(id, start).unwrap();
+        }
+    }
+    comp
+}
+
+fn time<F: FnMut()>(name: &str, runs: u32, mut f: F) {
+    f();
+    let start = Instant::now();
+    for _ in 0..runs {
+        f();
+    }
+    println!(
+        "{:<20} {:>8.2} ms",
+        name,
+        start.elapsed().as_secs_f64() * 1000.0 / runs as f64
+    );
+}
+
+fn main() {
+    let comp = song();
+    time("serial fill", 5, || {
+        for channel in 0..comp.channels() {
+            let mut buffer = vec![0.0; comp.length()];
+            comp.fill(channel, 0, &mut buffer).unwrap();
+        }
+    });
+    time("waveform", 5, || {
+        for channel in 0..comp.channels() {
+            comp.waveform(channel).unwrap();
+        }
+    });
+    #[cfg(feature = "parallel")]
+    time("render_parallel", 5, || {
+        comp.render_parallel().unwrap();
+    });
+}
@@ -0,0 +1,366 @@
+use crate::sample::Sample;
+use crate::Error;
+
+use std::error;
+use std::iter;
+
+// onsets, tempo, spectra and deconvolution
+#[cfg(feature = "analysis")]
+mod spectral;
+#[cfg(feature = "analysis")]
+pub use spectral::{
+    bin_frequency, deconvolve, detect_onsets, estimate_bpm, onset_strength, slice_on_onsets,
+    spectrogram_png, spectrum, SpectrogramOptions,
+};
+
+// frames of the onset and pitch tracks
+pub const HOP: usize = 512;
+// bs.1770 gating blocks and thresholds
+const LOUDNESS_BLOCK: f32 = 0.4;
+const LOUDNESS_STEP: f32 = 0.1;
+const ABSOLUTE_GATE: f32 = -70.0;
+const RELATIVE_GATE: f32 = -10.0;
+
+// yin pitch detection, the range covers a low bass to a high whistle
+const PITCH_WINDOW: usize = 1024;
+const MIN_PITCH: f32 = 40.0;
+const MAX_PITCH: f32 = 2000.0;
+const PITCH_THRESHOLD: f32 = 0.15;
+const PITCH_SILENCE: f32 = 1e-3;
+
+// average all the channels into one waveform
+pub fn mono(sample: &dyn Sample) -> Vec<f32> {
+    let mut mix = vec![0.0; sample.length()];
+    for channel in 0..sample.channels() {
+        if let Some(wave) = sample.waveform_ref(channel) {
+            for (total, value) in mix.iter_mut().zip(wave.iter()) {
+                *total += value;
+            }
+        }
+    }
+    let channels = sample.channels().max(1) as f32;
+    mix.iter_mut().for_each(|s| *s /= channels);
+    mix
+}
+
+pub fn to_db(level: f32) -> f32 {
+    20.0 * level.log10()
+}
+
+pub fn peak(sample: &dyn Sample) -> f32 {
+    sample.peak()
+}
+
+// the mean of each channel, anything far from 0 wants a DcBlock
+pub fn dc_offset(sample: &dyn Sample) -> Vec<f32> {
+    (0..sample.channels())
+        .map(|channel| match sample.waveform_ref(channel) {
+            Some(wave) if !wave.is_empty() => {
+                (wave.iter().map(|s| *s as f64).sum::<f64>() / wave.len() as f64) as f32
+            }
+            _ => 0.0,
+        })
+        .collect()
+}
+
+// root mean square over every channel
+pub fn rms(sample: &dyn Sample) -> f32 {
+    let mut total = 0.0f64;
+    let mut count = 0;
+    for channel in 0..sample.channels() {
+        if let Some(wave) = sample.waveform_ref(channel) {
+            total += wave.iter().map(|s| (*s as f64).powi(2)).sum::<f64>();
+            count += wave.len();
+        }
+    }
+    if count == 0 {
+        return 0.0;
+    }
+    (total / count as f64).sqrt() as f32
+}
+
+// EBU R128 integrated loudness in LUFS, negative infinity if the whole
+// sample is below the absolute gate
+pub fn integrated_loudness(sample: &dyn Sample) -> f32 {
+    let rate = sample.sample_rate();
+    let length = sample.length();
+    if length == 0 {
+        return f32::NEG_INFINITY;
+    }
+    let block = ((LOUDNESS_BLOCK * rate as f32) as usize).min(length);
+    let step = ((LOUDNESS_STEP * rate as f32) as usize).max(1);
+    let blocks = (length - block) / step + 1;
+
+    // mean square of every k weighted block on each channel
+    let mut powers = vec![0.0f64; blocks];
+    for channel in 0..sample.channels() {
+        let weight = match (sample.channels(), channel) {
+            // 5.1 skips the LFE and boosts the surrounds
+            (6, 3) => continue,
+            (6, 4) | (6, 5) => 1.41,
+            _ => 1.0,
+        };
+        let mut wave: Vec<f64> = match sample.waveform_ref(channel) {
+            Some(wave) => wave.iter().map(|s| *s as f64).collect(),
+            None => continue,
+        };
+        for (b, a) in k_weighting(rate).iter() {
+            let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+            for s in wave.iter_mut() {
+                let y = b[0] * *s + b[1] * x1 + b[2] * x2 - a[0] * y1 - a[1] * y2;
+                x2 = x1;
+                x1 = *s;
+                y2 = y1;
+                y1 = y;
+                *s = y;
+            }
+        }
+
+        for (index, power) in powers.iter_mut().enumerate() {
+            let start = index * step;
+            let sum: f64 = wave[start..start + block].iter().map(|s| s * s).sum();
+            *power += weight * sum / block as f64;
+        }
+    }
+
+    let loudness = |power: f64| -0.691 + 10.0 * power.log10() as f32;
+    let gated_mean = |threshold: f32| {
+        let kept: Vec<f64> = powers
+            .iter()
+            .copied()
+            .filter(|p| loudness(*p) > threshold)
+            .collect();
+        if kept.is_empty() {
+            None
+        } else {
+            Some(kept.iter().sum::<f64>() / kept.len() as f64)
+        }
+    };
+    let relative = match gated_mean(ABSOLUTE_GATE) {
+        Some(mean) => loudness(mean) + RELATIVE_GATE,
+        None => return f32::NEG_INFINITY,
+    };
+    gated_mean(relative.max(ABSOLUTE_GATE)).map_or(f32::NEG_INFINITY, loudness)
+}
+
+// the bs.1770 pre filter shelf and rlb high pass as (b, a) biquad
+// coefficients with a0 normalized out, redesigned for any sample rate
+fn k_weighting(rate: u32) -> [([f64; 3], [f64; 2]); 2] {
+    let rate = rate as f64;
+    let k = (std::f64::consts::PI * 1681.974450955533 / rate).tan();
+    let q = 0.7071752369554196;
+    let vh = 10f64.powf(3.999843853973347 / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = (
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let k = (std::f64::consts::PI * 38.13547087602444 / rate).tan();
+    let q = 0.5003270373238773;
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = (
+        [1.0, -2.0, 1.0],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+    [shelf, high_pass]
+}
+
+// fundamental frequency in Hz with the yin method, one value per HOP
+// steps, None where the sample is silent or has no clear pitch
+pub fn detect_pitch(
+    sample: &dyn Sample,
+    channel: u16,
+) -> Result<Vec<Option<f32>>, Box<dyn error::Error>> {
+    let wave = sample
+        .waveform_ref(channel)
+        .ok_or(Error::MissingChannel(channel))?;
+    let rate = sample.sample_rate() as f32;
+    let min_lag = ((rate / MAX_PITCH) as usize).max(2);
+    let max_lag = (rate / MIN_PITCH) as usize;
+    let value = |step: usize| wave.get(step).copied().unwrap_or(0.0);
+
+    let mut pitches = Vec::new();
+    let mut difference = vec![0.0f32; max_lag + 2];
+    for frame in 0..wave.len() / HOP {
+        let start = frame * HOP;
+        let energy: f32 = (start..start + PITCH_WINDOW)
+            .map(|t| value(t) * value(t))
+            .sum();
+        if (energy / PITCH_WINDOW as f32).sqrt() < PITCH_SILENCE {
+            pitches.push(None);
+            continue;
+        }
+
+        // the difference of the frame with itself lag steps later,
+        // normalized by the running mean so short lags don't win, worked
+        // out only as far as the bottom of the first dip under the threshold
+        let frame: Vec<f32> = (start..start + PITCH_WINDOW + max_lag + 2)
+            .map(value)
+            .collect();
+        let mut total = 0.0;
+        let mut dip = None;
+        let mut lag = 1;
+        difference[0] = 1.0;
+        while lag < difference.len() {
+            let raw: f32 = frame[..PITCH_WINDOW]
+                .iter()
+                .zip(&frame[lag..])
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum();
+            total += raw;
+            difference[lag] = if total > 0.0 {
+                raw * lag as f32 / total
+            } else {
+                1.0
+            };
+            if dip.is_none()
+                && lag >= min_lag
+                && lag <= max_lag
+                && difference[lag] < PITCH_THRESHOLD
+            {
+                dip = Some(lag);
+            }
+            if dip.is_some() && difference[lag] >= difference[lag - 1] {
+                break;
+            }
+            lag += 1;
+        }
+        let lag = match dip {
+            Some(_) => (lag - 1).min(max_lag),
+            None => {
+                pitches.push(None);
+                continue;
+            }
+        };
+
+        // a parabola through the dip for a lag between steps
+        let (before, at, after) = (difference[lag - 1], difference[lag], difference[lag + 1]);
+        let curve = before - 2.0 * at + after;
+        let shift = if curve > 0.0 {
+            (0.5 * (before - after) / curve).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        pitches.push(Some(rate / (lag as f32 + shift)));
+    }
+    Ok(pitches)
+}
+
+// ranges of steps where every channel stays below the threshold for at
+// least min_length steps, ends are exclusive
+pub fn detect_silence(
+    sample: &dyn Sample,
+    threshold_db: f32,
+    min_length: usize,
+) -> Vec<(usize, usize)> {
+    let threshold = 10f32.powf(threshold_db / 20.0);
+    let mut loud = vec![false; sample.length()];
+    for channel in 0..sample.channels() {
+        if let Some(wave) = sample.waveform_ref(channel) {
+            for (flag, value) in loud.iter_mut().zip(wave.iter()) {
+                *flag |= value.abs() >= threshold;
+            }
+        }
+    }
+
+    let mut regions = Vec::new();
+    let mut start = None;
+    for (index, is_loud) in loud.iter().chain(iter::once(&true)).enumerate() {
+        match (start, is_loud) {
+            (None, false) => start = Some(index),
+            (Some(from), true) => {
+                if index - from >= min_length.max(1) {
+                    regions.push((from, index));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::note::Note;
+    use crate::oscillator::{SawWave, WhiteNoise};
+    use crate::sample::{Composition, SineWave, WaveForm};
+
+    #[test]
+    fn silence_regions() -> Result<(), Box<dyn error::Error>> {
+        let tone = WaveForm::from(&[0.5; 1000]);
+        let mut comp = Composition::new();
+        comp.add_track(&tone, 500)?;
+        comp.add_track(&tone, 3000)?;
+        comp.add_track(&WaveForm::from(&[0.0; 4500]), 0)?;
+        let silence = detect_silence(&comp, -60.0, 100);
+        assert_eq!(silence, vec![(0, 500), (1500, 3000), (4000, 4500)]);
+
+        let trimmed = comp.trim_silence(-60.0);
+        assert_eq!(trimmed.length(), 3500);
+        assert!(trimmed.waveform(0).unwrap()[1].abs() > 0.0);
+        assert_eq!(WaveForm::from(&[0.0; 10]).trim_silence(-60.0).length(), 0);
+        Ok(())
+    }
+
+    #[cfg(feature = "mp3")]
+    #[test]
+    fn levels() -> Result<(), Box<dyn error::Error>> {
+        use crate::sample::MultiChannel;
+
+        let square = WaveForm::from(&[0.5, -0.5, 0.5, -0.5]);
+        assert_eq!(peak(&square), 0.5);
+        assert_eq!(rms(&square), 0.5);
+        assert!((to_db(0.5) + 6.0206).abs() < 1e-3);
+
+        // a full scale 1 kHz sine on one channel reads -3.01 LUFS
+        let sine = SineWave::new(1000.0, 48000 * 5, 1.0);
+        assert!((rms(&sine) - 0.5f32.sqrt()).abs() < 1e-3);
+        assert!((integrated_loudness(&sine) + 3.01).abs() < 0.2);
+        let stereo = MultiChannel::new_dual(&sine, &sine)?;
+        assert!((integrated_loudness(&stereo) - 0.0).abs() < 0.2);
+        let quiet = sine.scale(0.1);
+        assert!((integrated_loudness(&*quiet) + 23.01).abs() < 0.2);
+        assert_eq!(
+            integrated_loudness(&WaveForm::from(&[0.0; 48000])),
+            f32::NEG_INFINITY
+        );
+
+        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        let lufs = integrated_loudness(&song);
+        assert!(lufs < 0.0 && lufs > -40.0);
+        Ok(())
+    }
+
+    #[test]
+    fn pitch_of_notes() -> Result<(), Box<dyn error::Error>> {
+        let mut melody =
+            SineWave::new(220.0, 11025, 0.5).append(&WaveForm::from(&[0.0; 5512]), 0)?;
+        melody = melody.append(&SawWave::note(Note::E4, 0.25, 0.5), 0)?;
+        let pitches = detect_pitch(melody.as_ref(), 0)?;
+        assert_eq!(pitches.len(), melody.length() / 512);
+
+        // frames fully inside each part
+        for pitch in &pitches[..18] {
+            assert!((pitch.unwrap() - 220.0).abs() < 1.0);
+        }
+        assert!(pitches[24..30].iter().all(|pitch| pitch.is_none()));
+        for pitch in &pitches[34..48] {
+            let note = Note::from_frequency(pitch.unwrap());
+            assert_eq!(note, Some(Note::E4));
+        }
+
+        let noise = WhiteNoise::new(11025, 0.5);
+        let pitches = detect_pitch(&noise, 0)?;
+        assert!(pitches.iter().filter(|pitch| pitch.is_some()).count() < pitches.len() / 4);
+        assert!(detect_pitch(&noise, 1).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,454 @@
+use super::{mono, HOP};
+use crate::sample::{MultiChannel, Sample, WaveForm};
+use crate::Error;
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use std::error;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufWriter;
+
+const FRAME: usize = 1024;
+const MIN_BPM: f32 = 70.0;
+const MAX_BPM: f32 = 180.0;
+// tempos near this are preferred to avoid picking half or double time
+const PREFERRED_BPM: f32 = 120.0;
+// regularisation for deconvolve relative to the sweep's loudest bin
+const DECONVOLVE_FLOOR: f32 = 1e-5;
+
+// rise in log energy between frames, one value per HOP steps
+pub fn onset_strength(sample: &dyn Sample) -> Vec<f32> {
+    let wave = mono(sample);
+    let energies: Vec<f32> = (0..wave.len() / HOP)
+        .map(|frame| {
+            let start = frame * HOP;
+            let end = (start + FRAME).min(wave.len());
+            let energy: f32 = wave[start..end].iter().map(|s| s * s).sum();
+            (energy / FRAME as f32 + 1e-10).log10()
+        })
+        .collect();
+
+    // treat everything before the start as silence so a hit on step 0 counts
+    let silence = 1e-10f32.log10();
+    let mut previous = silence;
+    energies
+        .iter()
+        .map(|energy| {
+            let rise = (energy - previous).max(0.0);
+            previous = *energy;
+            rise
+        })
+        .collect()
+}
+
+// positions in steps where new notes or hits start
+pub fn detect_onsets(sample: &dyn Sample) -> Vec<usize> {
+    let strength = onset_strength(sample);
+    let window = 8;
+    let mut onsets = Vec::new();
+    let mut last: Option<usize> = None;
+    for frame in 0..strength.len() {
+        let from = frame.saturating_sub(window);
+        let to = (frame + window + 1).min(strength.len());
+        let local = &strength[from..to];
+        let mean = local.iter().sum::<f32>() / local.len() as f32;
+        let is_peak = local
+            .iter()
+            .enumerate()
+            .all(|(index, value)| *value <= strength[frame] || from + index == frame);
+        // adaptive threshold and a minimum gap so one hit gives one onset
+        let far_enough = last.is_none_or(|last| frame - last > 4);
+        if is_peak && strength[frame] > mean * 1.5 + 0.1 && far_enough {
+            onsets.push(frame * HOP);
+            last = Some(frame);
+        }
+    }
+    onsets
+}
+
+// cut the sample into one piece per onset, each running up to the next
+// onset, anything before the first onset is dropped
+pub fn slice_on_onsets(sample: &dyn Sample) -> Vec<Box<dyn Sample>> {
+    let mut onsets = detect_onsets(sample);
+    if onsets.is_empty() {
+        return vec![sample.box_clone()];
+    }
+    onsets.push(sample.length());
+    onsets
+        .windows(2)
+        .map(|bounds| sample.sample_clamped(bounds[0], bounds[1]))
+        .collect()
+}
+
+// autocorrelate the onset strength and pick the strongest beat period
+pub fn estimate_bpm(sample: &dyn Sample) -> f32 {
+    // spread each onset over neighbouring frames so beat periods that
+    // fall between two lags still line up
+    let raw = onset_strength(sample);
+    let strength: Vec<f32> = (0..raw.len())
+        .map(|frame| {
+            let before = if frame > 0 { raw[frame - 1] } else { 0.0 };
+            let after = raw.get(frame + 1).copied().unwrap_or(0.0);
+            0.25 * before + 0.5 * raw[frame] + 0.25 * after
+        })
+        .collect();
+    let frame_rate = sample.sample_rate() as f32 / HOP as f32;
+    // the scores start a lag before min_lag, which at low sample rates
+    // would be a lag of nothing
+    let min_lag = ((frame_rate * 60.0 / MAX_BPM).floor() as usize).max(2);
+    let max_lag = (frame_rate * 60.0 / MIN_BPM).ceil() as usize;
+    // too few frames a second to tell the slowest tempo from the fastest
+    if max_lag <= min_lag || strength.len() <= max_lag + 1 {
+        return 0.0;
+    }
+
+    let correlation = |lag: usize| -> f32 {
+        let score = strength
+            .iter()
+            .zip(&strength[lag..])
+            .map(|(a, b)| a * b)
+            .sum::<f32>()
+            / (strength.len() - lag) as f32;
+        let octaves = (60.0 * frame_rate / lag as f32 / PREFERRED_BPM).log2();
+        score * (-0.5 * octaves * octaves).exp()
+    };
+    let scores: Vec<f32> = (min_lag - 1..=max_lag + 1).map(correlation).collect();
+    let mut best = 1;
+    for index in 1..scores.len() - 1 {
+        if scores[index] > scores[best] {
+            best = index;
+        }
+    }
+
+    // fit a parabola through the peak for a fractional lag
+    let (left, middle, right) = (scores[best - 1], scores[best], scores[best + 1]);
+    let curve = left - 2.0 * middle + right;
+    let shift = if curve.abs() > 1e-12 {
+        0.5 * (left - right) / curve
+    } else {
+        0.0
+    };
+    let lag = (min_lag - 1 + best) as f32 + shift;
+    if lag <= 0.0 || middle <= 0.0 {
+        return 0.0;
+    }
+    60.0 * frame_rate / lag
+}
+
+// magnitude frames of a hann windowed short time fourier transform, each
+// frame has window / 2 + 1 bins and starts hop steps after the last
+pub fn spectrum(
+    sample: &dyn Sample,
+    channel: u16,
+    window: usize,
+    hop: usize,
+) -> Result<Vec<Vec<f32>>, Box<dyn error::Error>> {
+    if window == 0 || hop == 0 {
+        return Err(Box::new(Error::Invalid(
+            "Window and hop must be greater than 0".to_string(),
+        )));
+    }
+    let wave = sample
+        .waveform_ref(channel)
+        .ok_or(Error::MissingChannel(channel))?;
+
+    let hann: Vec<f32> = (0..window)
+        .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / window as f32).cos())
+        .collect();
+    // scale so a full scale sine peaks near 1.0
+    let scale = 2.0 / hann.iter().sum::<f32>();
+    let fft = FftPlanner::new().plan_fft_forward(window);
+
+    let mut frames = Vec::new();
+    let mut buffer = vec![Complex::new(0.0, 0.0); window];
+    for start in (0..wave.len()).step_by(hop) {
+        for (n, bin) in buffer.iter_mut().enumerate() {
+            let value = wave.get(start + n).copied().unwrap_or(0.0);
+            *bin = Complex::new(value * hann[n], 0.0);
+        }
+        fft.process(&mut buffer);
+        frames.push(
+            buffer[..window / 2 + 1]
+                .iter()
+                .map(|c| c.norm() * scale)
+                .collect(),
+        );
+    }
+    Ok(frames)
+}
+
+pub fn bin_frequency(bin: usize, window: usize, sample_rate: u32) -> f32 {
+    bin as f32 * sample_rate as f32 / window as f32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectrogramOptions {
+    pub channel: u16,
+    pub window: usize,
+    pub hop: usize,
+    // quietest level shown, anything below is drawn black
+    pub floor_db: f32,
+}
+
+impl Default for SpectrogramOptions {
+    fn default() -> Self {
+        SpectrogramOptions {
+            channel: 0,
+            window: 1024,
+            hop: 256,
+            floor_db: -90.0,
+        }
+    }
+}
+
+// one column per frame and one row per bin with low frequencies at the bottom
+pub fn spectrogram_png(
+    sample: &dyn Sample,
+    path: &str,
+    options: &SpectrogramOptions,
+) -> Result<(), Box<dyn error::Error>> {
+    let frames = spectrum(sample, options.channel, options.window, options.hop)?;
+    if frames.is_empty() {
+        return Err(Box::new(Error::Invalid("Sample is empty".to_string())));
+    }
+    let width = frames.len();
+    let height = frames[0].len();
+
+    let mut pixels = vec![0u8; width * height * 3];
+    for (x, frame) in frames.iter().enumerate() {
+        for (bin, magnitude) in frame.iter().enumerate() {
+            let db = 20.0 * (magnitude + 1e-10).log10();
+            let level = (1.0 - db / options.floor_db).clamp(0.0, 1.0);
+            let y = height - 1 - bin;
+            let index = (y * width + x) * 3;
+            pixels[index..index + 3].copy_from_slice(&heat(level));
+        }
+    }
+
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+    Ok(())
+}
+
+// black through blue, red and yellow to white
+fn heat(level: f32) -> [u8; 3] {
+    const STOPS: [[f32; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 0.6],
+        [0.8, 0.0, 0.2],
+        [1.0, 0.8, 0.0],
+        [1.0, 1.0, 1.0],
+    ];
+    let pos = level * (STOPS.len() - 1) as f32;
+    let index = (pos as usize).min(STOPS.len() - 2);
+    let frac = pos - index as f32;
+    let mut color = [0; 3];
+    for (c, value) in color.iter_mut().enumerate() {
+        let mixed = STOPS[index][c] * (1.0 - frac) + STOPS[index + 1][c] * frac;
+        *value = (mixed * 255.0).round() as u8;
+    }
+    color
+}
+
+// the impulse response of whatever the sweep was played through, from a
+// recording of it made with a sweep like a logarithmic Chirp. the recording
+// should carry on after the sweep for as long as the room rings and the
+// response is that much longer than one step, one channel per recorded one
+pub fn deconvolve(
+    recorded: &dyn Sample,
+    sweep: &dyn Sample,
+) -> Result<MultiChannel, Box<dyn error::Error>> {
+    if recorded.sample_rate() != sweep.sample_rate() {
+        return Err(Error::SampleRateMismatch {
+            expected: sweep.sample_rate(),
+            found: recorded.sample_rate(),
+        }
+        .into());
+    }
+    if recorded.length() < sweep.length() || sweep.length() == 0 {
+        return Err(Error::LengthMismatch {
+            expected: sweep.length().max(1),
+            found: recorded.length(),
+        }
+        .into());
+    }
+
+    let size = (recorded.length() + sweep.length()).next_power_of_two();
+    let mut planner = FftPlanner::new();
+    let forward = planner.plan_fft_forward(size);
+    let inverse = planner.plan_fft_inverse(size);
+    let transform = |wave: &[f32]| {
+        let mut buffer: Vec<Complex<f32>> = wave.iter().map(|v| Complex::new(*v, 0.0)).collect();
+        buffer.resize(size, Complex::new(0.0, 0.0));
+        forward.process(&mut buffer);
+        buffer
+    };
+
+    let length = recorded.length() - sweep.length() + 1;
+    let sweep = transform(&mono(sweep));
+    // keeps the division from blowing up noise where the sweep has no energy
+    let floor = DECONVOLVE_FLOOR * sweep.iter().map(|c| c.norm_sqr()).fold(0.0, f32::max);
+
+    let mut response = MultiChannel::new();
+    for channel in 0..recorded.channels() {
+        let wave = recorded
+            .waveform_ref(channel)
+            .ok_or(Error::MissingChannel(channel))?;
+        let mut buffer = transform(&wave);
+        for (value, sweep) in buffer.iter_mut().zip(sweep.iter()) {
+            *value = *value * sweep.conj() / (sweep.norm_sqr() + floor);
+        }
+        inverse.process(&mut buffer);
+        let wave: Vec<f32> = buffer[..length]
+            .iter()
+            .map(|c| c.re / size as f32)
+            .collect();
+        response.add_channel(&WaveForm::from_vec(wave, recorded.sample_rate()))?;
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oscillator::WhiteNoise;
+    use crate::sample::{Composition, SineWave};
+
+    fn click_track(bpm: f32, beats: usize) -> Result<Composition, Box<dyn error::Error>> {
+        let click = WhiteNoise::new(1000, 0.8);
+        let mut comp = Composition::new();
+        let id = comp.add_track(&click, 0)?;
+        for beat in 1..beats {
+            comp.add_track_id_sec(id, beat as f32 * 60.0 / bpm)?;
+        }
+        Ok(comp)
+    }
+
+    #[test]
+    fn onsets_of_clicks() -> Result<(), Box<dyn error::Error>> {
+        let clicks = click_track(120.0, 8)?;
+        let onsets = detect_onsets(&clicks);
+        assert_eq!(onsets.len(), 8);
+        for (beat, onset) in onsets.iter().enumerate() {
+            let expected = beat * 22050;
+            assert!((*onset as isize - expected as isize).abs() <= 1024);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn slice_clicks() -> Result<(), Box<dyn error::Error>> {
+        let clicks = click_track(120.0, 4)?;
+        let slices = slice_on_onsets(&clicks);
+        assert_eq!(slices.len(), 4);
+        let onsets = detect_onsets(&clicks);
+        let total: usize = slices.iter().map(|s| s.length()).sum();
+        assert_eq!(total, clicks.length() - onsets[0]);
+        for (index, slice) in slices.iter().enumerate() {
+            assert!(slice.peak() > 0.5);
+            slice.export(&format!("./test_files/output/slice_{}.wav", index))?;
+        }
+        assert_eq!(slice_on_onsets(&WaveForm::from(&[0.0; 100])).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn bpm_of_clicks() -> Result<(), Box<dyn error::Error>> {
+        for bpm in [90.0, 120.0, 150.0].iter() {
+            let clicks = click_track(*bpm, 24)?;
+            assert!((estimate_bpm(&clicks) - bpm).abs() < 1.5);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn bpm_at_low_rates() {
+        // so few frames a second that the fastest tempo is under one frame
+        let mut clicks = vec![0.0; 30000];
+        clicks.iter_mut().step_by(500).for_each(|s| *s = 1.0);
+        for rate in [1000, 600, 500, 100] {
+            let bpm = estimate_bpm(&WaveForm::with_rate(&clicks, rate));
+            assert!(bpm.is_finite() && bpm >= 0.0);
+        }
+    }
+
+    #[cfg(feature = "mp3")]
+    #[test]
+    fn bpm_of_song() -> Result<(), Box<dyn error::Error>> {
+        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        let bpm = estimate_bpm(&song);
+        assert!((MIN_BPM..=MAX_BPM).contains(&bpm));
+        assert!(!detect_onsets(&song).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn spectrum_of_sine() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(1000.0, 44100, 1.0);
+        let frames = spectrum(&wave, 0, 2048, 1024)?;
+        assert_eq!(frames.len(), 44);
+        assert_eq!(frames[0].len(), 1025);
+        let frame = &frames[10];
+        let (peak, magnitude) =
+            frame.iter().enumerate().fold(
+                (0, 0.0),
+                |best, (bin, m)| if *m > best.1 { (bin, *m) } else { best },
+            );
+        assert!((bin_frequency(peak, 2048, 44100) - 1000.0).abs() < 44100.0 / 2048.0);
+        assert!(magnitude > 0.8 && magnitude <= 1.01);
+        assert!(spectrum(&wave, 1, 2048, 1024).is_err());
+        assert!(spectrum(&wave, 0, 2048, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn spectrogram_image() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(2000.0, 44100, 0.5);
+        let options = SpectrogramOptions::default();
+        spectrogram_png(&wave, "./test_files/output/spectrogram.png", &options)?;
+        let decoder = png::Decoder::new(File::open("./test_files/output/spectrogram.png")?);
+        let reader = decoder.read_info()?;
+        assert_eq!(reader.info().width, 173);
+        assert_eq!(reader.info().height, 513);
+        assert_eq!(heat(0.0), [0, 0, 0]);
+        assert_eq!(heat(1.0), [255, 255, 255]);
+        Ok(())
+    }
+
+    #[test]
+    fn deconvolve_sweep() -> Result<(), Box<dyn error::Error>> {
+        use crate::effect::convolution::Convolver;
+        use crate::oscillator::{Chirp, Sweep};
+
+        // a direct sound and two reflections
+        let mut ir = vec![0.0; 400];
+        ir[0] = 1.0;
+        ir[100] = 0.5;
+        ir[300] = -0.25;
+        let room = Convolver::from_ir(&WaveForm::from(&ir))?;
+        let sweep = Chirp::new(20.0, 22050.0, 44100, 0.5).with_sweep(Sweep::Logarithmic);
+        let recorded = sweep.apply(&room)?;
+        assert_eq!(recorded.length(), sweep.length() + 399);
+
+        let response = deconvolve(recorded.as_ref(), &sweep)?;
+        assert_eq!(response.length(), 400);
+        let wave = response.waveform(0).unwrap();
+        for (step, expected) in [(0, 1.0), (100, 0.5), (300, -0.25)] {
+            assert!((wave[step] - expected).abs() < 0.02);
+        }
+        let others = wave
+            .iter()
+            .enumerate()
+            .filter(|(step, _)| ![0, 100, 300].contains(step));
+        assert!(others.map(|(_, value)| value.abs()).fold(0.0, f32::max) < 0.02);
+
+        assert!(deconvolve(&WaveForm::from(&[0.0; 10]), &sweep).is_err());
+        assert!(deconvolve(&WaveForm::with_rate(&ir, 48000), &WaveForm::from(&ir)).is_err());
+        Ok(())
+    }
+}
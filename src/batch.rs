@@ -0,0 +1,153 @@
+use crate::sample::MultiChannel;
+#[cfg(feature = "wav")]
+use crate::sample::{ExportOptions, Sample};
+use crate::Error;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+// the extensions import_dir picks up, anything else in the directory is left
+// alone so readme files and artwork in sample packs don't stop an import
+#[cfg(not(feature = "symphonia-backend"))]
+const AUDIO_EXTENSIONS: &[&str] = &["wav", "wave", "mp3", "ogg", "oga"];
+#[cfg(feature = "symphonia-backend")]
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "wav", "wave", "mp3", "ogg", "oga", "flac", "m4a", "mp4", "aac", "alac",
+];
+
+fn is_audio(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+// decode every audio file directly in a directory, sorted by path, the first
+// file that fails to decode fails the whole import and is named in the error
+pub fn import_dir<P: AsRef<Path>>(path: P) -> Result<Vec<(PathBuf, MultiChannel)>, Error> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if is_audio(&path) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    #[cfg(feature = "parallel")]
+    let files = paths.into_par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let files = paths.into_iter();
+    files
+        .map(|path| {
+            let sample = MultiChannel::from_file(&path.to_string_lossy()).map_err(|e| match e {
+                Error::Io(e) => Error::Io(e),
+                e => Error::Decode(format!("{}: {}", path.display(), e)),
+            })?;
+            Ok((path, sample))
+        })
+        .collect()
+}
+
+// write each sample to dir/name as a WAV file, creating dir if needed, names
+// without an extension get .wav added and any other extension than .wav is
+// an error before anything is written
+#[cfg(feature = "wav")]
+pub fn export_all<P: AsRef<Path>>(
+    samples: &[(&str, &dyn Sample)],
+    dir: P,
+    options: &ExportOptions,
+) -> Result<Vec<PathBuf>, Error> {
+    let dir = dir.as_ref();
+    let paths = samples
+        .iter()
+        .map(|(name, _)| {
+            let path = dir.join(name);
+            match path.extension().map(|e| e.to_string_lossy().to_lowercase()) {
+                None => Ok(path.with_extension("wav")),
+                Some(e) if e == "wav" || e == "wave" => Ok(path),
+                Some(_) => Err(Error::Unsupported(format!(
+                    "{}: only WAV files can be exported",
+                    path.display()
+                ))),
+            }
+        })
+        .collect::<Result<Vec<PathBuf>, Error>>()?;
+    fs::create_dir_all(dir)?;
+
+    #[cfg(feature = "parallel")]
+    let files = samples.par_iter().zip(paths.par_iter());
+    #[cfg(not(feature = "parallel"))]
+    let mut files = samples.iter().zip(paths.iter());
+    files.try_for_each(|((_, sample), path)| {
+        // export errors aren't Send so only their message crosses threads
+        sample
+            .export_with(&path.to_string_lossy(), options)
+            .map_err(|e| Error::Other(format!("{}: {}", path.display(), e)))
+    })?;
+    Ok(paths)
+}
+
+#[cfg(all(test, feature = "wav"))]
+mod tests {
+    use super::*;
+    use crate::sample::SineWave;
+    use std::error;
+
+    #[cfg(feature = "mp3")]
+    #[test]
+    fn import_and_export() -> Result<(), Box<dyn error::Error>> {
+        let songs = import_dir("./test_files/songs")?;
+        let names: Vec<_> = songs
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["Chameleon_short.mp3", "switch_lr_sine.wav"]);
+        assert_eq!(
+            songs[1].1.waveform(0),
+            MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?.waveform(0)
+        );
+
+        let sine = SineWave::new(440.0, 1000, 0.5);
+        let paths = export_all(
+            &[("sine", &sine), ("copy.wav", &songs[1].1)],
+            "./test_files/output/batch",
+            &ExportOptions::default(),
+        )?;
+        assert_eq!(
+            paths,
+            [
+                Path::new("./test_files/output/batch/sine.wav"),
+                Path::new("./test_files/output/batch/copy.wav")
+            ]
+        );
+        let exported = import_dir("./test_files/output/batch")?;
+        assert_eq!(exported.len(), 2);
+        assert_eq!(exported[1].1.waveform(0), sine.waveform(0));
+
+        assert!(import_dir("./test_files/missing").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn export_only_wav() -> Result<(), Box<dyn error::Error>> {
+        let sine = SineWave::new(440.0, 100, 0.5);
+        let dir = "./test_files/output/batch_names";
+        let result = export_all(
+            &[("first.wav", &sine), ("second.mp3", &sine)],
+            dir,
+            &ExportOptions::default(),
+        );
+        assert!(matches!(result, Err(Error::Unsupported(_))));
+        // nothing is written when any of the names is wrong
+        assert!(!Path::new(dir).join("first.wav").exists());
+
+        let paths = export_all(&[("loud.WAV", &sine)], dir, &ExportOptions::default())?;
+        assert_eq!(paths, [Path::new(dir).join("loud.WAV")]);
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use music_sample_machine::analysis;
+use music_sample_machine::project::Project;
+use music_sample_machine::sample::{MultiChannel, Sample};
+use music_sample_machine::Error;
+
+use std::env;
+use std::error;
+use std::process;
+
+const USAGE: &str = "usage:
+    msm slice <in> <start seconds> <end seconds> <out.wav>
+    msm bpm <in>
+    msm render <project.json|project.toml> <out.wav>";
+
+fn seconds(arg: &str) -> Result<f32, Error> {
+    arg.parse()
+        .map_err(|_| Error::Invalid(format!("{} is not a number of seconds", arg)))
+}
+
+// run one command and give back what to print
+fn run(args: &[String]) -> Result<String, Box<dyn error::Error>> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["slice", input, start, end, output] => {
+            let sample = MultiChannel::from_file(input)?;
+            sample
+                .sample_sec(seconds(start)?, seconds(end)?)?
+                .export(output)?;
+            Ok(format!("wrote {}", output))
+        }
+        ["bpm", input] => {
+            let sample = MultiChannel::from_file(input)?;
+            Ok(format!("{:.1}", analysis::estimate_bpm(&sample)))
+        }
+        ["render", project, output] => {
+            Project::load(project)?.to_composition()?.export(output)?;
+            Ok(format!("wrote {}", output))
+        }
+        _ => Err(Error::Invalid(USAGE.to_string()).into()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(message) => println!("{}", message),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use music_sample_machine::project::{ProjectTrack, Source};
+    use music_sample_machine::sample::SineWave;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn commands() -> Result<(), Box<dyn error::Error>> {
+        run(&args(&[
+            "slice",
+            "./test_files/songs/switch_lr_sine.wav",
+            "0.5",
+            "1.0",
+            "./test_files/output/msm_slice.wav",
+        ]))?;
+        let sliced = MultiChannel::from_wav("./test_files/output/msm_slice.wav")?;
+        assert_eq!(sliced.length(), sliced.sample_rate() as usize / 2);
+
+        let bpm: f32 = run(&args(&["bpm", "./test_files/songs/Chameleon_short.mp3"]))?.parse()?;
+        assert!(bpm > 0.0);
+
+        let mut project = Project::new();
+        let beep = SineWave::new(880.0, 4410, 0.5);
+        project
+            .tracks
+            .push(ProjectTrack::new(Source::embed(&beep)?, 100));
+        project.save("./test_files/output/msm_project.json")?;
+        let comp = project.to_composition()?;
+        run(&args(&[
+            "render",
+            "./test_files/output/msm_project.json",
+            "./test_files/output/msm_render.wav",
+        ]))?;
+        let rendered = MultiChannel::from_wav("./test_files/output/msm_render.wav")?;
+        assert_eq!(rendered.waveform(0), comp.waveform(0));
+
+        assert!(run(&args(&["slice", "in.wav"])).is_err());
+        assert!(run(&args(&["bpm", "./test_files/songs/missing.wav"])).is_err());
+        Ok(())
+    }
+}
@@ -1,7 +1,22 @@
+use crate::metadata::Metadata;
 use crate::sample::Sample;
 use crate::sample;
+use crate::Error;
 
 use std::error;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+pub mod automation;
+pub mod convolution;
+pub mod denoise;
+pub mod distortion;
+pub mod eq;
+pub mod fade;
+pub mod modulation;
+pub mod pitch;
 
 pub trait Effect {
     fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>>;
@@ -11,17 +26,149 @@ pub trait WaveformEffect {
     fn process(&self, waveform: &[f32]) -> Result<Box<dyn Sample>, Box<dyn error::Error>>;
 }
 
-impl Effect for dyn WaveformEffect {
+// receives every channel at once so channels can feed into each other,
+// may return a different number of channels than it was given
+pub trait MultiChannelEffect {
+    fn process_channels(
+        &self,
+        channels: &[Vec<f32>],
+        sample_rate: u32,
+    ) -> Result<Vec<Vec<f32>>, Box<dyn error::Error>>;
+}
+
+// every waveform effect is a multi channel effect that handles each
+// channel on its own
+impl<T: WaveformEffect + ?Sized> MultiChannelEffect for T {
+    fn process_channels(
+        &self,
+        channels: &[Vec<f32>],
+        _sample_rate: u32,
+    ) -> Result<Vec<Vec<f32>>, Box<dyn error::Error>> {
+        let mut processed = Vec::new();
+        for wave in channels {
+            processed.push(
+                self.process(wave)?
+                    .waveform(0)
+                    .ok_or(Error::MissingChannel(0))?,
+            );
+        }
+        Ok(processed)
+    }
+}
+
+// this covers concrete types as well as dyn WaveformEffect and
+// dyn MultiChannelEffect
+impl<T: MultiChannelEffect + ?Sized> Effect for T {
     fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
-        let mut channels = sample::MultiChannel::new();
+        let mut waves = Vec::new();
         for channel in 0..sample.channels() {
-            let wave = self.process(&sample.waveform(channel).unwrap())?;
-            channels.add_channel(&*wave)?;
+            waves.push(
+                sample
+                    .waveform(channel)
+                    .ok_or(Error::MissingChannel(channel))?,
+            );
+        }
+
+        let mut channels = sample::MultiChannel::new();
+        for wave in self.process_channels(&waves, sample.sample_rate())? {
+            channels.add_channel(&sample::WaveForm::with_rate(&wave, sample.sample_rate()))?;
         }
         Ok(Box::new(channels))
     }
 }
 
+// runs process over each channel on its own, for effects that need the
+// whole channel at once
+pub(crate) fn map_channels<F>(
+    sample: &dyn Sample,
+    process: F,
+) -> Result<Box<dyn Sample>, Box<dyn error::Error>>
+where
+    F: Fn(&[f32], u16) -> Vec<f32>,
+{
+    let mut channels = sample::MultiChannel::new();
+    for channel in 0..sample.channels() {
+        let wave = sample
+            .waveform_ref(channel)
+            .ok_or(Error::MissingChannel(channel))?;
+        let wave = process(&wave, channel);
+        channels.add_channel(&sample::WaveForm::from_vec(wave, sample.sample_rate()))?;
+    }
+    Ok(Box::new(channels))
+}
+
+// effects that only look at the block in front of them, applying one wraps
+// the sample in a Processed so the effect runs as the sample is read and a
+// long song never has to be held in memory a whole channel at a time
+pub trait BlockEffect: Send + Sync {
+    // buffer holds the channel from step offset on
+    fn process_block(&self, buffer: &mut [f32], offset: usize, sample_rate: u32);
+
+    // reads the block out of sample and processes it, effects that need a
+    // few steps from before the block can read further back here
+    fn fill(
+        &self,
+        sample: &dyn Sample,
+        channel: u16,
+        offset: usize,
+        buffer: &mut [f32],
+    ) -> Option<usize> {
+        let filled = sample.fill(channel, offset, buffer)?;
+        self.process_block(&mut buffer[..filled], offset, sample.sample_rate());
+        Some(filled)
+    }
+}
+
+// a sample run through a block effect as it is read
+pub struct Processed {
+    pub sample: Box<dyn Sample>,
+    effect: Arc<dyn BlockEffect>,
+}
+
+impl Processed {
+    pub fn new<E: BlockEffect + 'static>(sample: Box<dyn Sample>, effect: E) -> Processed {
+        Processed {
+            sample,
+            effect: Arc::new(effect),
+        }
+    }
+}
+
+impl Sample for Processed {
+    fn sample_rate(&self) -> u32 {
+        self.sample.sample_rate()
+    }
+    fn length(&self) -> usize {
+        self.sample.length()
+    }
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        let mut waveform = vec![0.0; self.length()];
+        let filled = self.fill(channel, 0, &mut waveform)?;
+        waveform.truncate(filled);
+        Some(waveform)
+    }
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        self.effect
+            .fill(self.sample.as_ref(), channel, offset, buffer)
+    }
+    fn channels(&self) -> u16 {
+        self.sample.channels()
+    }
+    fn metadata(&self) -> Option<&Metadata> {
+        self.sample.metadata()
+    }
+    fn set_metadata(&mut self, metadata: Metadata) -> Result<(), Error> {
+        self.sample.set_metadata(metadata)
+    }
+    fn box_clone(&self) -> Box<dyn Sample> {
+        Box::new(Processed {
+            sample: self.sample.box_clone(),
+            effect: self.effect.clone(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct LinearFadeEcho {
     pub delay: usize,
     pub fade_slope: f32,
@@ -42,7 +189,7 @@ impl Effect for LinearFadeEcho {
         let mut fade = 1.0 - self.fade_slope;
         let mut delay = self.delay;
         while fade > 0.0 {
-            echo.add_track(&*sample.scale(fade)?, delay)?;
+            echo.add_track(&*sample.scale(fade), delay)?;
             fade -= self.fade_slope;
             delay += self.delay;
         }
@@ -50,11 +197,364 @@ impl Effect for LinearFadeEcho {
     }
 }
 
+// freeverb tunings in samples at 44.1kHz
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+const STEREO_SPREAD: usize = 23;
+const REVERB_INPUT_GAIN: f32 = 0.015;
+
+#[derive(Serialize, Deserialize)]
+pub struct Reverb {
+    pub room_size: f32,
+    pub damping: f32,
+    pub wet: f32,
+    pub dry: f32,
+    pub tail: usize,
+}
+
+impl Reverb {
+    pub fn new(room_size: f32, damping: f32, wet: f32, dry: f32) -> Self {
+        Reverb {
+            room_size,
+            damping,
+            wet,
+            dry,
+            tail: 0,
+        }
+    }
+
+    fn process(&self, wave: &[f32], sample_rate: u32, spread: usize) -> Vec<f32> {
+        let scale = |tuning: usize| ((tuning + spread) * sample_rate as usize / 44100).max(1);
+        let feedback = self.room_size.clamp(0.0, 1.0) * 0.28 + 0.7;
+        let damp = self.damping.clamp(0.0, 1.0) * 0.4;
+
+        let mut combs: Vec<(Vec<f32>, f32)> = COMB_TUNINGS
+            .iter()
+            .map(|t| (vec![0.0; scale(*t)], 0.0))
+            .collect();
+        let mut allpasses: Vec<Vec<f32>> = ALLPASS_TUNINGS
+            .iter()
+            .map(|t| vec![0.0; scale(*t)])
+            .collect();
+
+        let mut output = Vec::with_capacity(wave.len() + self.tail);
+        for t in 0..wave.len() + self.tail {
+            let dry = wave.get(t).copied().unwrap_or(0.0);
+            let input = dry * REVERB_INPUT_GAIN;
+
+            // parallel low pass feedback combs
+            let mut wet = 0.0;
+            for (buffer, store) in combs.iter_mut() {
+                let index = t % buffer.len();
+                let out = buffer[index];
+                *store = out * (1.0 - damp) + *store * damp;
+                buffer[index] = input + *store * feedback;
+                wet += out;
+            }
+
+            // series allpasses to diffuse the echoes
+            for buffer in allpasses.iter_mut() {
+                let index = t % buffer.len();
+                let out = buffer[index];
+                buffer[index] = wet + out * 0.5;
+                wet = out - wet;
+            }
+
+            output.push(wet * self.wet + dry * self.dry);
+        }
+        output
+    }
+}
+
+impl Effect for Reverb {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        map_channels(sample, |wave, channel| {
+            // offset odd channels slightly so stereo sources stay wide
+            let spread = STEREO_SPREAD * (channel as usize % 2);
+            self.process(wave, sample.sample_rate(), spread)
+        })
+    }
+}
+
+
+
+#[derive(Serialize, Deserialize)]
+pub struct Compressor {
+    pub threshold_db: f32,
+    pub ratio: f32,
+    pub attack: f32,
+    pub release: f32,
+    pub makeup_db: f32,
+}
+
+impl Compressor {
+    pub fn new(threshold_db: f32, ratio: f32, attack: f32, release: f32, makeup_db: f32) -> Self {
+        Compressor {
+            threshold_db,
+            ratio,
+            attack,
+            release,
+            makeup_db,
+        }
+    }
+
+    fn process(&self, wave: &[f32], sample_rate: u32) -> Vec<f32> {
+        // one pole smoothing coefficients for attack and release times in seconds
+        let coefficient = |time: f32| {
+            if time <= 0.0 {
+                0.0
+            } else {
+                (-1.0 / (time * sample_rate as f32)).exp()
+            }
+        };
+        let attack = coefficient(self.attack);
+        let release = coefficient(self.release);
+        let slope = 1.0 - 1.0 / self.ratio.max(1.0);
+
+        let mut reduction = 0.0;
+        wave.iter()
+            .map(|s| {
+                let level = 20.0 * (s.abs() + 1e-9).log10();
+                let target = (level - self.threshold_db).max(0.0) * slope;
+                let coef = if target > reduction { attack } else { release };
+                reduction = coef * reduction + (1.0 - coef) * target;
+                s * 10f32.powf((self.makeup_db - reduction) / 20.0)
+            })
+            .collect()
+    }
+}
+
+impl Effect for Compressor {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        map_channels(sample, |wave, _| self.process(wave, sample.sample_rate()))
+    }
+}
+
+const DC_BLOCK_CUTOFF: f32 = 5.0;
+
+// one pole high pass that takes out the DC offset hardware recordings often
+// come with, the cutoff can be raised to clear rumble as well
+#[derive(Serialize, Deserialize)]
+pub struct DcBlock {
+    pub cutoff: f32,
+}
+
+impl DcBlock {
+    pub fn new() -> Self {
+        DcBlock {
+            cutoff: DC_BLOCK_CUTOFF,
+        }
+    }
+
+    pub fn with_cutoff(mut self, cutoff: f32) -> Self {
+        self.cutoff = cutoff;
+        self
+    }
+
+    fn process(&self, wave: &[f32], sample_rate: u32) -> Vec<f32> {
+        let pole = (-2.0 * PI * self.cutoff.max(0.0) / sample_rate as f32).exp();
+        // starting from the offset instead of silence means a constant
+        // offset is removed from the first step rather than decaying away
+        let mut last_input =
+            (wave.iter().map(|s| *s as f64).sum::<f64>() / wave.len().max(1) as f64) as f32;
+        let mut last_output = 0.0;
+        wave.iter()
+            .map(|s| {
+                last_output = s - last_input + pole * last_output;
+                last_input = *s;
+                last_output
+            })
+            .collect()
+    }
+}
+
+impl Default for DcBlock {
+    fn default() -> Self {
+        DcBlock::new()
+    }
+}
+
+impl Effect for DcBlock {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        map_channels(sample, |wave, _| self.process(wave, sample.sample_rate()))
+    }
+}
+
+// feedback delay line, each echo is the last one scaled by feedback
+#[derive(Serialize, Deserialize)]
+pub struct Delay {
+    pub time_ms: f32,
+    pub feedback: f32,
+    pub wet: f32,
+    pub dry: f32,
+    // extra steps after the input ends, None rings until the echoes fall
+    // below -60 dB
+    pub tail: Option<usize>,
+}
+
+impl Delay {
+    pub fn new(time_ms: f32, feedback: f32, wet: f32, dry: f32) -> Self {
+        Delay {
+            time_ms,
+            feedback,
+            wet,
+            dry,
+            tail: None,
+        }
+    }
+
+    fn process(&self, wave: &[f32], sample_rate: u32) -> Vec<f32> {
+        let delay = ((self.time_ms / 1000.0 * sample_rate as f32).round() as usize).max(1);
+        let feedback = self.feedback.clamp(0.0, 0.999);
+        let tail = self.tail.unwrap_or_else(|| {
+            let echoes = if feedback > 0.0 {
+                ((0.001f32).ln() / feedback.ln()).ceil() as usize
+            } else {
+                0
+            };
+            delay * (echoes + 1)
+        });
+
+        let mut line = vec![0.0; delay];
+        (0..wave.len() + tail)
+            .map(|t| {
+                let dry = wave.get(t).copied().unwrap_or(0.0);
+                let index = t % delay;
+                let echo = line[index];
+                line[index] = dry + echo * feedback;
+                echo * self.wet + dry * self.dry
+            })
+            .collect()
+    }
+}
+
+impl Effect for Delay {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        map_channels(sample, |wave, _| self.process(wave, sample.sample_rate()))
+    }
+}
+
+// echoes bounce between the left and right channels, mono input is
+// spread over both
+#[derive(Serialize, Deserialize)]
+pub struct PingPongDelay {
+    pub time_ms: f32,
+    pub feedback: f32,
+    pub wet: f32,
+    pub dry: f32,
+    // extra steps after the input ends like Delay, None rings until the
+    // echoes on both sides fall below -60 dB
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tail: Option<usize>,
+}
+
+impl PingPongDelay {
+    pub fn new(time_ms: f32, feedback: f32, wet: f32, dry: f32) -> Self {
+        PingPongDelay {
+            time_ms,
+            feedback,
+            wet,
+            dry,
+            tail: None,
+        }
+    }
+}
+
+impl MultiChannelEffect for PingPongDelay {
+    fn process_channels(
+        &self,
+        channels: &[Vec<f32>],
+        sample_rate: u32,
+    ) -> Result<Vec<Vec<f32>>, Box<dyn error::Error>> {
+        let (left, right) = match channels {
+            [mono] => (mono, mono),
+            [left, right] => (left, right),
+            _ => {
+                return Err(Box::new(Error::ChannelMismatch {
+                    expected: 2,
+                    found: channels.len() as u16,
+                }))
+            }
+        };
+        let delay = ((self.time_ms / 1000.0 * sample_rate as f32).round() as usize).max(1);
+        let feedback = self.feedback.clamp(0.0, 0.999);
+        // feedback is only applied once a round trip, two delays
+        let tail = self.tail.unwrap_or_else(|| {
+            let echoes = if feedback > 0.0 {
+                ((0.001f32).ln() / feedback.ln()).ceil() as usize
+            } else {
+                0
+            };
+            delay * 2 * (echoes + 1)
+        });
+
+        // the first echo comes from the mixed input on the left then each
+        // line feeds the other
+        let length = left.len() + tail;
+        let mut left_line = vec![0.0; delay];
+        let mut right_line = vec![0.0; delay];
+        let mut out_left = Vec::with_capacity(length);
+        let mut out_right = Vec::with_capacity(length);
+        for t in 0..length {
+            let dry_left = left.get(t).copied().unwrap_or(0.0);
+            let dry_right = right.get(t).copied().unwrap_or(0.0);
+            let index = t % delay;
+            let (echo_left, echo_right) = (left_line[index], right_line[index]);
+            let input = (dry_left + dry_right) / 2.0;
+            left_line[index] = input + echo_right * feedback;
+            right_line[index] = echo_left;
+            out_left.push(dry_left * self.dry + echo_left * self.wet);
+            out_right.push(dry_right * self.dry + echo_right * self.wet);
+        }
+        Ok(vec![out_left, out_right])
+    }
+}
+
+// effects applied one after another, cloning a chain shares the effects
+#[derive(Clone, Default)]
+pub struct EffectChain {
+    effects: Vec<Arc<dyn Effect>>,
+}
+
+impl EffectChain {
+    pub fn new() -> Self {
+        EffectChain {
+            effects: Vec::new(),
+        }
+    }
+
+    pub fn push<E: Effect + 'static>(&mut self, effect: E) {
+        self.effects.push(Arc::new(effect));
+    }
+
+    pub fn then<E: Effect + 'static>(mut self, effect: E) -> Self {
+        self.push(effect);
+        self
+    }
 
+    pub fn len(&self) -> usize {
+        self.effects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+}
+
+impl Effect for EffectChain {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        let mut result = sample.box_clone();
+        for effect in &self.effects {
+            result = effect.apply(&*result)?;
+        }
+        Ok(result)
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "wav")]
     #[test]
     fn echo() -> Result<(), Box<dyn error::Error>> {
         let wave = sample::SineWave::new(440.0, (44100.0 * 0.25) as usize, 0.6);
@@ -66,4 +566,224 @@ mod tests {
         wave.export("./test_files/output/echo.wav")?;
         Ok(())
     }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn reverb() -> Result<(), Box<dyn error::Error>> {
+        let mut impulse = vec![0.0; 44100];
+        impulse[0] = 1.0;
+        let impulse = sample::WaveForm::from(&impulse);
+        let mut reverb = Reverb::new(0.8, 0.5, 1.0, 0.0);
+        reverb.tail = 22050;
+        let wet = impulse.apply(&reverb)?;
+        assert_eq!(wet.length(), 44100 + 22050);
+        let wave = wet.waveform(0).unwrap();
+        assert!(wave[..1000].iter().all(|s| *s == 0.0));
+        assert!(wave[2000..].iter().any(|s| s.abs() > 1e-4));
+
+        let song = sample::MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
+        song.apply(&Reverb::new(0.7, 0.3, 0.4, 0.6))?
+            .export("./test_files/output/reverb.wav")?;
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn compressor() -> Result<(), Box<dyn error::Error>> {
+        let peak = |sample: &dyn Sample| {
+            sample.waveform(0).unwrap()[22050..]
+                .iter()
+                .fold(0.0f32, |max, s| max.max(s.abs()))
+        };
+        let compressor = Compressor::new(-20.0, 4.0, 0.005, 0.1, 0.0);
+
+        let loud = sample::SineWave::new(440.0, 44100, 0.9);
+        let squashed = loud.apply(&compressor)?;
+        assert!(peak(&*squashed) < 0.5);
+
+        let quiet = sample::SineWave::new(440.0, 44100, 0.05);
+        let untouched = quiet.apply(&compressor)?;
+        assert!((peak(&*untouched) - peak(&quiet)).abs() < 1e-3);
+
+        let song = sample::MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        song.sample_sec(10.8, 15.9)?
+            .apply(&Compressor::new(-18.0, 3.0, 0.01, 0.2, 6.0))?
+            .export("./test_files/output/compressor.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn dc_block() -> Result<(), Box<dyn error::Error>> {
+        let sine = sample::SineWave::new(440.0, 44100, 0.5);
+        let left: Vec<f32> = sine.waveform(0).unwrap().iter().map(|s| s + 0.25).collect();
+        let offset = sample::MultiChannel::new_dual(
+            &sample::WaveForm::from(&left),
+            &sample::WaveForm::from(&[-0.1; 44100]),
+        )?;
+        let measured = crate::analysis::dc_offset(&offset);
+        assert!((measured[0] - 0.25).abs() < 1e-3);
+        assert!((measured[1] + 0.1).abs() < 1e-6);
+        let cleaned = offset.apply(&DcBlock::new())?;
+        assert!(crate::analysis::dc_offset(cleaned.as_ref())
+            .iter()
+            .all(|offset| offset.abs() < 1e-3));
+        // a constant is gone straight away and the sine comes through
+        assert!(cleaned.waveform(1).unwrap().iter().all(|s| s.abs() < 1e-6));
+        let left = cleaned.waveform(0).unwrap();
+        let expected = sine.waveform(0).unwrap();
+        // only a small phase shift this far above the cutoff
+        assert!(left
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, b)| (a - b).abs() < 0.02));
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn delay() -> Result<(), Box<dyn error::Error>> {
+        let impulse = sample::WaveForm::from(&[1.0]);
+        let mut delay = Delay::new(10.0, 0.5, 1.0, 1.0);
+        delay.tail = Some(1000);
+        let wave = impulse.apply(&delay)?.waveform(0).unwrap();
+        assert_eq!(wave.len(), 1001);
+        assert_eq!(wave[0], 1.0);
+        assert_eq!(wave[441], 1.0);
+        assert_eq!(wave[882], 0.5);
+        assert_eq!(wave.iter().filter(|s| **s != 0.0).count(), 3);
+
+        // long enough for the echoes to die out
+        let ringing = impulse.apply(&Delay::new(10.0, 0.5, 1.0, 0.0))?;
+        let wave = ringing.waveform(0).unwrap();
+        assert!(wave[wave.len() - 441..].iter().all(|s| s.abs() < 0.001));
+
+        let song = sample::MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
+        song.apply(&Delay::new(250.0, 0.4, 0.5, 1.0))?
+            .export("./test_files/output/delay.wav")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn ping_pong() -> Result<(), Box<dyn error::Error>> {
+        let mut impulse = vec![0.0; 2000];
+        impulse[0] = 1.0;
+        let impulse = sample::WaveForm::from(&impulse);
+        let wet = impulse.apply(&PingPongDelay::new(10.0, 0.5, 1.0, 0.0))?;
+        assert_eq!(wet.channels(), 2);
+        let left = wet.waveform(0).unwrap();
+        let right = wet.waveform(1).unwrap();
+        assert_eq!(left[441], 1.0);
+        assert_eq!(right[441], 0.0);
+        assert_eq!(right[882], 1.0);
+        assert_eq!(left[1323], 0.5);
+
+        let song = sample::MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
+        song.apply(&PingPongDelay::new(300.0, 0.5, 0.5, 1.0))?
+            .export("./test_files/output/ping_pong.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn ping_pong_tail() -> Result<(), Box<dyn error::Error>> {
+        let impulse = sample::WaveForm::from(&[1.0]);
+        let mut delay = PingPongDelay::new(10.0, 0.5, 1.0, 1.0);
+        delay.tail = Some(1000);
+        let wet = impulse.apply(&delay)?;
+        assert_eq!(wet.length(), 1001);
+        assert_eq!(wet.waveform(1).unwrap()[882], 1.0);
+
+        // rings on both sides until the echoes die out
+        let ringing = impulse.apply(&PingPongDelay::new(10.0, 0.5, 1.0, 0.0))?;
+        assert_eq!(ringing.length(), 1 + 441 * 2 * 11);
+        for channel in 0..2 {
+            let wave = ringing.waveform(channel).unwrap();
+            assert!(wave[..wave.len() - 882].iter().any(|s| s.abs() > 0.001));
+            assert!(wave[wave.len() - 882..].iter().all(|s| s.abs() < 0.001));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn chain() -> Result<(), Box<dyn error::Error>> {
+        let wave = sample::SineWave::new(440.0, 22050, 0.9);
+        let compressor = Compressor::new(-20.0, 4.0, 0.005, 0.1, 0.0);
+        let reverb = Reverb::new(0.5, 0.5, 0.3, 0.7);
+        let chain = EffectChain::new()
+            .then(Compressor::new(-20.0, 4.0, 0.005, 0.1, 0.0))
+            .then(Reverb::new(0.5, 0.5, 0.3, 0.7));
+        assert_eq!(chain.len(), 2);
+
+        let nested = wave.apply(&compressor)?.apply(&reverb)?;
+        let chained = wave.apply(&chain.clone())?;
+        let listed = wave.apply_all(&[&compressor, &reverb])?;
+        assert_eq!(chained.waveform(0), nested.waveform(0));
+        assert_eq!(listed.waveform(0), nested.waveform(0));
+        assert_eq!(
+            wave.apply(&EffectChain::new())?.waveform(0),
+            wave.waveform(0)
+        );
+        chained.export("./test_files/output/chain.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn block_effects() -> Result<(), Box<dyn error::Error>> {
+        use crate::effect::distortion::{BitCrush, HardClip, SoftClip};
+        use crate::effect::fade::{Curve, GainRamp};
+        use crate::effect::modulation::Tremolo;
+
+        let sine = sample::SineWave::new(440.0, 10000, 0.9);
+        let effects: [&dyn Effect; 6] = [
+            &GainRamp::new(0.2, 1.0, 0.01, 0.1, Curve::Linear),
+            &GainRamp::new(1.0, 0.0, 0.05, 0.1, Curve::Exponential),
+            &HardClip::new(0.5),
+            &SoftClip::new(3.0),
+            &BitCrush::new(4, 3),
+            &Tremolo::new(5.0, 0.8),
+        ];
+        for effect in effects.iter() {
+            let processed = sine.apply(*effect)?;
+            assert_eq!(processed.length(), sine.length());
+            let whole = processed.waveform(0).unwrap();
+            // blocks that don't line up with anything give the same audio,
+            // the linear ramp only to rounding
+            let mut streamed = vec![0.0; whole.len()];
+            for (index, block) in streamed.chunks_mut(7).enumerate() {
+                processed.fill(0, index * 7, block);
+            }
+            assert!(streamed
+                .iter()
+                .zip(whole.iter())
+                .all(|(a, b)| (a - b).abs() < 1e-6));
+        }
+        Ok(())
+    }
+
+    struct Invert;
+
+    impl WaveformEffect for Invert {
+        fn process(&self, waveform: &[f32]) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+            let inverted: Vec<f32> = waveform.iter().map(|s| -s).collect();
+            Ok(Box::new(sample::WaveForm::from(&inverted)))
+        }
+    }
+
+    #[test]
+    fn waveform_effect() -> Result<(), Box<dyn error::Error>> {
+        let left = sample::WaveForm::with_rate(&[0.5, -0.25], 48000);
+        let right = sample::WaveForm::with_rate(&[0.1, 0.2], 48000);
+        let stereo = sample::MultiChannel::new_dual(&left, &right)?;
+        let inverted = stereo.apply(&Invert)?;
+        assert_eq!(inverted.sample_rate(), 48000);
+        assert_eq!(inverted.waveform(0), Some(vec![-0.5, 0.25]));
+        assert_eq!(inverted.waveform(1), Some(vec![-0.1, -0.2]));
+
+        let boxed: Box<dyn WaveformEffect> = Box::new(Invert);
+        let chain = EffectChain::new().then(Invert).then(Invert);
+        assert_eq!(boxed.apply(&stereo)?.waveform(0), inverted.waveform(0));
+        assert_eq!(stereo.apply(&chain)?.waveform(1), stereo.waveform(1));
+        Ok(())
+    }
 }
@@ -0,0 +1,208 @@
+use crate::effect::Effect;
+use crate::sample::{MultiChannel, Sample, WaveForm};
+use crate::Error;
+
+use std::error;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+// how a parameter moves over the duration of a sample
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Automation {
+    // (seconds, value) points joined by straight lines, held flat before
+    // the first point and after the last
+    Breakpoints(Vec<(f32, f32)>),
+    // a sine between min and max going round rate times a second
+    Lfo { rate: f32, min: f32, max: f32 },
+}
+
+impl Automation {
+    pub fn value(&self, time: f32) -> f32 {
+        match self {
+            Automation::Breakpoints(points) => {
+                let next = points.iter().position(|(at, _)| *at > time);
+                match next {
+                    None => points.last().map_or(0.0, |(_, value)| *value),
+                    Some(0) => points[0].1,
+                    Some(next) => {
+                        let (start, from) = points[next - 1];
+                        let (end, to) = points[next];
+                        from + (to - from) * (time - start) / (end - start)
+                    }
+                }
+            }
+            Automation::Lfo { rate, min, max } => {
+                let x = 0.5 - 0.5 * (2.0 * PI * rate * time).cos();
+                min + (max - min) * x
+            }
+        }
+    }
+
+    // the lowest and highest value the parameter reaches
+    pub fn range(&self) -> (f32, f32) {
+        match self {
+            Automation::Breakpoints(points) => points
+                .iter()
+                .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), (_, v)| {
+                    (low.min(*v), high.max(*v))
+                }),
+            Automation::Lfo { min, max, .. } => (min.min(*max), min.max(*max)),
+        }
+    }
+}
+
+type Setter<E> = Arc<dyn Fn(&mut E, f32) + Send + Sync>;
+
+// an effect with one parameter following an automation curve, set writes
+// the parameter into a copy of the effect
+//
+// the effect is rendered over the whole sample at levels evenly spaced
+// values of the parameter and each step crossfades between the two
+// renders closest to the curve, so filters and tails keep their state
+// while the parameter moves
+#[derive(Clone)]
+pub struct AutomatedEffect<E> {
+    pub effect: E,
+    pub automation: Automation,
+    pub levels: usize,
+    set: Setter<E>,
+}
+
+impl<E: Effect + Clone> AutomatedEffect<E> {
+    pub fn new<F>(effect: E, set: F, automation: Automation) -> Self
+    where
+        F: Fn(&mut E, f32) + Send + Sync + 'static,
+    {
+        AutomatedEffect {
+            effect,
+            automation,
+            levels: 16,
+            set: Arc::new(set),
+        }
+    }
+
+    pub fn with_levels(mut self, levels: usize) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    // the effect with the parameter set to value
+    pub fn at(&self, value: f32) -> E {
+        let mut effect = self.effect.clone();
+        (self.set)(&mut effect, value);
+        effect
+    }
+}
+
+impl<E: Effect + Clone> Effect for AutomatedEffect<E> {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        let (low, high) = self.automation.range();
+        if !low.is_finite() || !high.is_finite() {
+            return Err(Box::new(Error::Invalid(
+                "Automation has no points".to_string(),
+            )));
+        }
+        if low == high {
+            return self.at(low).apply(sample);
+        }
+
+        let levels = self.levels.max(2);
+        let spacing = (high - low) / (levels - 1) as f32;
+        let mut renders = Vec::new();
+        for level in 0..levels {
+            renders.push(self.at(low + spacing * level as f32).apply(sample)?);
+        }
+
+        let rate = sample.sample_rate();
+        let length = renders.iter().map(|r| r.length()).max().unwrap_or(0);
+        let mut channels = MultiChannel::new();
+        for channel in 0..renders[0].channels() {
+            let waves = renders
+                .iter()
+                .map(|render| render.waveform_ref(channel))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(Error::MissingChannel(channel))?;
+            let mut wave = vec![0.0; length];
+            for (step, value) in wave.iter_mut().enumerate() {
+                let parameter = self.automation.value(step as f32 / rate as f32);
+                let position = ((parameter - low) / spacing).clamp(0.0, (levels - 1) as f32);
+                let below = (position.floor() as usize).min(levels - 2);
+                let fraction = position - below as f32;
+                let from = waves[below].get(step).copied().unwrap_or(0.0);
+                let to = waves[below + 1].get(step).copied().unwrap_or(0.0);
+                *value = from + fraction * (to - from);
+            }
+            channels.add_channel(&WaveForm::from_vec(wave, rate))?;
+        }
+        Ok(Box::new(channels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn automation_curves() {
+        let points = Automation::Breakpoints(vec![(0.5, 1.0), (1.5, 3.0), (2.0, 0.0)]);
+        assert_eq!(points.value(0.0), 1.0);
+        assert_eq!(points.value(1.0), 2.0);
+        assert_eq!(points.value(1.75), 1.5);
+        assert_eq!(points.value(5.0), 0.0);
+        assert_eq!(points.range(), (0.0, 3.0));
+
+        let lfo = Automation::Lfo {
+            rate: 2.0,
+            min: 100.0,
+            max: 300.0,
+        };
+        assert_eq!(lfo.value(0.0), 100.0);
+        assert!((lfo.value(0.25) - 300.0).abs() < 1e-3);
+        assert_eq!(lfo.range(), (100.0, 300.0));
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn filter_sweep() -> Result<(), Box<dyn error::Error>> {
+        use crate::analysis;
+        use crate::effect::eq::Band;
+        use crate::oscillator::WhiteNoise;
+
+        let noise = WhiteNoise::new(2 * 44100, 0.5);
+        let sweep = AutomatedEffect::new(
+            Band::low_pass(200.0, 0.7),
+            |band: &mut Band, cutoff| band.frequency = cutoff,
+            Automation::Breakpoints(vec![(0.0, 200.0), (2.0, 8000.0)]),
+        );
+        let swept = noise.apply(&sweep)?;
+        assert_eq!(swept.length(), noise.length());
+
+        let brightness = |start: usize| {
+            let part = swept.sample(start, start + 11025).unwrap();
+            let highs = part.apply(&Band::high_pass(2000.0, 0.7)).unwrap();
+            analysis::rms(highs.as_ref())
+        };
+        assert!(brightness(0) * 10.0 < brightness(6 * 11025));
+
+        // a flat curve is the plain effect
+        let flat = AutomatedEffect::new(
+            Band::low_pass(200.0, 0.7),
+            |band: &mut Band, cutoff| band.frequency = cutoff,
+            Automation::Breakpoints(vec![(0.0, 500.0)]),
+        );
+        assert_eq!(
+            noise.apply(&flat)?.waveform(0),
+            noise.apply(&Band::low_pass(500.0, 0.7))?.waveform(0)
+        );
+        let empty = AutomatedEffect {
+            automation: Automation::Breakpoints(Vec::new()),
+            ..flat
+        };
+        assert!(noise.apply(&empty).is_err());
+
+        swept.export("./test_files/output/filter_sweep.wav")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+use crate::effect::Effect;
+use crate::resample;
+use crate::sample::{MultiChannel, Sample, WaveForm};
+use crate::Error;
+
+use std::error;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+const BLOCK: usize = 1024;
+
+// convolves with an impulse response, like a recorded room for reverb or
+// a speaker cabinet, the output rings on for the length of the response
+//
+// a mono response is used for every channel, otherwise channels are
+// paired up and a mono sample through a stereo response comes out stereo
+#[derive(Debug, Clone)]
+pub struct Convolver {
+    pub wet: f32,
+    pub dry: f32,
+    ir: Arc<[Vec<f32>]>,
+    sample_rate: u32,
+}
+
+impl Convolver {
+    pub fn from_ir(sample: &dyn Sample) -> Result<Self, Error> {
+        let ir = (0..sample.channels())
+            .map(|channel| {
+                sample
+                    .waveform(channel)
+                    .ok_or(Error::MissingChannel(channel))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if ir.is_empty() {
+            return Err(Error::Invalid(
+                "Impulse response has no channels".to_string(),
+            ));
+        }
+        Ok(Convolver {
+            wet: 1.0,
+            dry: 0.0,
+            ir: ir.into(),
+            sample_rate: sample.sample_rate(),
+        })
+    }
+
+    pub fn with_mix(mut self, wet: f32, dry: f32) -> Self {
+        self.wet = wet;
+        self.dry = dry;
+        self
+    }
+
+    pub fn ir_length(&self) -> usize {
+        self.ir[0].len()
+    }
+}
+
+impl Effect for Convolver {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        let rate = sample.sample_rate();
+        // the response is matched to the sample instead of the other way
+        let ir: Vec<Vec<f32>> = if rate == self.sample_rate {
+            self.ir.to_vec()
+        } else {
+            self.ir
+                .iter()
+                .map(|wave| {
+                    resample::resample_wave(
+                        wave,
+                        self.sample_rate,
+                        rate,
+                        resample::DEFAULT_INTERPOLATION,
+                    )
+                })
+                .collect()
+        };
+
+        let mut channels = MultiChannel::new();
+        let count = sample.channels().max(ir.len() as u16);
+        for channel in 0..count {
+            let source = channel.min(sample.channels().saturating_sub(1));
+            let wave = sample
+                .waveform_ref(source)
+                .ok_or(Error::MissingChannel(source))?;
+            let response = &ir[(channel as usize).min(ir.len() - 1)];
+            let mut output = convolve(&wave, response, BLOCK);
+            for (out, dry) in output.iter_mut().zip(wave.iter()) {
+                *out = *out * self.wet + dry * self.dry;
+            }
+            channels.add_channel(&WaveForm::from_vec(output, rate))?;
+        }
+        Ok(Box::new(channels))
+    }
+}
+
+// uniformly partitioned convolution, the response is cut into blocks that
+// are each multiplied with the spectrum of an earlier input block so long
+// responses cost about as much per step as short ones
+fn convolve(input: &[f32], ir: &[f32], block: usize) -> Vec<f32> {
+    let length = (input.len() + ir.len()).saturating_sub(1);
+    let mut output = vec![0.0; length];
+    if input.is_empty() || ir.is_empty() {
+        return output;
+    }
+
+    let size = 2 * block;
+    let mut planner = FftPlanner::new();
+    let forward = planner.plan_fft_forward(size);
+    let inverse = planner.plan_fft_inverse(size);
+    let spectrum = |part: &[f32], buffer: &mut Vec<Complex<f32>>| {
+        buffer.clear();
+        buffer.extend(part.iter().map(|value| Complex::new(*value, 0.0)));
+        buffer.resize(size, Complex::new(0.0, 0.0));
+        forward.process(buffer);
+    };
+
+    let partitions: Vec<Vec<Complex<f32>>> = ir
+        .chunks(block)
+        .map(|part| {
+            let mut buffer = Vec::with_capacity(size);
+            spectrum(part, &mut buffer);
+            buffer
+        })
+        .collect();
+    // the spectra of the last few input blocks, one per partition
+    let mut history = vec![Vec::with_capacity(size); partitions.len()];
+    let mut sum = vec![Complex::new(0.0, 0.0); size];
+    let scale = 1.0 / size as f32;
+
+    for index in 0..length.div_ceil(block) {
+        let start = index * block;
+        let part = &input[start.min(input.len())..(start + block).min(input.len())];
+        spectrum(part, &mut history[index % partitions.len()]);
+
+        sum.iter_mut().for_each(|bin| *bin = Complex::new(0.0, 0.0));
+        for (delay, partition) in partitions.iter().enumerate().take(index + 1) {
+            let earlier = &history[(index - delay) % partitions.len()];
+            for ((bin, x), h) in sum.iter_mut().zip(earlier).zip(partition) {
+                *bin += x * h;
+            }
+        }
+        inverse.process(&mut sum);
+        for (out, bin) in output[start..].iter_mut().zip(&sum) {
+            *out += bin.re * scale;
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oscillator::WhiteNoise;
+
+    #[test]
+    fn matches_direct_convolution() {
+        let input = WhiteNoise::new(50, 1.0).waveform(0).unwrap();
+        let ir = WhiteNoise::new(13, 1.0).with_seed(1).waveform(0).unwrap();
+        let mut direct = vec![0.0; input.len() + ir.len() - 1];
+        for (i, x) in input.iter().enumerate() {
+            for (j, h) in ir.iter().enumerate() {
+                direct[i + j] += x * h;
+            }
+        }
+        // blocks smaller and larger than the response
+        for block in [4, 64].iter() {
+            let fast = convolve(&input, &ir, *block);
+            assert_eq!(fast.len(), direct.len());
+            for (a, b) in fast.iter().zip(&direct) {
+                assert!((a - b).abs() < 1e-4);
+            }
+        }
+        assert_eq!(convolve(&[], &ir, 4).len(), 12);
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn impulse_responses() -> Result<(), Box<dyn error::Error>> {
+        use crate::sample::SineWave;
+
+        let sine = SineWave::new(440.0, 4410, 0.5);
+        let mut impulse = vec![0.0; 101];
+        impulse[100] = 0.5;
+        let delay = Convolver::from_ir(&WaveForm::from(&impulse))?;
+        let wave = sine.apply(&delay)?.waveform(0).unwrap();
+        assert_eq!(wave.len(), 4510);
+        let original = sine.waveform(0).unwrap();
+        for step in 0..4410 {
+            assert!((wave[step + 100] - 0.5 * original[step]).abs() < 1e-5);
+        }
+
+        // a stereo response turns a mono sample stereo
+        let left = WaveForm::from(&[1.0]);
+        let right = WaveForm::from(&[-1.0]);
+        let stereo =
+            Convolver::from_ir(&MultiChannel::new_dual(&left, &right)?)?.with_mix(0.5, 0.5);
+        let wide = sine.apply(&stereo)?;
+        assert_eq!(wide.channels(), 2);
+        assert!((wide.waveform(0).unwrap()[100] - original[100]).abs() < 1e-5);
+        assert!(wide.waveform(1).unwrap()[100].abs() < 1e-5);
+
+        // a decaying noise tail as a room
+        let room: Vec<f32> = WhiteNoise::new(44100, 0.1)
+            .waveform(0)
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(t, v)| v * (-6.9 * t as f32 / 44100.0).exp())
+            .collect();
+        let reverb = Convolver::from_ir(&WaveForm::from(&room))?.with_mix(0.3, 1.0);
+        let wet = SineWave::new(440.0, 22050, 0.5).apply(&reverb)?;
+        assert_eq!(wet.length(), 22050 + 44099);
+        wet.export("./test_files/output/convolution_reverb.wav")?;
+        Ok(())
+    }
+}
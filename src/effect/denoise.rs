@@ -0,0 +1,332 @@
+use crate::effect::{map_channels, Effect};
+use crate::sample::Sample;
+use crate::Error;
+
+use std::error;
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+use serde::{Deserialize, Serialize};
+
+const FRAME_SIZE: usize = 2048;
+const HOP: usize = FRAME_SIZE / 4;
+// seconds of audio a click is compared against
+const DECLICK_WINDOW: f32 = 0.01;
+// anything quieter than this isn't heard as a click even in silence
+const DECLICK_FLOOR: f32 = 1e-3;
+
+fn hann() -> Vec<f32> {
+    (0..FRAME_SIZE)
+        .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / FRAME_SIZE as f32).cos())
+        .collect()
+}
+
+// spectral noise reduction, the average spectrum of a stretch of nothing but
+// noise like the run in before a record starts is taken off every frame.
+// bins that aren't threshold times louder than the noise are turned down as
+// far as reduction_db, a channel without its own profile uses the last one
+#[derive(Debug, Clone)]
+pub struct SpectralGate {
+    pub threshold: f32,
+    pub reduction_db: f32,
+    profile: Arc<[Vec<f32>]>,
+    sample_rate: u32,
+}
+
+impl SpectralGate {
+    // learn the noise from start to end of sample, at least 2048 steps
+    pub fn learn(sample: &dyn Sample, start: usize, end: usize) -> Result<Self, Error> {
+        let noise = sample.sample(start, end)?;
+        if noise.length() < FRAME_SIZE {
+            return Err(Error::LengthMismatch {
+                expected: FRAME_SIZE,
+                found: noise.length(),
+            });
+        }
+
+        let window = hann();
+        let fft = FftPlanner::new().plan_fft_forward(FRAME_SIZE);
+        let mut buffer = vec![Complex::new(0.0, 0.0); FRAME_SIZE];
+        let mut profile = Vec::new();
+        for channel in 0..noise.channels() {
+            let wave = noise
+                .waveform_ref(channel)
+                .ok_or(Error::MissingChannel(channel))?;
+            let mut average = vec![0.0; FRAME_SIZE / 2 + 1];
+            let mut frames = 0;
+            for start in (0..=wave.len() - FRAME_SIZE).step_by(HOP) {
+                for (n, bin) in buffer.iter_mut().enumerate() {
+                    *bin = Complex::new(wave[start + n] * window[n], 0.0);
+                }
+                fft.process(&mut buffer);
+                for (total, bin) in average.iter_mut().zip(buffer.iter()) {
+                    *total += bin.norm();
+                }
+                frames += 1;
+            }
+            average.iter_mut().for_each(|total| *total /= frames as f32);
+            profile.push(average);
+        }
+        if profile.is_empty() {
+            return Err(Error::Invalid("Noise sample has no channels".to_string()));
+        }
+
+        Ok(SpectralGate {
+            threshold: 1.5,
+            reduction_db: -30.0,
+            profile: profile.into(),
+            sample_rate: sample.sample_rate(),
+        })
+    }
+
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_reduction(mut self, reduction_db: f32) -> Self {
+        self.reduction_db = reduction_db;
+        self
+    }
+
+    fn process(&self, wave: &[f32], profile: &[f32]) -> Vec<f32> {
+        let window = hann();
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(FRAME_SIZE);
+        let inverse = planner.plan_fft_inverse(FRAME_SIZE);
+        let floor = 10f32.powf(self.reduction_db.min(0.0) / 20.0);
+
+        // frames start before the wave so every step is covered by the
+        // same number of them
+        let offset = FRAME_SIZE - HOP;
+        let mut output = vec![0.0; wave.len() + offset + FRAME_SIZE];
+        let mut weights = vec![0.0; wave.len() + offset + FRAME_SIZE];
+        let mut buffer = vec![Complex::new(0.0, 0.0); FRAME_SIZE];
+        for start in (0..wave.len() + offset).step_by(HOP) {
+            for (n, bin) in buffer.iter_mut().enumerate() {
+                let value = (start + n)
+                    .checked_sub(offset)
+                    .and_then(|index| wave.get(index))
+                    .copied()
+                    .unwrap_or(0.0);
+                *bin = Complex::new(value * window[n], 0.0);
+            }
+            forward.process(&mut buffer);
+            for index in 0..FRAME_SIZE {
+                // the upper half mirrors the lower
+                let noise = profile[index.min(FRAME_SIZE - index)];
+                let magnitude = buffer[index].norm();
+                let gain = if magnitude > 0.0 {
+                    (1.0 - self.threshold * noise / magnitude).max(floor)
+                } else {
+                    floor
+                };
+                buffer[index] *= gain;
+            }
+            inverse.process(&mut buffer);
+            for (n, bin) in buffer.iter().enumerate() {
+                output[start + n] += bin.re / FRAME_SIZE as f32 * window[n];
+                weights[start + n] += window[n] * window[n];
+            }
+        }
+
+        output[offset..offset + wave.len()]
+            .iter()
+            .zip(weights[offset..].iter())
+            .map(|(value, weight)| if *weight > 1e-6 { value / weight } else { 0.0 })
+            .collect()
+    }
+}
+
+impl Effect for SpectralGate {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        if sample.sample_rate() != self.sample_rate {
+            return Err(Box::new(Error::SampleRateMismatch {
+                expected: self.sample_rate,
+                found: sample.sample_rate(),
+            }));
+        }
+        map_channels(sample, |wave, channel| {
+            let profile = &self.profile[(channel as usize).min(self.profile.len() - 1)];
+            self.process(wave, profile)
+        })
+    }
+}
+
+// finds clicks and pops as spikes in the second difference of the wave that
+// stand sensitivity times above its average over the surrounding 10 ms, and
+// bridges width_ms either side of each with a curve that carries on the
+// slope of the audio at both ends
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Declick {
+    pub sensitivity: f32,
+    pub width_ms: f32,
+}
+
+impl Declick {
+    pub fn new() -> Self {
+        Declick {
+            sensitivity: 8.0,
+            width_ms: 0.2,
+        }
+    }
+
+    pub fn with_sensitivity(mut self, sensitivity: f32) -> Self {
+        self.sensitivity = sensitivity;
+        self
+    }
+
+    pub fn with_width(mut self, width_ms: f32) -> Self {
+        self.width_ms = width_ms;
+        self
+    }
+
+    // the steps that belong to a click
+    fn detect(&self, wave: &[f32], sample_rate: u32) -> Vec<bool> {
+        let curve: Vec<f32> = (0..wave.len())
+            .map(|n| match (n.checked_sub(1), wave.get(n + 1)) {
+                (Some(before), Some(after)) => (wave[before] - 2.0 * wave[n] + after).abs(),
+                _ => 0.0,
+            })
+            .collect();
+        let mut totals = vec![0.0f64; curve.len() + 1];
+        for (n, value) in curve.iter().enumerate() {
+            totals[n + 1] = totals[n] + *value as f64;
+        }
+
+        let around = (DECLICK_WINDOW * sample_rate as f32) as usize / 2;
+        let width = (self.width_ms / 1000.0 * sample_rate as f32).ceil() as usize;
+        let mut clicks = vec![false; wave.len()];
+        for (n, value) in curve.iter().enumerate() {
+            let (from, to) = (n.saturating_sub(around), (n + around + 1).min(curve.len()));
+            let average = ((totals[to] - totals[from]) / (to - from) as f64) as f32;
+            if *value > DECLICK_FLOOR && *value > self.sensitivity * average {
+                let end = (n + width + 1).min(wave.len());
+                clicks[n.saturating_sub(width)..end].fill(true);
+            }
+        }
+        clicks
+    }
+
+    fn process(&self, wave: &[f32], sample_rate: u32) -> Vec<f32> {
+        let clicks = self.detect(wave, sample_rate);
+        let mut output = wave.to_vec();
+        let mut n = 0;
+        while n < wave.len() {
+            if !clicks[n] {
+                n += 1;
+                continue;
+            }
+            let start = n;
+            while n < wave.len() && clicks[n] {
+                n += 1;
+            }
+            // cubic hermite from the last good step before to the first
+            // good one after, an edge holds the value at the other end
+            let at = |step: Option<usize>| step.and_then(|step| wave.get(step)).copied();
+            let after = at(Some(n));
+            let before = at(start.checked_sub(1)).or(after).unwrap_or(0.0);
+            let after = after.unwrap_or(before);
+            let span = (n - start + 1) as f32;
+            let slope = |from: Option<f32>, to: Option<f32>| match (from, to) {
+                (Some(from), Some(to)) => (to - from) * span,
+                _ => 0.0,
+            };
+            let start_slope = slope(at(start.checked_sub(2)), at(start.checked_sub(1)));
+            let end_slope = slope(at(Some(n)), at(Some(n + 1)));
+            for (index, value) in output[start..n].iter_mut().enumerate() {
+                let t = (index + 1) as f32 / span;
+                let (t2, t3) = (t * t, t * t * t);
+                *value = (2.0 * t3 - 3.0 * t2 + 1.0) * before
+                    + (t3 - 2.0 * t2 + t) * start_slope
+                    + (-2.0 * t3 + 3.0 * t2) * after
+                    + (t3 - t2) * end_slope;
+            }
+        }
+        output
+    }
+}
+
+impl Default for Declick {
+    fn default() -> Self {
+        Declick::new()
+    }
+}
+
+impl Effect for Declick {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        map_channels(sample, |wave, _| self.process(wave, sample.sample_rate()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SineWave;
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn spectral_gate() -> Result<(), Box<dyn error::Error>> {
+        use crate::analysis;
+        use crate::oscillator::WhiteNoise;
+        use crate::sample::Composition;
+
+        // half a second of hiss before a tone starts
+        let hiss = WhiteNoise::new(88200, 0.05);
+        let tone = SineWave::new(440.0, 66150, 0.5);
+        let mut noisy = Composition::new();
+        noisy.add_track(&hiss, 0)?;
+        noisy.add_track(&tone, 22050)?;
+
+        let gate = SpectralGate::learn(&noisy, 0, 22050)?;
+        let cleaned = noisy.apply(&gate)?;
+        assert_eq!(cleaned.length(), noisy.length());
+
+        // the hiss on its own is mostly gone
+        let before = analysis::rms(noisy.sample(4410, 17640)?.as_ref());
+        let after = analysis::rms(cleaned.sample(4410, 17640)?.as_ref());
+        assert!(analysis::to_db(after / before) < -15.0);
+
+        // and what is left around the tone is much closer to it
+        let clean = tone.sample(22050, 44100)?.waveform(0).unwrap();
+        let error = |sample: &dyn Sample| {
+            let wave = sample.sample(44100, 66150).unwrap().waveform(0).unwrap();
+            let difference: Vec<f32> = wave.iter().zip(clean.iter()).map(|(a, b)| a - b).collect();
+            analysis::rms(&difference)
+        };
+        assert!(error(cleaned.as_ref()) < error(&noisy) / 2.0);
+        cleaned.export("./test_files/output/spectral_gate.wav")?;
+
+        assert!(SpectralGate::learn(&noisy, 0, 1000).is_err());
+        assert!(SpectralGate::learn(&noisy, 0, 100000).is_err());
+        assert!(noisy.resample(22050)?.apply(&gate).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn declick() -> Result<(), Box<dyn error::Error>> {
+        let sine = SineWave::new(440.0, 44100, 0.5);
+        let clean = sine.waveform(0).unwrap();
+        let mut clicky = clean.clone();
+        for start in [10000, 30000] {
+            clicky[start] += 0.8;
+            clicky[start + 1] -= 0.6;
+            clicky[start + 2] += 0.3;
+        }
+        let fixed = clicky.apply(&Declick::new())?.waveform(0).unwrap();
+
+        // only the steps around the clicks change and they end up close to
+        // the sine again
+        for (step, (fixed, clean)) in fixed.iter().zip(clean.iter()).enumerate() {
+            if (9900..10100).contains(&step) || (29900..30100).contains(&step) {
+                assert!((fixed - clean).abs() < 0.05);
+            } else {
+                assert_eq!(fixed, clean);
+            }
+        }
+        assert_eq!(sine.apply(&Declick::new())?.waveform(0), Some(clean));
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+use crate::effect::{BlockEffect, Effect, Processed};
+use crate::sample::Sample;
+use crate::Error;
+
+use std::error;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct HardClip {
+    pub threshold: f32,
+}
+
+impl HardClip {
+    pub fn new(threshold: f32) -> Self {
+        HardClip { threshold }
+    }
+}
+
+impl BlockEffect for HardClip {
+    fn process_block(&self, buffer: &mut [f32], _offset: usize, _sample_rate: u32) {
+        let threshold = self.threshold.abs();
+        buffer
+            .iter_mut()
+            .for_each(|s| *s = s.clamp(-threshold, threshold));
+    }
+}
+
+impl Effect for HardClip {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        Ok(Box::new(Processed::new(sample.box_clone(), *self)))
+    }
+}
+
+// tanh overdrive, scaled so a full scale input still peaks at 1.0
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct SoftClip {
+    pub drive: f32,
+}
+
+impl SoftClip {
+    pub fn new(drive: f32) -> Self {
+        SoftClip { drive }
+    }
+}
+
+impl BlockEffect for SoftClip {
+    fn process_block(&self, buffer: &mut [f32], _offset: usize, _sample_rate: u32) {
+        let scale = 1.0 / self.drive.tanh();
+        buffer
+            .iter_mut()
+            .for_each(|s| *s = (*s * self.drive).tanh() * scale);
+    }
+}
+
+impl Effect for SoftClip {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        if self.drive <= 0.0 {
+            return Ok(sample.box_clone());
+        }
+        Ok(Box::new(Processed::new(sample.box_clone(), *self)))
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct BitCrush {
+    pub bits: u32,
+    // hold every value for this many steps
+    pub downsample: usize,
+}
+
+impl BitCrush {
+    pub fn new(bits: u32, downsample: usize) -> Self {
+        BitCrush { bits, downsample }
+    }
+}
+
+impl BlockEffect for BitCrush {
+    // offset has to be a step where a new value is taken
+    fn process_block(&self, buffer: &mut [f32], offset: usize, _sample_rate: u32) {
+        let levels = (1u32 << (self.bits - 1)) as f32;
+        let downsample = self.downsample.max(1);
+        let mut held = 0.0;
+        for (t, s) in buffer.iter_mut().enumerate() {
+            if (offset + t).is_multiple_of(downsample) {
+                held = (*s * levels).round() / levels;
+            }
+            *s = held;
+        }
+    }
+
+    // a block starting part way through a held value reads back to where
+    // the value was taken
+    fn fill(
+        &self,
+        sample: &dyn Sample,
+        channel: u16,
+        offset: usize,
+        buffer: &mut [f32],
+    ) -> Option<usize> {
+        let back = offset % self.downsample.max(1);
+        let mut block = vec![0.0; back + buffer.len()];
+        let read = sample.fill(channel, offset - back, &mut block)?;
+        self.process_block(&mut block[..read], offset - back, sample.sample_rate());
+        let filled = read.saturating_sub(back);
+        buffer[..filled].copy_from_slice(&block[back..back + filled]);
+        Some(filled)
+    }
+}
+
+impl Effect for BitCrush {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        if self.bits == 0 || self.bits > 24 {
+            return Err(Box::new(Error::Invalid(
+                "Bit depth must be between 1 and 24".to_string(),
+            )));
+        }
+        Ok(Box::new(Processed::new(sample.box_clone(), *self)))
+    }
+}
+
+#[cfg(all(test, feature = "wav"))]
+mod tests {
+    use super::*;
+    use crate::sample::{MultiChannel, SineWave, WaveForm};
+
+    #[test]
+    fn clipping() -> Result<(), Box<dyn error::Error>> {
+        let ramp = WaveForm::from(&[-1.0, -0.5, 0.0, 0.25, 1.0]);
+        let hard = ramp.apply(&HardClip::new(0.5))?.waveform(0).unwrap();
+        assert_eq!(hard, vec![-0.5, -0.5, 0.0, 0.25, 0.5]);
+
+        let soft = ramp.apply(&SoftClip::new(4.0))?.waveform(0).unwrap();
+        assert!((soft[4] - 1.0).abs() < 1e-6);
+        assert!(soft[3] > 0.25 && soft[3] < 1.0);
+        assert_eq!(soft[2], 0.0);
+
+        let sine = SineWave::new(110.0, 44100, 0.8);
+        sine.apply(&SoftClip::new(6.0))?
+            .export("./test_files/output/overdrive.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn bitcrush() -> Result<(), Box<dyn error::Error>> {
+        let ramp = WaveForm::from(&[0.1, 0.3, 0.6, 0.9]);
+        let crushed = ramp.apply(&BitCrush::new(2, 2))?.waveform(0).unwrap();
+        assert_eq!(crushed, vec![0.0, 0.0, 0.5, 0.5]);
+        assert!(ramp.apply(&BitCrush::new(0, 1)).is_err());
+
+        let song = MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
+        song.apply(&BitCrush::new(6, 4))?
+            .export("./test_files/output/bitcrush.wav")?;
+        Ok(())
+    }
+}
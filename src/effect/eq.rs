@@ -0,0 +1,326 @@
+use crate::effect::{map_channels, Effect};
+use crate::sample::Sample;
+
+use std::error;
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FilterType {
+    LowPass,
+    HighPass,
+    BandPass,
+    // gain in dB for the peaking and shelving filters
+    Peaking(f32),
+    LowShelf(f32),
+    HighShelf(f32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Band {
+    pub filter: FilterType,
+    pub frequency: f32,
+    pub q: f32,
+}
+
+impl Band {
+    pub fn new(filter: FilterType, frequency: f32, q: f32) -> Self {
+        Band {
+            filter,
+            frequency,
+            q,
+        }
+    }
+
+    pub fn low_pass(frequency: f32, q: f32) -> Self {
+        Band::new(FilterType::LowPass, frequency, q)
+    }
+
+    pub fn high_pass(frequency: f32, q: f32) -> Self {
+        Band::new(FilterType::HighPass, frequency, q)
+    }
+
+    pub fn band_pass(frequency: f32, q: f32) -> Self {
+        Band::new(FilterType::BandPass, frequency, q)
+    }
+
+    pub fn peaking(frequency: f32, q: f32, gain_db: f32) -> Self {
+        Band::new(FilterType::Peaking(gain_db), frequency, q)
+    }
+
+    pub fn low_shelf(frequency: f32, q: f32, gain_db: f32) -> Self {
+        Band::new(FilterType::LowShelf(gain_db), frequency, q)
+    }
+
+    pub fn high_shelf(frequency: f32, q: f32, gain_db: f32) -> Self {
+        Band::new(FilterType::HighShelf(gain_db), frequency, q)
+    }
+}
+
+impl Effect for Band {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        filter_channels(sample, &[*self])
+    }
+}
+
+// direct form 1 biquad with coefficients from the RBJ audio eq cookbook
+#[derive(Debug, Clone)]
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Biquad {
+    pub fn new(band: &Band, sample_rate: u32) -> Self {
+        let w0 = 2.0 * PI * band.frequency / sample_rate as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * band.q.max(1e-3));
+
+        let (b0, b1, b2, a0, a1, a2) = match band.filter {
+            FilterType::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterType::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterType::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            FilterType::Peaking(gain) => {
+                let a = 10f32.powf(gain / 40.0);
+                (
+                    1.0 + alpha * a,
+                    -2.0 * cos,
+                    1.0 - alpha * a,
+                    1.0 + alpha / a,
+                    -2.0 * cos,
+                    1.0 - alpha / a,
+                )
+            }
+            FilterType::LowShelf(gain) => {
+                let a = 10f32.powf(gain / 40.0);
+                let beta = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + beta),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - beta),
+                    (a + 1.0) + (a - 1.0) * cos + beta,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - beta,
+                )
+            }
+            FilterType::HighShelf(gain) => {
+                let a = 10f32.powf(gain / 40.0);
+                let beta = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + beta),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - beta),
+                    (a + 1.0) - (a - 1.0) * cos + beta,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - beta,
+                )
+            }
+        };
+
+        Biquad {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            x1: 0.0,
+            x2: 0.0,
+            y1: 0.0,
+            y2: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+
+    pub fn reset(&mut self) {
+        self.x1 = 0.0;
+        self.x2 = 0.0;
+        self.y1 = 0.0;
+        self.y2 = 0.0;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ParametricEq {
+    pub bands: Vec<Band>,
+}
+
+impl ParametricEq {
+    pub fn new() -> Self {
+        ParametricEq { bands: Vec::new() }
+    }
+
+    pub fn add_band(&mut self, band: Band) {
+        self.bands.push(band);
+    }
+}
+
+impl Effect for ParametricEq {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        filter_channels(sample, &self.bands)
+    }
+}
+
+// compresses only what is above frequency so harsh s and t sounds are
+// turned down while the rest of the voice is left alone. the split is a
+// linkwitz-riley crossover, two butterworth stages each side, so the bands
+// add back up flat when nothing is being reduced
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DeEss {
+    pub frequency: f32,
+    pub threshold_db: f32,
+    pub ratio: f32,
+    pub attack: f32,
+    pub release: f32,
+}
+
+impl DeEss {
+    pub fn new(frequency: f32, threshold_db: f32, ratio: f32) -> Self {
+        DeEss {
+            frequency,
+            threshold_db,
+            ratio,
+            attack: 0.001,
+            release: 0.05,
+        }
+    }
+
+    fn process(&self, wave: &[f32], sample_rate: u32) -> Vec<f32> {
+        let coefficient = |time: f32| {
+            if time <= 0.0 {
+                0.0
+            } else {
+                (-1.0 / (time * sample_rate as f32)).exp()
+            }
+        };
+        let attack = coefficient(self.attack);
+        let release = coefficient(self.release);
+        let slope = 1.0 - 1.0 / self.ratio.max(1.0);
+
+        let mut lows = vec![Biquad::new(&Band::low_pass(self.frequency, 0.707), sample_rate); 2];
+        let mut highs = vec![Biquad::new(&Band::high_pass(self.frequency, 0.707), sample_rate); 2];
+        let mut reduction = 0.0;
+        wave.iter()
+            .map(|s| {
+                let low = lows.iter_mut().fold(*s, |s, filter| filter.process(s));
+                let high = highs.iter_mut().fold(*s, |s, filter| filter.process(s));
+                let level = 20.0 * (high.abs() + 1e-9).log10();
+                let target = (level - self.threshold_db).max(0.0) * slope;
+                let coef = if target > reduction { attack } else { release };
+                reduction = coef * reduction + (1.0 - coef) * target;
+                low + high * 10f32.powf(-reduction / 20.0)
+            })
+            .collect()
+    }
+}
+
+impl Effect for DeEss {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        map_channels(sample, |wave, _| self.process(wave, sample.sample_rate()))
+    }
+}
+
+fn filter_channels(
+    sample: &dyn Sample,
+    bands: &[Band],
+) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+    map_channels(sample, |wave, _| {
+        let mut wave = wave.to_vec();
+        for band in bands {
+            let mut filter = Biquad::new(band, sample.sample_rate());
+            wave.iter_mut().for_each(|s| *s = filter.process(*s));
+        }
+        wave
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SineWave;
+
+    fn rms(sample: &dyn Sample) -> f32 {
+        let wave = sample.waveform(0).unwrap();
+        // skip the filter settling at the start
+        let wave = &wave[wave.len() / 4..];
+        (wave.iter().map(|s| s * s).sum::<f32>() / wave.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn low_pass() -> Result<(), Box<dyn error::Error>> {
+        let low = SineWave::new(100.0, 44100, 0.5);
+        let high = SineWave::new(8000.0, 44100, 0.5);
+        let filter = Band::low_pass(1000.0, 0.707);
+        let low_out = low.apply(&filter)?;
+        let high_out = high.apply(&filter)?;
+        assert!((rms(&*low_out) - rms(&low)).abs() < 0.02);
+        assert!(rms(&*high_out) < rms(&high) * 0.05);
+        Ok(())
+    }
+
+    #[test]
+    fn de_ess() -> Result<(), Box<dyn error::Error>> {
+        let de_ess = DeEss::new(5000.0, -30.0, 6.0);
+        let voice = SineWave::new(200.0, 44100, 0.5);
+        let hiss = SineWave::new(7000.0, 44100, 0.5);
+        assert!((rms(&*voice.apply(&de_ess)?) - rms(&voice)).abs() < 0.01);
+        assert!(rms(&*hiss.apply(&de_ess)?) < rms(&hiss) * 0.5);
+
+        // quiet highs are under the threshold and come through as they were
+        let quiet = SineWave::new(7000.0, 44100, 0.01);
+        assert!((rms(&*quiet.apply(&de_ess)?) - rms(&quiet)).abs() < 1e-3);
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn parametric_eq() -> Result<(), Box<dyn error::Error>> {
+        use crate::sample::MultiChannel;
+
+        let wave = SineWave::new(1000.0, 44100, 0.25);
+        let mut eq = ParametricEq::new();
+        eq.add_band(Band::high_pass(40.0, 0.707));
+        eq.add_band(Band::peaking(1000.0, 1.0, 6.0));
+        eq.add_band(Band::high_shelf(8000.0, 0.707, -3.0));
+        let boosted = wave.apply(&eq)?;
+        let gain = rms(&*boosted) / rms(&wave);
+        assert!((gain - 2.0).abs() < 0.1);
+
+        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        song.sample_sec(10.8, 15.9)?
+            .apply(&eq)?
+            .export("./test_files/output/parametric_eq.wav")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,228 @@
+use crate::effect::{BlockEffect, Effect, Processed};
+use crate::sample::Sample;
+use crate::simd;
+
+use std::error;
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Curve {
+    Linear,
+    // even steps in dB over a 60 dB range
+    Exponential,
+    // sine and cosine so crossfades keep the same power
+    EqualPower,
+}
+
+impl Curve {
+    // rising shape from 0 to 1 as x goes from 0 to 1
+    fn shape(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => x,
+            Curve::Exponential => (1000f32.powf(x) - 1.0) / 999.0,
+            Curve::EqualPower => (x * PI / 2.0).sin(),
+        }
+    }
+
+    // falling ramps use the mirror of the curve so a fade out sounds like
+    // a fade in played backwards
+    pub fn gain(&self, from: f32, to: f32, x: f32) -> f32 {
+        if to >= from {
+            from + (to - from) * self.shape(x)
+        } else {
+            to + (from - to) * self.shape(1.0 - x)
+        }
+    }
+}
+
+// goes from one gain to another over duration seconds starting at start,
+// holding from before the ramp and to after it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GainRamp {
+    pub from: f32,
+    pub to: f32,
+    pub start: f32,
+    pub duration: f32,
+    pub curve: Curve,
+}
+
+impl GainRamp {
+    pub fn new(from: f32, to: f32, start: f32, duration: f32, curve: Curve) -> Self {
+        GainRamp {
+            from,
+            to,
+            start,
+            duration,
+            curve,
+        }
+    }
+
+    fn gain(&self, step: usize, sample_rate: u32) -> f32 {
+        let time = step as f32 / sample_rate as f32;
+        if self.duration <= 0.0 {
+            return if time < self.start {
+                self.from
+            } else {
+                self.to
+            };
+        }
+        let x = (time - self.start) / self.duration;
+        self.curve.gain(self.from, self.to, x)
+    }
+
+    // a linear ramp is a constant gain step so it can go through the
+    // vectorized path instead of working out the curve every step, wave
+    // holds the steps from offset on
+    fn apply_linear(&self, wave: &mut [f32], offset: usize, sample_rate: u32) {
+        let rate = sample_rate as f32;
+        let end = (((self.start + self.duration) * rate).floor() + 1.0).max(0.0) as usize;
+        let begin = ((self.start * rate).ceil().max(0.0) as usize).min(end);
+        let step = (self.to - self.from) / (self.duration * rate);
+        let first = self.from + step * (begin.max(offset) as f32 - self.start * rate);
+        let within = |at: usize| at.clamp(offset, offset + wave.len()) - offset;
+        let (begin, end) = (within(begin), within(end));
+        simd::scale(&mut wave[..begin], self.from);
+        simd::ramp(&mut wave[begin..end], first, step);
+        simd::scale(&mut wave[end..], self.to);
+    }
+}
+
+impl BlockEffect for GainRamp {
+    fn process_block(&self, buffer: &mut [f32], offset: usize, sample_rate: u32) {
+        if self.curve == Curve::Linear && self.duration > 0.0 {
+            self.apply_linear(buffer, offset, sample_rate);
+        } else {
+            buffer
+                .iter_mut()
+                .enumerate()
+                .for_each(|(t, s)| *s *= self.gain(offset + t, sample_rate));
+        }
+    }
+}
+
+impl Effect for GainRamp {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        Ok(Box::new(Processed::new(sample.box_clone(), *self)))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FadeIn {
+    pub duration: f32,
+    pub curve: Curve,
+}
+
+impl FadeIn {
+    pub fn new(duration: f32) -> Self {
+        FadeIn {
+            duration,
+            curve: Curve::Linear,
+        }
+    }
+
+    pub fn with_curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+}
+
+impl Effect for FadeIn {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        GainRamp::new(0.0, 1.0, 0.0, self.duration, self.curve).apply(sample)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FadeOut {
+    pub duration: f32,
+    pub curve: Curve,
+}
+
+impl FadeOut {
+    pub fn new(duration: f32) -> Self {
+        FadeOut {
+            duration,
+            curve: Curve::Linear,
+        }
+    }
+
+    pub fn with_curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+}
+
+impl Effect for FadeOut {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        // end the ramp on the last step so it reaches silence
+        let last = sample.length().saturating_sub(1) as f32 / sample.sample_rate() as f32;
+        let start = (last - self.duration).max(0.0);
+        GainRamp::new(1.0, 0.0, start, last - start, self.curve).apply(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::WaveForm;
+
+    #[test]
+    fn curves() {
+        for curve in [Curve::Linear, Curve::Exponential, Curve::EqualPower].iter() {
+            assert_eq!(curve.gain(0.0, 1.0, 0.0), 0.0);
+            assert!((curve.gain(0.0, 1.0, 1.0) - 1.0).abs() < 1e-6);
+            assert!((curve.gain(1.0, 0.0, 1.0)).abs() < 1e-6);
+        }
+        assert_eq!(Curve::Linear.gain(0.0, 1.0, 0.25), 0.25);
+        assert!(Curve::Exponential.gain(0.0, 1.0, 0.5) < 0.05);
+        // equal power crossfades sum to a constant power
+        let up = Curve::EqualPower.gain(0.0, 1.0, 0.3);
+        let down = Curve::EqualPower.gain(1.0, 0.0, 0.3);
+        assert!((up * up + down * down - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn fades() -> Result<(), Box<dyn error::Error>> {
+        let flat = WaveForm::with_rate(&[1.0; 101], 100);
+        let faded = flat.apply(&FadeIn::new(0.5))?.waveform(0).unwrap();
+        assert_eq!(faded[0], 0.0);
+        assert_eq!(faded[25], 0.5);
+        assert_eq!(faded[50..], [1.0; 51]);
+
+        let faded = flat
+            .apply(&FadeOut::new(0.5).with_curve(Curve::EqualPower))?
+            .waveform(0)
+            .unwrap();
+        assert_eq!(faded[..51], [1.0; 51]);
+        assert!(faded[100].abs() < 1e-6);
+
+        // the vectorized linear ramp lands on the same gains as the curve
+        let ramp = GainRamp::new(0.8, 0.1, 0.123, 0.456, Curve::Linear);
+        let ramped = flat.apply(&ramp)?.waveform(0).unwrap();
+        for (t, value) in ramped.iter().enumerate() {
+            assert!((value - ramp.gain(t, 100)).abs() < 1e-5);
+        }
+
+        let ramp = GainRamp::new(1.0, 0.5, 0.2, 0.0, Curve::Linear);
+        let stepped = flat.apply(&ramp)?.waveform(0).unwrap();
+        assert_eq!(stepped[19], 1.0);
+        assert_eq!(stepped[20], 0.5);
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn fade_slice() -> Result<(), Box<dyn error::Error>> {
+        use crate::sample::MultiChannel;
+
+        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        song.sample_sec(10.8, 12.8)?
+            .apply(&FadeIn::new(0.01))?
+            .apply(&FadeOut::new(0.5).with_curve(Curve::Exponential))?
+            .export("./test_files/output/fades.wav")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,400 @@
+use crate::effect::{map_channels, BlockEffect, Effect, MultiChannelEffect, Processed};
+use crate::sample::Sample;
+use crate::stereo;
+use crate::Error;
+
+use std::error;
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+const CHORUS_DELAY_MS: f32 = 20.0;
+const CHORUS_SWEEP_MS: f32 = 10.0;
+const FLANGER_DELAY_MS: f32 = 0.5;
+const FLANGER_SWEEP_MS: f32 = 4.0;
+const PHASER_MIN_HZ: f32 = 200.0;
+const PHASER_MAX_HZ: f32 = 4000.0;
+
+// sine low frequency oscillator, phase is in cycles
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lfo {
+    pub rate: f32,
+    pub phase: f32,
+}
+
+impl Lfo {
+    pub fn new(rate: f32) -> Self {
+        Lfo { rate, phase: 0.0 }
+    }
+
+    pub fn with_phase(mut self, phase: f32) -> Self {
+        self.phase = phase;
+        self
+    }
+
+    // between -1 and 1
+    pub fn value(&self, step: usize, sample_rate: u32) -> f32 {
+        let cycles = self.phase as f64 + self.rate as f64 * step as f64 / sample_rate as f64;
+        (2.0 * PI * cycles).sin() as f32
+    }
+
+    // between 0 and 1
+    pub fn unipolar(&self, step: usize, sample_rate: u32) -> f32 {
+        (self.value(step, sample_rate) + 1.0) / 2.0
+    }
+}
+
+// depth goes from 0 to 1 on all of these, mix is the amount of the
+// processed signal with the rest made up by the input
+#[derive(Serialize, Deserialize)]
+pub struct Chorus {
+    pub rate: f32,
+    pub depth: f32,
+    pub feedback: f32,
+    pub mix: f32,
+}
+
+impl Chorus {
+    pub fn new(rate: f32, depth: f32, feedback: f32) -> Self {
+        Chorus {
+            rate,
+            depth,
+            feedback,
+            mix: 0.5,
+        }
+    }
+}
+
+impl Effect for Chorus {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        let depth = self.depth.clamp(0.0, 1.0);
+        // offset the channels in time so stereo sounds wider
+        map_channels(sample, |wave, channel| {
+            let lfo = Lfo::new(self.rate).with_phase(channel as f32 * 0.25);
+            modulated_delay(
+                wave,
+                sample.sample_rate(),
+                |t| CHORUS_DELAY_MS + depth * CHORUS_SWEEP_MS * lfo.value(t, sample.sample_rate()),
+                self.feedback,
+                self.mix,
+            )
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Flanger {
+    pub rate: f32,
+    pub depth: f32,
+    pub feedback: f32,
+    pub mix: f32,
+}
+
+impl Flanger {
+    pub fn new(rate: f32, depth: f32, feedback: f32) -> Self {
+        Flanger {
+            rate,
+            depth,
+            feedback,
+            mix: 0.5,
+        }
+    }
+}
+
+impl Effect for Flanger {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        let depth = self.depth.clamp(0.0, 1.0);
+        let lfo = Lfo::new(self.rate);
+        map_channels(sample, |wave, _| {
+            modulated_delay(
+                wave,
+                sample.sample_rate(),
+                |t| {
+                    FLANGER_DELAY_MS
+                        + depth * FLANGER_SWEEP_MS * lfo.unipolar(t, sample.sample_rate())
+                },
+                self.feedback,
+                self.mix,
+            )
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Phaser {
+    pub rate: f32,
+    pub depth: f32,
+    pub feedback: f32,
+    pub mix: f32,
+    pub stages: usize,
+}
+
+impl Phaser {
+    pub fn new(rate: f32, depth: f32, feedback: f32) -> Self {
+        Phaser {
+            rate,
+            depth,
+            feedback,
+            mix: 0.5,
+            stages: 4,
+        }
+    }
+
+    fn process(&self, wave: &[f32], sample_rate: u32) -> Vec<f32> {
+        let lfo = Lfo::new(self.rate);
+        let depth = self.depth.clamp(0.0, 1.0);
+        let feedback = self.feedback.clamp(-0.95, 0.95);
+        // (last input, last output) of each first order allpass
+        let mut stages = vec![(0.0, 0.0); self.stages];
+        let mut last = 0.0;
+
+        wave.iter()
+            .enumerate()
+            .map(|(t, dry)| {
+                // sweep the notches on a log scale
+                let sweep = depth * lfo.unipolar(t, sample_rate);
+                let frequency = PHASER_MIN_HZ * (PHASER_MAX_HZ / PHASER_MIN_HZ).powf(sweep);
+                let tan = (std::f32::consts::PI * frequency / sample_rate as f32).tan();
+                let a = (1.0 - tan) / (1.0 + tan);
+
+                let mut value = dry + last * feedback;
+                for (input, output) in stages.iter_mut() {
+                    let out = a * value + *input - a * *output;
+                    *input = value;
+                    *output = out;
+                    value = out;
+                }
+                last = value;
+                dry * (1.0 - self.mix) + value * self.mix
+            })
+            .collect()
+    }
+}
+
+impl Effect for Phaser {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        map_channels(sample, |wave, _| self.process(wave, sample.sample_rate()))
+    }
+}
+
+// amplitude modulation, depth 1 fades all the way to silence
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Tremolo {
+    pub rate: f32,
+    pub depth: f32,
+}
+
+impl Tremolo {
+    pub fn new(rate: f32, depth: f32) -> Self {
+        Tremolo { rate, depth }
+    }
+}
+
+impl BlockEffect for Tremolo {
+    fn process_block(&self, buffer: &mut [f32], offset: usize, sample_rate: u32) {
+        let depth = self.depth.clamp(0.0, 1.0);
+        // start at the bottom of the lfo so the sample starts at full level
+        let lfo = Lfo::new(self.rate).with_phase(0.75);
+        for (t, s) in buffer.iter_mut().enumerate() {
+            *s *= 1.0 - depth * lfo.unipolar(offset + t, sample_rate);
+        }
+    }
+}
+
+impl Effect for Tremolo {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        Ok(Box::new(Processed::new(sample.box_clone(), *self)))
+    }
+}
+
+// sweeps the position between left and right, mono samples come out as
+// stereo and stereo samples use the same balance as Composition panning
+#[derive(Serialize, Deserialize)]
+pub struct AutoPan {
+    pub rate: f32,
+    pub depth: f32,
+}
+
+impl AutoPan {
+    pub fn new(rate: f32, depth: f32) -> Self {
+        AutoPan { rate, depth }
+    }
+}
+
+impl MultiChannelEffect for AutoPan {
+    fn process_channels(
+        &self,
+        channels: &[Vec<f32>],
+        sample_rate: u32,
+    ) -> Result<Vec<Vec<f32>>, Box<dyn error::Error>> {
+        let depth = self.depth.clamp(0.0, 1.0);
+        let lfo = Lfo::new(self.rate);
+        let pan = |t: usize| depth * lfo.value(t, sample_rate);
+
+        let (left, right): (Vec<f32>, Vec<f32>) = match channels {
+            // equal power so the level holds steady in the middle
+            [mono] => mono
+                .iter()
+                .enumerate()
+                .map(|(t, s)| {
+                    let (left, right) = stereo::pan_gains(pan(t));
+                    (s * left, s * right)
+                })
+                .unzip(),
+            [left, right] => left
+                .iter()
+                .zip(right)
+                .enumerate()
+                .map(|(t, (l, r))| {
+                    let pan = pan(t);
+                    (l * (1.0 - pan).min(1.0), r * (1.0 + pan).min(1.0))
+                })
+                .unzip(),
+            _ => {
+                return Err(Box::new(Error::Unsupported(
+                    "Auto pan needs a mono or stereo sample".to_string(),
+                )))
+            }
+        };
+        Ok(vec![left, right])
+    }
+}
+
+// a delay line read between steps at a time that changes every step
+fn modulated_delay<F>(
+    wave: &[f32],
+    sample_rate: u32,
+    delay_ms: F,
+    feedback: f32,
+    mix: f32,
+) -> Vec<f32>
+where
+    F: Fn(usize) -> f32,
+{
+    let feedback = feedback.clamp(-0.95, 0.95);
+    let steps_per_ms = sample_rate as f64 / 1000.0;
+    let size = ((CHORUS_DELAY_MS + CHORUS_SWEEP_MS) as f64 * steps_per_ms) as usize + 2;
+    let mut line = vec![0.0; size];
+
+    wave.iter()
+        .enumerate()
+        .map(|(t, dry)| {
+            let delay = (delay_ms(t) as f64 * steps_per_ms).clamp(1.0, (size - 2) as f64);
+            // anything from before the start is silence
+            let pos = t as f64 - delay;
+            let index = pos.floor() as isize;
+            let frac = (pos - index as f64) as f32;
+            let read = |i: isize| if i < 0 { 0.0 } else { line[i as usize % size] };
+            let delayed = read(index) * (1.0 - frac) + read(index + 1) * frac;
+            line[t % size] = dry + delayed * feedback;
+            dry * (1.0 - mix) + delayed * mix
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "wav")]
+    use crate::sample::MultiChannel;
+    use crate::sample::WaveForm;
+
+    #[cfg(feature = "wav")]
+    fn impulse() -> WaveForm {
+        let mut wave = vec![0.0; 4410];
+        wave[0] = 1.0;
+        WaveForm::from(&wave)
+    }
+
+    #[test]
+    fn lfo() {
+        let lfo = Lfo::new(1.0);
+        assert_eq!(lfo.value(0, 4), 0.0);
+        assert!((lfo.value(1, 4) - 1.0).abs() < 1e-6);
+        assert!((lfo.unipolar(3, 4)).abs() < 1e-6);
+        assert!((Lfo::new(1.0).with_phase(0.25).value(0, 4) - 1.0).abs() < 1e-6);
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn chorus_and_flanger() -> Result<(), Box<dyn error::Error>> {
+        // without any sweep these are plain delays
+        let mut chorus = Chorus::new(1.0, 0.0, 0.0);
+        chorus.mix = 1.0;
+        let wave = impulse().apply(&chorus)?.waveform(0).unwrap();
+        assert_eq!(wave.len(), 4410);
+        assert_eq!(wave[882], 1.0);
+        assert_eq!(wave.iter().filter(|s| **s != 0.0).count(), 1);
+
+        let mut flanger = Flanger::new(1.0, 0.0, 0.5);
+        flanger.mix = 0.5;
+        let wave = impulse().apply(&flanger)?.waveform(0).unwrap();
+        // 22.05 steps so each echo is spread over two steps
+        assert_eq!(wave[0], 0.5);
+        assert!((wave[1..30].iter().sum::<f32>() - 0.5).abs() < 1e-6);
+        assert!((wave[30..60].iter().sum::<f32>() - 0.25).abs() < 1e-6);
+
+        let song = MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
+        song.apply(&Chorus::new(0.8, 0.5, 0.2))?
+            .export("./test_files/output/chorus.wav")?;
+        song.apply(&Flanger::new(0.25, 1.0, 0.7))?
+            .export("./test_files/output/flanger.wav")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn phaser() -> Result<(), Box<dyn error::Error>> {
+        use crate::sample::SineWave;
+
+        let sine = SineWave::new(440.0, 44100, 0.5);
+        let mut dry = Phaser::new(0.5, 1.0, 0.0);
+        dry.mix = 0.0;
+        assert_eq!(sine.apply(&dry)?.waveform(0), sine.waveform(0));
+
+        // allpass stages change the phase but not the level
+        let mut wet = Phaser::new(0.5, 0.0, 0.0);
+        wet.mix = 1.0;
+        let shifted = sine.apply(&wet)?.waveform(0).unwrap();
+        let peak = shifted[4410..]
+            .iter()
+            .fold(0.0f32, |max, s| max.max(s.abs()));
+        assert!((peak - 0.5).abs() < 0.01);
+
+        sine.apply(&Phaser::new(0.5, 1.0, 0.5))?
+            .export("./test_files/output/phaser.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn tremolo() -> Result<(), Box<dyn error::Error>> {
+        let flat = WaveForm::from(&[1.0; 44100]);
+        let wave = flat.apply(&Tremolo::new(4.0, 0.5))?.waveform(0).unwrap();
+        // full level at the start and the deepest dip half a cycle later
+        assert!((wave[0] - 1.0).abs() < 1e-6);
+        assert!((wave[5512] - 0.5).abs() < 1e-3);
+        assert!(wave.iter().all(|s| *s >= 0.5 - 1e-6 && *s <= 1.0));
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn auto_pan() -> Result<(), Box<dyn error::Error>> {
+        let flat = WaveForm::from(&[1.0; 44100]);
+        let panned = flat.apply(&AutoPan::new(1.0, 1.0))?;
+        assert_eq!(panned.channels(), 2);
+        let left = panned.waveform(0).unwrap();
+        let right = panned.waveform(1).unwrap();
+        // centered, then hard right a quarter cycle later
+        assert!((left[0] - right[0]).abs() < 1e-6);
+        assert!(left[11025].abs() < 1e-3 && (right[11025] - 1.0).abs() < 1e-3);
+        assert!((left[0].powi(2) + right[0].powi(2) - 1.0).abs() < 1e-5);
+
+        let song = MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
+        song.apply(&AutoPan::new(0.5, 0.8))?
+            .export("./test_files/output/auto_pan.wav")?;
+        let empty = MultiChannel::new();
+        assert!(empty.apply(&AutoPan::new(1.0, 1.0)).is_err());
+        Ok(())
+    }
+}
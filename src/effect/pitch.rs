@@ -0,0 +1,368 @@
+use crate::analysis;
+use crate::effect::{map_channels, Effect};
+use crate::note::{Note, Scale};
+use crate::resample;
+use crate::sample::{keep_metadata, MultiChannel, Sample, WaveForm};
+use crate::Error;
+
+use std::error;
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+const FRAME_SIZE: usize = 2048;
+const SEARCH_RANGE: usize = 256;
+// grain spacing in seconds where there is no pitch to follow
+const UNVOICED_PERIOD: f32 = 0.005;
+
+// waveform similarity overlap-add, stretches a wave by factor without
+// changing pitch by picking each grain where it best lines up with the last
+pub fn stretch(wave: &[f32], factor: f32) -> Vec<f32> {
+    let length = (wave.len() as f32 * factor).round() as usize;
+    if wave.is_empty() || length == 0 {
+        return Vec::new();
+    }
+
+    let hop_out = FRAME_SIZE / 2;
+    let hop_in = hop_out as f32 / factor;
+    let window: Vec<f32> = (0..FRAME_SIZE)
+        .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / FRAME_SIZE as f32).cos())
+        .collect();
+    let at = |index: isize| {
+        if index < 0 {
+            0.0
+        } else {
+            wave.get(index as usize).copied().unwrap_or(0.0)
+        }
+    };
+
+    let mut output = vec![0.0; length + FRAME_SIZE];
+    let mut weights = vec![0.0; length + FRAME_SIZE];
+    let mut previous: isize = 0;
+    let mut frame = 0;
+    while frame * hop_out < length {
+        let nominal = (frame as f32 * hop_in) as isize;
+        let mut best = nominal;
+        if frame > 0 {
+            // match against what would have followed the previous grain
+            let target = previous + hop_out as isize;
+            let mut best_score = f32::MIN;
+            let range = SEARCH_RANGE as isize;
+            for offset in -range..=range {
+                let start = nominal + offset;
+                let score: f32 = (0..hop_out as isize)
+                    .step_by(4)
+                    .map(|n| at(start + n) * at(target + n))
+                    .sum();
+                if score > best_score {
+                    best_score = score;
+                    best = start;
+                }
+            }
+        }
+
+        let out = frame * hop_out;
+        for n in 0..FRAME_SIZE {
+            output[out + n] += at(best + n as isize) * window[n];
+            weights[out + n] += window[n];
+        }
+        previous = best;
+        frame += 1;
+    }
+
+    output.truncate(length);
+    for (value, weight) in output.iter_mut().zip(weights) {
+        if weight > 1e-3 {
+            *value /= weight;
+        }
+    }
+    output
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PitchShift {
+    pub semitones: f32,
+}
+
+impl PitchShift {
+    pub fn new(semitones: f32) -> Self {
+        PitchShift { semitones }
+    }
+}
+
+impl Effect for PitchShift {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        // stretch by the pitch ratio then play it back faster to get the
+        // original length at the new pitch
+        let ratio = 2f32.powf(self.semitones / 12.0);
+        let rate = sample.sample_rate();
+        map_channels(sample, |wave, _| {
+            let stretched = stretch(wave, ratio);
+            let mut shifted = resample::resample_wave(
+                &stretched,
+                (rate as f32 * ratio).round() as u32,
+                rate,
+                resample::Interpolation::Linear,
+            );
+            shifted.resize(wave.len(), 0.0);
+            shifted
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TimeStretch {
+    pub factor: f32,
+}
+
+impl TimeStretch {
+    pub fn new(factor: f32) -> Self {
+        TimeStretch { factor }
+    }
+
+    // stretch a loop recorded at one tempo so it plays at another
+    pub fn from_tempo(from_bpm: f32, to_bpm: f32) -> Self {
+        TimeStretch {
+            factor: from_bpm / to_bpm,
+        }
+    }
+}
+
+impl Effect for TimeStretch {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        if self.factor <= 0.0 {
+            return Err(Box::new(Error::Invalid(
+                "Time stretch factor must be positive".to_string(),
+            )));
+        }
+
+        // markers and loops move with the audio around them
+        let mut stretched = map_channels(sample, |wave, _| stretch(wave, self.factor))?;
+        if let Some(metadata) = sample.metadata() {
+            let factor = stretched.length() as f64 / sample.length().max(1) as f64;
+            keep_metadata(Some(&metadata.stretched(factor)), stretched.as_mut());
+        }
+        Ok(stretched)
+    }
+}
+
+// snaps the detected pitch to the closest note of a scale, speed is
+// roughly the seconds a note takes to slide onto the target, 0 jumps
+// straight there for the hard tuned sound
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PitchCorrect {
+    pub root: Note,
+    pub scale: Scale,
+    pub speed: f32,
+}
+
+impl PitchCorrect {
+    pub fn new(root: Note, scale: Scale) -> Self {
+        PitchCorrect {
+            root,
+            scale,
+            speed: 0.05,
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    // the pitch ratio to apply over each frame of the pitch track
+    fn ratios(&self, pitches: &[Option<f32>], sample_rate: u32) -> Vec<f32> {
+        let frame = analysis::HOP as f32 / sample_rate as f32;
+        let glide = if self.speed > 0.0 {
+            1.0 - (-frame / self.speed).exp()
+        } else {
+            1.0
+        };
+        let mut shift = 0.0;
+        pitches
+            .iter()
+            .map(|pitch| {
+                let target = match pitch {
+                    Some(frequency) => {
+                        let key = 69.0 + 12.0 * (frequency / 440.0).log2();
+                        self.scale.nearest(self.root, key) - key
+                    }
+                    None => 0.0,
+                };
+                shift += glide * (target - shift);
+                2f32.powf(shift / 12.0)
+            })
+            .collect()
+    }
+}
+
+impl Effect for PitchCorrect {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        let rate = sample.sample_rate();
+        let mono = WaveForm::from_vec(analysis::mono(sample), rate);
+        let pitches = analysis::detect_pitch(&mono, 0)?;
+        let ratios = self.ratios(&pitches, rate);
+        let frame_of = |step: f32| (step as usize / analysis::HOP).min(pitches.len().max(1) - 1);
+        let period = |step: f32| match pitches.get(frame_of(step)).copied().flatten() {
+            Some(frequency) => rate as f32 / frequency,
+            None => UNVOICED_PERIOD * rate as f32,
+        };
+        let ratio = |step: f32| ratios.get(frame_of(step)).copied().unwrap_or(1.0);
+
+        // pitch synchronous overlap-add, grains two periods long are cut
+        // one period apart and laid back down ratio times closer together
+        let length = sample.length();
+        let mut grains = Vec::new();
+        let (mut analysis_mark, mut synthesis_mark) = (0.0f32, 0.0f32);
+        while synthesis_mark < length as f32 {
+            loop {
+                let next = analysis_mark + period(analysis_mark);
+                if (next - synthesis_mark).abs() > (analysis_mark - synthesis_mark).abs() {
+                    break;
+                }
+                analysis_mark = next;
+            }
+            let width = period(analysis_mark);
+            grains.push((analysis_mark, synthesis_mark, width));
+            synthesis_mark += width / ratio(synthesis_mark);
+        }
+
+        let mut channels = MultiChannel::new();
+        for channel in 0..sample.channels() {
+            let wave = sample
+                .waveform_ref(channel)
+                .ok_or(Error::MissingChannel(channel))?;
+            let mut output = vec![0.0; length];
+            let mut weights = vec![0.0; length];
+            for (from, to, width) in &grains {
+                let size = width.ceil() as isize;
+                for n in -size..=size {
+                    let x = n as f32 / width;
+                    if x.abs() >= 1.0 {
+                        continue;
+                    }
+                    let out = *to as isize + n;
+                    let input = *from as isize + n;
+                    if out < 0 || out >= length as isize {
+                        continue;
+                    }
+                    let weight = 0.5 + 0.5 * (PI * x).cos();
+                    let value = if input < 0 {
+                        0.0
+                    } else {
+                        wave.get(input as usize).copied().unwrap_or(0.0)
+                    };
+                    output[out as usize] += value * weight;
+                    weights[out as usize] += weight;
+                }
+            }
+            for (value, weight) in output.iter_mut().zip(weights) {
+                if weight > 1e-3 {
+                    *value /= weight;
+                }
+            }
+            channels.add_channel(&WaveForm::from_vec(output, rate))?;
+        }
+        Ok(Box::new(channels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::SineWave;
+
+    fn crossings(wave: &[f32]) -> usize {
+        wave.windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count()
+    }
+
+    #[test]
+    fn stretch_keeps_pitch() {
+        let wave = SineWave::new(440.0, 44100, 0.5).waveform(0).unwrap();
+        let longer = stretch(&wave, 1.5);
+        assert_eq!(longer.len(), 66150);
+        // same frequency means 1.5 times the zero crossings over 1.5 times the time
+        let expected = crossings(&wave) as f32 * 1.5;
+        assert!((crossings(&longer) as f32 - expected).abs() / expected < 0.05);
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn pitch_shift_octave() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(220.0, 44100, 0.5);
+        let shifted = wave.apply(&PitchShift::new(12.0))?;
+        assert_eq!(shifted.length(), wave.length());
+        let original = crossings(&wave.waveform(0).unwrap()) as f32;
+        let octave = crossings(&shifted.waveform(0).unwrap()) as f32;
+        assert!((octave / original - 2.0).abs() < 0.1);
+        shifted.export("./test_files/output/pitch_shift.wav")?;
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn time_stretch_tempo() -> Result<(), Box<dyn error::Error>> {
+        let beat = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?
+            .sample_sec(11.1, 16.2)?;
+        let slower = beat.apply(&TimeStretch::from_tempo(100.0, 80.0))?;
+        assert_eq!(slower.channels(), beat.channels());
+        assert_eq!(
+            slower.length(),
+            (beat.length() as f32 * 1.25).round() as usize
+        );
+        slower.export("./test_files/output/time_stretch.wav")?;
+
+        assert!(beat.apply(&TimeStretch::new(0.0)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn time_stretch_markers() -> Result<(), Box<dyn error::Error>> {
+        use crate::metadata::Marker;
+
+        let mut beat = MultiChannel::new();
+        beat.add_channel(&SineWave::new(220.0, 44100, 0.5))?;
+        beat.add_marker(Marker::region("bar", 11025, 22050))?;
+        beat.add_loop(22050, 44100)?;
+        let slower = beat.apply(&TimeStretch::new(2.0))?;
+        let factor = slower.length() as f64 / beat.length() as f64;
+        let moved = |position: usize| (position as f64 * factor).round() as usize;
+        assert_eq!(
+            slower.marker("bar")?,
+            &Marker::region("bar", moved(11025), moved(22050))
+        );
+        assert_eq!(
+            slower.metadata().unwrap().loops,
+            [(moved(22050), moved(44100))]
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn pitch_correction() -> Result<(), Box<dyn error::Error>> {
+        let average = |pitches: &[Option<f32>]| {
+            pitches.iter().map(|p| p.unwrap()).sum::<f32>() / pitches.len() as f32
+        };
+        // a little sharp of A4 comes back down
+        let sharp = SineWave::new(450.0, 22050, 0.5);
+        let tuned = sharp.apply(&PitchCorrect::new(Note::C4, Scale::Chromatic).with_speed(0.0))?;
+        assert_eq!(tuned.length(), sharp.length());
+        let pitches = analysis::detect_pitch(tuned.as_ref(), 0)?;
+        assert!((average(&pitches[4..38]) - 440.0).abs() < 2.0);
+
+        // A#4 isn't in C major so it goes to A4, slowly
+        let off_key = SineWave::new(462.0, 22050, 0.5);
+        let slow = PitchCorrect::new(Note::C4, Scale::Major).with_speed(0.1);
+        let tuned = off_key.apply(&slow)?;
+        let pitches = analysis::detect_pitch(tuned.as_ref(), 0)?;
+        let start = average(&pitches[1..4]);
+        let end = average(&pitches[30..38]);
+        assert!(start > 450.0);
+        assert!((end - 440.0).abs() < 2.0);
+        tuned.export("./test_files/output/pitch_correct.wav")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use crate::effect::Effect;
+use crate::metadata::Metadata;
+use crate::sample::Sample;
+use crate::Error;
+
+use std::error;
+
+use serde::{Deserialize, Serialize};
+
+// attack, decay and release are in seconds, sustain is a level
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Envelope {
+    pub fn new(attack: f32, decay: f32, sustain: f32, release: f32) -> Self {
+        Envelope {
+            attack,
+            decay,
+            sustain,
+            release,
+        }
+    }
+
+    // gain at step for a note lasting length steps, the release is the
+    // last part of the note
+    pub fn gain(&self, step: usize, length: usize, sample_rate: u32) -> f32 {
+        let rate = sample_rate as f32;
+        let release = self.release * rate;
+        let note_off = (length as f32 - release).max(0.0);
+        let t = step as f32;
+        if t < note_off {
+            self.held(t, rate)
+        } else if release > 0.0 {
+            self.held(note_off, rate) * (1.0 - (t - note_off) / release).max(0.0)
+        } else {
+            0.0
+        }
+    }
+
+    fn held(&self, t: f32, rate: f32) -> f32 {
+        let attack = self.attack * rate;
+        let decay = self.decay * rate;
+        if t < attack {
+            t / attack
+        } else if t < attack + decay {
+            1.0 - (1.0 - self.sustain) * (t - attack) / decay
+        } else {
+            self.sustain
+        }
+    }
+}
+
+impl Effect for Envelope {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        Ok(Box::new(Enveloped::from(sample, *self)))
+    }
+}
+
+pub struct Enveloped {
+    pub envelope: Envelope,
+    pub sample: Box<dyn Sample>,
+}
+
+impl Enveloped {
+    pub fn from(sample: &dyn Sample, envelope: Envelope) -> Enveloped {
+        Enveloped {
+            envelope,
+            sample: sample.box_clone(),
+        }
+    }
+}
+
+impl Sample for Enveloped {
+    fn sample_rate(&self) -> u32 {
+        self.sample.sample_rate()
+    }
+    fn length(&self) -> usize {
+        self.sample.length()
+    }
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        let mut wave = self.sample.waveform(channel)?;
+        let length = wave.len();
+        for (step, value) in wave.iter_mut().enumerate() {
+            *value *= self.envelope.gain(step, length, self.sample_rate());
+        }
+        Some(wave)
+    }
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        let filled = self.sample.fill(channel, offset, buffer)?;
+        for (index, value) in buffer[..filled].iter_mut().enumerate() {
+            *value *= self
+                .envelope
+                .gain(offset + index, self.length(), self.sample_rate());
+        }
+        Some(filled)
+    }
+    fn channels(&self) -> u16 {
+        self.sample.channels()
+    }
+    fn metadata(&self) -> Option<&Metadata> {
+        self.sample.metadata()
+    }
+    fn set_metadata(&mut self, metadata: Metadata) -> Result<(), Error> {
+        self.sample.set_metadata(metadata)
+    }
+    fn box_clone(&self) -> Box<dyn Sample> {
+        Box::new(Enveloped {
+            envelope: self.envelope,
+            sample: self.sample.box_clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::WaveForm;
+
+    #[test]
+    fn adsr_shape() {
+        let envelope = Envelope::new(0.1, 0.1, 0.5, 0.2);
+        let note = Enveloped::from(&WaveForm::from(&[1.0; 44100]), envelope);
+        let wave = note.waveform(0).unwrap();
+        assert_eq!(wave[0], 0.0);
+        assert!((wave[2205] - 0.5).abs() < 1e-3);
+        assert!((wave[4410] - 1.0).abs() < 1e-3);
+        assert!((wave[6615] - 0.75).abs() < 1e-3);
+        assert!((wave[20000] - 0.5).abs() < 1e-3);
+        assert!((wave[35280] - 0.5).abs() < 1e-3);
+        assert!(wave[44099] < 1e-3);
+
+        let mut block = [0.0; 100];
+        note.fill(0, 4400, &mut block);
+        assert_eq!(block, wave[4400..4500]);
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn envelope_effect() -> Result<(), Box<dyn error::Error>> {
+        use crate::sample::SineWave;
+
+        let tone = SineWave::new(440.0, 22050, 0.6);
+        let note = tone.apply(&Envelope::new(0.01, 0.05, 0.7, 0.1))?;
+        assert_eq!(note.length(), tone.length());
+        assert_eq!(note.waveform(0).unwrap()[0], 0.0);
+        note.export("./test_files/output/envelope.wav")?;
+        Ok(())
+    }
+}
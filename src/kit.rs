@@ -0,0 +1,435 @@
+use crate::note::Note;
+use crate::resample;
+use crate::sample::{Composition, MultiChannel, Sample, TrackOptions, WaveForm};
+use crate::Error;
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+// how long a choked hit takes to fade out instead of clicking off
+const CHOKE_FADE: f32 = 0.005;
+
+// (pad, layer, alternative) a hit plays
+type Source = (usize, usize, usize);
+
+// samples played for hits at or above a velocity
+pub struct Layer {
+    pub velocity: f32,
+    // alternatives played in turn so repeated hits don't all sound the same
+    pub samples: Vec<Box<dyn Sample>>,
+}
+
+impl Clone for Layer {
+    fn clone(&self) -> Self {
+        Layer {
+            velocity: self.velocity,
+            samples: self.samples.iter().map(|s| s.box_clone()).collect(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Pad {
+    pub name: String,
+    // sorted by velocity, the first layer plays anything below the others
+    pub layers: Vec<Layer>,
+    pub gain: f32,
+    // in semitones, played faster or slower so the length changes too
+    pub pitch: f32,
+    // a hit cuts off anything still ringing in the same group, like an
+    // open hi-hat closed by the closed one
+    pub choke: Option<u32>,
+}
+
+impl Pad {
+    pub fn new(name: &str, sample: &dyn Sample) -> Self {
+        Pad {
+            name: name.to_string(),
+            layers: vec![Layer {
+                velocity: 0.0,
+                samples: vec![sample.box_clone()],
+            }],
+            gain: 1.0,
+            pitch: 0.0,
+            choke: None,
+        }
+    }
+
+    pub fn with_gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    pub fn with_pitch(mut self, pitch: f32) -> Self {
+        self.pitch = pitch;
+        self
+    }
+
+    pub fn with_choke(mut self, group: u32) -> Self {
+        self.choke = Some(group);
+        self
+    }
+
+    // a new layer for hits from velocity up, or another round robin
+    // alternative if the pad already has a layer at that velocity
+    pub fn with_layer(mut self, velocity: f32, sample: &dyn Sample) -> Self {
+        self.add_layer(velocity, sample);
+        self
+    }
+
+    pub fn add_layer(&mut self, velocity: f32, sample: &dyn Sample) {
+        match self.layers.iter_mut().find(|l| l.velocity == velocity) {
+            Some(layer) => layer.samples.push(sample.box_clone()),
+            None => {
+                let index = self.layers.partition_point(|l| l.velocity < velocity);
+                self.layers.insert(
+                    index,
+                    Layer {
+                        velocity,
+                        samples: vec![sample.box_clone()],
+                    },
+                );
+            }
+        }
+    }
+
+    // the first sample of the softest layer
+    pub fn sample(&self) -> &dyn Sample {
+        self.layers[0].samples[0].as_ref()
+    }
+
+    // the loudest layer the velocity reaches
+    pub fn layer(&self, velocity: f32) -> usize {
+        self.layers
+            .iter()
+            .rposition(|l| l.velocity <= velocity)
+            .unwrap_or(0)
+    }
+
+    // the sample a hit plays with the pad's pitch and gain applied, round
+    // counts the earlier hits of the same layer
+    pub fn render(&self, velocity: f32, round: usize) -> Result<Box<dyn Sample>, Error> {
+        let samples = &self.layers[self.layer(velocity)].samples;
+        self.render_sample(samples[round % samples.len()].as_ref())
+    }
+
+    fn render_sample(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Error> {
+        if self.pitch == 0.0 {
+            return Ok(sample.scale(self.gain));
+        }
+        let rate = sample.sample_rate();
+        let from = (rate as f32 * 2f32.powf(self.pitch / 12.0)).round() as u32;
+        let mut channels = MultiChannel::new();
+        for channel in 0..sample.channels() {
+            let wave = sample
+                .waveform_ref(channel)
+                .ok_or(Error::MissingChannel(channel))?;
+            let shifted =
+                resample::resample_wave(&wave, from, rate, resample::DEFAULT_INTERPOLATION);
+            channels.add_channel(&WaveForm::from_vec(shifted, rate))?;
+        }
+        Ok(channels.scale(self.gain))
+    }
+}
+
+// one trigger of a pad, start is in steps and velocity from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub pad: usize,
+    pub start: usize,
+    pub velocity: f32,
+}
+
+impl Hit {
+    pub fn new(pad: usize, start: usize, velocity: f32) -> Self {
+        Hit {
+            pad,
+            start,
+            velocity,
+        }
+    }
+}
+
+// named pads that can also be played from midi keys, all pads share a
+// sample rate and channel count so they can go in one composition
+#[derive(Clone, Default)]
+pub struct SampleKit {
+    pads: Vec<Pad>,
+    keys: HashMap<u8, usize>,
+}
+
+impl SampleKit {
+    pub fn new() -> Self {
+        SampleKit::default()
+    }
+
+    pub fn add_pad(&mut self, pad: Pad) -> Result<usize, Error> {
+        if self.index(&pad.name).is_some() {
+            return Err(Error::Invalid(format!(
+                "The kit already has a pad called {}",
+                pad.name
+            )));
+        }
+        if pad.layers.is_empty() {
+            return Err(Error::Invalid("A pad needs at least one layer".to_string()));
+        }
+        if pad.layers.iter().any(|l| l.samples.is_empty()) {
+            return Err(Error::Invalid(
+                "Every layer of a pad needs a sample".to_string(),
+            ));
+        }
+        let first = self.pads.first().unwrap_or(&pad).sample();
+        for sample in pad.layers.iter().flat_map(|l| &l.samples) {
+            if first.sample_rate() != sample.sample_rate() {
+                return Err(Error::SampleRateMismatch {
+                    expected: first.sample_rate(),
+                    found: sample.sample_rate(),
+                });
+            }
+            if first.channels() != sample.channels() {
+                return Err(Error::ChannelMismatch {
+                    expected: first.channels(),
+                    found: sample.channels(),
+                });
+            }
+        }
+        self.pads.push(pad);
+        Ok(self.pads.len() - 1)
+    }
+
+    // play the named pad when this key comes in from midi
+    pub fn map_key(&mut self, key: Note, name: &str) -> Result<(), Error> {
+        let index = self
+            .index(name)
+            .ok_or_else(|| Error::Invalid(format!("The kit has no pad called {}", name)))?;
+        self.keys.insert(key.key, index);
+        Ok(())
+    }
+
+    pub fn pads(&self) -> &[Pad] {
+        &self.pads
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.pads.iter().position(|pad| pad.name == name)
+    }
+
+    pub fn pad(&self, name: &str) -> Option<&Pad> {
+        self.index(name).map(|index| &self.pads[index])
+    }
+
+    pub fn pad_mut(&mut self, name: &str) -> Option<&mut Pad> {
+        let index = self.index(name)?;
+        self.pads.get_mut(index)
+    }
+
+    pub fn key(&self, key: u8) -> Option<usize> {
+        self.keys.get(&key).copied()
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.pads.first().map(|pad| pad.sample().sample_rate())
+    }
+
+    // add the hits to the composition with each one choked by the next hit
+    // in its group, the velocity picks the layer and repeated hits of a
+    // layer take turns through its alternatives
+    pub fn place(&self, comp: &mut Composition, hits: &[Hit]) -> Result<(), Error> {
+        if let Some(hit) = hits.iter().find(|hit| hit.pad >= self.pads.len()) {
+            return Err(Error::OutOfRange {
+                what: "pad",
+                index: hit.pad,
+                len: self.pads.len(),
+            });
+        }
+        let mut hits = hits.to_vec();
+        hits.sort_by_key(|hit| hit.start);
+
+        let mut rendered: HashMap<Source, Box<dyn Sample>> = HashMap::new();
+        let mut rounds: HashMap<(usize, usize), usize> = HashMap::new();
+        // (pad, layer, alternative, velocity, choked length, track id)
+        let mut tracks: Vec<(Source, f32, Option<usize>, usize)> = Vec::new();
+        for (index, hit) in hits.iter().enumerate() {
+            let pad = &self.pads[hit.pad];
+            let layer = pad.layer(hit.velocity);
+            let round = rounds.entry((hit.pad, layer)).or_insert(0);
+            let alternative = *round % pad.layers[layer].samples.len();
+            *round += 1;
+            let source = (hit.pad, layer, alternative);
+            let sample = match rendered.entry(source) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(pad.render(hit.velocity, alternative)?),
+            };
+            let cut = pad
+                .choke
+                .and_then(|group| {
+                    hits[index + 1..].iter().find(|next| {
+                        next.start > hit.start && self.pads[next.pad].choke == Some(group)
+                    })
+                })
+                .map(|next| next.start - hit.start)
+                .filter(|cut| *cut < sample.length());
+
+            let existing = tracks
+                .iter()
+                .find(|(from, velocity, length, _)| {
+                    *from == source && *velocity == hit.velocity && *length == cut
+                })
+                .map(|track| track.3);
+            match existing {
+                Some(id) => comp.add_track_id(id, hit.start)?,
+                None => {
+                    let options = TrackOptions::new(hit.velocity, 0.0);
+                    let id = match cut {
+                        Some(length) => comp.add_track_with(
+                            &choked(sample.as_ref(), length)?,
+                            hit.start,
+                            options,
+                        )?,
+                        None => comp.add_track_with(sample.as_ref(), hit.start, options)?,
+                    };
+                    tracks.push((source, hit.velocity, cut, id));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn arrange(&self, hits: &[Hit]) -> Result<Composition, Error> {
+        let mut comp = Composition::new();
+        self.place(&mut comp, hits)?;
+        Ok(comp)
+    }
+}
+
+// cut the sample off at length with a short fade so it doesn't click
+fn choked(sample: &dyn Sample, length: usize) -> Result<MultiChannel, Error> {
+    let fade = ((CHOKE_FADE * sample.sample_rate() as f32) as usize).clamp(1, length.max(1));
+    let mut channels = MultiChannel::new();
+    for channel in 0..sample.channels() {
+        let mut wave = vec![0.0; length];
+        let filled = sample
+            .fill(channel, 0, &mut wave)
+            .ok_or(Error::MissingChannel(channel))?;
+        wave.truncate(filled);
+        let start = wave.len().saturating_sub(fade);
+        for (index, value) in wave[start..].iter_mut().enumerate() {
+            *value *= 1.0 - (index + 1) as f32 / fade as f32;
+        }
+        channels.add_channel(&WaveForm::from_vec(wave, sample.sample_rate()))?;
+    }
+    Ok(channels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oscillator::WhiteNoise;
+    use crate::sample::SineWave;
+    use std::error;
+
+    fn kit() -> Result<SampleKit, Error> {
+        let mut kit = SampleKit::new();
+        kit.add_pad(Pad::new("kick", &SineWave::new(60.0, 8000, 0.9)))?;
+        kit.add_pad(Pad::new("closed", &WhiteNoise::new(2000, 0.3)).with_choke(1))?;
+        kit.add_pad(
+            Pad::new("open", &WhiteNoise::new(20000, 0.3))
+                .with_choke(1)
+                .with_gain(0.5),
+        )?;
+        kit.map_key(Note::C2, "kick")?;
+        kit.map_key(Note::FS2, "closed")?;
+        kit.map_key(Note::AS2, "open")?;
+        Ok(kit)
+    }
+
+    #[test]
+    fn pads() -> Result<(), Box<dyn error::Error>> {
+        let mut kit = kit()?;
+        assert!(kit
+            .add_pad(Pad::new("kick", &WaveForm::from(&[1.0])))
+            .is_err());
+        assert!(kit.map_key(Note::C4, "snare").is_err());
+        assert_eq!(kit.key(36), Some(0));
+        assert_eq!(kit.pad("open").unwrap().gain, 0.5);
+
+        // an octave up plays twice as fast
+        kit.pad_mut("kick").unwrap().pitch = 12.0;
+        let kick = kit.pad("kick").unwrap().render(1.0, 0)?;
+        assert!((kick.length() as i32 - 4000).abs() <= 1);
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn choke_groups() -> Result<(), Box<dyn error::Error>> {
+        let kit = kit()?;
+        let open = kit.index("open").unwrap();
+        let closed = kit.index("closed").unwrap();
+        let kick = kit.index("kick").unwrap();
+        let comp = kit.arrange(&[
+            Hit::new(open, 0, 1.0),
+            Hit::new(kick, 5000, 1.0),
+            Hit::new(closed, 10000, 1.0),
+            Hit::new(open, 30000, 0.5),
+        ])?;
+        let wave = comp.waveform(0).unwrap();
+        // the open hat stops when the closed one starts
+        assert!(wave[9000].abs() > 0.0);
+        assert!(wave[13000..30000].iter().all(|s| *s == 0.0));
+        // nothing after the last open hat chokes it
+        assert_eq!(comp.length(), 50000);
+        assert!(wave[49999].abs() > 0.0);
+        assert!(kit.arrange(&[Hit::new(3, 0, 1.0)]).is_err());
+        comp.export("./test_files/output/kit.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn layers_and_round_robin() -> Result<(), Box<dyn error::Error>> {
+        let soft = WaveForm::from(&[0.1; 10]);
+        let hard = WaveForm::from(&[0.8; 10]);
+        let hard_again = WaveForm::from(&[0.9; 10]);
+        let pad = Pad::new("snare", &soft)
+            .with_layer(0.7, &hard)
+            .with_layer(0.7, &hard_again);
+        assert_eq!(pad.layers.len(), 2);
+        assert_eq!(pad.layer(0.2), 0);
+        assert_eq!(pad.layer(0.7), 1);
+        assert_eq!(pad.sample().waveform(0).unwrap()[0], 0.1);
+
+        let mut kit = SampleKit::new();
+        let snare = kit.add_pad(pad)?;
+        let wave = kit
+            .arrange(&[
+                Hit::new(snare, 0, 0.5),
+                Hit::new(snare, 10, 1.0),
+                Hit::new(snare, 20, 1.0),
+                Hit::new(snare, 30, 1.0),
+                Hit::new(snare, 40, 0.5),
+            ])?
+            .waveform(0)
+            .unwrap();
+        let first: Vec<f32> = wave.iter().step_by(10).copied().collect();
+        assert_eq!(first, vec![0.05, 0.8, 0.9, 0.8, 0.05]);
+
+        let stereo = MultiChannel::new_dual(&soft, &soft)?;
+        let mixed = Pad::new("mixed", &soft).with_layer(0.5, &stereo);
+        assert!(SampleKit::new().add_pad(mixed).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn empty_pads() {
+        let mut kit = SampleKit::new();
+        let mut empty = Pad::new("empty", &WaveForm::from(&[0.5; 10]));
+        empty.layers.clear();
+        assert!(kit.add_pad(empty.clone()).is_err());
+        empty.layers.push(Layer {
+            velocity: 0.0,
+            samples: Vec::new(),
+        });
+        assert!(kit.add_pad(empty).is_err());
+        assert!(kit.pads().is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::Error;
+
+use std::error;
+use std::os::raw::{c_float, c_int, c_uchar};
+use std::ptr;
+
+const BLOCK_SIZE: usize = 4096;
+
+// lame's MPEG_mode enum
+const MODE_JOINT_STEREO: c_int = 1;
+const MODE_MONO: c_int = 3;
+
+#[repr(C)]
+struct LameGlobalFlags {
+    _private: [u8; 0],
+}
+
+#[link(name = "mp3lame")]
+extern "C" {
+    fn lame_init() -> *mut LameGlobalFlags;
+    fn lame_close(gfp: *mut LameGlobalFlags) -> c_int;
+    fn lame_set_num_channels(gfp: *mut LameGlobalFlags, channels: c_int) -> c_int;
+    fn lame_set_in_samplerate(gfp: *mut LameGlobalFlags, rate: c_int) -> c_int;
+    fn lame_set_brate(gfp: *mut LameGlobalFlags, brate: c_int) -> c_int;
+    fn lame_set_mode(gfp: *mut LameGlobalFlags, mode: c_int) -> c_int;
+    fn lame_init_params(gfp: *mut LameGlobalFlags) -> c_int;
+    fn lame_encode_buffer_ieee_float(
+        gfp: *mut LameGlobalFlags,
+        left: *const c_float,
+        right: *const c_float,
+        samples: c_int,
+        mp3buf: *mut c_uchar,
+        mp3buf_size: c_int,
+    ) -> c_int;
+    fn lame_encode_flush(gfp: *mut LameGlobalFlags, mp3buf: *mut c_uchar, size: c_int) -> c_int;
+}
+
+struct Encoder {
+    gfp: *mut LameGlobalFlags,
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        unsafe {
+            lame_close(self.gfp);
+        }
+    }
+}
+
+// encode one or two equal length channels into an mp3 byte stream
+pub fn encode(
+    channels: &[Vec<f32>],
+    sample_rate: u32,
+    bitrate: u32,
+) -> Result<Vec<u8>, Box<dyn error::Error>> {
+    if channels.is_empty() || channels.len() > 2 {
+        return Err(Box::new(Error::Unsupported(
+            "MP3 export supports mono or stereo samples".to_string(),
+        )));
+    }
+
+    let gfp = unsafe { lame_init() };
+    if gfp.is_null() {
+        return Err(Box::new(Error::Other(
+            "Could not initialize the MP3 encoder".to_string(),
+        )));
+    }
+    let encoder = Encoder { gfp };
+
+    let mode = if channels.len() == 1 {
+        MODE_MONO
+    } else {
+        MODE_JOINT_STEREO
+    };
+    let status = unsafe {
+        lame_set_num_channels(encoder.gfp, channels.len() as c_int);
+        lame_set_in_samplerate(encoder.gfp, sample_rate as c_int);
+        lame_set_brate(encoder.gfp, bitrate as c_int);
+        lame_set_mode(encoder.gfp, mode);
+        lame_init_params(encoder.gfp)
+    };
+    if status < 0 {
+        return Err(Box::new(Error::Unsupported(
+            "MP3 export does not support that sample rate or bitrate".to_string(),
+        )));
+    }
+
+    // worst case output size recommended by lame.h
+    let mut buffer = vec![0u8; BLOCK_SIZE * 5 / 4 + 7200];
+    let mut bytes = Vec::new();
+    let left = &channels[0];
+    let right = channels.get(1).unwrap_or(left);
+    for start in (0..left.len()).step_by(BLOCK_SIZE) {
+        let end = (start + BLOCK_SIZE).min(left.len());
+        let written = unsafe {
+            lame_encode_buffer_ieee_float(
+                encoder.gfp,
+                left[start..end].as_ptr(),
+                if channels.len() == 1 {
+                    ptr::null()
+                } else {
+                    right[start..end].as_ptr()
+                },
+                (end - start) as c_int,
+                buffer.as_mut_ptr(),
+                buffer.len() as c_int,
+            )
+        };
+        if written < 0 {
+            return Err(Box::new(Error::Other("MP3 encoding failed".to_string())));
+        }
+        bytes.extend_from_slice(&buffer[..written as usize]);
+    }
+
+    let written =
+        unsafe { lame_encode_flush(encoder.gfp, buffer.as_mut_ptr(), buffer.len() as c_int) };
+    if written < 0 {
+        return Err(Box::new(Error::Other("MP3 encoding failed".to_string())));
+    }
+    bytes.extend_from_slice(&buffer[..written as usize]);
+    Ok(bytes)
+}
+
+#[cfg(all(test, feature = "mp3"))]
+mod tests {
+    use crate::sample::{MultiChannel, Sample, SineWave};
+    use std::error;
+
+    #[test]
+    fn export_mp3_round_trip() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(440.0, 44100, 0.5);
+        let stereo = MultiChannel::new_dual(&wave, &wave)?;
+        stereo.export_mp3("./test_files/output/export.mp3", 128)?;
+        let song = MultiChannel::from_mp3("./test_files/output/export.mp3")?;
+        assert_eq!(song.channels(), 2);
+        assert!(song.length() >= wave.length());
+        Ok(())
+    }
+}
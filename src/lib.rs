@@ -1,37 +1,181 @@
+pub mod analysis;
+pub mod batch;
 pub mod effect;
+pub mod envelope;
+pub mod kit;
+#[cfg(feature = "mp3-export")]
+mod lame;
+pub mod metadata;
+pub mod midi;
+pub mod note;
+pub mod oscillator;
+#[cfg(feature = "playback")]
+pub mod playback;
+pub mod progress;
+pub mod project;
+#[cfg(feature = "record")]
+pub mod record;
+pub mod resample;
 pub mod sample;
+pub mod script;
+pub mod sequencer;
+mod simd;
+pub mod stereo;
+#[cfg(feature = "mp3")]
+pub mod stream;
+pub mod synth;
+pub mod tempo;
 
-#[derive(Debug, PartialEq)]
-pub struct Error {
-    message: String,
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+
+// no longer PartialEq since io::Error isn't, match on the variant instead of
+// comparing whole errors
+#[derive(Debug)]
+pub enum Error {
+    // samples that should line up but have different channel counts
+    ChannelMismatch {
+        expected: u16,
+        found: u16,
+    },
+    SampleRateMismatch {
+        expected: u32,
+        found: u32,
+    },
+    LengthMismatch {
+        expected: usize,
+        found: usize,
+    },
+    // a channel, track or similar asked for past the end, what says which
+    OutOfRange {
+        what: &'static str,
+        index: usize,
+        len: usize,
+    },
+    // no track has the name it was looked up by
+    UnknownTrack(String),
+    UnknownMarker(String),
+    // a sample didn't give back a channel it says it has
+    MissingChannel(u16),
+    Io(io::Error),
+    // a file that could be read but not understood
+    Decode(String),
+    // a format or layout the crate can't handle
+    Unsupported(String),
+    // arguments that can't work, like an empty list or a track ducking itself
+    Invalid(String),
+    // stopped part way by a CancelToken
+    Cancelled,
+    // anything else, made with Error::new
+    Other(String),
 }
 
 impl Error {
     pub fn new_box(message: &str) -> Box<Error> {
-        Box::new(Error {
-            message: String::from(message),
-        })
+        Box::new(Error::new(message))
     }
 
     pub fn new(message: &str) -> Error {
-        Error {
-            message: String::from(message),
-        }
+        Error::Other(String::from(message))
     }
 
-    pub fn message(&self) -> &str {
-        &self.message
+    #[deprecated(note = "match on the variant or use to_string")]
+    pub fn message(&self) -> Cow<'_, str> {
+        match self {
+            Error::Decode(message)
+            | Error::Unsupported(message)
+            | Error::Invalid(message)
+            | Error::Other(message) => Cow::Borrowed(message),
+            error => Cow::Owned(error.to_string()),
+        }
     }
 }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ChannelMismatch { expected, found } => write!(
+                f,
+                "Expected {} channels but the sample has {}",
+                expected, found
+            ),
+            Error::SampleRateMismatch { expected, found } => write!(
+                f,
+                "Expected a sample rate of {} but the sample is {}",
+                expected, found
+            ),
+            Error::LengthMismatch { expected, found } => write!(
+                f,
+                "Expected a length of {} but the sample is {} long",
+                expected, found
+            ),
+            Error::OutOfRange { what, index, len } => {
+                write!(f, "There is no {} {}, there are {}", what, index, len)
+            }
+            Error::UnknownTrack(name) => write!(f, "There is no track named {}", name),
+            Error::UnknownMarker(name) => write!(f, "There is no marker named {}", name),
+            Error::MissingChannel(channel) => write!(f, "Sample is missing channel {}", channel),
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Cancelled => write!(f, "Cancelled"),
+            Error::Decode(message)
+            | Error::Unsupported(message)
+            | Error::Invalid(message)
+            | Error::Other(message) => {
+                write!(f, "{}", message)
+            }
+        }
     }
 }
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        None
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+#[cfg(feature = "wav")]
+impl From<hound::Error> for Error {
+    fn from(error: hound::Error) -> Self {
+        match error {
+            hound::Error::IoError(error) => Error::Io(error),
+            error => Error::Decode(error.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "mp3")]
+impl From<minimp3::Error> for Error {
+    fn from(error: minimp3::Error) -> Self {
+        match error {
+            minimp3::Error::Io(error) => Error::Io(error),
+            error => Error::Decode(error.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "ogg")]
+impl From<lewton::VorbisError> for Error {
+    fn from(error: lewton::VorbisError) -> Self {
+        Error::Decode(error.to_string())
+    }
+}
+
+#[cfg(feature = "symphonia-backend")]
+impl From<symphonia::core::errors::Error> for Error {
+    fn from(error: symphonia::core::errors::Error) -> Self {
+        match error {
+            symphonia::core::errors::Error::IoError(error) => Error::Io(error),
+            error => Error::Decode(error.to_string()),
+        }
     }
 }
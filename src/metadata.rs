@@ -0,0 +1,251 @@
+use crate::note::Note;
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+// reading and writing the chunks WAV files keep metadata in
+#[cfg(feature = "wav")]
+mod wav;
+#[cfg(feature = "wav")]
+pub(crate) use wav::{append_chunks, read_wav_chunks, read_wav_metadata};
+
+// a named point in a sample, or a region when it has a length
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Marker {
+    pub name: String,
+    pub position: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<usize>,
+}
+
+impl Marker {
+    pub fn new(name: &str, position: usize) -> Self {
+        Marker {
+            name: name.to_string(),
+            position,
+            length: None,
+        }
+    }
+
+    pub fn region(name: &str, start: usize, end: usize) -> Self {
+        Marker {
+            name: name.to_string(),
+            position: start,
+            length: Some(end.saturating_sub(start)),
+        }
+    }
+
+    // where a region stops, a point ends where it starts
+    pub fn end(&self) -> usize {
+        self.position + self.length.unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub name: Option<String>,
+    // the file the audio was loaded from
+    pub source: Option<String>,
+    pub bpm: Option<f32>,
+    // the musical key like "A minor" or "F#"
+    pub key: Option<String>,
+    pub tags: Vec<String>,
+    // sorted by position with no two sharing a name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub markers: Vec<Marker>,
+    // the note the sample sounds at when played back unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root_note: Option<Note>,
+    // sustain loops as (start, end) steps, the end not included
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub loops: Vec<(usize, usize)>,
+}
+
+impl Metadata {
+    pub fn new() -> Self {
+        Metadata::default()
+    }
+
+    // named after the file without its extension
+    pub fn from_file(path: &str) -> Self {
+        Metadata {
+            name: Path::new(path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned()),
+            source: Some(path.to_string()),
+            ..Metadata::default()
+        }
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    // a marker with the same name is replaced
+    pub fn with_marker(mut self, marker: Marker) -> Self {
+        self.add_marker(marker);
+        self
+    }
+
+    pub fn add_marker(&mut self, marker: Marker) {
+        self.markers.retain(|m| m.name != marker.name);
+        let index = self
+            .markers
+            .partition_point(|m| m.position <= marker.position);
+        self.markers.insert(index, marker);
+    }
+
+    pub fn with_root_note(mut self, note: Note) -> Self {
+        self.root_note = Some(note);
+        self
+    }
+
+    pub fn with_loop(mut self, start: usize, end: usize) -> Self {
+        self.loops.push((start, end));
+        self
+    }
+
+    // the metadata for the steps from start to end, with markers and loops
+    // moved to match, regions are cut to fit and anything else outside is
+    // dropped
+    pub fn slice(&self, start: usize, end: usize) -> Metadata {
+        let markers = self
+            .markers
+            .iter()
+            .filter(|m| {
+                m.position <= end && m.end() >= start && (m.length.is_some() || m.position >= start)
+            })
+            .map(|m| Marker {
+                name: m.name.clone(),
+                position: m.position.max(start) - start,
+                length: m.length.map(|_| m.end().min(end) - m.position.max(start)),
+            })
+            .collect();
+        let loops = self
+            .loops
+            .iter()
+            .filter(|(from, to)| *from >= start && *to <= end)
+            .map(|(from, to)| (from - start, to - start))
+            .collect();
+        Metadata {
+            markers,
+            loops,
+            ..self.clone()
+        }
+    }
+
+    // every marker and loop moved by map, which takes a position and gives
+    // back where it ends up, kept sorted by position
+    fn moved<F: Fn(usize) -> usize>(&self, map: F) -> Metadata {
+        let mut markers: Vec<Marker> = self
+            .markers
+            .iter()
+            .map(|m| {
+                let (from, to) = (map(m.position), map(m.end()));
+                Marker {
+                    name: m.name.clone(),
+                    position: from.min(to),
+                    length: m.length.map(|_| from.max(to) - from.min(to)),
+                }
+            })
+            .collect();
+        markers.sort_by_key(|m| m.position);
+        let loops = self
+            .loops
+            .iter()
+            .map(|(start, end)| {
+                let (from, to) = (map(*start), map(*end));
+                (from.min(to), from.max(to))
+            })
+            .collect();
+        Metadata {
+            markers,
+            loops,
+            ..self.clone()
+        }
+    }
+
+    // positions scaled by factor, for a sample made longer or shorter
+    // without changing what is in it like a resample or a time stretch
+    pub fn stretched(&self, factor: f64) -> Metadata {
+        self.moved(|position| (position as f64 * factor).round() as usize)
+    }
+
+    // mirrored for the sample played backwards, length is the sample's
+    pub fn reversed(&self, length: usize) -> Metadata {
+        self.moved(|position| length.saturating_sub(position))
+    }
+
+    // moved later for a sample that starts delay steps in
+    pub fn shifted(&self, delay: usize) -> Metadata {
+        self.moved(|position| position + delay)
+    }
+
+    pub fn marker(&self, name: &str) -> Option<&Marker> {
+        self.markers.iter().find(|m| m.name == name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Metadata::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slice() {
+        let info = Metadata::from_file("./songs/break.wav")
+            .with_marker(Marker::new("before", 10))
+            .with_marker(Marker::region("across", 50, 150))
+            .with_marker(Marker::new("inside", 120))
+            .with_loop(110, 190)
+            .with_loop(90, 130);
+        let part = info.slice(100, 200);
+        assert_eq!(part.name.as_deref(), Some("break"));
+        assert_eq!(
+            part.markers,
+            vec![Marker::region("across", 0, 50), Marker::new("inside", 20)]
+        );
+        assert_eq!(part.loops, vec![(10, 90)]);
+    }
+
+    #[test]
+    fn moved() {
+        let info = Metadata::new()
+            .with_marker(Marker::new("start", 0))
+            .with_marker(Marker::region("verse", 20, 60))
+            .with_loop(40, 80);
+
+        let stretched = info.stretched(1.5);
+        assert_eq!(
+            stretched.markers,
+            vec![Marker::new("start", 0), Marker::region("verse", 30, 90)]
+        );
+        assert_eq!(stretched.loops, vec![(60, 120)]);
+
+        // the order flips with the sample
+        let reversed = info.reversed(100);
+        assert_eq!(
+            reversed.markers,
+            vec![Marker::region("verse", 40, 80), Marker::new("start", 100)]
+        );
+        assert_eq!(reversed.loops, vec![(20, 60)]);
+        assert_eq!(reversed.reversed(100), info);
+
+        let shifted = info.shifted(10);
+        assert_eq!(
+            shifted.marker("verse"),
+            Some(&Marker::region("verse", 30, 70))
+        );
+        assert_eq!(shifted.loops, vec![(50, 90)]);
+    }
+}
@@ -0,0 +1,401 @@
+use super::{Marker, Metadata};
+use crate::note::Note;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+
+impl Metadata {
+    fn has_info(&self) -> bool {
+        self.name.is_some()
+            || self.source.is_some()
+            || self.bpm.is_some()
+            || self.key.is_some()
+            || !self.tags.is_empty()
+    }
+
+    // a RIFF LIST chunk of INFO entries, the name goes in INAM, the source
+    // in ISRC, the tags in IKEY and the tempo and key in ICMT
+    fn info_chunk(&self) -> Vec<u8> {
+        let mut comment = Vec::new();
+        if let Some(bpm) = self.bpm {
+            comment.push(format!("bpm={}", bpm));
+        }
+        if let Some(key) = &self.key {
+            comment.push(format!("key={}", key));
+        }
+        let entries = [
+            (b"INAM", self.name.clone()),
+            (b"ISRC", self.source.clone()),
+            (
+                b"IKEY",
+                Some(self.tags.join("; ")).filter(|t| !t.is_empty()),
+            ),
+            (b"ICMT", Some(comment.join("; ")).filter(|c| !c.is_empty())),
+        ];
+
+        let mut chunk = b"INFO".to_vec();
+        for (id, text) in entries.iter() {
+            let text = match text {
+                Some(text) => text,
+                None => continue,
+            };
+            // null terminated and padded to an even length
+            let size = text.len() + 1;
+            chunk.extend_from_slice(*id);
+            chunk.extend_from_slice(&(size as u32).to_le_bytes());
+            chunk.extend_from_slice(text.as_bytes());
+            chunk.push(0);
+            if size % 2 == 1 {
+                chunk.push(0);
+            }
+        }
+        riff_chunk(b"LIST", &chunk)
+    }
+
+    // a sampler chunk with the root note and the loops, the period is the
+    // length of one step in nanoseconds
+    fn smpl_chunk(&self, sample_rate: u32) -> Vec<u8> {
+        let root = self.root_note.map_or(60, |note| note.key as u32);
+        let mut data = Vec::new();
+        for value in [
+            0,
+            0,
+            1_000_000_000 / sample_rate.max(1),
+            root,
+            0,
+            0,
+            0,
+            self.loops.len() as u32,
+            0,
+        ]
+        .iter()
+        {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for (index, (start, end)) in self.loops.iter().enumerate() {
+            // forward loops playing forever, the end in the chunk is the
+            // last step played
+            for value in [
+                index as u32,
+                0,
+                *start as u32,
+                end.saturating_sub(1) as u32,
+                0,
+                0,
+            ]
+            .iter()
+            {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        riff_chunk(b"smpl", &data)
+    }
+
+    // a cue chunk with a point for every marker followed by a LIST adtl
+    // chunk naming them, regions get an ltxt entry with their length
+    fn cue_chunks(&self) -> Vec<u8> {
+        let mut cue = (self.markers.len() as u32).to_le_bytes().to_vec();
+        let mut labels = b"adtl".to_vec();
+        for (index, marker) in self.markers.iter().enumerate() {
+            let id = (index as u32 + 1).to_le_bytes();
+            let position = (marker.position as u32).to_le_bytes();
+            cue.extend_from_slice(&id);
+            cue.extend_from_slice(&position);
+            cue.extend_from_slice(b"data");
+            cue.extend_from_slice(&[0; 8]);
+            cue.extend_from_slice(&position);
+
+            let mut label = id.to_vec();
+            label.extend_from_slice(marker.name.as_bytes());
+            label.push(0);
+            labels.extend(riff_chunk(b"labl", &label));
+            if let Some(length) = marker.length {
+                let mut text = id.to_vec();
+                text.extend_from_slice(&(length as u32).to_le_bytes());
+                text.extend_from_slice(b"rgn ");
+                // country, language, dialect and code page left unset
+                text.extend_from_slice(&[0; 8]);
+                labels.extend(riff_chunk(b"ltxt", &text));
+            }
+        }
+        let mut chunks = riff_chunk(b"cue ", &cue);
+        chunks.extend(riff_chunk(b"LIST", &labels));
+        chunks
+    }
+}
+
+// id, size and data padded to an even length
+fn riff_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+// add the metadata to the end of a finished WAV and fix up the RIFF size,
+// the WAV has to start at the beginning of the writer
+pub(crate) fn append_chunks<W: Write + Seek + ?Sized>(
+    wav: &mut W,
+    metadata: &Metadata,
+    sample_rate: u32,
+) -> io::Result<()> {
+    let end = wav.seek(SeekFrom::End(0))?;
+    if end % 2 == 1 {
+        wav.write_all(&[0])?;
+    }
+    if metadata.has_info() {
+        wav.write_all(&metadata.info_chunk())?;
+    }
+    if !metadata.markers.is_empty() {
+        wav.write_all(&metadata.cue_chunks())?;
+    }
+    if metadata.root_note.is_some() || !metadata.loops.is_empty() {
+        wav.write_all(&metadata.smpl_chunk(sample_rate))?;
+    }
+    let length = wav.seek(SeekFrom::End(0))?;
+    wav.seek(SeekFrom::Start(4))?;
+    wav.write_all(&((length - 8) as u32).to_le_bytes())?;
+    Ok(())
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Option<u32> {
+    let bytes = bytes.get(at..at + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// the chunks inside a LIST or the top of a RIFF file as (id, data) pairs
+fn sub_chunks(mut bytes: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    let mut chunks = Vec::new();
+    while bytes.len() >= 8 {
+        let id = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        let size = u32_at(bytes, 4).unwrap() as usize;
+        let data = &bytes[8..(8 + size).min(bytes.len())];
+        chunks.push((id, data));
+        bytes = &bytes[(8 + size + size % 2).min(bytes.len())..];
+    }
+    chunks
+}
+
+// text up to the null at the end
+fn chunk_text(data: &[u8]) -> String {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+impl Metadata {
+    fn read_info(&mut self, list: &[u8]) {
+        for (id, data) in sub_chunks(list) {
+            let text = chunk_text(data);
+            match &id {
+                b"INAM" => self.name = Some(text),
+                b"IKEY" => self.tags = text.split("; ").map(String::from).collect(),
+                b"ICMT" => {
+                    for entry in text.split("; ") {
+                        match entry.split_once('=') {
+                            Some(("bpm", bpm)) => self.bpm = bpm.parse().ok(),
+                            Some(("key", key)) => self.key = Some(key.to_string()),
+                            _ => (),
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn read_smpl(&mut self, data: &[u8]) {
+        if let Some(root) = u32_at(data, 12).filter(|root| *root <= 127) {
+            self.root_note = Some(Note::from_key(root as u8));
+        }
+        let count = u32_at(data, 28).unwrap_or(0) as usize;
+        for index in 0..count.min(data.len().saturating_sub(36) / 24) {
+            let at = 36 + index * 24;
+            if let (Some(start), Some(end)) = (u32_at(data, at + 8), u32_at(data, at + 12)) {
+                self.loops.push((start as usize, end as usize + 1));
+            }
+        }
+    }
+}
+
+// the metadata kept in a WAV file's INFO, cue, adtl and smpl chunks on top
+// of the name and source from the path, the audio itself is skipped over
+pub(crate) fn read_wav_chunks(file: &str) -> io::Result<Metadata> {
+    read_wav_metadata(
+        &mut BufReader::new(File::open(file)?),
+        Metadata::from_file(file),
+    )
+}
+
+// the same from any reader positioned at the start of the file
+pub(crate) fn read_wav_metadata<R: Read + Seek>(
+    wav: &mut R,
+    mut metadata: Metadata,
+) -> io::Result<Metadata> {
+    let mut header = [0; 12];
+    wav.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a WAV file"));
+    }
+
+    let mut cues: Vec<(u32, usize)> = Vec::new();
+    let mut labels = HashMap::new();
+    let mut lengths = HashMap::new();
+    let mut chunk = [0; 8];
+    while wav.read_exact(&mut chunk).is_ok() {
+        let size = u32_at(&chunk, 4).unwrap() as u64;
+        let id = &chunk[0..4];
+        if !matches!(id, b"LIST" | b"cue " | b"smpl") {
+            wav.seek(SeekFrom::Current((size + size % 2) as i64))?;
+            continue;
+        }
+        // the size comes from the file so only read what is really there
+        // instead of trusting it for the allocation
+        let mut data = Vec::new();
+        wav.by_ref().take(size).read_to_end(&mut data)?;
+        if (data.len() as u64) < size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "WAV chunk runs past the end of the file",
+            ));
+        }
+        if size % 2 == 1 {
+            wav.seek(SeekFrom::Current(1))?;
+        }
+        match id {
+            b"LIST" if data.starts_with(b"INFO") => metadata.read_info(&data[4..]),
+            b"LIST" if data.starts_with(b"adtl") => {
+                for (id, data) in sub_chunks(&data[4..]) {
+                    let cue = u32_at(data, 0).unwrap_or(0);
+                    match &id {
+                        b"labl" => {
+                            labels.insert(cue, chunk_text(&data[4.min(data.len())..]));
+                        }
+                        b"ltxt" => {
+                            if let Some(length) = u32_at(data, 4) {
+                                lengths.insert(cue, length as usize);
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            b"cue " => {
+                let count = u32_at(&data, 0).unwrap_or(0) as usize;
+                for index in 0..count.min(data.len() / 24) {
+                    let at = 4 + index * 24;
+                    if let (Some(cue), Some(position)) = (u32_at(&data, at), u32_at(&data, at + 20))
+                    {
+                        cues.push((cue, position as usize));
+                    }
+                }
+            }
+            b"smpl" => metadata.read_smpl(&data),
+            _ => (),
+        }
+    }
+
+    // cues without a label are named after their id
+    for (cue, position) in cues {
+        metadata.add_marker(Marker {
+            name: labels
+                .remove(&cue)
+                .unwrap_or_else(|| format!("cue {}", cue)),
+            position,
+            length: lengths.get(&cue).copied(),
+        });
+    }
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn info_chunk() {
+        assert!(Metadata::new().is_empty());
+        let info = Metadata::from_file("./songs/break.wav").with_tag("drums");
+        assert_eq!(info.name.as_deref(), Some("break"));
+        assert!(!info.is_empty());
+
+        let chunk = info.info_chunk();
+        assert_eq!(&chunk[..4], b"LIST");
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        assert_eq!(size as usize, chunk.len() - 8);
+        // the size counts the null at the end of the text
+        assert_eq!(&chunk[8..20], b"INFOINAM\x06\0\0\0");
+        assert_eq!(&chunk[20..26], b"break\0");
+        assert_eq!(&chunk[26..30], b"ISRC");
+        assert_eq!(chunk.len() % 2, 0);
+    }
+
+    #[test]
+    fn cue_chunks() {
+        let info = Metadata::new()
+            .with_marker(Marker::region("verse 2", 300, 500))
+            .with_marker(Marker::new("drop", 100))
+            .with_marker(Marker::new("drop", 200));
+        assert_eq!(info.markers.len(), 2);
+        assert_eq!(info.markers[0].name, "drop");
+        assert_eq!(info.marker("verse 2").unwrap().end(), 500);
+
+        let chunks = info.cue_chunks();
+        assert_eq!(&chunks[..8], b"cue \x34\0\0\0");
+        assert_eq!(&chunks[8..12], &2u32.to_le_bytes());
+        // the first point is the drop at 200
+        assert_eq!(&chunks[12..20], &[1, 0, 0, 0, 200, 0, 0, 0]);
+        assert_eq!(&chunks[20..24], b"data");
+        assert_eq!(&chunks[32..36], &200u32.to_le_bytes());
+        let list = &chunks[60..];
+        assert_eq!(&list[..4], b"LIST");
+        assert_eq!(&list[8..12], b"adtl");
+        assert_eq!(&list[12..20], b"labl\x09\0\0\0");
+        assert_eq!(&list[24..30], b"drop\0\0");
+        assert!(list.windows(4).any(|w| w == b"ltxt"));
+        assert_eq!(list.len() % 2, 0);
+    }
+
+    #[test]
+    fn smpl_chunk() {
+        let info = Metadata::new()
+            .with_root_note(Note::A4)
+            .with_loop(1000, 5000);
+        let chunk = info.smpl_chunk(44100);
+        assert_eq!(&chunk[..8], b"smpl\x3c\0\0\0");
+        let value = |index: usize| {
+            let at = 8 + index * 4;
+            u32::from_le_bytes([chunk[at], chunk[at + 1], chunk[at + 2], chunk[at + 3]])
+        };
+        assert_eq!(value(2), 22675);
+        assert_eq!(value(3), 69);
+        assert_eq!(value(7), 1);
+        assert_eq!((value(11), value(12)), (1000, 4999));
+    }
+
+    #[test]
+    fn truncated_chunks() {
+        // a cue chunk that claims 4 GB and a count to match
+        let mut wav = b"RIFF\0\0\0\0WAVEcue \xf0\xff\xff\xff".to_vec();
+        wav.extend_from_slice(&u32::MAX.to_le_bytes());
+        let read = read_wav_metadata(&mut io::Cursor::new(&wav), Metadata::new());
+        assert_eq!(read.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        // the count is only trusted as far as the chunk goes
+        let mut wav = b"RIFF\0\0\0\0WAVEcue \x04\0\0\0".to_vec();
+        wav.extend_from_slice(&u32::MAX.to_le_bytes());
+        let read = read_wav_metadata(&mut io::Cursor::new(&wav), Metadata::new());
+        assert!(read.unwrap().markers.is_empty());
+
+        // and the same for the loops in a smpl chunk
+        let mut smpl = vec![0; 36];
+        smpl[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut wav = b"RIFF\0\0\0\0WAVEsmpl\x24\0\0\0".to_vec();
+        wav.extend_from_slice(&smpl);
+        let read = read_wav_metadata(&mut io::Cursor::new(&wav), Metadata::new());
+        assert!(read.unwrap().loops.is_empty());
+    }
+}
@@ -0,0 +1,349 @@
+use crate::kit::{Hit, SampleKit};
+use crate::note::Note;
+use crate::sample::{Composition, Sample, TrackOptions};
+use crate::tempo::{Tempo, TimeSignature};
+use crate::Error;
+
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use std::collections::HashMap;
+use std::error;
+use std::fs;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidiNote {
+    pub channel: u8,
+    pub key: u8,
+    pub velocity: u8,
+    // in seconds
+    pub start: f64,
+    pub length: f64,
+}
+
+impl MidiNote {
+    pub fn note(&self) -> Note {
+        Note::from_key(self.key)
+    }
+
+    pub fn steps(&self, sample_rate: u32) -> usize {
+        (self.length * sample_rate as f64).round() as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Midi {
+    pub notes: Vec<MidiNote>,
+    pub tempo: Tempo,
+}
+
+impl Midi {
+    pub fn from_file(file: &str) -> Result<Midi, Box<dyn error::Error>> {
+        Midi::from_bytes(&fs::read(file)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Midi, Box<dyn error::Error>> {
+        let smf = Smf::parse(bytes)?;
+
+        // the tempo map can live in any track so collect it first
+        let mut tempo = Tempo::default();
+        if let Timing::Metrical(ticks_per_beat) = smf.header.timing {
+            let ticks_per_beat = ticks_per_beat.as_int() as f64;
+            for track in &smf.tracks {
+                let mut tick = 0u64;
+                for event in track {
+                    tick += event.delta.as_int() as u64;
+                    match event.kind {
+                        TrackEventKind::Meta(MetaMessage::Tempo(micros)) => {
+                            if micros.as_int() == 0 {
+                                return Err(Box::new(Error::Decode(
+                                    "A tempo of 0 microseconds per beat".to_string(),
+                                )));
+                            }
+                            let bpm = 60_000_000.0 / micros.as_int() as f32;
+                            tempo.add_change(0, (tick as f64 / ticks_per_beat) as f32, bpm);
+                        }
+                        TrackEventKind::Meta(MetaMessage::TimeSignature(top, bottom, _, _)) => {
+                            // the bottom is stored as a power of two
+                            let bottom = 1u32.checked_shl(bottom.into()).ok_or_else(|| {
+                                Error::Decode(format!("A time signature over 2^{}", bottom))
+                            })?;
+                            tempo.signature = TimeSignature::new(top as u32, bottom);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let seconds = |tick: u64| -> f64 {
+            match smf.header.timing {
+                Timing::Metrical(ticks_per_beat) => {
+                    tempo.beats_to_seconds(tick as f64 / ticks_per_beat.as_int() as f64)
+                }
+                Timing::Timecode(fps, subframes) => {
+                    tick as f64 / (fps.as_f32() as f64 * subframes as f64)
+                }
+            }
+        };
+
+        let mut notes = Vec::new();
+        for track in &smf.tracks {
+            // (channel, key) -> (start tick, velocity) of notes being held
+            let mut held: HashMap<(u8, u8), (u64, u8)> = HashMap::new();
+            let mut tick = 0u64;
+            let mut finish = |channel: u8, key: u8, start: u64, velocity: u8, end: u64| {
+                let start_sec = seconds(start);
+                notes.push(MidiNote {
+                    channel,
+                    key,
+                    velocity,
+                    start: start_sec,
+                    length: seconds(end) - start_sec,
+                });
+            };
+            for event in track {
+                tick += event.delta.as_int() as u64;
+                if let TrackEventKind::Midi { channel, message } = event.kind {
+                    let channel = channel.as_int();
+                    let (key, velocity) = match message {
+                        MidiMessage::NoteOn { key, vel } => (key.as_int(), vel.as_int()),
+                        MidiMessage::NoteOff { key, .. } => (key.as_int(), 0),
+                        _ => continue,
+                    };
+                    if let Some((start, held_velocity)) = held.remove(&(channel, key)) {
+                        finish(channel, key, start, held_velocity, tick);
+                    }
+                    // a note on with velocity 0 is a note off
+                    if velocity > 0 {
+                        held.insert((channel, key), (tick, velocity));
+                    }
+                }
+            }
+            // notes still held when the track ends stop there
+            for ((channel, key), (start, velocity)) in held {
+                finish(channel, key, start, velocity, tick);
+            }
+        }
+        notes.sort_by(|a, b| a.start.total_cmp(&b.start).then(a.key.cmp(&b.key)));
+
+        Ok(Midi { notes, tempo })
+    }
+
+    // trigger the whole sample for every note, louder for higher velocities
+    pub fn compose(&self, sample: &dyn Sample) -> Result<Composition, Box<dyn error::Error>> {
+        let mut comp = Composition::new();
+        comp.set_tempo(self.tempo.clone());
+        let mut ids = HashMap::new();
+        for note in &self.notes {
+            let start = (note.start * sample.sample_rate() as f64).round() as usize;
+            match ids.get(&note.velocity) {
+                Some(id) => comp.add_track_id(*id, start)?,
+                None => {
+                    let options = TrackOptions::new(note.velocity as f32 / 127.0, 0.0);
+                    let id = comp.add_track_with(sample, start, options)?;
+                    ids.insert(note.velocity, id);
+                }
+            }
+        }
+        Ok(comp)
+    }
+
+    // play each note on the pad mapped to its key, notes without a pad are
+    // left out
+    pub fn compose_kit(&self, kit: &SampleKit) -> Result<Composition, Box<dyn error::Error>> {
+        let rate = kit
+            .sample_rate()
+            .ok_or_else(|| Error::Invalid("The kit has no pads".to_string()))?;
+        let hits: Vec<Hit> = self
+            .notes
+            .iter()
+            .filter_map(|note| {
+                let pad = kit.key(note.key)?;
+                let start = (note.start * rate as f64).round() as usize;
+                Some(Hit::new(pad, start, note.velocity as f32 / 127.0))
+            })
+            .collect();
+        let mut comp = Composition::new();
+        comp.set_tempo(self.tempo.clone());
+        kit.place(&mut comp, &hits)?;
+        Ok(comp)
+    }
+
+    // build a sound for each note, so oscillators can follow key and length
+    pub fn compose_with<F>(&self, instrument: F) -> Result<Composition, Box<dyn error::Error>>
+    where
+        F: Fn(&MidiNote) -> Box<dyn Sample>,
+    {
+        let mut comp = Composition::new();
+        comp.set_tempo(self.tempo.clone());
+        for note in &self.notes {
+            let sample = instrument(note);
+            let start = (note.start * sample.sample_rate() as f64).round() as usize;
+            let options = TrackOptions::new(note.velocity as f32 / 127.0, 0.0);
+            comp.add_track_with(&*sample, start, options)?;
+        }
+        Ok(comp)
+    }
+}
+
+// equal temperament with A4 (key 69) at 440 Hz
+#[deprecated(note = "use MidiNote::note and Note::to_frequency")]
+pub fn key_frequency(key: u8) -> f32 {
+    Note::from_key(key).to_frequency()
+}
+
+#[deprecated(note = "use MidiNote::steps")]
+pub fn duration_steps(note: &MidiNote, sample_rate: u32) -> usize {
+    note.steps(sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kit::Pad;
+    use crate::sample::WaveForm;
+    use midly::{Format, Header, TrackEvent};
+
+    fn event(delta: u32, kind: TrackEventKind<'static>) -> TrackEvent<'static> {
+        TrackEvent {
+            delta: delta.into(),
+            kind,
+        }
+    }
+
+    fn note(delta: u32, key: u8, vel: u8) -> TrackEvent<'static> {
+        event(
+            delta,
+            TrackEventKind::Midi {
+                channel: 0.into(),
+                message: MidiMessage::NoteOn {
+                    key: key.into(),
+                    vel: vel.into(),
+                },
+            },
+        )
+    }
+
+    fn song(micros_per_beat: u32) -> Result<Vec<u8>, Box<dyn error::Error>> {
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(480.into()),
+        ));
+        smf.tracks.push(vec![
+            event(
+                0,
+                TrackEventKind::Meta(MetaMessage::Tempo(micros_per_beat.into())),
+            ),
+            note(0, 60, 127),
+            note(480, 60, 0),
+            note(0, 64, 64),
+            note(240, 67, 100),
+            note(240, 64, 0),
+            event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+        ]);
+        let mut bytes = Vec::new();
+        smf.write_std(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    #[test]
+    fn read_notes() -> Result<(), Box<dyn error::Error>> {
+        let midi = Midi::from_bytes(&song(500_000)?)?;
+        let keys: Vec<u8> = midi.notes.iter().map(|n| n.key).collect();
+        assert_eq!(keys, vec![60, 64, 67]);
+        assert_eq!(midi.notes[0].length, 0.5);
+        assert_eq!(midi.notes[1].start, 0.5);
+        assert_eq!(midi.notes[1].velocity, 64);
+        // held to the end of the track
+        assert_eq!(midi.notes[2].start, 0.75);
+        assert_eq!(midi.notes[2].length, 0.25);
+
+        let fast = Midi::from_bytes(&song(250_000)?)?;
+        assert_eq!(fast.notes[1].start, 0.25);
+        assert_eq!(fast.tempo.bpm_at(0, 0.0), 240.0);
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn malformed_meta_events() -> Result<(), Box<dyn error::Error>> {
+        let file = |kind: TrackEventKind<'static>| -> Result<Vec<u8>, Box<dyn error::Error>> {
+            let mut smf = Smf::new(Header::new(
+                Format::SingleTrack,
+                Timing::Metrical(480.into()),
+            ));
+            smf.tracks.push(vec![
+                event(0, kind),
+                note(0, 60, 127),
+                note(480, 60, 0),
+                event(0, TrackEventKind::Meta(MetaMessage::EndOfTrack)),
+            ]);
+            let mut bytes = Vec::new();
+            smf.write_std(&mut bytes)?;
+            Ok(bytes)
+        };
+        let signature = |bottom| TrackEventKind::Meta(MetaMessage::TimeSignature(4, bottom, 24, 8));
+        assert_eq!(
+            Midi::from_bytes(&file(signature(3))?)?.tempo.signature,
+            TimeSignature::new(4, 8)
+        );
+        assert!(Midi::from_bytes(&file(signature(0x40))?).is_err());
+        assert!(
+            Midi::from_bytes(&file(TrackEventKind::Meta(MetaMessage::Tempo(0.into())))?).is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn compose_from_midi() -> Result<(), Box<dyn error::Error>> {
+        use crate::sample::SineWave;
+
+        let midi = Midi::from_bytes(&song(500_000)?)?;
+        let click = WaveForm::from(&[1.0; 100]);
+        let comp = midi.compose(&click)?;
+        let wave = comp.waveform(0).unwrap();
+        assert_eq!(wave[0], 1.0);
+        assert!((wave[22050] - 64.0 / 127.0).abs() < 1e-6);
+        assert_eq!(comp.length(), 33075 + 100);
+
+        let comp = midi.compose_with(|note| {
+            Box::new(SineWave::new(
+                key_frequency(note.key),
+                duration_steps(note, 44100),
+                0.3,
+            ))
+        })?;
+        assert_eq!(comp.length(), 44100);
+        comp.export("./test_files/output/midi.wav")?;
+        assert_eq!(key_frequency(69), 440.0);
+        Ok(())
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn note_helpers() -> Result<(), Box<dyn error::Error>> {
+        let midi = Midi::from_bytes(&song(500_000)?)?;
+        for note in &midi.notes {
+            assert_eq!(note.note().to_frequency(), key_frequency(note.key));
+            assert_eq!(note.steps(44100), duration_steps(note, 44100));
+        }
+        assert_eq!(midi.notes[2].note(), Note::G4);
+        Ok(())
+    }
+
+    #[test]
+    fn compose_from_kit() -> Result<(), Box<dyn error::Error>> {
+        let midi = Midi::from_bytes(&song(500_000)?)?;
+        let mut kit = SampleKit::new();
+        assert!(midi.compose_kit(&kit).is_err());
+        kit.add_pad(Pad::new("low", &WaveForm::from(&[1.0; 100])))?;
+        kit.add_pad(Pad::new("high", &WaveForm::from(&[0.5; 100])).with_gain(0.5))?;
+        kit.map_key(Note::C4, "low")?;
+        kit.map_key(Note::G4, "high")?;
+        // E4 has no pad so only two of the three notes play
+        let wave = midi.compose_kit(&kit)?.waveform(0).unwrap();
+        assert_eq!(wave[0], 1.0);
+        assert_eq!(wave[22050], 0.0);
+        assert!((wave[33075] - 0.25 * 100.0 / 127.0).abs() < 1e-6);
+        Ok(())
+    }
+}
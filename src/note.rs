@@ -0,0 +1,217 @@
+use crate::Error;
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+const NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+// a key on the midi scale, so C4 (middle C) is 60 and A4 is 69
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Note {
+    pub key: u8,
+}
+
+// constants for every note from C0 to B8, sharps are spelled with an S
+// so CS4 is C#4
+macro_rules! octave {
+    ($octave:expr, $c:ident, $cs:ident, $d:ident, $ds:ident, $e:ident, $f:ident,
+     $fs:ident, $g:ident, $gs:ident, $a:ident, $as_:ident, $b:ident) => {
+        pub const $c: Note = Note::octave_key($octave, 0);
+        pub const $cs: Note = Note::octave_key($octave, 1);
+        pub const $d: Note = Note::octave_key($octave, 2);
+        pub const $ds: Note = Note::octave_key($octave, 3);
+        pub const $e: Note = Note::octave_key($octave, 4);
+        pub const $f: Note = Note::octave_key($octave, 5);
+        pub const $fs: Note = Note::octave_key($octave, 6);
+        pub const $g: Note = Note::octave_key($octave, 7);
+        pub const $gs: Note = Note::octave_key($octave, 8);
+        pub const $a: Note = Note::octave_key($octave, 9);
+        pub const $as_: Note = Note::octave_key($octave, 10);
+        pub const $b: Note = Note::octave_key($octave, 11);
+    };
+}
+
+impl Note {
+    octave!(0, C0, CS0, D0, DS0, E0, F0, FS0, G0, GS0, A0, AS0, B0);
+    octave!(1, C1, CS1, D1, DS1, E1, F1, FS1, G1, GS1, A1, AS1, B1);
+    octave!(2, C2, CS2, D2, DS2, E2, F2, FS2, G2, GS2, A2, AS2, B2);
+    octave!(3, C3, CS3, D3, DS3, E3, F3, FS3, G3, GS3, A3, AS3, B3);
+    octave!(4, C4, CS4, D4, DS4, E4, F4, FS4, G4, GS4, A4, AS4, B4);
+    octave!(5, C5, CS5, D5, DS5, E5, F5, FS5, G5, GS5, A5, AS5, B5);
+    octave!(6, C6, CS6, D6, DS6, E6, F6, FS6, G6, GS6, A6, AS6, B6);
+    octave!(7, C7, CS7, D7, DS7, E7, F7, FS7, G7, GS7, A7, AS7, B7);
+    octave!(8, C8, CS8, D8, DS8, E8, F8, FS8, G8, GS8, A8, AS8, B8);
+
+    pub fn from_key(key: u8) -> Self {
+        Note { key }
+    }
+
+    const fn octave_key(octave: u8, semitone: u8) -> Note {
+        Note {
+            key: (octave + 1) * 12 + semitone,
+        }
+    }
+
+    // equal temperament with A4 at 440 Hz
+    pub fn to_frequency(&self) -> f32 {
+        440.0 * 2f32.powf((self.key as f32 - 69.0) / 12.0)
+    }
+
+    // the closest note, None outside the midi range
+    pub fn from_frequency(frequency: f32) -> Option<Note> {
+        let key = (69.0 + 12.0 * (frequency / 440.0).log2()).round();
+        if (0.0..=127.0).contains(&key) {
+            Some(Note { key: key as u8 })
+        } else {
+            None
+        }
+    }
+
+    pub fn transpose(&self, semitones: i32) -> Option<Note> {
+        let key = self.key as i32 + semitones;
+        if (0..=127).contains(&key) {
+            Some(Note { key: key as u8 })
+        } else {
+            None
+        }
+    }
+
+    pub fn octave(&self) -> i32 {
+        self.key as i32 / 12 - 1
+    }
+}
+
+// the notes of a scale as semitones above its root
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Scale {
+    Chromatic,
+    Major,
+    Minor,
+    Custom(Vec<u8>),
+}
+
+impl Scale {
+    pub fn degrees(&self) -> &[u8] {
+        match self {
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::Custom(degrees) => degrees,
+        }
+    }
+
+    pub fn contains(&self, root: Note, note: Note) -> bool {
+        let semitone = (note.key as i32 - root.key as i32).rem_euclid(12) as u8;
+        self.degrees().iter().any(|degree| degree % 12 == semitone)
+    }
+
+    // the closest key in the scale to a key with a fraction, so 69.3 is a
+    // little sharp of A4, the same key back if the scale is empty
+    pub fn nearest(&self, root: Note, key: f32) -> f32 {
+        let center = key.round() as i32;
+        (center - 6..=center + 6)
+            .filter(|k| (0..=127).contains(k) && self.contains(root, Note::from_key(*k as u8)))
+            .map(|k| k as f32)
+            .min_by(|a, b| (a - key).abs().total_cmp(&(b - key).abs()))
+            .unwrap_or(key)
+    }
+}
+
+impl FromStr for Note {
+    type Err = Error;
+
+    // a letter, any number of # or b, then the octave like C#3, Eb4 or A-1
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::Invalid(format!("\"{}\" is not a note name", name));
+        let mut chars = name.trim().chars();
+        let mut semitone = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('C') => 0,
+            Some('D') => 2,
+            Some('E') => 4,
+            Some('F') => 5,
+            Some('G') => 7,
+            Some('A') => 9,
+            Some('B') => 11,
+            _ => return Err(invalid()),
+        };
+        let rest = chars.as_str();
+        let octave = rest.trim_start_matches(['#', 'b']);
+        for accidental in rest[..rest.len() - octave.len()].chars() {
+            semitone += if accidental == '#' { 1 } else { -1 };
+        }
+
+        let octave: i32 = octave.parse().map_err(|_| invalid())?;
+        let key = octave
+            .checked_add(1)
+            .and_then(|octave| octave.checked_mul(12))
+            .and_then(|key| key.checked_add(semitone))
+            .filter(|key| (0..=127).contains(key))
+            .ok_or_else(|| Error::Invalid(format!("{} is outside the midi range", name)))?;
+        Ok(Note { key: key as u8 })
+    }
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", NAMES[self.key as usize % 12], self.octave())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error;
+
+    #[test]
+    fn parse_names() -> Result<(), Box<dyn error::Error>> {
+        assert_eq!("A4".parse::<Note>()?, Note::A4);
+        assert_eq!("C#3".parse::<Note>()?, Note::CS3);
+        assert_eq!("Db3".parse::<Note>()?, Note::CS3);
+        assert_eq!("c4".parse::<Note>()?.key, 60);
+        assert_eq!("C-1".parse::<Note>()?.key, 0);
+        assert_eq!("Cb4".parse::<Note>()?, Note::B3);
+        assert!("H2".parse::<Note>().is_err());
+        assert!("C".parse::<Note>().is_err());
+        assert!("G9".parse::<Note>().is_ok());
+        assert!("G#9".parse::<Note>().is_err());
+        assert!(matches!(
+            "C2147483647".parse::<Note>(),
+            Err(Error::Invalid(_))
+        ));
+        assert!("C-2147483648".parse::<Note>().is_err());
+        assert_eq!(Note::FS2.to_string(), "F#2");
+        assert_eq!(Note::from_key(0).to_string(), "C-1");
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn frequencies() -> Result<(), Box<dyn error::Error>> {
+        use crate::sample::{Sample, SineWave};
+
+        assert_eq!(Note::A4.to_frequency(), 440.0);
+        assert_eq!(Note::A5.to_frequency(), 880.0);
+        assert!((Note::C4.to_frequency() - 261.626).abs() < 0.01);
+        assert_eq!(Note::A4.transpose(12), Some(Note::A5));
+        assert_eq!(Note::C0.transpose(-13), None);
+        assert_eq!(Note::from_frequency(440.0), Some(Note::A4));
+        assert_eq!(Note::from_frequency(265.0), Some(Note::C4));
+        assert_eq!(Note::from_frequency(0.0), None);
+
+        assert!(Scale::Major.contains(Note::D4, Note::FS2));
+        assert!(!Scale::Minor.contains(Note::A3, Note::CS4));
+        assert_eq!(Scale::Chromatic.nearest(Note::C4, 69.3), 69.0);
+        assert_eq!(Scale::Major.nearest(Note::C4, 70.2), 71.0);
+        assert_eq!(Scale::Custom(Vec::new()).nearest(Note::C4, 70.2), 70.2);
+
+        let melody = [Note::C4, Note::E4, Note::G4];
+        let wave = SineWave::note(melody[1], 0.5, 0.5);
+        assert_eq!(wave.length(), 22050);
+        wave.export("./test_files/output/note_e4.wav")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,637 @@
+use crate::note::Note;
+use crate::sample::Sample;
+use crate::Error;
+
+use std::f32::consts::PI;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+const RATE: u32 = 44100;
+const PINK_OCTAVES: u32 = 16;
+
+// the Sample impl of a mono sound worked out one step at a time, the type
+// needs sample_rate and length fields and a value_at(step) method
+macro_rules! generated_sample {
+    (impl<$($generic:ident: $bound:path),*> $sample:ty) => {
+        impl<$($generic: $bound),*> Sample for $sample {
+            fn sample_rate(&self) -> u32 {
+                self.sample_rate
+            }
+
+            fn length(&self) -> usize {
+                self.length
+            }
+
+            fn channels(&self) -> u16 {
+                1
+            }
+
+            fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+                let mut waveform = vec![0.0; self.length];
+                self.fill(channel, 0, &mut waveform)?;
+                Some(waveform)
+            }
+
+            fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+                if channel > 0 {
+                    return None;
+                }
+
+                let size = buffer.len().min(self.length.saturating_sub(offset));
+                for (index, value) in buffer[..size].iter_mut().enumerate() {
+                    *value = self.value_at(offset + index);
+                }
+                Some(size)
+            }
+
+            fn box_clone(&self) -> Box<dyn Sample> {
+                Box::new(self.clone())
+            }
+        }
+    };
+    ($($sample:ty),*) => {
+        $(
+            generated_sample!(impl<> $sample);
+        )*
+    };
+}
+pub(crate) use generated_sample;
+
+// the shape of a single cycle, phase and duty are both in [0, 1)
+pub trait Waveshape: Clone + Send + Sync + 'static {
+    fn value(phase: f32, duty: f32) -> f32;
+}
+
+#[derive(Debug, Clone)]
+pub struct Sine;
+
+impl Waveshape for Sine {
+    fn value(phase: f32, _duty: f32) -> f32 {
+        (2.0 * PI * phase).sin()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Square;
+
+impl Waveshape for Square {
+    fn value(phase: f32, duty: f32) -> f32 {
+        if phase < duty {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Saw;
+
+impl Waveshape for Saw {
+    fn value(phase: f32, _duty: f32) -> f32 {
+        2.0 * phase - 1.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Triangle;
+
+impl Waveshape for Triangle {
+    fn value(phase: f32, duty: f32) -> f32 {
+        // duty is the part of the cycle spent rising
+        let duty = duty.clamp(1e-6, 1.0 - 1e-6);
+        if phase < duty {
+            2.0 * phase / duty - 1.0
+        } else {
+            1.0 - 2.0 * (phase - duty) / (1.0 - duty)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Oscillator<S: Waveshape> {
+    pub frequency: f32,
+    pub amplitude: f32,
+    pub phase: f32,
+    pub duty: f32,
+    sample_rate: u32,
+    length: usize,
+    shape: PhantomData<S>,
+}
+
+pub type SineOscillator = Oscillator<Sine>;
+pub type SquareWave = Oscillator<Square>;
+pub type SawWave = Oscillator<Saw>;
+pub type TriangleWave = Oscillator<Triangle>;
+
+impl<S: Waveshape> Oscillator<S> {
+    pub fn new(frequency: f32, length: usize, amplitude: f32) -> Self {
+        Oscillator {
+            frequency,
+            amplitude,
+            phase: 0.0,
+            duty: 0.5,
+            sample_rate: RATE,
+            length,
+            shape: PhantomData,
+        }
+    }
+
+    pub fn note(note: Note, duration: f32, amplitude: f32) -> Self {
+        let length = (duration * RATE as f32) as usize;
+        Oscillator::new(note.to_frequency(), length, amplitude)
+    }
+
+    pub fn with_phase(mut self, phase: f32) -> Self {
+        self.phase = phase;
+        self
+    }
+
+    pub fn with_duty(mut self, duty: f32) -> Self {
+        self.duty = duty;
+        self
+    }
+
+    fn value_at(&self, step: usize) -> f32 {
+        let cycles =
+            self.phase as f64 + self.frequency as f64 * step as f64 / self.sample_rate as f64;
+        self.amplitude * S::value(cycles.rem_euclid(1.0) as f32, self.duty)
+    }
+}
+
+generated_sample!(impl<S: Waveshape> Oscillator<S>);
+
+// single cycle waveforms an oscillator can sweep through, clones share
+// the tables
+#[derive(Debug, Clone)]
+pub struct Wavetable {
+    tables: Arc<[Vec<f32>]>,
+}
+
+impl Wavetable {
+    pub fn new(tables: Vec<Vec<f32>>) -> Result<Self, Error> {
+        if tables.is_empty() || tables.iter().any(|table| table.is_empty()) {
+            return Err(Error::Invalid(
+                "A wavetable needs at least one non empty table".to_string(),
+            ));
+        }
+        Ok(Wavetable {
+            tables: tables.into(),
+        })
+    }
+
+    // count tables of size steps each, cut one after another from the
+    // first channel of the sample starting at start
+    pub fn from_sample(
+        sample: &dyn Sample,
+        start: usize,
+        size: usize,
+        count: usize,
+    ) -> Result<Self, Error> {
+        let end = start + size * count;
+        if end > sample.length() {
+            return Err(Error::OutOfRange {
+                what: "step",
+                index: end,
+                len: sample.length(),
+            });
+        }
+        let wave = sample.waveform_ref(0).ok_or(Error::MissingChannel(0))?;
+        Wavetable::new(
+            wave[start..end]
+                .chunks_exact(size.max(1))
+                .map(|table| table.to_vec())
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    // position goes from the first table at 0 to the last at 1, values
+    // between tables and between the steps of a table are interpolated
+    pub fn value(&self, position: f32, phase: f32) -> f32 {
+        let position = position.clamp(0.0, 1.0) * (self.tables.len() - 1) as f32;
+        let first = position.floor() as usize;
+        let fraction = position - first as f32;
+        let value = read_table(&self.tables[first], phase);
+        if fraction == 0.0 {
+            return value;
+        }
+        value + fraction * (read_table(&self.tables[first + 1], phase) - value)
+    }
+}
+
+fn read_table(table: &[f32], phase: f32) -> f32 {
+    let index = phase * table.len() as f32;
+    let first = (index.floor() as usize).min(table.len() - 1);
+    let fraction = index - first as f32;
+    let next = table[(first + 1) % table.len()];
+    table[first] + fraction * (next - table[first])
+}
+
+#[derive(Debug, Clone)]
+pub struct WavetableOsc {
+    pub frequency: f32,
+    pub amplitude: f32,
+    pub phase: f32,
+    // the table position at the start and the end of the sound, see
+    // Wavetable::value
+    pub start: f32,
+    pub end: f32,
+    table: Wavetable,
+    sample_rate: u32,
+    length: usize,
+}
+
+impl WavetableOsc {
+    pub fn new(table: &Wavetable, frequency: f32, length: usize, amplitude: f32) -> Self {
+        WavetableOsc {
+            frequency,
+            amplitude,
+            phase: 0.0,
+            start: 0.0,
+            end: 0.0,
+            table: table.clone(),
+            sample_rate: RATE,
+            length,
+        }
+    }
+
+    pub fn note(table: &Wavetable, note: Note, duration: f32, amplitude: f32) -> Self {
+        let length = (duration * RATE as f32) as usize;
+        WavetableOsc::new(table, note.to_frequency(), length, amplitude)
+    }
+
+    pub fn with_phase(mut self, phase: f32) -> Self {
+        self.phase = phase;
+        self
+    }
+
+    // sweep through the tables from start to end over the length
+    pub fn with_position(mut self, start: f32, end: f32) -> Self {
+        self.start = start;
+        self.end = end;
+        self
+    }
+
+    pub fn table(&self) -> &Wavetable {
+        &self.table
+    }
+
+    fn value_at(&self, step: usize) -> f32 {
+        let cycles =
+            self.phase as f64 + self.frequency as f64 * step as f64 / self.sample_rate as f64;
+        let progress = step as f32 / self.length.max(1) as f32;
+        let position = self.start + (self.end - self.start) * progress;
+        self.amplitude * self.table.value(position, cycles.rem_euclid(1.0) as f32)
+    }
+}
+
+generated_sample!(WavetableOsc);
+
+// counter based random numbers so any block of noise can be rendered
+// without generating everything before it
+pub(crate) fn random(seed: u64, step: u64) -> f32 {
+    let mut z = seed
+        .wrapping_add(step.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+        .wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+#[derive(Debug, Clone)]
+pub struct WhiteNoise {
+    pub amplitude: f32,
+    pub seed: u64,
+    sample_rate: u32,
+    length: usize,
+}
+
+impl WhiteNoise {
+    pub fn new(length: usize, amplitude: f32) -> Self {
+        WhiteNoise {
+            amplitude,
+            seed: 0,
+            sample_rate: RATE,
+            length,
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn value_at(&self, step: usize) -> f32 {
+        self.amplitude * random(self.seed, step as u64)
+    }
+}
+
+generated_sample!(WhiteNoise);
+
+// Voss-McCartney pink noise, octave k holds a new random value every 2^k steps
+#[derive(Debug, Clone)]
+pub struct PinkNoise {
+    pub amplitude: f32,
+    pub seed: u64,
+    sample_rate: u32,
+    length: usize,
+}
+
+impl PinkNoise {
+    pub fn new(length: usize, amplitude: f32) -> Self {
+        PinkNoise {
+            amplitude,
+            seed: 0,
+            sample_rate: RATE,
+            length,
+        }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn value_at(&self, step: usize) -> f32 {
+        let step = step as u64;
+        let sum: f32 = (0..PINK_OCTAVES)
+            .map(|octave| random(self.seed.wrapping_add(octave as u64), step >> octave))
+            .sum();
+        self.amplitude * sum / (PINK_OCTAVES as f32).sqrt() / 2.0
+    }
+}
+
+generated_sample!(PinkNoise);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sweep {
+    // the same number of Hz every second
+    Linear,
+    // the same number of octaves every second, what impulse response
+    // measurements use
+    Logarithmic,
+}
+
+// a sine sweeping from start to end Hz over its length
+#[derive(Debug, Clone)]
+pub struct Chirp {
+    pub start: f32,
+    pub end: f32,
+    pub amplitude: f32,
+    pub sweep: Sweep,
+    sample_rate: u32,
+    length: usize,
+}
+
+impl Chirp {
+    pub fn new(start: f32, end: f32, length: usize, amplitude: f32) -> Self {
+        Chirp {
+            start,
+            end,
+            amplitude,
+            sweep: Sweep::Linear,
+            sample_rate: RATE,
+            length,
+        }
+    }
+
+    pub fn with_sweep(mut self, sweep: Sweep) -> Self {
+        self.sweep = sweep;
+        self
+    }
+
+    // cycles done by this step, the integral of the frequency
+    fn cycles_at(&self, step: usize) -> f64 {
+        let t = step as f64 / self.sample_rate as f64;
+        let duration = self.length.max(1) as f64 / self.sample_rate as f64;
+        let (start, end) = (self.start as f64, self.end as f64);
+        let ratio = end / start;
+        match self.sweep {
+            Sweep::Logarithmic if start > 0.0 && end > 0.0 && ratio != 1.0 => {
+                start * duration / ratio.ln() * (ratio.powf(t / duration) - 1.0)
+            }
+            _ => start * t + (end - start) * t * t / (2.0 * duration),
+        }
+    }
+
+    fn value_at(&self, step: usize) -> f32 {
+        let phase = self.cycles_at(step).rem_euclid(1.0) as f32;
+        self.amplitude * (2.0 * PI * phase).sin()
+    }
+}
+
+generated_sample!(Chirp);
+
+// any function of the time in seconds, for chirps and modulation
+// experiments that don't need a struct of their own. clones share the
+// closure
+//
+//     let chirp = FnSample::new(44100, 44100, |t| (2.0 * PI * (100.0 + 400.0 * t) * t).sin());
+#[derive(Clone)]
+pub struct FnSample {
+    function: Arc<dyn Fn(f32) -> f32 + Send + Sync>,
+    sample_rate: u32,
+    length: usize,
+}
+
+impl FnSample {
+    pub fn new<F>(sample_rate: u32, length: usize, function: F) -> Self
+    where
+        F: Fn(f32) -> f32 + Send + Sync + 'static,
+    {
+        FnSample {
+            function: Arc::new(function),
+            sample_rate,
+            length,
+        }
+    }
+
+    fn value_at(&self, step: usize) -> f32 {
+        (self.function)((step as f64 / self.sample_rate as f64) as f32)
+    }
+}
+
+generated_sample!(FnSample);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "wav")]
+    use std::error;
+
+    #[test]
+    fn shapes() {
+        let square = SquareWave::new(1.0, 4, 1.0);
+        let square = Oscillator {
+            sample_rate: 4,
+            ..square
+        };
+        assert_eq!(square.waveform(0).unwrap(), vec![1.0, 1.0, -1.0, -1.0]);
+        let narrow = square.clone().with_duty(0.25);
+        assert_eq!(narrow.waveform(0).unwrap(), vec![1.0, -1.0, -1.0, -1.0]);
+
+        let saw = SawWave::new(1.0, 4, 1.0);
+        let saw = Oscillator {
+            sample_rate: 4,
+            ..saw
+        };
+        assert_eq!(saw.waveform(0).unwrap(), vec![-1.0, -0.5, 0.0, 0.5]);
+
+        let triangle = TriangleWave::new(1.0, 4, 1.0).with_phase(0.25);
+        let triangle = Oscillator {
+            sample_rate: 4,
+            ..triangle
+        };
+        let wave = triangle.waveform(0).unwrap();
+        assert!((wave[0] - 0.0).abs() < 1e-5);
+        assert!((wave[1] - 1.0).abs() < 1e-5);
+        assert!((wave[2] - 0.0).abs() < 1e-5);
+        assert!((wave[3] + 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn noise_is_repeatable() {
+        let white = WhiteNoise::new(1000, 0.5);
+        let wave = white.waveform(0).unwrap();
+        assert!(wave.iter().all(|s| s.abs() <= 0.5));
+        assert!(wave.iter().any(|s| s.abs() > 0.25));
+        let mut block = [0.0; 10];
+        white.fill(0, 500, &mut block);
+        assert_eq!(block, wave[500..510]);
+        assert_ne!(white.clone().with_seed(1).waveform(0).unwrap(), wave);
+
+        let pink = PinkNoise::new(1000, 0.5);
+        let wave = pink.waveform(0).unwrap();
+        pink.fill(0, 990, &mut block);
+        assert_eq!(block, wave[990..]);
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn oscillators_to_wav() -> Result<(), Box<dyn error::Error>> {
+        SquareWave::new(220.0, RATE as usize, 0.3)
+            .with_duty(0.25)
+            .export("./test_files/output/square.wav")?;
+        SawWave::new(220.0, RATE as usize, 0.3).export("./test_files/output/saw.wav")?;
+        TriangleWave::new(220.0, RATE as usize, 0.3).export("./test_files/output/triangle.wav")?;
+        WhiteNoise::new(RATE as usize, 0.3).export("./test_files/output/white_noise.wav")?;
+        PinkNoise::new(RATE as usize, 0.3).export("./test_files/output/pink_noise.wav")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn chirps() -> Result<(), Box<dyn error::Error>> {
+        // rising zero crossings in a tenth of a second around step
+        let crossings = |wave: &[f32], step: usize| {
+            wave[step..step + 4410]
+                .windows(2)
+                .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+                .count()
+        };
+
+        let steady = Chirp::new(441.0, 441.0, 1000, 0.5).waveform(0).unwrap();
+        let sine = SineOscillator::new(441.0, 1000, 0.5).waveform(0).unwrap();
+        for (a, b) in steady.iter().zip(sine.iter()) {
+            assert!((a - b).abs() < 1e-3);
+        }
+
+        // halfway through a linear sweep is the average frequency and through
+        // a logarithmic one the geometric mean
+        let length = 2 * RATE as usize;
+        let linear = Chirp::new(100.0, 1700.0, length, 0.5);
+        let wave = linear.waveform(0).unwrap();
+        assert!((crossings(&wave, 0) as i32 - 14).abs() <= 1);
+        assert!((crossings(&wave, RATE as usize - 2205) as i32 - 90).abs() <= 1);
+        let log = linear.clone().with_sweep(Sweep::Logarithmic);
+        let wave = log.waveform(0).unwrap();
+        assert!((crossings(&wave, RATE as usize - 2205) as i32 - 41).abs() <= 1);
+
+        let mut block = [0.0; 10];
+        log.fill(0, 100, &mut block);
+        assert_eq!(block, wave[100..110]);
+        log.export("./test_files/output/log_chirp.wav")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn fn_sample() -> Result<(), Box<dyn error::Error>> {
+        let ramp = FnSample::new(4, 4, |t| t);
+        assert_eq!(ramp.waveform(0).unwrap(), vec![0.0, 0.25, 0.5, 0.75]);
+        assert_eq!(ramp.waveform(1), None);
+
+        let sine = FnSample::new(RATE, 1000, |t| 0.5 * (2.0 * PI * 441.0 * t).sin());
+        let expected = SineOscillator::new(441.0, 1000, 0.5).waveform(0).unwrap();
+        for (a, b) in sine.waveform(0).unwrap().iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-3);
+        }
+        let mut block = [0.0; 10];
+        assert_eq!(sine.box_clone().fill(0, 995, &mut block), Some(5));
+        assert_eq!(block[..5], sine.waveform(0).unwrap()[995..]);
+
+        let am = FnSample::new(RATE, RATE as usize, |t| {
+            (2.0 * PI * 440.0 * t).sin() * (0.5 + 0.5 * (2.0 * PI * 4.0 * t).sin()) * 0.5
+        });
+        am.export("./test_files/output/fn_sample_am.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn oscillator_note() {
+        let saw = SawWave::note(Note::A3, 1.0, 0.5);
+        assert_eq!(saw.frequency, 220.0);
+        assert_eq!(saw.length(), 44100);
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn wavetables() -> Result<(), Box<dyn error::Error>> {
+        use crate::sample::WaveForm;
+
+        // one 100 step cycle of a sine is 441 Hz at 44100
+        let sine = SineOscillator::new(441.0, 1000, 1.0);
+        let table = Wavetable::from_sample(&sine, 0, 100, 1)?;
+        let wave = WavetableOsc::new(&table, 441.0, 1000, 1.0)
+            .waveform(0)
+            .unwrap();
+        for (a, b) in wave.iter().zip(sine.waveform(0).unwrap().iter()) {
+            assert!((a - b).abs() < 1e-5);
+        }
+        // between the steps of the table
+        let half = WavetableOsc::new(&table, 220.5, 10, 1.0);
+        let wave = half.waveform(0).unwrap();
+        let expected = (sine.waveform(0).unwrap()[0] + sine.waveform(0).unwrap()[1]) / 2.0;
+        assert!((wave[1] - expected).abs() < 1e-5);
+        assert!(Wavetable::from_sample(&sine, 950, 100, 1).is_err());
+
+        // sweeping from a silent table to a constant one
+        let morph = Wavetable::new(vec![vec![0.0; 4], vec![1.0; 4]])?;
+        let wave = WavetableOsc::new(&morph, 100.0, 4, 1.0)
+            .with_position(0.0, 1.0)
+            .waveform(0)
+            .unwrap();
+        assert_eq!(wave, vec![0.0, 0.25, 0.5, 0.75]);
+        assert!(Wavetable::new(Vec::new()).is_err());
+
+        let saw = SawWave::new(441.0, 400, 1.0);
+        let square = SquareWave::new(441.0, 400, 1.0);
+        let mut cycles = saw.waveform(0).unwrap()[..100].to_vec();
+        cycles.extend_from_slice(&square.waveform(0).unwrap()[..100]);
+        let table = Wavetable::from_sample(&WaveForm::from(&cycles), 0, 100, 2)?;
+        assert_eq!(table.len(), 2);
+        WavetableOsc::note(&table, Note::A3, 1.0, 0.3)
+            .with_position(0.0, 1.0)
+            .export("./test_files/output/wavetable.wav")?;
+        Ok(())
+    }
+}
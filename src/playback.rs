@@ -0,0 +1,158 @@
+use crate::sample::Sample;
+use crate::Error;
+
+use std::error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use cpal;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+const BLOCK_SIZE: usize = 4096;
+
+pub struct Player {
+    stream: cpal::Stream,
+    data: Arc<Vec<f32>>,
+    position: Arc<AtomicUsize>,
+}
+
+impl Player {
+    pub fn new(sample: &dyn Sample) -> Result<Player, Box<dyn error::Error>> {
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .ok_or_else(|| Error::Other("No output device available".to_string()))?;
+        let config = cpal::StreamConfig {
+            channels: device.default_output_config()?.channels(),
+            sample_rate: cpal::SampleRate(sample.sample_rate()),
+            buffer_size: cpal::BufferSize::Default,
+        };
+
+        // the callback runs on the audio thread so hand it a rendered copy
+        let data = Arc::new(interleave(sample, config.channels)?);
+        let position = Arc::new(AtomicUsize::new(0));
+        let stream = {
+            let data = data.clone();
+            let position = position.clone();
+            device.build_output_stream(
+                &config,
+                move |out: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    let start = position.load(Ordering::SeqCst).min(data.len());
+                    let end = (start + out.len()).min(data.len());
+                    out[..end - start].copy_from_slice(&data[start..end]);
+                    out[end - start..].iter_mut().for_each(|s| *s = 0.0);
+                    position.store(end, Ordering::SeqCst);
+                },
+                |err| eprintln!("Playback error: {}", err),
+                None,
+            )?
+        };
+        // some backends start streams as soon as they are built
+        stream.pause()?;
+
+        Ok(Player {
+            stream,
+            data,
+            position,
+        })
+    }
+
+    pub fn play(&self) -> Result<(), Box<dyn error::Error>> {
+        self.stream.play()?;
+        Ok(())
+    }
+
+    pub fn pause(&self) -> Result<(), Box<dyn error::Error>> {
+        self.stream.pause()?;
+        Ok(())
+    }
+
+    pub fn stop(&self) -> Result<(), Box<dyn error::Error>> {
+        self.stream.pause()?;
+        self.position.store(0, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position.load(Ordering::SeqCst) >= self.data.len()
+    }
+
+    pub fn play_to_end(&self) -> Result<(), Box<dyn error::Error>> {
+        self.play()?;
+        while !self.is_finished() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        self.stop()
+    }
+}
+
+pub fn play(sample: &dyn Sample) -> Result<Player, Box<dyn error::Error>> {
+    let player = Player::new(sample)?;
+    player.play()?;
+    Ok(player)
+}
+
+// render the sample into interleaved frames for a device with the given
+// number of channels, mono is sent to every channel and extra channels
+// on the device are left silent
+fn interleave(sample: &dyn Sample, channels: u16) -> Result<Vec<f32>, Box<dyn error::Error>> {
+    if sample.channels() == 0 {
+        return Err(Box::new(Error::Invalid(
+            "Sample has no channels to play".to_string(),
+        )));
+    }
+
+    let channels = channels as usize;
+    let mut data = vec![0.0; sample.length() * channels];
+    let mut block = vec![0.0; BLOCK_SIZE];
+    for channel in 0..channels {
+        let source = if sample.channels() == 1 {
+            0
+        } else if channel < sample.channels() as usize {
+            channel as u16
+        } else {
+            continue;
+        };
+
+        let mut offset = 0;
+        while offset < sample.length() {
+            let filled = sample
+                .fill(source, offset, &mut block)
+                .ok_or(Error::MissingChannel(source))?;
+            if filled == 0 {
+                break;
+            }
+            for (index, value) in block[..filled].iter().enumerate() {
+                data[(offset + index) * channels + channel] = *value;
+            }
+            offset += filled;
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::{MultiChannel, WaveForm};
+
+    #[test]
+    fn interleave_mono_to_stereo() -> Result<(), Box<dyn error::Error>> {
+        let wave = WaveForm::from(&[0.1, 0.2, 0.3]);
+        let data = interleave(&wave, 2)?;
+        assert_eq!(data, vec![0.1, 0.1, 0.2, 0.2, 0.3, 0.3]);
+        Ok(())
+    }
+
+    #[test]
+    fn interleave_stereo_to_quad() -> Result<(), Box<dyn error::Error>> {
+        let left = WaveForm::from(&[0.1, 0.2]);
+        let right = WaveForm::from(&[0.3, 0.4]);
+        let stereo = MultiChannel::new_dual(&left, &right)?;
+        let data = interleave(&stereo, 4)?;
+        assert_eq!(data, vec![0.1, 0.3, 0.0, 0.0, 0.2, 0.4, 0.0, 0.0]);
+        Ok(())
+    }
+}
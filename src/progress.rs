@@ -0,0 +1,108 @@
+#[cfg(any(feature = "wav", feature = "mp3", feature = "ogg"))]
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// told how far a long import, render or export has got as done out of total,
+// counted in bytes for imports and steps otherwise. any Fn(u64, u64) works,
+// so an indicatif ProgressBar hooks up through a closure like
+//
+//     let bar = ProgressBar::new(0);
+//     let song = MultiChannel::from_file_with_progress("song.mp3", &|done, total| {
+//         bar.set_length(total);
+//         bar.set_position(done);
+//     })?;
+pub trait Progress {
+    fn update(&self, done: u64, total: u64);
+}
+
+impl<F: Fn(u64, u64)> Progress for F {
+    fn update(&self, done: u64, total: u64) {
+        self(done, total)
+    }
+}
+
+// shared between the caller and a long render, export or effect chain, which
+// stops with Error::Cancelled the next time it checks after cancel is called.
+// clones share the same flag so one can be handed to another thread
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+// for the paths that take a Progress but weren't given one
+pub(crate) fn ignore(_: u64, _: u64) {}
+
+// passes reads and seeks through and reports how far into the file they are
+#[cfg(any(feature = "wav", feature = "mp3", feature = "ogg"))]
+pub(crate) struct ProgressReader<'a, R> {
+    inner: R,
+    position: u64,
+    total: u64,
+    progress: &'a dyn Progress,
+}
+
+#[cfg(any(feature = "wav", feature = "mp3", feature = "ogg"))]
+impl<'a, R> ProgressReader<'a, R> {
+    pub(crate) fn new(inner: R, total: u64, progress: &'a dyn Progress) -> Self {
+        progress.update(0, total);
+        ProgressReader {
+            inner,
+            position: 0,
+            total,
+            progress,
+        }
+    }
+}
+
+#[cfg(any(feature = "wav", feature = "mp3", feature = "ogg"))]
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        self.progress
+            .update(self.position.min(self.total), self.total);
+        Ok(read)
+    }
+}
+
+#[cfg(any(feature = "wav", feature = "mp3", feature = "ogg"))]
+impl<R: Seek> Seek for ProgressReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(all(test, any(feature = "wav", feature = "mp3", feature = "ogg")))]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::Cursor;
+
+    #[test]
+    fn reader() -> io::Result<()> {
+        let updates = RefCell::new(Vec::new());
+        let record = |done, total| updates.borrow_mut().push((done, total));
+        let mut reader = ProgressReader::new(Cursor::new(vec![0u8; 10]), 10, &record);
+        reader.read_exact(&mut [0; 4])?;
+        reader.seek(SeekFrom::Start(8))?;
+        reader.read_to_end(&mut Vec::new())?;
+        assert_eq!(updates.borrow()[..3], [(0, 10), (4, 10), (10, 10)]);
+        Ok(())
+    }
+}
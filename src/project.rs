@@ -0,0 +1,391 @@
+use crate::effect::distortion::{BitCrush, HardClip, SoftClip};
+use crate::effect::denoise::Declick;
+use crate::effect::eq::{Band, DeEss, ParametricEq};
+use crate::effect::fade::{FadeIn, FadeOut, GainRamp};
+use crate::effect::modulation::{AutoPan, Chorus, Flanger, Phaser, Tremolo};
+use crate::effect::pitch::{PitchCorrect, PitchShift, TimeStretch};
+use crate::effect::{Compressor, DcBlock, Delay, Effect, PingPongDelay, Reverb};
+use crate::envelope::Envelope;
+use crate::sample::{Composition, MultiChannel, Sample, Sidechain, TrackOptions, WaveForm};
+use crate::stereo::StereoWidth;
+use crate::tempo::Tempo;
+use crate::Error;
+
+use std::error;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+// where the audio of a track comes from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Source {
+    // any file MultiChannel::from_file can read, optionally cut down to
+    // start and end in seconds
+    File {
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        start: Option<f32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        end: Option<f32>,
+    },
+    // the raw waveform of every channel stored in the project itself
+    Embedded {
+        sample_rate: u32,
+        channels: Vec<Vec<f32>>,
+    },
+}
+
+impl Source {
+    pub fn file(path: &str) -> Self {
+        Source::File {
+            path: path.to_string(),
+            start: None,
+            end: None,
+        }
+    }
+
+    pub fn embed(sample: &dyn Sample) -> Result<Self, Error> {
+        let channels = (0..sample.channels())
+            .map(|channel| {
+                sample
+                    .waveform(channel)
+                    .ok_or(Error::MissingChannel(channel))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Source::Embedded {
+            sample_rate: sample.sample_rate(),
+            channels,
+        })
+    }
+
+    pub fn load(&self) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        match self {
+            Source::File { path, start, end } => {
+                let sample = MultiChannel::from_file(path)?;
+                if start.is_none() && end.is_none() {
+                    return Ok(Box::new(sample));
+                }
+                let rate = sample.sample_rate() as f32;
+                let start = (start.unwrap_or(0.0).max(0.0) * rate) as usize;
+                let end = end.map_or(sample.length(), |end| (end * rate) as usize);
+                Ok(sample.sample_clamped(start, end))
+            }
+            Source::Embedded {
+                sample_rate,
+                channels,
+            } => {
+                let mut sample = MultiChannel::new();
+                for wave in channels {
+                    sample.add_channel(&WaveForm::with_rate(wave, *sample_rate))?;
+                }
+                Ok(Box::new(sample))
+            }
+        }
+    }
+}
+
+// the built in effects that can be stored in a project, applied in order
+// to the source before it is placed
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "snake_case")]
+pub enum TrackEffect {
+    Reverb(Reverb),
+    Compressor(Compressor),
+    Delay(Delay),
+    PingPongDelay(PingPongDelay),
+    HardClip(HardClip),
+    SoftClip(SoftClip),
+    BitCrush(BitCrush),
+    Band(Band),
+    ParametricEq(ParametricEq),
+    GainRamp(GainRamp),
+    FadeIn(FadeIn),
+    FadeOut(FadeOut),
+    Chorus(Chorus),
+    Flanger(Flanger),
+    Phaser(Phaser),
+    Tremolo(Tremolo),
+    AutoPan(AutoPan),
+    PitchShift(PitchShift),
+    TimeStretch(TimeStretch),
+    PitchCorrect(PitchCorrect),
+    StereoWidth(StereoWidth),
+    Envelope(Envelope),
+    DcBlock(DcBlock),
+    DeEss(DeEss),
+    Declick(Declick),
+}
+
+impl TrackEffect {
+    pub fn effect(&self) -> &dyn Effect {
+        match self {
+            TrackEffect::Reverb(effect) => effect,
+            TrackEffect::Compressor(effect) => effect,
+            TrackEffect::Delay(effect) => effect,
+            TrackEffect::PingPongDelay(effect) => effect,
+            TrackEffect::HardClip(effect) => effect,
+            TrackEffect::SoftClip(effect) => effect,
+            TrackEffect::BitCrush(effect) => effect,
+            TrackEffect::Band(effect) => effect,
+            TrackEffect::ParametricEq(effect) => effect,
+            TrackEffect::GainRamp(effect) => effect,
+            TrackEffect::FadeIn(effect) => effect,
+            TrackEffect::FadeOut(effect) => effect,
+            TrackEffect::Chorus(effect) => effect,
+            TrackEffect::Flanger(effect) => effect,
+            TrackEffect::Phaser(effect) => effect,
+            TrackEffect::Tremolo(effect) => effect,
+            TrackEffect::AutoPan(effect) => effect,
+            TrackEffect::PitchShift(effect) => effect,
+            TrackEffect::TimeStretch(effect) => effect,
+            TrackEffect::PitchCorrect(effect) => effect,
+            TrackEffect::StereoWidth(effect) => effect,
+            TrackEffect::Envelope(effect) => effect,
+            TrackEffect::DcBlock(effect) => effect,
+            TrackEffect::DeEss(effect) => effect,
+            TrackEffect::Declick(effect) => effect,
+        }
+    }
+}
+
+impl Effect for TrackEffect {
+    fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        self.effect().apply(sample)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProjectTrack {
+    pub source: Source,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub effects: Vec<TrackEffect>,
+    // in steps like Composition::add_track
+    pub starts: Vec<usize>,
+    #[serde(default)]
+    pub options: TrackOptions,
+    // (step, gain) points, see Composition::automate_gain
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub automation: Vec<(usize, f32)>,
+}
+
+impl ProjectTrack {
+    pub fn new(source: Source, start: usize) -> Self {
+        ProjectTrack {
+            source,
+            name: None,
+            effects: Vec::new(),
+            starts: vec![start],
+            options: TrackOptions::default(),
+            automation: Vec::new(),
+        }
+    }
+}
+
+// an arrangement that can be saved as JSON or TOML and turned back into a
+// Composition later, file paths are relative to the working directory
+#[derive(Default, Serialize, Deserialize)]
+pub struct Project {
+    #[serde(default)]
+    pub tempo: Tempo,
+    #[serde(default)]
+    pub auto_resample: bool,
+    // the composition is padded with silence to at least this many steps
+    #[serde(default)]
+    pub length: usize,
+    pub tracks: Vec<ProjectTrack>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sidechains: Vec<Sidechain>,
+}
+
+impl Project {
+    pub fn new() -> Self {
+        Project::default()
+    }
+
+    // tracks of a composition have no file to point back to so their
+    // audio is embedded
+    pub fn from_composition(comp: &Composition) -> Result<Project, Error> {
+        let mut tracks = Vec::new();
+        for (id, track) in comp.tracks.iter().enumerate() {
+            tracks.push(ProjectTrack {
+                source: Source::embed(track.as_ref())?,
+                name: comp.names[id].clone(),
+                effects: Vec::new(),
+                starts: comp.starts[id].clone(),
+                options: comp.options[id],
+                automation: comp.automation[id].clone(),
+            });
+        }
+        Ok(Project {
+            tempo: comp.tempo().clone(),
+            auto_resample: comp.auto_resample,
+            length: comp.length,
+            tracks,
+            sidechains: comp.sidechains.clone(),
+        })
+    }
+
+    pub fn to_composition(&self) -> Result<Composition, Box<dyn error::Error>> {
+        self.tempo.validate()?;
+        let mut comp = Composition::new();
+        comp.set_tempo(self.tempo.clone());
+        comp.set_auto_resample(self.auto_resample);
+        for track in &self.tracks {
+            let mut sample = track.source.load()?;
+            for effect in &track.effects {
+                sample = sample.apply(effect)?;
+            }
+            let (first, rest) = track
+                .starts
+                .split_first()
+                .ok_or_else(|| Error::Invalid("Every project track needs a start".to_string()))?;
+            let id = comp.add_track_with(sample.as_ref(), *first, track.options)?;
+            for start in rest {
+                comp.add_track_id(id, *start)?;
+            }
+            comp.set_track_name(id, track.name.as_deref())?;
+            let mut automation = track.automation.clone();
+            automation.sort_by_key(|(step, _)| *step);
+            comp.automation[id] = automation;
+        }
+        for sidechain in &self.sidechains {
+            comp.sidechain(
+                sidechain.source,
+                sidechain.target,
+                sidechain.amount,
+                sidechain.attack,
+                sidechain.release,
+            )?;
+        }
+        comp.extend_to(self.length);
+        Ok(comp)
+    }
+
+    // the format is picked from the extension, .toml for TOML and JSON
+    // for anything else
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn error::Error>> {
+        let text = if is_toml(path) {
+            toml::to_string(self)?
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+
+    pub fn load(path: &str) -> Result<Project, Box<dyn error::Error>> {
+        let text = fs::read_to_string(path)?;
+        if is_toml(path) {
+            Ok(toml::from_str(&text)?)
+        } else {
+            Ok(serde_json::from_str(&text)?)
+        }
+    }
+}
+
+fn is_toml(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("toml"))
+}
+
+impl Composition {
+    pub fn save_project(&self, path: &str) -> Result<(), Box<dyn error::Error>> {
+        Project::from_composition(self)?.save(path)
+    }
+
+    pub fn load_project(path: &str) -> Result<Composition, Box<dyn error::Error>> {
+        Project::load(path)?.to_composition()
+    }
+}
+
+#[cfg(all(test, feature = "wav"))]
+mod tests {
+    use super::*;
+    use crate::effect::fade::Curve;
+    use crate::sample::SineWave;
+
+    fn arrangement() -> Project {
+        let mut project = Project::new();
+        let mut song = ProjectTrack::new(
+            Source::File {
+                path: "./test_files/songs/switch_lr_sine.wav".to_string(),
+                start: Some(0.5),
+                end: Some(1.5),
+            },
+            0,
+        );
+        song.effects.push(TrackEffect::FadeOut(
+            FadeOut::new(0.2).with_curve(Curve::EqualPower),
+        ));
+        song.effects
+            .push(TrackEffect::Delay(Delay::new(120.0, 0.3, 0.4, 1.0)));
+        song.starts.push(44100);
+        song.automation = vec![(0, 0.2), (22050, 1.0)];
+        project.tracks.push(song);
+
+        let beep = SineWave::new(880.0, 4410, 0.5);
+        let stereo = MultiChannel::new_dual(&beep, &beep).unwrap();
+        let mut beeps = ProjectTrack::new(Source::embed(&stereo).unwrap(), 11025);
+        beeps.starts.push(55125);
+        beeps.options = TrackOptions::new(0.8, -0.5);
+        beeps.name = Some("beeps".to_string());
+        project.tracks.push(beeps);
+        project.sidechains.push(Sidechain {
+            source: 1,
+            target: 0,
+            amount: 0.7,
+            attack: 0.005,
+            release: 0.1,
+        });
+        project.length = 3 * 44100;
+        project
+    }
+
+    #[test]
+    fn save_and_load() -> Result<(), Box<dyn error::Error>> {
+        let project = arrangement();
+        let expected = project.to_composition()?;
+        assert_eq!(expected.length(), 3 * 44100);
+
+        for path in [
+            "./test_files/output/project.json",
+            "./test_files/output/project.toml",
+        ]
+        .iter()
+        {
+            project.save(path)?;
+            let loaded = Project::load(path)?;
+            assert_eq!(
+                serde_json::to_string(&loaded)?,
+                serde_json::to_string(&project)?
+            );
+            let comp = loaded.to_composition()?;
+            assert_eq!(comp.waveform(0), expected.waveform(0));
+            assert_eq!(comp.waveform(1), expected.waveform(1));
+        }
+        expected.export("./test_files/output/project.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn composition_round_trip() -> Result<(), Box<dyn error::Error>> {
+        let comp = arrangement().to_composition()?;
+        comp.save_project("./test_files/output/embedded_project.json")?;
+        let loaded = Composition::load_project("./test_files/output/embedded_project.json")?;
+        assert_eq!(loaded.length(), comp.length());
+        assert_eq!(loaded.waveform(0), comp.waveform(0));
+        assert_eq!(loaded.track_id("beeps"), Some(1));
+
+        let empty = ProjectTrack::new(Source::file("./test_files/songs/missing.wav"), 0);
+        let mut broken = Project::new();
+        broken.tracks.push(empty);
+        assert!(broken.to_composition().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+use crate::sample::{MultiChannel, WaveForm};
+use crate::Error;
+
+use std::error;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cpal;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+
+// how much longer than the recording to wait for a device that stalls
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+// names of the devices from_input can record from
+pub fn input_devices() -> Result<Vec<String>, Box<dyn error::Error>> {
+    let mut names = Vec::new();
+    for device in cpal::default_host().input_devices()? {
+        names.push(device.name()?);
+    }
+    Ok(names)
+}
+
+// record duration seconds from the named input device or the default one,
+// at the device's own sample rate and number of channels
+pub fn from_input(
+    duration: f32,
+    device: Option<&str>,
+) -> Result<MultiChannel, Box<dyn error::Error>> {
+    let host = cpal::default_host();
+    let device = match device {
+        Some(name) => host
+            .input_devices()?
+            .find(|device| device.name().is_ok_and(|n| n == name))
+            .ok_or_else(|| Error::Invalid(format!("No input device named {}", name)))?,
+        None => host
+            .default_input_device()
+            .ok_or_else(|| Error::Other("No input device available".to_string()))?,
+    };
+    let supported = device.default_input_config()?;
+    let config = supported.config();
+    let frames = (duration.max(0.0) * config.sample_rate.0 as f32) as usize;
+    let wanted = frames * config.channels as usize;
+
+    let data = Arc::new(Mutex::new(Vec::with_capacity(wanted)));
+    let stream = match supported.sample_format() {
+        cpal::SampleFormat::F32 => capture::<f32>(&device, &config, &data, wanted)?,
+        cpal::SampleFormat::I16 => capture::<i16>(&device, &config, &data, wanted)?,
+        cpal::SampleFormat::U16 => capture::<u16>(&device, &config, &data, wanted)?,
+        cpal::SampleFormat::I32 => capture::<i32>(&device, &config, &data, wanted)?,
+        format => {
+            return Err(Box::new(Error::Unsupported(format!(
+                "Unsupported input sample format {}",
+                format
+            ))))
+        }
+    };
+    stream.play()?;
+
+    let deadline = Instant::now() + Duration::from_secs_f32(duration.max(0.0)) + TIMEOUT;
+    while data
+        .lock()
+        .map_err(|_| Error::Other("Recording thread panicked".to_string()))?
+        .len()
+        < wanted
+    {
+        if Instant::now() > deadline {
+            return Err(Box::new(Error::Other(
+                "Input device stopped sending audio".to_string(),
+            )));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    drop(stream);
+
+    let data = data
+        .lock()
+        .map_err(|_| Error::Other("Recording thread panicked".to_string()))?;
+    Ok(deinterleave(&data, config.channels, config.sample_rate.0)?)
+}
+
+fn capture<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    data: &Arc<Mutex<Vec<f32>>>,
+    wanted: usize,
+) -> Result<cpal::Stream, Box<dyn error::Error>>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let data = data.clone();
+    let stream = device.build_input_stream(
+        config,
+        move |input: &[T], _: &cpal::InputCallbackInfo| {
+            if let Ok(mut data) = data.lock() {
+                let room = wanted.saturating_sub(data.len());
+                data.extend(
+                    input
+                        .iter()
+                        .take(room)
+                        .map(|value| value.to_sample::<f32>()),
+                );
+            }
+        },
+        |err| eprintln!("Recording error: {}", err),
+        None,
+    )?;
+    Ok(stream)
+}
+
+// split interleaved frames into one waveform per channel
+fn deinterleave(data: &[f32], channels: u16, sample_rate: u32) -> Result<MultiChannel, Error> {
+    let count = channels.max(1) as usize;
+    let mut recording = MultiChannel::new();
+    for channel in 0..count {
+        let wave: Vec<f32> = data.iter().skip(channel).step_by(count).copied().collect();
+        recording.add_channel(&WaveForm::from_vec(wave, sample_rate))?;
+    }
+    Ok(recording)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::Sample;
+
+    #[test]
+    fn deinterleave_stereo() -> Result<(), Box<dyn error::Error>> {
+        let recording = deinterleave(&[0.1, 0.3, 0.2, 0.4], 2, 48000)?;
+        assert_eq!(recording.channels(), 2);
+        assert_eq!(recording.sample_rate(), 48000);
+        assert_eq!(recording.waveform(0).unwrap(), vec![0.1, 0.2]);
+        assert_eq!(recording.waveform(1).unwrap(), vec![0.3, 0.4]);
+        Ok(())
+    }
+}
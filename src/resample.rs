@@ -0,0 +1,133 @@
+use crate::sample::Sample;
+
+use std::f32::consts::PI;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    // windowed sinc using this many taps on each side of a point
+    Sinc(usize),
+}
+
+pub const DEFAULT_INTERPOLATION: Interpolation = Interpolation::Sinc(16);
+
+pub fn resample_wave(wave: &[f32], from: u32, to: u32, method: Interpolation) -> Vec<f32> {
+    if from == to || wave.is_empty() {
+        return wave.to_vec();
+    }
+
+    let step = from as f64 / to as f64;
+    let length = ((wave.len() as f64) / step).round() as usize;
+    match method {
+        Interpolation::Linear => (0..length)
+            .map(|n| {
+                let pos = n as f64 * step;
+                let index = pos as usize;
+                let frac = (pos - index as f64) as f32;
+                match (wave.get(index), wave.get(index + 1)) {
+                    (Some(a), Some(b)) => a * (1.0 - frac) + b * frac,
+                    (Some(a), None) => *a,
+                    _ => 0.0,
+                }
+            })
+            .collect(),
+        Interpolation::Sinc(taps) => {
+            // lower the cutoff when downsampling so nothing aliases
+            let cutoff = (to as f32 / from as f32).min(1.0);
+            let width = (taps as f32 / cutoff).ceil() as isize;
+            (0..length)
+                .map(|n| {
+                    let pos = n as f64 * step;
+                    let center = pos.floor() as isize;
+                    let mut value = 0.0;
+                    for k in (center - width + 1)..=(center + width) {
+                        if k < 0 || k as usize >= wave.len() {
+                            continue;
+                        }
+                        let x = (pos - k as f64) as f32;
+                        value += wave[k as usize] * cutoff * sinc(cutoff * x) * window(x, width);
+                    }
+                    value
+                })
+                .collect()
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+// blackman window over [-width, width]
+fn window(x: f32, width: isize) -> f32 {
+    let width = width as f32;
+    if x.abs() >= width {
+        return 0.0;
+    }
+    let phase = PI * (x / width + 1.0);
+    0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos()
+}
+
+pub fn resample(sample: &dyn Sample, rate: u32, method: Interpolation) -> Option<Vec<Vec<f32>>> {
+    let mut channels = Vec::new();
+    for channel in 0..sample.channels() {
+        let wave = sample.waveform(channel)?;
+        channels.push(resample_wave(&wave, sample.sample_rate(), rate, method));
+    }
+    Some(channels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::{Composition, SineWave};
+    use std::error;
+
+    #[cfg(feature = "wav")]
+    fn crossings(wave: &[f32]) -> usize {
+        wave.windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count()
+    }
+
+    #[test]
+    fn linear_doubles_length() {
+        let wave = [0.0, 1.0, 0.0, -1.0];
+        let up = resample_wave(&wave, 22050, 44100, Interpolation::Linear);
+        assert_eq!(up.len(), 8);
+        assert_eq!(up[1], 0.5);
+        assert_eq!(up[2], 1.0);
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn sinc_keeps_pitch() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(440.0, 44100, 0.5);
+        let resampled = wave.resample(48000)?;
+        assert_eq!(resampled.sample_rate(), 48000);
+        assert_eq!(resampled.length(), 48000);
+        let original = crossings(&wave.waveform(0).unwrap());
+        let converted = crossings(&resampled.waveform(0).unwrap());
+        assert!((original as isize - converted as isize).abs() <= 2);
+        resampled.export("./test_files/output/resample_48k.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn composition_auto_resample() -> Result<(), Box<dyn error::Error>> {
+        let low = SineWave::new(440.0, 44100, 0.5);
+        let high = low.resample(48000)?;
+        let mut comp = Composition::new();
+        comp.add_track(&low, 0)?;
+        assert!(comp.add_track(&*high, 0).is_err());
+        comp.set_auto_resample(true);
+        comp.add_track(&*high, 0)?;
+        assert_eq!(comp.sample_rate(), 44100);
+        assert!((comp.length() as isize - 44100).abs() <= 1);
+        Ok(())
+    }
+}
@@ -1,88 +1,653 @@
 use crate::Error;
+use crate::analysis;
 use crate::effect::Effect;
+#[cfg(feature = "mp3-export")]
+use crate::lame;
+#[cfg(feature = "wav")]
+use crate::metadata;
+use crate::metadata::{Marker, Metadata};
+use crate::note::Note;
+#[cfg(any(feature = "wav", feature = "mp3", feature = "ogg"))]
+use crate::progress::ProgressReader;
+use crate::progress::{self, CancelToken, Progress};
+use crate::resample;
+use crate::simd;
+use crate::tempo::Tempo;
 
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::error;
 use std::f32::consts::PI;
 use std::fs::File;
-use std::iter;
+#[cfg(all(feature = "async", feature = "wav"))]
+use std::future::Future;
+#[cfg(any(feature = "wav", feature = "mp3", feature = "ogg"))]
+use std::io::BufReader;
+use std::io::Read;
+#[cfg(any(feature = "wav", feature = "ogg", feature = "symphonia-backend"))]
+use std::io::Cursor;
+#[cfg(any(feature = "wav", feature = "ogg"))]
+use std::io::Seek;
+#[cfg(feature = "wav")]
+use std::io::{BufWriter, SeekFrom, Write};
+use std::iter::{self, FromIterator};
+use std::ops::{Add, Mul, Shr};
+use std::path::Path;
+#[cfg(all(feature = "async", feature = "wav"))]
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use hound;
-use minimp3;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 const RATE: u32 = 44100;
+#[cfg(feature = "wav")]
 const BITS_PER_SAMPLE: u16 = 32;
+const BLOCK_SIZE: usize = 4096;
+const ZERO_CROSSING_SEARCH: usize = 1024;
+#[cfg(feature = "wav")]
+const LIMITER_RELEASE: f32 = 0.05;
 
-pub trait Sample {
+// anything a WAV can be exported into
+#[cfg(feature = "wav")]
+pub trait WriteSeek: Write + Seek {}
+
+#[cfg(feature = "wav")]
+impl<T: Write + Seek> WriteSeek for T {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ExportOptions {
+    // scale the whole sample so its peak is at full scale before writing
+    pub normalize: bool,
+    // run a peak limiter so no value goes above this level
+    pub ceiling: Option<f32>,
+}
+
+// how sample_zero_crossing_with snaps slice boundaries
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZeroCrossingOptions {
+    // the channel to look for crossings in, None sums every channel
+    pub channel: Option<u16>,
+    // steps either side of a boundary to look, a boundary without a
+    // crossing that close stays where it is
+    pub search: usize,
+    // steps faded in and out after snapping to take out any click left
+    pub fade: usize,
+}
+
+impl Default for ZeroCrossingOptions {
+    fn default() -> Self {
+        ZeroCrossingOptions {
+            channel: None,
+            search: ZERO_CROSSING_SEARCH,
+            fade: 0,
+        }
+    }
+}
+
+// the step of the nearest sign change within search of step, whichever
+// of the two steps either side is closer to zero
+fn nearest_zero_crossing(wave: &[f32], step: usize, search: usize) -> Option<usize> {
+    let crossing = |index: usize| {
+        (index > 0 && index < wave.len())
+            && (wave[index] == 0.0 || (wave[index - 1] < 0.0) != (wave[index] < 0.0))
+    };
+    for distance in 0..=search {
+        for index in [step.checked_sub(distance), step.checked_add(distance)] {
+            match index {
+                Some(index) if crossing(index) => {
+                    return Some(if wave[index - 1].abs() < wave[index].abs() {
+                        index - 1
+                    } else {
+                        index
+                    });
+                }
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+pub trait Sample: Send + Sync {
     fn sample_rate(&self) -> u32;
     fn length(&self) -> usize;
     fn waveform(&self, channel: u16) -> Option<Vec<f32>>;
     fn channels(&self) -> u16;
+    #[must_use]
     fn box_clone(&self) -> Box<dyn Sample>; // nesscarry for cloning
+    #[cfg(feature = "wav")]
     fn export(&self, file: &str) -> Result<(), Box<dyn error::Error>> {
+        self.export_with(file, &ExportOptions::default())
+    }
+    #[cfg(feature = "wav")]
+    fn export_with(
+        &self,
+        file: &str,
+        options: &ExportOptions,
+    ) -> Result<(), Box<dyn error::Error>> {
+        self.export_with_progress(file, options, &progress::ignore)
+    }
+    // progress is counted in steps written
+    #[cfg(feature = "wav")]
+    fn export_with_progress(
+        &self,
+        file: &str,
+        options: &ExportOptions,
+        progress: &dyn Progress,
+    ) -> Result<(), Box<dyn error::Error>> {
+        export_file(self, file, options, progress, None)
+    }
+    // stops between blocks once cancel is set and removes the unfinished file
+    #[cfg(feature = "wav")]
+    fn export_cancellable(
+        &self,
+        file: &str,
+        options: &ExportOptions,
+        cancel: &CancelToken,
+    ) -> Result<(), Box<dyn error::Error>> {
+        export_file(self, file, options, &progress::ignore, Some(cancel))
+    }
+    // a WAV written into anything seekable, like an in-memory Cursor, the
+    // writer should be empty since the WAV starts at its beginning
+    #[cfg(feature = "wav")]
+    fn export_to_writer(
+        &self,
+        writer: &mut dyn WriteSeek,
+        options: &ExportOptions,
+    ) -> Result<(), Box<dyn error::Error>> {
+        write_wav(self, writer, options, &progress::ignore, None)
+    }
+    // the whole WAV file in memory, for targets without a filesystem like
+    // wasm32-unknown-unknown
+    #[cfg(feature = "wav")]
+    fn export_to_bytes(&self, options: &ExportOptions) -> Result<Vec<u8>, Box<dyn error::Error>> {
+        let mut buffer = Cursor::new(Vec::new());
+        self.export_to_writer(&mut buffer, options)?;
+        Ok(buffer.into_inner())
+    }
+    // exports a copy of the sample on tokio's blocking pool so the caller's
+    // executor keeps running, has to be awaited inside a tokio runtime
+    #[cfg(all(feature = "async", feature = "wav"))]
+    fn export_async(
+        &self,
+        file: &str,
+        options: &ExportOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send>> {
+        let sample = self.box_clone();
+        let file = file.to_string();
+        let options = *options;
+        Box::pin(blocking(move || {
+            sample.export_with(&file, &options).map_err(into_error)
+        }))
+    }
+    #[cfg(feature = "mp3-export")]
+    fn export_mp3(&self, file: &str, bitrate: u32) -> Result<(), Box<dyn error::Error>> {
         // store all the channels in a 2D vec
         let mut wave_data = Vec::new();
         for channel in 0..self.channels() {
             wave_data.push(
                 self.waveform(channel)
-                    .ok_or(Error::new_box("Sample is missing a channel"))?,
+                    .ok_or(Error::MissingChannel(channel))?,
             );
         }
 
-        // set up hound
-        let spec = hound::WavSpec {
-            channels: self.channels(),
-            sample_rate: self.sample_rate(),
-            bits_per_sample: BITS_PER_SAMPLE,
-            sample_format: hound::SampleFormat::Float,
+        let bytes = lame::encode(&wave_data, self.sample_rate(), bitrate)?;
+        std::fs::write(file, bytes)?;
+        Ok(())
+    }
+    // the steps from start up to end, a range running past the end of the
+    // sample or starting after it ends is an error
+    fn sample(&self, start: usize, end: usize) -> Result<Box<dyn Sample>, Error> {
+        if end > self.length() {
+            return Err(Error::OutOfRange {
+                what: "step",
+                index: end,
+                len: self.length(),
+            });
+        }
+        if start > end {
+            return Err(Error::Invalid(format!(
+                "Slice starts at {} after it ends at {}",
+                start, end
+            )));
+        }
+        Ok(self.sample_clamped(start, end))
+    }
+    // any part of the range outside the sample is cut off instead
+    #[must_use]
+    fn sample_clamped(&self, start: usize, end: usize) -> Box<dyn Sample> {
+        Box::new(Slice::new(self.box_clone(), start, end))
+    }
+    // times are rounded to the nearest step so the end of the sample in
+    // seconds lands on its last step
+    fn sample_sec(&self, start: f32, end: f32) -> Result<Box<dyn Sample>, Error> {
+        let step = |time: f32| {
+            if time >= 0.0 {
+                Ok((time * self.sample_rate() as f32).round() as usize)
+            } else {
+                Err(Error::Invalid(format!(
+                    "{} is not a time in the sample",
+                    time
+                )))
+            }
+        };
+        self.sample(step(start)?, step(end)?)
+    }
+    #[must_use]
+    fn sample_sec_clamped(&self, start: f32, end: f32) -> Box<dyn Sample> {
+        let rate = self.sample_rate() as f32;
+        let start = (start.max(0.0) * rate).round() as usize;
+        let end = (end.max(0.0) * rate).round() as usize;
+        self.sample_clamped(start, end)
+    }
+    // like sample but the boundaries move to the nearest zero crossings so
+    // a chopped slice doesn't click, the step at zero is kept at both ends
+    fn sample_zero_crossing(&self, start: usize, end: usize) -> Result<Box<dyn Sample>, Error> {
+        self.sample_zero_crossing_with(start, end, &ZeroCrossingOptions::default())
+    }
+    fn sample_zero_crossing_with(
+        &self,
+        start: usize,
+        end: usize,
+        options: &ZeroCrossingOptions,
+    ) -> Result<Box<dyn Sample>, Error> {
+        if end > self.length() || start > end {
+            return self.sample(start, end);
+        }
+        // only the steps around a boundary are filled in, one more before
+        // so the first can be compared with the step ahead of it
+        let snap = |step: usize| {
+            let from = step.saturating_sub(options.search + 1);
+            let to = (step + options.search + 1).min(self.length());
+            let mut guide = vec![0.0; to - from];
+            let mut block = vec![0.0; to - from];
+            let channels = options
+                .channel
+                .map_or(0..self.channels(), |channel| channel..channel + 1);
+            for channel in channels {
+                let filled = self
+                    .fill(channel, from, &mut block)
+                    .ok_or(Error::MissingChannel(channel))?;
+                guide
+                    .iter_mut()
+                    .zip(&block[..filled])
+                    .for_each(|(s, v)| *s += v);
+            }
+            let crossing = nearest_zero_crossing(&guide, step - from, options.search);
+            Ok::<_, Error>(crossing.map(|index| from + index))
         };
-        let mut writer = hound::WavWriter::create(file, spec)?;
+        let start = snap(start)?.unwrap_or(start);
+        let end = snap(end)?
+            .map_or(end, |step| (step + 1).min(self.length()))
+            .max(start);
+        let slice = self.sample(start, end)?;
+        if options.fade == 0 {
+            return Ok(slice);
+        }
 
-        // interleave channel data
-        for index in 0..self.length() {
-            for channel in 0..self.channels() {
-                writer.write_sample(wave_data[channel as usize][index])?
+        let fade = options.fade.min(slice.length() / 2);
+        let mut channels = MultiChannel::new();
+        for channel in 0..slice.channels() {
+            let mut wave = slice
+                .waveform(channel)
+                .ok_or(Error::MissingChannel(channel))?;
+            let length = wave.len();
+            for index in 0..fade {
+                let gain = index as f32 / fade as f32;
+                wave[index] *= gain;
+                wave[length - 1 - index] *= gain;
             }
+            channels.add_channel(&WaveForm::from_vec(wave, self.sample_rate()))?;
         }
-        writer.finalize()?;
-        Ok(())
+        keep_metadata(slice.metadata(), &mut channels);
+        Ok(Box::new(channels))
     }
-    fn sample(&self, start: usize, end: usize) -> Box<dyn Sample> {
-        let mut sample = MultiChannel::new();
-        for channel in 0..self.channels() {
-            sample
-                .add_channel(&WaveForm::from(
-                    &self.waveform(channel).unwrap()[start..end],
-                ))
-                .unwrap();
+    fn len_sec(&self) -> f32 {
+        match self.sample_rate() {
+            0 => 0.0,
+            rate => self.length() as f32 / rate as f32,
         }
-        Box::new(sample)
     }
-    fn sample_sec(&self, start: f32, end: f32) -> Box<dyn Sample> {
-        let start = (start * (self.sample_rate() as f32)) as usize;
-        let end = (end * (self.sample_rate() as f32)) as usize;
-        self.sample(start, end)
+    fn duration(&self) -> Duration {
+        match self.sample_rate() {
+            0 => Duration::default(),
+            rate => Duration::from_secs_f64(self.length() as f64 / rate as f64),
+        }
     }
     fn apply(&self, effect: &dyn Effect) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
         let clone = self.box_clone();
-        effect.apply(&*clone)
+        let mut result = effect.apply(&*clone)?;
+        keep_metadata(self.metadata(), result.as_mut());
+        Ok(result)
+    }
+    fn apply_all(&self, effects: &[&dyn Effect]) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        let mut result = self.box_clone();
+        for effect in effects {
+            result = effect.apply(&*result)?;
+        }
+        keep_metadata(self.metadata(), result.as_mut());
+        Ok(result)
+    }
+    // checked before each effect, an effect already running finishes first
+    fn apply_all_cancellable(
+        &self,
+        effects: &[&dyn Effect],
+        cancel: &CancelToken,
+    ) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        let mut result = self.box_clone();
+        for effect in effects {
+            if cancel.is_cancelled() {
+                return Err(Error::Cancelled.into());
+            }
+            result = effect.apply(&*result)?;
+        }
+        keep_metadata(self.metadata(), result.as_mut());
+        Ok(result)
+    }
+    #[must_use]
+    fn scale(&self, scale: f32) -> Box<dyn Sample> {
+        Box::new(ScaledSample::new(self.box_clone(), scale))
+    }
+    fn peak(&self) -> f32 {
+        let mut peak = 0.0f32;
+        let mut block = vec![0.0; BLOCK_SIZE];
+        for channel in 0..self.channels() {
+            let mut offset = 0;
+            while let Some(filled) = self.fill(channel, offset, &mut block) {
+                if filled == 0 {
+                    break;
+                }
+                peak = block[..filled].iter().fold(peak, |max, s| max.max(s.abs()));
+                offset += filled;
+            }
+        }
+        peak
+    }
+    #[must_use]
+    fn normalize(&self) -> Box<dyn Sample> {
+        match self.peak() {
+            peak if peak > 0.0 => self.scale(1.0 / peak),
+            _ => self.box_clone(),
+        }
+    }
+    // gain so the integrated loudness lands on target_lufs, silent samples
+    // are left alone since no gain can bring them up
+    #[must_use]
+    fn normalize_loudness(&self, target_lufs: f32) -> Box<dyn Sample> {
+        let sample = self.box_clone();
+        match analysis::integrated_loudness(sample.as_ref()) {
+            lufs if lufs.is_finite() => sample.scale(10f32.powf((target_lufs - lufs) / 20.0)),
+            _ => sample,
+        }
+    }
+    fn append(
+        &self,
+        other: &dyn Sample,
+        crossfade: usize,
+    ) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        if self.channels() != other.channels() {
+            return Err(Box::new(Error::ChannelMismatch {
+                expected: self.channels(),
+                found: other.channels(),
+            }));
+        }
+        if self.sample_rate() != other.sample_rate() {
+            return Err(Box::new(Error::SampleRateMismatch {
+                expected: self.sample_rate(),
+                found: other.sample_rate(),
+            }));
+        }
+
+        let crossfade = crossfade.min(self.length()).min(other.length());
+        let mut channels = MultiChannel::new();
+        for channel in 0..self.channels() {
+            let first = self
+                .waveform(channel)
+                .ok_or(Error::MissingChannel(channel))?;
+            let second = other
+                .waveform(channel)
+                .ok_or(Error::MissingChannel(channel))?;
+
+            // equal power crossfade over the overlapping part
+            let split = first.len() - crossfade;
+            let mut waveform = first[..split].to_vec();
+            for index in 0..crossfade {
+                let t = (index as f32 + 0.5) / crossfade as f32 * PI / 2.0;
+                waveform.push(first[split + index] * t.cos() + second[index] * t.sin());
+            }
+            waveform.extend_from_slice(&second[crossfade..]);
+            channels.add_channel(&WaveForm {
+                sample_rate: self.sample_rate(),
+                waveform: waveform.into(),
+            })?;
+        }
+        Ok(Box::new(channels))
+    }
+    #[must_use]
+    fn reverse(&self) -> Box<dyn Sample> {
+        Box::new(Reversed::new(self.box_clone()))
+    }
+    #[must_use]
+    fn repeat(&self, times: u16) -> Box<dyn Sample> {
+        Box::new(Loop::new(self.box_clone(), times))
+    }
+    #[must_use]
+    fn loop_to_length(&self, length: usize) -> Box<dyn Sample> {
+        Box::new(Loop::new(self.box_clone(), 0).with_length(length))
+    }
+    // silence in front, the same as sample >> steps but in seconds
+    #[must_use]
+    fn delayed(&self, seconds: f32) -> Box<dyn Sample> {
+        let delay = (seconds.max(0.0) * self.sample_rate() as f32) as usize;
+        Box::new(Delayed::new(self.box_clone(), delay))
+    }
+    fn modulate_amplitude_by(
+        &self,
+        control: &dyn Sample,
+    ) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+        let control = control.waveform(0).ok_or(Error::MissingChannel(0))?;
+        if control.is_empty() {
+            return Err(Box::new(Error::Invalid(
+                "Control sample is empty".to_string(),
+            )));
+        }
+
+        // stretch the control signal over the length of this sample
+        let length = self.length();
+        let stretch = if length > 1 {
+            (control.len() - 1) as f32 / (length - 1) as f32
+        } else {
+            0.0
+        };
+        let envelope: Vec<f32> = (0..length)
+            .map(|t| {
+                let pos = t as f32 * stretch;
+                let index = pos as usize;
+                let frac = pos - index as f32;
+                match control.get(index + 1) {
+                    Some(next) => control[index] * (1.0 - frac) + next * frac,
+                    None => control[index.min(control.len() - 1)],
+                }
+            })
+            .collect();
+
+        let mut channels = MultiChannel::new();
+        for channel in 0..self.channels() {
+            let wave = self
+                .waveform(channel)
+                .ok_or(Error::MissingChannel(channel))?;
+            let data: Vec<f32> = wave.iter().zip(&envelope).map(|(s, e)| s * e).collect();
+            channels.add_channel(&WaveForm {
+                sample_rate: self.sample_rate(),
+                waveform: data.into(),
+            })?;
+        }
+        Ok(Box::new(channels))
     }
-    fn scale(&self, scale: f32) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+    fn to_raw_i24_le(&self) -> Result<Vec<u8>, Box<dyn error::Error>> {
         // store all the channels in a 2D vec
         let mut wave_data = Vec::new();
         for channel in 0..self.channels() {
             wave_data.push(
                 self.waveform(channel)
-                    .ok_or(Error::new_box("Sample is missing a channel"))?,
+                    .ok_or(Error::MissingChannel(channel))?,
             );
         }
 
+        // interleave channel data, 3 little endian bytes per sample
+        let max = ((1 << 23) - 1) as f32;
+        let mut bytes = Vec::with_capacity(self.length() * wave_data.len() * 3);
+        for index in 0..self.length() {
+            for wave in &wave_data {
+                let value = (wave[index].clamp(-1.0, 1.0) * max).round() as i32;
+                bytes.extend_from_slice(&value.to_le_bytes()[..3]);
+            }
+        }
+        Ok(bytes)
+    }
+    fn resample(&self, rate: u32) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
         let mut channels = MultiChannel::new();
-        for wave in wave_data {
-            let data: Vec<_> = wave.iter().map(|s| scale*s).collect();
-            channels.add_channel(&WaveForm::from(&data))?;
+        for channel in 0..self.channels() {
+            let wave = self
+                .waveform(channel)
+                .ok_or(Error::MissingChannel(channel))?;
+            channels.add_channel(&WaveForm {
+                sample_rate: rate,
+                waveform: resample::resample_wave(
+                    &wave,
+                    self.sample_rate(),
+                    rate,
+                    resample::DEFAULT_INTERPOLATION,
+                )
+                .into(),
+            })?;
         }
+        let factor = rate as f64 / self.sample_rate() as f64;
+        keep_metadata(
+            self.metadata().map(|m| m.stretched(factor)).as_ref(),
+            &mut channels,
+        );
         Ok(Box::new(channels))
     }
+    #[must_use]
+    fn to_mono(&self) -> Box<dyn Sample> {
+        let mono = WaveForm {
+            sample_rate: self.sample_rate(),
+            waveform: analysis::mono(self.box_clone().as_ref()).into(),
+        };
+        match self.metadata() {
+            Some(metadata) => {
+                let mut channels = MultiChannel::new();
+                channels.add_channel(&mono).unwrap();
+                channels.metadata = Some(metadata.clone());
+                Box::new(channels)
+            }
+            None => Box::new(mono),
+        }
+    }
+    #[must_use]
+    fn trim_silence(&self, threshold_db: f32) -> Box<dyn Sample> {
+        let clone = self.box_clone();
+        let silence = analysis::detect_silence(clone.as_ref(), threshold_db, 1);
+        let mut start = 0;
+        let mut end = self.length();
+        for (from, to) in silence {
+            if from == 0 {
+                start = to;
+            }
+            if to == self.length() {
+                end = from.max(start);
+            }
+        }
+        self.sample_clamped(start, end)
+    }
+    // the channel without copying it when the sample already holds it in
+    // memory, anything that has to compute the channel hands out a new one
+    fn waveform_ref(&self, channel: u16) -> Option<Cow<'_, [f32]>> {
+        self.waveform(channel).map(Cow::Owned)
+    }
+    // one value per channel for each step, channels that are missing or
+    // shorter than the sample read as silence
+    fn iter_frames(&self) -> Box<dyn Iterator<Item = Vec<f32>> + '_> {
+        let waves: Vec<_> = (0..self.channels())
+            .map(|channel| self.waveform_ref(channel).unwrap_or_default())
+            .collect();
+        Box::new((0..self.length()).map(move |index| {
+            waves
+                .iter()
+                .map(|wave| wave.get(index).copied().unwrap_or(0.0))
+                .collect()
+        }))
+    }
+    // descriptive data carried along with the audio, samples that are
+    // computed rather than loaded don't keep any
+    fn metadata(&self) -> Option<&Metadata> {
+        None
+    }
+    fn set_metadata(&mut self, _metadata: Metadata) -> Result<(), Error> {
+        Err(Error::Unsupported(
+            "This sample can not hold metadata".to_string(),
+        ))
+    }
+    // markers live in the metadata so only samples that keep it have any
+    fn markers(&self) -> &[Marker] {
+        self.metadata().map_or(&[], |m| &m.markers)
+    }
+    fn marker(&self, name: &str) -> Result<&Marker, Error> {
+        self.metadata()
+            .and_then(|m| m.marker(name))
+            .ok_or_else(|| Error::UnknownMarker(name.to_string()))
+    }
+    // a marker with the same name is moved
+    fn add_marker(&mut self, marker: Marker) -> Result<(), Error> {
+        if marker.end() > self.length() {
+            return Err(Error::OutOfRange {
+                what: "step",
+                index: marker.end(),
+                len: self.length(),
+            });
+        }
+        let mut metadata = self.metadata().cloned().unwrap_or_default();
+        metadata.add_marker(marker);
+        self.set_metadata(metadata)
+    }
+    // steps from start up to end played over and over by samplers
+    fn add_loop(&mut self, start: usize, end: usize) -> Result<(), Error> {
+        if end > self.length() || start >= end {
+            return Err(Error::Invalid(format!(
+                "A loop from {} to {} doesn't fit in {} steps",
+                start,
+                end,
+                self.length()
+            )));
+        }
+        let metadata = self.metadata().cloned().unwrap_or_default();
+        self.set_metadata(metadata.with_loop(start, end))
+    }
+    // from where the first marker is to where the second one is
+    fn sample_between_markers(&self, from: &str, to: &str) -> Result<Box<dyn Sample>, Error> {
+        self.sample(self.marker(from)?.position, self.marker(to)?.position)
+    }
+    fn sample_region(&self, name: &str) -> Result<Box<dyn Sample>, Error> {
+        let marker = self.marker(name)?;
+        if marker.length.is_none() {
+            return Err(Error::Invalid(format!("Marker {} is not a region", name)));
+        }
+        self.sample(marker.position, marker.end())
+    }
+    // writes the channel starting at offset into buffer and returns how many
+    // values were written, samples that can compute a block at a time
+    // should override this so they can be streamed without a whole waveform
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        let wave = self.waveform_ref(channel)?;
+        let start = offset.min(wave.len());
+        let end = (offset + buffer.len()).min(wave.len());
+        buffer[..end - start].copy_from_slice(&wave[start..end]);
+        Some(end - start)
+    }
 }
 
 #[derive(Clone)]
@@ -102,6 +667,11 @@ impl SineWave {
             sample_rate: RATE,
         }
     }
+
+    pub fn note(note: Note, duration: f32, amplitude: f32) -> Self {
+        let length = (duration * RATE as f32) as usize;
+        SineWave::new(note.to_frequency(), length, amplitude)
+    }
 }
 
 impl Sample for SineWave {
@@ -130,24 +700,65 @@ impl Sample for SineWave {
         Some(waveform)
     }
 
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        if channel > 0 {
+            return None;
+        }
+
+        let size = buffer.len().min(self.length.saturating_sub(offset));
+        for (index, value) in buffer[..size].iter_mut().enumerate() {
+            let t = ((offset + index) as f32) * 1.0 / (self.sample_rate() as f32);
+            *value = self.amplitude * (2.0 * PI * self.frequency * t).sin();
+        }
+        Some(size)
+    }
+
     fn box_clone(&self) -> Box<dyn Sample> {
         Box::new(self.clone())
     }
 }
 
+// clones share the same data so placing a waveform many times or cloning
+// a sample made of them doesn't copy the audio
 #[derive(Clone)]
 pub struct WaveForm {
     sample_rate: u32,
-    waveform: Vec<f32>,
+    waveform: Arc<[f32]>,
 }
 
 impl WaveForm {
     pub fn from(waveform: &[f32]) -> WaveForm {
         WaveForm {
             sample_rate: RATE,
-            waveform: waveform.to_vec(),
+            waveform: waveform.into(),
+        }
+    }
+
+    pub fn with_rate(waveform: &[f32], sample_rate: u32) -> WaveForm {
+        WaveForm {
+            sample_rate,
+            waveform: waveform.into(),
         }
     }
+
+    // takes the buffer instead of copying it
+    pub fn from_vec(waveform: Vec<f32>, sample_rate: u32) -> WaveForm {
+        WaveForm {
+            sample_rate,
+            waveform: waveform.into(),
+        }
+    }
+
+    // collect at a rate other than the 44100 collect() uses
+    pub fn from_iter<I: IntoIterator<Item = f32>>(sample_rate: u32, values: I) -> WaveForm {
+        WaveForm::from_vec(values.into_iter().collect(), sample_rate)
+    }
+}
+
+impl FromIterator<f32> for WaveForm {
+    fn from_iter<I: IntoIterator<Item = f32>>(values: I) -> WaveForm {
+        WaveForm::from_iter(RATE, values)
+    }
 }
 
 impl Sample for WaveForm {
@@ -168,7 +779,26 @@ impl Sample for WaveForm {
             return None;
         }
 
-        Some(self.waveform.clone())
+        Some(self.waveform.to_vec())
+    }
+
+    fn waveform_ref(&self, channel: u16) -> Option<Cow<'_, [f32]>> {
+        if channel > 0 {
+            return None;
+        }
+
+        Some(Cow::Borrowed(&self.waveform))
+    }
+
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        if channel > 0 {
+            return None;
+        }
+
+        let start = offset.min(self.waveform.len());
+        let end = (offset + buffer.len()).min(self.waveform.len());
+        buffer[..end - start].copy_from_slice(&self.waveform[start..end]);
+        Some(end - start)
     }
 
     fn box_clone(&self) -> Box<dyn Sample> {
@@ -180,6 +810,214 @@ pub struct MultiChannel {
     sample_rate: u32,
     length: usize,
     channels: Vec<Box<dyn Sample>>,
+    metadata: Option<Metadata>,
+}
+
+// the containers the built in decoders read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Wav,
+    Mp3,
+    Ogg,
+}
+
+impl Format {
+    // from the magic bytes at the start of a file, the first 12 are enough
+    pub fn detect(header: &[u8]) -> Option<Format> {
+        if header.len() >= 12 && &header[0..4] == b"RIFF" && &header[8..12] == b"WAVE" {
+            Some(Format::Wav)
+        } else if header.starts_with(b"OggS") {
+            Some(Format::Ogg)
+        } else if header.starts_with(b"ID3")
+            || (header.len() >= 2 && header[0] == 0xFF && header[1] & 0xE0 == 0xE0)
+        {
+            Some(Format::Mp3)
+        } else {
+            None
+        }
+    }
+
+    #[cfg(feature = "symphonia-backend")]
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Wav => "wav",
+            Format::Mp3 => "mp3",
+            Format::Ogg => "ogg",
+        }
+    }
+}
+
+// each decoder gives back the sample rate and the audio split into channels
+
+#[cfg(feature = "mp3")]
+fn mp3_waveforms<R: Read>(reader: R) -> Result<(u32, Vec<Vec<f32>>), Error> {
+    let mut waveforms: Vec<Vec<f32>> = Vec::new();
+    let mut decoder = minimp3::Decoder::new(reader);
+    let mut rate = 0;
+    loop {
+        match decoder.next_frame() {
+            Ok(minimp3::Frame {
+                data,
+                sample_rate,
+                channels,
+                ..
+            }) => {
+                if rate != 0 && sample_rate != rate {
+                    return Err(Error::Decode("Sample rate changed in file".to_string()));
+                }
+                rate = sample_rate;
+
+                if waveforms.is_empty() {
+                    waveforms = iter::repeat_n(Vec::new(), channels).collect();
+                }
+                if waveforms.len() != channels {
+                    return Err(Error::Decode(
+                        "Number of waveforms changed mid song".to_string(),
+                    ));
+                }
+
+                for (index, sample) in data.iter().enumerate() {
+                    let sample = (*sample as f32) / (i16::MAX as f32);
+                    let channel = index % waveforms.len();
+                    waveforms[channel].push(sample);
+                }
+            }
+            Err(minimp3::Error::Eof) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if waveforms.is_empty() {
+        return Err(Error::Decode("No MP3 frames found".to_string()));
+    }
+    Ok((rate as u32, waveforms))
+}
+
+#[cfg(feature = "wav")]
+fn wav_waveforms<R: Read>(reader: R) -> Result<(u32, Vec<Vec<f32>>), Error> {
+    let mut reader = hound::WavReader::new(reader)?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        // integer pcm is scaled so full scale lands on -1.0..1.0
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let mut waveforms: Vec<Vec<f32>> = iter::repeat_n(Vec::new(), channels).collect();
+    for (index, sample) in samples.into_iter().enumerate() {
+        waveforms[index % channels].push(sample);
+    }
+    Ok((spec.sample_rate, waveforms))
+}
+
+#[cfg(feature = "ogg")]
+fn ogg_waveforms<R: Read + Seek>(reader: R) -> Result<(u32, Vec<Vec<f32>>), Error> {
+    let mut reader = lewton::inside_ogg::OggStreamReader::new(reader)?;
+    let mut waveforms: Vec<Vec<f32>> =
+        iter::repeat_n(Vec::new(), reader.ident_hdr.audio_channels as usize).collect();
+    while let Some(packet) = reader.read_dec_packet_generic::<Vec<Vec<f32>>>()? {
+        if packet.len() != waveforms.len() {
+            return Err(Error::Decode(
+                "Number of waveforms changed mid song".to_string(),
+            ));
+        }
+        for (wave, data) in waveforms.iter_mut().zip(packet) {
+            wave.extend(data);
+        }
+    }
+    Ok((reader.ident_hdr.audio_sample_rate, waveforms))
+}
+
+// the gapless playback info a LAME style encoder writes in the first frame
+#[cfg(feature = "mp3")]
+struct Mp3Gapless {
+    frames: usize,
+    frame_samples: usize,
+    delay: usize,
+    padding: usize,
+}
+
+#[cfg(feature = "mp3")]
+impl Mp3Gapless {
+    // the synthesis filterbank delays every decoder's output by this much
+    const DECODER_DELAY: usize = 529;
+
+    fn read(bytes: &[u8]) -> Option<Mp3Gapless> {
+        let mut at = 0;
+        if bytes.starts_with(b"ID3") && bytes.len() >= 10 {
+            let size = bytes[6..10]
+                .iter()
+                .fold(0, |size, byte| (size << 7) | (*byte as usize & 0x7F));
+            let footer = if bytes[5] & 0x10 != 0 { 10 } else { 0 };
+            at = 10 + size + footer;
+        }
+        let header = bytes.get(at..at + 4)?;
+        let version = (header[1] >> 3) & 3;
+        let layer = (header[1] >> 1) & 3;
+        if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 || layer != 1 {
+            return None;
+        }
+
+        // the tag sits right after the side info, whose size depends on the
+        // mpeg version and channel mode
+        let mono = header[3] >> 6 == 3;
+        let (side_info, frame_samples) = match (version == 3, mono) {
+            (true, false) => (32, 1152),
+            (true, true) => (17, 1152),
+            (false, false) => (17, 576),
+            (false, true) => (9, 576),
+        };
+        let mut at = at + 4 + side_info;
+        let tag = bytes.get(at..at + 8)?;
+        if &tag[0..4] != b"Xing" && &tag[0..4] != b"Info" {
+            return None;
+        }
+        let flags = u32::from_be_bytes([tag[4], tag[5], tag[6], tag[7]]);
+        at += 8;
+        // only the frame count says how much audio there really is
+        if flags & 1 == 0 {
+            return None;
+        }
+        let count = bytes.get(at..at + 4)?;
+        let frames = u32::from_be_bytes([count[0], count[1], count[2], count[3]]) as usize;
+        at += 4;
+        if flags & 2 != 0 {
+            at += 4;
+        }
+        if flags & 4 != 0 {
+            at += 100;
+        }
+        if flags & 8 != 0 {
+            at += 4;
+        }
+
+        // delay and padding are packed as two 12 bit numbers after the
+        // encoder version, vbr method, lowpass, replay gain, ath and bitrate
+        let lame = bytes.get(at..at + 24)?;
+        let delay = ((lame[21] as usize) << 4) | (lame[22] as usize >> 4);
+        let padding = ((lame[22] as usize & 0x0F) << 8) | lame[23] as usize;
+        Some(Mp3Gapless {
+            frames,
+            frame_samples,
+            delay,
+            padding,
+        })
+    }
+
+    // the range of decoded samples that is real audio, counted back from the
+    // end since some decoders also output the header frame as silence
+    fn trim(&self, decoded: usize) -> (usize, usize) {
+        let encoded = self.frames * self.frame_samples;
+        let start =
+            (decoded.saturating_sub(encoded) + self.delay + Self::DECODER_DELAY).min(decoded);
+        let end = (start + encoded.saturating_sub(self.delay + self.padding)).min(decoded);
+        (start, end)
+    }
 }
 
 impl MultiChannel {
@@ -188,223 +1026,425 @@ impl MultiChannel {
             sample_rate: 0,
             length: 0,
             channels: Vec::new(),
+            metadata: None,
         }
     }
 
     pub fn new_dual(left: &dyn Sample, right: &dyn Sample) -> Result<MultiChannel, Error> {
         if left.length() != right.length() {
-            return Err(Error::new("Left and right sample lengths do not match"));
+            return Err(Error::LengthMismatch {
+                expected: left.length(),
+                found: right.length(),
+            });
         }
         if left.sample_rate() != right.sample_rate() {
-            return Err(Error::new("Left and right sample rates do not match"));
-        }
-        if left.channels() != 1 {
-            return Err(Error::new("Left channel has more than one channel"));
+            return Err(Error::SampleRateMismatch {
+                expected: left.sample_rate(),
+                found: right.sample_rate(),
+            });
         }
-        if right.channels() != 1 {
-            return Err(Error::new("Right channel has more than one channel"));
+        for side in [left, right].iter() {
+            if side.channels() != 1 {
+                return Err(Error::ChannelMismatch {
+                    expected: 1,
+                    found: side.channels(),
+                });
+            }
         }
 
         Ok(MultiChannel {
             sample_rate: left.sample_rate(),
             length: left.length(),
             channels: vec![left.box_clone(), right.box_clone()],
+            metadata: None,
         })
     }
 
-    pub fn from_mp3(filename: &str) -> Result<MultiChannel, Box<dyn error::Error>> {
-        let mut waveforms: Vec<Vec<f32>> = Vec::new();
-        let mut decoder = minimp3::Decoder::new(File::open(filename)?);
-        let mut rate = 0;
-        loop {
-            match decoder.next_frame() {
-                Ok(minimp3::Frame {
-                    data,
-                    sample_rate,
-                    channels,
-                    ..
-                }) => {
-                    if rate != 0 && sample_rate != rate {
-                        return Err(Error::new_box("Sample rate changed in file"));
-                    }
-                    rate = sample_rate;
-
-                    if waveforms.is_empty() {
-                        waveforms = iter::repeat(Vec::new()).take(channels).collect();
-                    }
-                    if waveforms.len() != channels {
-                        return Err(Error::new_box("Number of waveforms changed mid song"));
-                    }
+    // with the symphonia-backend feature anything the built in decoders
+    // can't read, like aac, alac, flac or a damaged file, goes through
+    // symphonia instead
+    pub fn from_file(filename: &str) -> Result<MultiChannel, Error> {
+        MultiChannel::from_file_with_progress(filename, &progress::ignore)
+    }
 
-                    for (index, sample) in data.iter().enumerate() {
-                        let sample = (*sample as f32) / (i16::MAX as f32);
-                        let channel = index % waveforms.len();
-                        waveforms[channel].push(sample);
-                    }
-                }
-                Err(minimp3::Error::Eof) => break,
-                Err(e) => return Err(Box::new(e)),
+    // progress is counted in bytes of the file read, files that fall back to
+    // symphonia don't report any
+    pub fn from_file_with_progress(
+        filename: &str,
+        progress: &dyn Progress,
+    ) -> Result<MultiChannel, Error> {
+        let builtin = MultiChannel::from_builtin(filename, progress);
+        #[cfg(feature = "symphonia-backend")]
+        {
+            if builtin.is_err() {
+                return MultiChannel::from_symphonia(filename);
             }
         }
-
-        let mut channels: Vec<Box<dyn Sample>> = Vec::new();
-        for wave in waveforms {
-            channels.push(Box::new(WaveForm::from(&wave)));
-        }
-
-        Ok(MultiChannel {
-            sample_rate: rate as u32,
-            length: channels[0].length(),
-            channels,
-        })
+        builtin
     }
 
-    pub fn from_wav(filename: &str) -> Result<MultiChannel, Box<dyn error::Error>> {
-        let mut reader = hound::WavReader::open(filename)?;
-        let length = reader.duration() as usize;
-        let sample_rate = reader.spec().sample_rate;
-        let channels = reader.spec().channels as usize;
-        let mut waveforms: Vec<Vec<f32>> = iter::repeat(Vec::new()).take(channels).collect();
-        for (index, sample) in reader.samples::<f32>().enumerate() {
-            waveforms[index % channels].push(sample?);
-        }
+    #[cfg_attr(
+        not(any(feature = "wav", feature = "mp3", feature = "ogg")),
+        allow(unused_variables)
+    )]
+    fn from_builtin(filename: &str, progress: &dyn Progress) -> Result<MultiChannel, Error> {
+        let mut header = Vec::new();
+        File::open(filename)?.take(12).read_to_end(&mut header)?;
 
-        let mut channels: Vec<Box<dyn Sample>> = Vec::new();
-        for wave in waveforms {
-            channels.push(Box::new(WaveForm::from(&wave)));
+        // check magic bytes first and fall back to the file extension
+        let extension = Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let format = Format::detect(&header).or(match extension.as_deref() {
+            Some("wav") | Some("wave") => Some(Format::Wav),
+            Some("mp3") => Some(Format::Mp3),
+            Some("ogg") | Some("oga") => Some(Format::Ogg),
+            _ => None,
+        });
+        match format {
+            #[cfg(feature = "wav")]
+            Some(Format::Wav) => MultiChannel::decode_wav(filename, progress),
+            #[cfg(feature = "mp3")]
+            Some(Format::Mp3) => MultiChannel::decode_mp3(filename, false, progress),
+            #[cfg(feature = "ogg")]
+            Some(Format::Ogg) => MultiChannel::decode_ogg(filename, progress),
+            None => Err(Error::Unsupported(format!(
+                "Unsupported audio container for {}, expected WAV, MP3 or OGG",
+                filename
+            ))),
+            // a format whose decoder feature is turned off
+            #[allow(unreachable_patterns)]
+            Some(format) => Err(Error::Unsupported(format!(
+                "{:?} decoding isn't enabled in this build, can't read {}",
+                format, filename
+            ))),
         }
-
-        Ok(MultiChannel {
-            sample_rate,
-            length,
-            channels,
-        })
     }
 
-    pub fn add_channel(&mut self, track: &dyn Sample) -> Result<(), Error> {
-        if track.channels() > 1 {
-            return Err(Error::new(
-                "Can only add single channel tracks to a multi-channel",
-            ));
-        }
-        if self.channels.len() == 0 {
-            self.sample_rate = track.sample_rate();
-            self.length = track.length();
-        } else {
-            if self.sample_rate != track.sample_rate() {
-                return Err(Error::new("Channels must have same sample rate"));
-            }
-            if self.length != track.length() {
-                return Err(Error::new("Channels must have same length"));
+    // a whole file already in memory, like one embedded with include_bytes
+    // or the body of a download. with a decoder feature turned off, such as
+    // mp3 on wasm where minimp3 can't be built, symphonia-backend decodes
+    // that format instead
+    pub fn from_bytes(bytes: &[u8], format: Format) -> Result<MultiChannel, Error> {
+        match format {
+            #[cfg(feature = "wav")]
+            Format::Wav => MultiChannel::from_wav_reader(Cursor::new(bytes)),
+            #[cfg(feature = "mp3")]
+            Format::Mp3 => MultiChannel::from_mp3_reader(bytes),
+            #[cfg(feature = "ogg")]
+            Format::Ogg => MultiChannel::from_ogg_reader(Cursor::new(bytes)),
+            #[cfg(feature = "symphonia-backend")]
+            #[allow(unreachable_patterns)]
+            format => {
+                let mut hint = symphonia::core::probe::Hint::new();
+                hint.with_extension(format.extension());
+                MultiChannel::decode_symphonia(Box::new(Cursor::new(bytes.to_vec())), &hint)
             }
+            #[cfg(not(feature = "symphonia-backend"))]
+            #[allow(unreachable_patterns)]
+            format => Err(Error::Unsupported(format!(
+                "{:?} decoding isn't enabled in this build, can't read {} bytes",
+                format,
+                bytes.len()
+            ))),
         }
-        self.channels.push(track.box_clone());
-        Ok(())
     }
-}
 
-impl Sample for MultiChannel {
-    fn sample_rate(&self) -> u32 {
-        self.sample_rate
+    #[cfg(feature = "mp3")]
+    pub fn from_mp3(filename: &str) -> Result<MultiChannel, Error> {
+        MultiChannel::decode_mp3(filename, false, &progress::ignore)
     }
 
-    fn length(&self) -> usize {
-        self.length
+    // like from_mp3 but trims the encoder delay and padding recorded in a
+    // LAME/Xing header so loops line up, files without one load as is
+    #[cfg(feature = "mp3")]
+    pub fn from_mp3_gapless(filename: &str) -> Result<MultiChannel, Error> {
+        MultiChannel::decode_mp3(filename, true, &progress::ignore)
     }
 
-    fn channels(&self) -> u16 {
-        self.channels.len() as u16
+    #[cfg(feature = "mp3")]
+    pub fn from_mp3_reader<R: Read>(reader: R) -> Result<MultiChannel, Error> {
+        let (sample_rate, waveforms) = mp3_waveforms(reader)?;
+        Ok(MultiChannel::from_waveforms(sample_rate, waveforms, None))
     }
 
-    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
-        match self.channels.get(channel as usize) {
-            Some(sample) => sample.waveform(0),
-            None => None,
-        }
+    // the file buffered and reporting how much of it has been read
+    #[cfg(any(feature = "wav", feature = "mp3", feature = "ogg"))]
+    fn open_with<'a>(
+        filename: &str,
+        progress: &'a dyn Progress,
+    ) -> Result<ProgressReader<'a, BufReader<File>>, Error> {
+        let file = File::open(filename)?;
+        let total = file.metadata()?.len();
+        Ok(ProgressReader::new(BufReader::new(file), total, progress))
     }
 
-    fn box_clone(&self) -> Box<dyn Sample> {
-        let mut channels = Vec::new();
-        for channel in &self.channels {
-            channels.push(channel.box_clone())
+    #[cfg(feature = "mp3")]
+    fn decode_mp3(
+        filename: &str,
+        gapless: bool,
+        progress: &dyn Progress,
+    ) -> Result<MultiChannel, Error> {
+        let (sample_rate, mut waveforms) =
+            mp3_waveforms(MultiChannel::open_with(filename, progress)?)?;
+        if gapless {
+            if let Some(gapless) = Mp3Gapless::read(&std::fs::read(filename)?) {
+                let decoded = waveforms.first().map_or(0, Vec::len);
+                let (start, end) = gapless.trim(decoded);
+                for wave in &mut waveforms {
+                    wave.truncate(end);
+                    wave.drain(..start);
+                }
+            }
         }
+        let metadata = Metadata::from_file(filename);
+        Ok(MultiChannel::from_waveforms(
+            sample_rate,
+            waveforms,
+            Some(metadata),
+        ))
+    }
 
-        Box::new(MultiChannel {
-            sample_rate: self.sample_rate,
-            length: self.length,
+    #[cfg(feature = "wav")]
+    pub fn from_wav(filename: &str) -> Result<MultiChannel, Error> {
+        MultiChannel::decode_wav(filename, &progress::ignore)
+    }
+
+    // the metadata chunks are read first and then the reader goes back to
+    // where it started for the audio
+    #[cfg(feature = "wav")]
+    pub fn from_wav_reader<R: Read + Seek>(mut reader: R) -> Result<MultiChannel, Error> {
+        let start = reader.stream_position()?;
+        let metadata = metadata::read_wav_metadata(&mut reader, Metadata::default())
+            .ok()
+            .filter(|m| !m.is_empty());
+        reader.seek(SeekFrom::Start(start))?;
+        let (sample_rate, waveforms) = wav_waveforms(reader)?;
+        Ok(MultiChannel::from_waveforms(
+            sample_rate,
+            waveforms,
+            metadata,
+        ))
+    }
+
+    #[cfg(feature = "wav")]
+    fn decode_wav(filename: &str, progress: &dyn Progress) -> Result<MultiChannel, Error> {
+        let (sample_rate, waveforms) = wav_waveforms(MultiChannel::open_with(filename, progress)?)?;
+        // chunks that can't be made sense of don't stop the audio loading
+        let metadata =
+            metadata::read_wav_chunks(filename).unwrap_or_else(|_| Metadata::from_file(filename));
+        Ok(MultiChannel::from_waveforms(
+            sample_rate,
+            waveforms,
+            Some(metadata),
+        ))
+    }
+
+    #[cfg(feature = "ogg")]
+    pub fn from_ogg(filename: &str) -> Result<MultiChannel, Error> {
+        MultiChannel::decode_ogg(filename, &progress::ignore)
+    }
+
+    #[cfg(feature = "ogg")]
+    pub fn from_ogg_reader<R: Read + Seek>(reader: R) -> Result<MultiChannel, Error> {
+        let (sample_rate, waveforms) = ogg_waveforms(reader)?;
+        Ok(MultiChannel::from_waveforms(sample_rate, waveforms, None))
+    }
+
+    #[cfg(feature = "ogg")]
+    fn decode_ogg(filename: &str, progress: &dyn Progress) -> Result<MultiChannel, Error> {
+        let (sample_rate, waveforms) = ogg_waveforms(MultiChannel::open_with(filename, progress)?)?;
+        let metadata = Metadata::from_file(filename);
+        Ok(MultiChannel::from_waveforms(
+            sample_rate,
+            waveforms,
+            Some(metadata),
+        ))
+    }
+
+    #[cfg(any(feature = "wav", feature = "mp3", feature = "ogg"))]
+    fn from_waveforms(
+        sample_rate: u32,
+        waveforms: Vec<Vec<f32>>,
+        metadata: Option<Metadata>,
+    ) -> MultiChannel {
+        let channels: Vec<Box<dyn Sample>> = waveforms
+            .into_iter()
+            .map(|wave| Box::new(WaveForm::from_vec(wave, sample_rate)) as Box<dyn Sample>)
+            .collect();
+        MultiChannel {
+            sample_rate,
+            length: channels.first().map_or(0, |c| c.length()),
             channels,
-        })
+            metadata,
+        }
     }
-}
 
-pub struct Composition {
-    sample_rate: u32,
-    length: usize,
-    channels: u16,
-    tracks: Vec<Box<dyn Sample>>,
-    starts: Vec<Vec<usize>>,
-}
+    // the async loaders decode on tokio's blocking pool and have to be
+    // awaited inside a tokio runtime
+    #[cfg(feature = "async")]
+    pub async fn from_file_async(filename: &str) -> Result<MultiChannel, Error> {
+        let filename = filename.to_string();
+        blocking(move || MultiChannel::from_file(&filename)).await
+    }
 
-impl Composition {
-    pub fn new() -> Composition {
-        Composition {
-            sample_rate: 0,
-            length: 0,
-            channels: 0,
-            tracks: Vec::new(),
-            starts: Vec::new(),
+    #[cfg(all(feature = "async", feature = "mp3"))]
+    pub async fn from_mp3_async(filename: &str) -> Result<MultiChannel, Error> {
+        let filename = filename.to_string();
+        blocking(move || MultiChannel::from_mp3(&filename)).await
+    }
+
+    #[cfg(all(feature = "async", feature = "wav"))]
+    pub async fn from_wav_async(filename: &str) -> Result<MultiChannel, Error> {
+        let filename = filename.to_string();
+        blocking(move || MultiChannel::from_wav(&filename)).await
+    }
+
+    #[cfg(all(feature = "async", feature = "ogg"))]
+    pub async fn from_ogg_async(filename: &str) -> Result<MultiChannel, Error> {
+        let filename = filename.to_string();
+        blocking(move || MultiChannel::from_ogg(&filename)).await
+    }
+
+    #[cfg(feature = "symphonia-backend")]
+    pub fn from_symphonia(filename: &str) -> Result<MultiChannel, Error> {
+        let mut hint = symphonia::core::probe::Hint::new();
+        if let Some(extension) = Path::new(filename).extension().and_then(|e| e.to_str()) {
+            hint.with_extension(extension);
         }
+        let mut channels = MultiChannel::decode_symphonia(Box::new(File::open(filename)?), &hint)?;
+        channels.metadata = Some(Metadata::from_file(filename));
+        Ok(channels)
     }
 
-    pub fn add_track(&mut self, track: &dyn Sample, start: usize) -> Result<usize, Error> {
-        if self.tracks.is_empty() {
+    #[cfg(feature = "symphonia-backend")]
+    fn decode_symphonia(
+        source: Box<dyn symphonia::core::io::MediaSource>,
+        hint: &symphonia::core::probe::Hint,
+    ) -> Result<MultiChannel, Error> {
+        use symphonia::core::audio::SampleBuffer;
+        use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+        use symphonia::core::errors::Error as DecodeError;
+        use symphonia::core::io::MediaSourceStream;
+
+        let source = MediaSourceStream::new(source, Default::default());
+        let mut format = symphonia::default::get_probe()
+            .format(hint, source, &Default::default(), &Default::default())?
+            .format;
+        let track = format
+            .tracks()
+            .iter()
+            .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or(Error::Decode("No audio track in file".to_string()))?;
+        let track_id = track.id;
+        let mut sample_rate = track.codec_params.sample_rate.unwrap_or(RATE);
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&track.codec_params, &DecoderOptions::default())?;
+
+        let mut waveforms: Vec<Vec<f32>> = Vec::new();
+        let mut buffer: Option<SampleBuffer<f32>> = None;
+        loop {
+            let packet = match format.next_packet() {
+                Ok(packet) => packet,
+                Err(DecodeError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    break
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if packet.track_id() != track_id {
+                continue;
+            }
+            let decoded = match decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // a damaged packet is dropped instead of failing the file
+                Err(DecodeError::DecodeError(_)) => continue,
+                Err(e) => return Err(e.into()),
+            };
+
+            let spec = *decoded.spec();
+            let channels = spec.channels.count();
+            if waveforms.is_empty() {
+                waveforms = iter::repeat_n(Vec::new(), channels).collect();
+                sample_rate = spec.rate;
+            }
+            if waveforms.len() != channels {
+                return Err(Error::Decode(
+                    "Number of waveforms changed mid song".to_string(),
+                ));
+            }
+            let needed = decoded.capacity() * channels;
+            if buffer.as_ref().is_none_or(|b| b.capacity() < needed) {
+                buffer = Some(SampleBuffer::new(decoded.capacity() as u64, spec));
+            }
+            let buffer = buffer.as_mut().unwrap();
+            buffer.copy_interleaved_ref(decoded);
+            for (index, value) in buffer.samples().iter().enumerate() {
+                waveforms[index % channels].push(*value);
+            }
+        }
+
+        let mut channels = MultiChannel::new();
+        for waveform in waveforms {
+            channels.add_channel(&WaveForm::from_vec(waveform, sample_rate))?;
+        }
+        Ok(channels)
+    }
+
+    pub fn add_channel(&mut self, track: &dyn Sample) -> Result<(), Error> {
+        if track.channels() > 1 {
+            return Err(Error::ChannelMismatch {
+                expected: 1,
+                found: track.channels(),
+            });
+        }
+        if self.channels.is_empty() {
             self.sample_rate = track.sample_rate();
-            self.length = track.length() + start;
-            self.channels = track.channels();
+            self.length = track.length();
         } else {
             if self.sample_rate != track.sample_rate() {
-                return Err(Error::new(
-                    "Tracks of the same composition must have the same sample rate",
-                ));
-            }
-            if self.channels != track.channels() {
-                return Err(Error::new(
-                    "Tracks of the same composition must have the same number of channels",
-                ));
+                return Err(Error::SampleRateMismatch {
+                    expected: self.sample_rate,
+                    found: track.sample_rate(),
+                });
             }
-            if track.length() + start > self.length {
-                self.length = track.length() + start;
+            if self.length != track.length() {
+                return Err(Error::LengthMismatch {
+                    expected: self.length,
+                    found: track.length(),
+                });
             }
         }
-        let id = self.tracks.len();
-        self.tracks.push(track.box_clone());
-        self.starts.push(vec![start]);
-        Ok(id)
+        self.channels.push(track.box_clone());
+        Ok(())
     }
 
-    pub fn add_track_sec(&mut self, track: &dyn Sample, start: f32) -> Result<usize, Error> {
-        let start = (start * (self.sample_rate() as f32)) as usize;
-        self.add_track(track, start)
+    pub fn split(&self) -> Vec<Box<dyn Sample>> {
+        self.channels.iter().map(|c| c.box_clone()).collect()
     }
 
-    pub fn add_track_id(&mut self, id: usize, start: usize) -> Result<(), Error> {
-        if id >= self.tracks.len() {
-            return Err(Error::new("That track does not exist"));
-        }
-        if self.tracks[id].length() + start > self.length {
-            self.length = self.tracks[id].length() + start;
+    pub fn swap_channels(&mut self, a: u16, b: u16) -> Result<(), Error> {
+        for channel in [a, b].iter() {
+            if *channel as usize >= self.channels.len() {
+                return Err(Error::OutOfRange {
+                    what: "channel",
+                    index: *channel as usize,
+                    len: self.channels.len(),
+                });
+            }
         }
-        self.starts[id].push(start);
+        self.channels.swap(a as usize, b as usize);
         Ok(())
     }
+}
 
-    pub fn add_track_id_sec(&mut self, id: usize, start: f32) -> Result<(), Error> {
-        let start = (start * (self.sample_rate() as f32)) as usize;
-        self.add_track_id(id, start)
+impl Default for MultiChannel {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-impl Sample for Composition {
+impl Sample for MultiChannel {
     fn sample_rate(&self) -> u32 {
         self.sample_rate
     }
@@ -414,161 +1454,3705 @@ impl Sample for Composition {
     }
 
     fn channels(&self) -> u16 {
-        self.channels
+        self.channels.len() as u16
     }
 
     fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
-        if channel >= self.channels {
-            return None;
+        match self.channels.get(channel as usize) {
+            Some(sample) => sample.waveform(0),
+            None => None,
         }
+    }
 
-        let mut waveform: Vec<f32> = iter::repeat(0.0).take(self.length).collect();
-        for (id, track) in self.tracks.iter().enumerate() {
-            let track = track.waveform(channel).unwrap();
-            for start in &self.starts[id] {
-                for (t, val) in track.iter().enumerate() {
-                    waveform[t + start] += val;
-                }
-            }
+    fn waveform_ref(&self, channel: u16) -> Option<Cow<'_, [f32]>> {
+        match self.channels.get(channel as usize) {
+            Some(sample) => sample.waveform_ref(0),
+            None => None,
+        }
+    }
+
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        match self.channels.get(channel as usize) {
+            Some(sample) => sample.fill(0, offset, buffer),
+            None => None,
         }
-        Some(waveform)
     }
 
     fn box_clone(&self) -> Box<dyn Sample> {
-        let mut tracks = Vec::new();
-        for track in &self.tracks {
-            tracks.push(track.box_clone())
+        let mut channels = Vec::new();
+        for channel in &self.channels {
+            channels.push(channel.box_clone())
         }
 
-        Box::new(Composition {
+        Box::new(MultiChannel {
             sample_rate: self.sample_rate,
             length: self.length,
-            channels: self.channels,
-            tracks,
-            starts: self.starts.clone(),
+            channels,
+            metadata: self.metadata.clone(),
         })
     }
+
+    fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    fn set_metadata(&mut self, metadata: Metadata) -> Result<(), Error> {
+        self.metadata = Some(metadata);
+        Ok(())
+    }
 }
 
-pub struct Loop {
-    pub times: u16,
-    pub sample: Box<dyn Sample>,
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TrackOptions {
+    pub gain: f32,
+    // -1.0 is hard left and 1.0 is hard right, only used for stereo
+    pub pan: f32,
+    #[serde(default)]
+    pub mute: bool,
+    // when any track is soloed only the soloed ones are heard
+    #[serde(default)]
+    pub solo: bool,
 }
 
-impl Loop {
-    pub fn from(sample: &dyn Sample, times: u16) -> Loop {
-        Loop {
-            times,
-            sample: sample.box_clone(),
+impl TrackOptions {
+    pub fn new(gain: f32, pan: f32) -> Self {
+        TrackOptions {
+            gain,
+            pan,
+            ..TrackOptions::default()
         }
     }
-}
 
-impl Sample for Loop {
-    fn sample_rate(&self) -> u32 {
-        self.sample.sample_rate()
+    fn channel_gain(&self, channel: u16, channels: u16) -> f32 {
+        if channels != 2 {
+            return self.gain;
+        }
+        let pan = self.pan.clamp(-1.0, 1.0);
+        match channel {
+            0 => self.gain * (1.0 - pan).min(1.0),
+            _ => self.gain * (1.0 + pan).min(1.0),
+        }
     }
-    fn length(&self) -> usize {
-        self.sample.length() * self.times as usize
+}
+
+impl Default for TrackOptions {
+    fn default() -> Self {
+        TrackOptions {
+            gain: 1.0,
+            pan: 0.0,
+            mute: false,
+            solo: false,
+        }
     }
-    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
-        match self.sample.waveform(channel) {
-            Some(wave) => {
-                let mut waveform = Vec::new();
-                for _ in 0..self.times {
-                    waveform.extend_from_slice(&wave);
-                }
-                Some(waveform)
-            },
-            None => None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sidechain {
+    pub source: usize,
+    pub target: usize,
+    pub amount: f32,
+    pub attack: f32,
+    pub release: f32,
+}
+
+pub struct Composition {
+    sample_rate: u32,
+    pub(crate) length: usize,
+    // the length asked for with extend_to, kept apart so removing tracks
+    // doesn't cut off the padding
+    padding: usize,
+    channels: u16,
+    pub(crate) tracks: Vec<Box<dyn Sample>>,
+    pub(crate) names: Vec<Option<String>>,
+    pub(crate) starts: Vec<Vec<usize>>,
+    pub(crate) options: Vec<TrackOptions>,
+    // (step, gain) points sorted by step, empty for tracks left alone
+    pub(crate) automation: Vec<Vec<(usize, f32)>>,
+    pub(crate) sidechains: Vec<Sidechain>,
+    pub(crate) auto_resample: bool,
+    tempo: Tempo,
+    metadata: Option<Metadata>,
+    // every channel mixed down, dropped whenever the arrangement changes
+    rendered: Mutex<Option<Vec<Arc<[f32]>>>>,
+    // the envelope each sidechain follows over the whole composition, by
+    // sidechain index and dropped along with the render
+    envelopes: Mutex<HashMap<usize, Arc<[f32]>>>,
+}
+
+impl Composition {
+    pub fn new() -> Composition {
+        Composition {
+            sample_rate: 0,
+            length: 0,
+            padding: 0,
+            channels: 0,
+            tracks: Vec::new(),
+            names: Vec::new(),
+            starts: Vec::new(),
+            options: Vec::new(),
+            automation: Vec::new(),
+            sidechains: Vec::new(),
+            auto_resample: false,
+            tempo: Tempo::default(),
+            metadata: None,
+            rendered: Mutex::new(None),
+            envelopes: Mutex::new(HashMap::new()),
         }
     }
-    fn channels(&self) -> u16 {
-        self.sample.channels()
+
+    pub fn builder() -> CompositionBuilder {
+        CompositionBuilder::new()
     }
-    fn box_clone(&self) -> Box<dyn Sample> {
-        Box::new(Loop {
-            times: self.times,
-            sample: self.sample.box_clone(),
-        })
+
+    // the whole arrangement as plain channels, mixed once and kept until the
+    // composition changes so exporting or playing it again is only a copy
+    pub fn render(&self) -> MultiChannel {
+        // only a cancelled render comes back empty
+        self.render_from(None, None).unwrap_or_default()
     }
-}
 
+    // progress is counted in steps mixed over all the channels, rendering
+    // this way mixes block by block on one thread
+    pub fn render_with_progress(&self, progress: &dyn Progress) -> MultiChannel {
+        self.render_from(Some(progress), None).unwrap_or_default()
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    // stops between blocks once cancel is set, nothing is cached then
+    pub fn render_cancellable(&self, cancel: &CancelToken) -> Result<MultiChannel, Error> {
+        self.render_from(None, Some(cancel)).ok_or(Error::Cancelled)
+    }
 
-    #[test]
-    fn sine_440_to_wav() -> Result<(), Box<dyn error::Error>> {
-        let wave = SineWave::new(440.0, (RATE * 5) as usize, 0.5);
-        wave.export("./test_files/output/sine.wav")?;
-        Ok(())
+    fn render_from(
+        &self,
+        progress: Option<&dyn Progress>,
+        cancel: Option<&CancelToken>,
+    ) -> Option<MultiChannel> {
+        let channels = self
+            .rendered_channels(progress, cancel)?
+            .into_iter()
+            .map(|waveform| {
+                Box::new(WaveForm {
+                    sample_rate: self.sample_rate,
+                    waveform,
+                }) as Box<dyn Sample>
+            })
+            .collect();
+        Some(MultiChannel {
+            sample_rate: self.sample_rate,
+            length: self.length,
+            channels,
+            metadata: self.metadata.clone(),
+        })
     }
 
-    #[test]
-    fn left_sine() -> Result<(), Box<dyn error::Error>> {
-        let wave = SineWave::new(440.0, (RATE * 5) as usize, 0.5);
-        let silence = SineWave::new(440.0, (RATE * 5) as usize, 0.0);
-        let left = MultiChannel::new_dual(&wave, &silence)?;
-        left.export("./test_files/output/left_sine.wav")?;
-        Ok(())
+    // None when cancelled part way through
+    fn rendered_channels(
+        &self,
+        progress: Option<&dyn Progress>,
+        cancel: Option<&CancelToken>,
+    ) -> Option<Vec<Arc<[f32]>>> {
+        let mut rendered = self.rendered.lock().unwrap_or_else(|e| e.into_inner());
+        let total = self.channels as u64 * self.length as u64;
+        if let Some(channels) = rendered.as_ref() {
+            if let Some(progress) = progress {
+                progress.update(total, total);
+            }
+            return Some(channels.clone());
+        }
+        let mut channels: Vec<Arc<[f32]>> = Vec::new();
+        for channel in 0..self.channels {
+            let waveform = match (progress, cancel) {
+                (None, None) => self.mix_channel(channel)?,
+                (progress, cancel) => {
+                    let progress = progress.unwrap_or(&progress::ignore);
+                    self.mix_channel_with(channel, progress, cancel)?
+                }
+            };
+            channels.push(waveform.into());
+        }
+        *rendered = Some(channels.clone());
+        Some(channels)
     }
 
-    #[test]
-    fn right_sine() -> Result<(), Box<dyn error::Error>> {
-        let wave = SineWave::new(440.0, (RATE * 5) as usize, 0.5);
-        let silence = SineWave::new(440.0, (RATE * 5) as usize, 0.0);
-        let right = MultiChannel::new_dual(&silence, &wave)?;
-        right.export("./test_files/output/right_sine.wav")?;
-        Ok(())
+    fn cached(&self, channel: u16) -> Option<Arc<[f32]>> {
+        let rendered = self.rendered.lock().unwrap_or_else(|e| e.into_inner());
+        rendered.as_ref()?.get(channel as usize).cloned()
     }
 
-    #[test]
-    fn switch_lr_sine() -> Result<(), Box<dyn error::Error>> {
-        let wave = SineWave::new(440.0, (RATE * 1) as usize, 0.5);
-        let silence = SineWave::new(440.0, (RATE * 1) as usize, 0.0);
-        let left = MultiChannel::new_dual(&wave, &silence)?;
-        let right = MultiChannel::new_dual(&silence, &wave)?;
-        let mut comp = Composition::new();
-        let left = comp.add_track(&left, 0)?;
-        let right = comp.add_track_sec(&right, 1.0)?;
-        comp.add_track_id_sec(left, 2.0)?;
-        comp.add_track_id_sec(right, 3.0)?;
-        comp.add_track_id_sec(left, 4.0)?;
+    fn missing_track(&self, id: usize) -> Error {
+        Error::OutOfRange {
+            what: "track",
+            index: id,
+            len: self.tracks.len(),
+        }
+    }
 
-        comp.export("./test_files/output/switch_lr_sine.wav")?;
-        Ok(())
+    fn invalidate(&mut self) {
+        *self.rendered.get_mut().unwrap_or_else(|e| e.into_inner()) = None;
+        self.envelopes
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
     }
 
-    #[test]
-    fn from_mp3() -> Result<(), Box<dyn error::Error>> {
-        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
-        song.export("./test_files/output/from_mp3.wav")?;
-        Ok(())
+    // convert tracks with a different sample rate instead of rejecting them
+    pub fn set_auto_resample(&mut self, auto_resample: bool) {
+        self.auto_resample = auto_resample;
     }
 
-    #[test]
-    fn from_wav() -> Result<(), Box<dyn error::Error>> {
-        let song = MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
-        song.export("./test_files/output/from_wav.wav")?;
-        Ok(())
+    pub fn add_track(&mut self, track: &dyn Sample, start: usize) -> Result<usize, Error> {
+        self.add_track_with(track, start, TrackOptions::default())
     }
 
-    #[test]
-    fn pick_sample() -> Result<(), Box<dyn error::Error>> {
-        let song =
-            MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?.sample_sec(10.8, 15.9);
-        song.export("./test_files/output/sample.wav")?;
-        Ok(())
+    pub fn add_track_with(
+        &mut self,
+        track: &dyn Sample,
+        start: usize,
+        options: TrackOptions,
+    ) -> Result<usize, Error> {
+        let resampled;
+        let mut track = track;
+        if self.auto_resample && !self.tracks.is_empty() && track.sample_rate() != self.sample_rate
+        {
+            let mut channels = MultiChannel::new();
+            for waveform in
+                resample::resample(track, self.sample_rate, resample::DEFAULT_INTERPOLATION)
+                    .ok_or(Error::MissingChannel(0))?
+            {
+                channels.add_channel(&WaveForm {
+                    sample_rate: self.sample_rate,
+                    waveform: waveform.into(),
+                })?;
+            }
+            resampled = channels;
+            track = &resampled;
+        }
+
+        if !self.tracks.is_empty() {
+            if self.sample_rate != track.sample_rate() {
+                return Err(Error::SampleRateMismatch {
+                    expected: self.sample_rate,
+                    found: track.sample_rate(),
+                });
+            }
+            if self.channels != track.channels() {
+                return Err(Error::ChannelMismatch {
+                    expected: self.channels,
+                    found: track.channels(),
+                });
+            }
+        }
+        let end = placement_end(start, track.length())?;
+        if self.tracks.is_empty() {
+            self.sample_rate = track.sample_rate();
+            self.channels = track.channels();
+        }
+        // only ever grows so padding from extend_to is kept
+        self.length = self.length.max(end);
+        let id = self.tracks.len();
+        self.tracks.push(track.box_clone());
+        self.names.push(None);
+        self.starts.push(vec![start]);
+        self.options.push(options);
+        self.automation.push(Vec::new());
+        self.invalidate();
+        Ok(id)
+    }
+
+    pub fn add_named_track(
+        &mut self,
+        name: &str,
+        track: &dyn Sample,
+        start: usize,
+    ) -> Result<usize, Error> {
+        if self.track_id(name).is_some() {
+            return Err(Error::Invalid(format!(
+                "There is already a track named {}",
+                name
+            )));
+        }
+        let id = self.add_track(track, start)?;
+        self.names[id] = Some(name.to_string());
+        Ok(id)
+    }
+
+    // names have to be unique, None takes the name away
+    pub fn set_track_name(
+        &mut self,
+        track: impl TrackKey,
+        name: Option<&str>,
+    ) -> Result<(), Error> {
+        let id = track.track_id(self)?;
+        if let Some(name) = name {
+            if self.track_id(name).is_some_and(|other| other != id) {
+                return Err(Error::Invalid(format!(
+                    "There is already a track named {}",
+                    name
+                )));
+            }
+        }
+        self.names[id] = name.map(String::from);
+        Ok(())
+    }
+
+    pub fn track_id(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n.as_deref() == Some(name))
+    }
+
+    pub fn track_name(&self, id: usize) -> Option<&str> {
+        self.names.get(id)?.as_deref()
+    }
+
+    pub fn set_track_options(
+        &mut self,
+        track: impl TrackKey,
+        options: TrackOptions,
+    ) -> Result<(), Error> {
+        let id = track.track_id(self)?;
+        self.options[id] = options;
+        self.invalidate();
+        Ok(())
+    }
+
+    pub fn set_mute(&mut self, track: impl TrackKey, mute: bool) -> Result<(), Error> {
+        let id = track.track_id(self)?;
+        self.options[id].mute = mute;
+        self.invalidate();
+        Ok(())
+    }
+
+    pub fn set_solo(&mut self, track: impl TrackKey, solo: bool) -> Result<(), Error> {
+        let id = track.track_id(self)?;
+        self.options[id].solo = solo;
+        self.invalidate();
+        Ok(())
+    }
+
+    // (time in seconds, gain) points with the gain changing linearly between
+    // them and held before the first and after the last, it multiplies the
+    // track options gain and an empty list removes the automation, the times
+    // need the sample rate of the tracks so it has to be above 0
+    pub fn automate_gain(
+        &mut self,
+        track: impl TrackKey,
+        points: &[(f32, f32)],
+    ) -> Result<(), Error> {
+        let id = track.track_id(self)?;
+        if self.sample_rate == 0 && !points.is_empty() {
+            return Err(Error::Invalid("Gain automation at a sample rate of 0".to_string()));
+        }
+        let rate = self.sample_rate as f32;
+        let automation = &mut self.automation[id];
+        *automation = points
+            .iter()
+            .map(|(time, gain)| ((time.max(0.0) * rate).round() as usize, *gain))
+            .collect();
+        automation.sort_by_key(|(step, _)| *step);
+        self.invalidate();
+        Ok(())
+    }
+
+    fn automation_gain(&self, id: usize, position: usize) -> f32 {
+        let points = &self.automation[id];
+        let next = points.partition_point(|(step, _)| *step <= position);
+        match (
+            next.checked_sub(1).map(|i| points[i]),
+            points.get(next).copied(),
+        ) {
+            (None, None) => 1.0,
+            (Some((_, gain)), None) | (None, Some((_, gain))) => gain,
+            (Some((from, a)), Some((to, b))) => {
+                let x = (position - from) as f32 / (to - from) as f32;
+                a + (b - a) * x
+            }
+        }
+    }
+
+    // for reusing a section, like a verse made as its own composition, with
+    // some changes each time it comes back. block effects like gain ramps,
+    // fades and clipping stay on the track and run as each block of it is
+    // mixed, an effect that needs whole channels like a reverb or a time
+    // stretch runs here since placing the track needs the length it leaves
+    pub fn add_track_with_effects(
+        &mut self,
+        track: &dyn Sample,
+        start: usize,
+        effects: &[&dyn Effect],
+    ) -> Result<usize, Box<dyn error::Error>> {
+        let processed = track.apply_all(effects)?;
+        Ok(self.add_track(processed.as_ref(), start)?)
+    }
+
+    pub fn add_track_sec(&mut self, track: &dyn Sample, start: f32) -> Result<usize, Error> {
+        // the first track sets the sample rate of the composition
+        let rate = if self.tracks.is_empty() {
+            track.sample_rate()
+        } else {
+            self.sample_rate
+        };
+        let start = (start * rate as f32) as usize;
+        self.add_track(track, start)
+    }
+
+    pub fn add_track_id(&mut self, track: impl TrackKey, start: usize) -> Result<(), Error> {
+        let id = track.track_id(self)?;
+        let end = placement_end(start, self.tracks[id].length())?;
+        self.length = self.length.max(end);
+        self.starts[id].push(start);
+        self.invalidate();
+        Ok(())
+    }
+
+    pub fn add_track_id_sec(&mut self, track: impl TrackKey, start: f32) -> Result<(), Error> {
+        let start = (start * (self.sample_rate() as f32)) as usize;
+        self.add_track_id(track, start)
+    }
+
+    pub fn tempo(&self) -> &Tempo {
+        &self.tempo
+    }
+
+    pub fn set_tempo(&mut self, tempo: Tempo) {
+        self.tempo = tempo;
+    }
+
+    pub fn add_track_beat(
+        &mut self,
+        track: &dyn Sample,
+        bar: u32,
+        beat: f32,
+    ) -> Result<usize, Error> {
+        // the first track sets the sample rate of the composition
+        let rate = if self.tracks.is_empty() {
+            track.sample_rate()
+        } else {
+            self.sample_rate
+        };
+        let start = self.tempo.position(bar, beat, rate);
+        self.add_track(track, start)
+    }
+
+    pub fn add_marker_beat(&mut self, name: &str, bar: u32, beat: f32) -> Result<(), Error> {
+        let position = self.tempo.position(bar, beat, self.sample_rate);
+        self.add_marker(Marker::new(name, position))
+    }
+
+    pub fn add_track_id_beat(
+        &mut self,
+        track: impl TrackKey,
+        bar: u32,
+        beat: f32,
+    ) -> Result<(), Error> {
+        let start = self.tempo.position(bar, beat, self.sample_rate);
+        self.add_track_id(track, start)
+    }
+
+    // mix one track into buffer, only rendering the part of each placement
+    // that overlaps it
+    fn render_track(
+        &self,
+        id: usize,
+        channel: u16,
+        offset: usize,
+        buffer: &mut [f32],
+    ) -> Option<()> {
+        let track = &self.tracks[id];
+        let size = buffer.len();
+        let gain = self.options[id].channel_gain(channel, self.channels);
+        let automated = !self.automation[id].is_empty();
+        let mut scratch = vec![0.0; size];
+        let mut gains = if automated {
+            vec![0.0; size]
+        } else {
+            Vec::new()
+        };
+        for start in &self.starts[id] {
+            let end = start + track.length();
+            if end <= offset || *start >= offset + size {
+                continue;
+            }
+            let from = offset.max(*start);
+            let to = (offset + size).min(end);
+            let scratch = &mut scratch[..to - from];
+            let filled = track.fill(channel, from - start, scratch)?;
+            let out = &mut buffer[from - offset..from - offset + filled];
+            if automated {
+                let gains = &mut gains[..filled];
+                for (t, value) in gains.iter_mut().enumerate() {
+                    *value = gain * self.automation_gain(id, from + t);
+                }
+                simd::mix_product(out, &scratch[..filled], gains);
+            } else {
+                simd::mix(out, &scratch[..filled], gain);
+            }
+        }
+        Some(())
+    }
+
+    // the source track's level pushes the target track down by up to amount,
+    // attack and release are how fast the ducking follows in seconds
+    pub fn sidechain(
+        &mut self,
+        source: impl TrackKey,
+        target: impl TrackKey,
+        amount: f32,
+        attack: f32,
+        release: f32,
+    ) -> Result<(), Error> {
+        let source = source.track_id(self)?;
+        let target = target.track_id(self)?;
+        if source == target {
+            return Err(Error::Invalid("A track can not duck itself".to_string()));
+        }
+        self.sidechains.push(Sidechain {
+            source,
+            target,
+            amount: amount.clamp(0.0, 1.0),
+            attack,
+            release,
+        });
+        self.invalidate();
+        Ok(())
+    }
+
+    // how far the sidechain pushes its target down at every step, the
+    // source is rendered once over all its channels and kept until the
+    // arrangement changes, channels the source can't give don't duck
+    fn envelope(&self, index: usize) -> Arc<[f32]> {
+        let mut envelopes = self.envelopes.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(envelope) = envelopes.get(&index) {
+            return envelope.clone();
+        }
+
+        let sidechain = &self.sidechains[index];
+        let rate = self.sample_rate as f32;
+        let coefficient = |time: f32| {
+            if time <= 0.0 {
+                0.0
+            } else {
+                (-1.0 / (time * rate)).exp()
+            }
+        };
+        let attack = coefficient(sidechain.attack);
+        let release = coefficient(sidechain.release);
+
+        let mut level = vec![0.0f32; self.length];
+        let mut channel_level = vec![0.0f32; self.length];
+        for channel in 0..self.channels {
+            channel_level.iter_mut().for_each(|s| *s = 0.0);
+            if self
+                .render_track(sidechain.source, channel, 0, &mut channel_level)
+                .is_none()
+            {
+                continue;
+            }
+            for (max, s) in level.iter_mut().zip(&channel_level) {
+                *max = max.max(s.abs());
+            }
+        }
+        let mut envelope = 0.0;
+        for value in level.iter_mut() {
+            let coef = if *value > envelope { attack } else { release };
+            envelope = coef * envelope + (1.0 - coef) * *value;
+            *value = 1.0 - sidechain.amount * envelope.min(1.0);
+        }
+
+        let envelope: Arc<[f32]> = level.into();
+        envelopes.insert(index, envelope.clone());
+        envelope
+    }
+
+    // gain for every step of the block or None when nothing ducks the track
+    fn ducking(&self, target: usize, offset: usize, size: usize) -> Option<Vec<f32>> {
+        let mut gains: Option<Vec<f32>> = None;
+        for (index, _) in self
+            .sidechains
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.target == target)
+        {
+            let envelope = self.envelope(index);
+            let gains = gains.get_or_insert_with(|| vec![1.0; size]);
+            for (gain, duck) in gains.iter_mut().zip(envelope.iter().skip(offset)) {
+                *gain *= duck;
+            }
+        }
+        gains
+    }
+
+    // pad the end with silence, never shortens the composition
+    pub fn extend_to(&mut self, length: usize) {
+        self.padding = self.padding.max(length);
+        if length > self.length {
+            self.length = length;
+            self.invalidate();
+        }
+    }
+
+    // the end of the last placement or the padding, whichever is later
+    fn update_length(&mut self) {
+        let ends = self
+            .tracks
+            .iter()
+            .zip(&self.starts)
+            .flat_map(|(track, starts)| starts.iter().map(move |start| start + track.length()));
+        self.length = ends.fold(self.padding, usize::max);
+    }
+
+    // later tracks move down to fill the gap so their ids go down by one,
+    // sidechains to or from the track are dropped
+    pub fn remove_track(&mut self, track: impl TrackKey) -> Result<(), Error> {
+        let id = track.track_id(self)?;
+        self.tracks.remove(id);
+        self.names.remove(id);
+        self.starts.remove(id);
+        self.options.remove(id);
+        self.automation.remove(id);
+        self.sidechains
+            .retain(|sidechain| sidechain.source != id && sidechain.target != id);
+        for sidechain in &mut self.sidechains {
+            if sidechain.source > id {
+                sidechain.source -= 1;
+            }
+            if sidechain.target > id {
+                sidechain.target -= 1;
+            }
+        }
+        self.update_length();
+        self.invalidate();
+        Ok(())
+    }
+
+    // placements are counted in the order they were added, the first one
+    // being where the track was added, returns where the placement started
+    pub fn remove_placement(&mut self, track: impl TrackKey, index: usize) -> Result<usize, Error> {
+        let id = self.placement(track, index)?;
+        let start = self.starts[id].remove(index);
+        self.update_length();
+        self.invalidate();
+        Ok(start)
+    }
+
+    pub fn move_placement(
+        &mut self,
+        track: impl TrackKey,
+        index: usize,
+        start: usize,
+    ) -> Result<(), Error> {
+        let id = self.placement(track, index)?;
+        placement_end(start, self.tracks[id].length())?;
+        self.starts[id][index] = start;
+        self.update_length();
+        self.invalidate();
+        Ok(())
+    }
+
+    // drops every track and the padding, the next track added sets the
+    // sample rate and channels again
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+        self.names.clear();
+        self.starts.clear();
+        self.options.clear();
+        self.automation.clear();
+        self.sidechains.clear();
+        self.padding = 0;
+        self.length = 0;
+        self.sample_rate = 0;
+        self.channels = 0;
+        self.invalidate();
+    }
+
+    // the id of the track after checking it has that placement
+    fn placement(&self, track: impl TrackKey, index: usize) -> Result<usize, Error> {
+        let id = track.track_id(self)?;
+        let placements = self.starts[id].len();
+        if index >= placements {
+            return Err(Error::OutOfRange {
+                what: "placement",
+                index,
+                len: placements,
+            });
+        }
+        Ok(id)
+    }
+
+    // add one track to buffer with any sidechain ducking applied, the
+    // track is rendered into scratch first so one that can't give the
+    // channel is left out of the mix without touching buffer
+    fn mix_track(
+        &self,
+        id: usize,
+        channel: u16,
+        offset: usize,
+        buffer: &mut [f32],
+        scratch: &mut [f32],
+    ) -> Option<()> {
+        let scratch = &mut scratch[..buffer.len()];
+        scratch.iter_mut().for_each(|s| *s = 0.0);
+        self.render_track(id, channel, offset, scratch)?;
+        match self.ducking(id, offset, buffer.len()) {
+            None => simd::mix(buffer, scratch, 1.0),
+            Some(ducking) => simd::mix_product(buffer, scratch, &ducking),
+        }
+        Some(())
+    }
+
+    // the tracks left after mute and solo, a muted track still ducks the
+    // tracks it is a sidechain source for
+    fn audible(&self) -> Vec<usize> {
+        let soloed = self.options.iter().any(|options| options.solo);
+        (0..self.tracks.len())
+            .filter(|id| {
+                let options = &self.options[*id];
+                !options.mute && (options.solo || !soloed)
+            })
+            .collect()
+    }
+
+    // None only when the composition doesn't have the channel, a track
+    // that can't give it is skipped and the others still play
+    fn mix_block(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<()> {
+        if channel >= self.channels {
+            return None;
+        }
+        buffer.iter_mut().for_each(|s| *s = 0.0);
+        let mut scratch = vec![0.0; buffer.len()];
+        for id in self.audible() {
+            let _ = self.mix_track(id, channel, offset, buffer, &mut scratch);
+        }
+        Some(())
+    }
+
+    fn mix_channel(&self, channel: u16) -> Option<Vec<f32>> {
+        // the whole channel at once is big enough to be worth splitting
+        // the tracks between threads
+        #[cfg(feature = "parallel")]
+        return self.render_channel_parallel(channel);
+
+        #[cfg(not(feature = "parallel"))]
+        {
+            let mut waveform: Vec<f32> = iter::repeat_n(0.0, self.length).collect();
+            self.mix_block(channel, 0, &mut waveform)?;
+            Some(waveform)
+        }
+    }
+
+    // None when the mix was cancelled or the channel is missing
+    fn mix_channel_with(
+        &self,
+        channel: u16,
+        progress: &dyn Progress,
+        cancel: Option<&CancelToken>,
+    ) -> Option<Vec<f32>> {
+        let total = self.channels as u64 * self.length as u64;
+        let done = channel as u64 * self.length as u64;
+        let mut waveform = vec![0.0; self.length];
+        for (index, block) in waveform.chunks_mut(BLOCK_SIZE).enumerate() {
+            if cancel.is_some_and(CancelToken::is_cancelled) {
+                return None;
+            }
+            progress.update(done + (index * BLOCK_SIZE) as u64, total);
+            self.mix_block(channel, index * BLOCK_SIZE, block)?;
+        }
+        progress.update(done + self.length as u64, total);
+        Some(waveform)
+    }
+
+    // every channel rendered at once with the channels and the tracks
+    // within them spread over the rayon thread pool
+    #[cfg(feature = "parallel")]
+    pub fn render_parallel(&self) -> Option<Vec<Vec<f32>>> {
+        (0..self.channels)
+            .into_par_iter()
+            .map(|channel| self.render_channel_parallel(channel))
+            .collect()
+    }
+
+    #[cfg(feature = "parallel")]
+    fn render_channel_parallel(&self, channel: u16) -> Option<Vec<f32>> {
+        if channel >= self.channels {
+            return None;
+        }
+        // each thread mixes its share of the tracks into one buffer and the
+        // buffers are summed at the end
+        let (mix, _) = self
+            .audible()
+            .into_par_iter()
+            .fold(
+                || (vec![0.0; self.length], vec![0.0; self.length]),
+                |(mut mix, mut scratch), id| {
+                    let _ = self.mix_track(id, channel, 0, &mut mix, &mut scratch);
+                    (mix, scratch)
+                },
+            )
+            .reduce(
+                || (vec![0.0; self.length], Vec::new()),
+                |(mut mix, scratch), (track, _)| {
+                    simd::mix(&mut mix, &track, 1.0);
+                    (mix, scratch)
+                },
+            );
+        Some(mix)
+    }
+}
+
+// a track given by the id add_track returned or by the name it was added
+// under with add_named_track
+pub trait TrackKey {
+    fn track_id(&self, comp: &Composition) -> Result<usize, Error>;
+}
+
+impl TrackKey for usize {
+    fn track_id(&self, comp: &Composition) -> Result<usize, Error> {
+        if *self < comp.tracks.len() {
+            Ok(*self)
+        } else {
+            Err(comp.missing_track(*self))
+        }
+    }
+}
+
+impl TrackKey for &str {
+    fn track_id(&self, comp: &Composition) -> Result<usize, Error> {
+        comp.track_id(self)
+            .ok_or_else(|| Error::UnknownTrack(self.to_string()))
+    }
+}
+
+impl TrackKey for &String {
+    fn track_id(&self, comp: &Composition) -> Result<usize, Error> {
+        self.as_str().track_id(comp)
+    }
+}
+
+// where a placement ends, erroring instead of wrapping for starts so far out
+// the end can't be counted
+fn placement_end(start: usize, length: usize) -> Result<usize, Error> {
+    start.checked_add(length).ok_or_else(|| {
+        Error::Invalid(format!(
+            "A track starting at step {} ends past the longest possible composition",
+            start
+        ))
+    })
+}
+
+impl Default for Composition {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sample for Composition {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn length(&self) -> usize {
+        self.length
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    // asking for a whole channel renders all of them, exports read every
+    // channel in turn and only the first one mixes
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        if channel >= self.channels {
+            return None;
+        }
+        Some(self.rendered_channels(None, None)?[channel as usize].to_vec())
+    }
+
+    // blocks come from the render when there is one, otherwise only the
+    // block is mixed so playback can start straight away
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        if channel >= self.channels {
+            return None;
+        }
+
+        let size = buffer.len().min(self.length.saturating_sub(offset));
+        let buffer = &mut buffer[..size];
+        if let Some(rendered) = self.cached(channel) {
+            if let Some(part) = rendered.get(offset..offset + size) {
+                buffer.copy_from_slice(part);
+                return Some(size);
+            }
+        }
+        self.mix_block(channel, offset, buffer)?;
+        Some(size)
+    }
+
+    fn box_clone(&self) -> Box<dyn Sample> {
+        let mut tracks = Vec::new();
+        for track in &self.tracks {
+            tracks.push(track.box_clone())
+        }
+
+        Box::new(Composition {
+            sample_rate: self.sample_rate,
+            length: self.length,
+            padding: self.padding,
+            channels: self.channels,
+            tracks,
+            names: self.names.clone(),
+            starts: self.starts.clone(),
+            options: self.options.clone(),
+            automation: self.automation.clone(),
+            sidechains: self.sidechains.clone(),
+            auto_resample: self.auto_resample,
+            tempo: self.tempo.clone(),
+            metadata: self.metadata.clone(),
+            rendered: Mutex::new(self.rendered.lock().ok().and_then(|r| r.clone())),
+            envelopes: Mutex::new(self.envelopes.lock().map(|e| e.clone()).unwrap_or_default()),
+        })
+    }
+
+    fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    fn set_metadata(&mut self, metadata: Metadata) -> Result<(), Error> {
+        self.metadata = Some(metadata);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Start {
+    Step(usize),
+    Seconds(f32),
+    Beat(u32, f32),
+}
+
+struct BuilderTrack {
+    sample: Box<dyn Sample>,
+    name: Option<String>,
+    starts: Vec<Start>,
+    options: TrackOptions,
+}
+
+// an arrangement written out track by track, the placement and options
+// calls apply to the last track added and any mistake comes back from build
+//
+//     let comp = Composition::builder()
+//         .track(&kick).at_sec(0.0).at_sec(1.0)
+//         .track(&pad).at_sec(1.0).gain(0.8).pan(-0.3)
+//         .build()?;
+pub struct CompositionBuilder {
+    tracks: Vec<BuilderTrack>,
+    tempo: Tempo,
+    auto_resample: bool,
+    error: Option<&'static str>,
+}
+
+impl CompositionBuilder {
+    pub fn new() -> Self {
+        CompositionBuilder {
+            tracks: Vec::new(),
+            tempo: Tempo::default(),
+            auto_resample: false,
+            error: None,
+        }
+    }
+
+    pub fn tempo(mut self, tempo: Tempo) -> Self {
+        self.tempo = tempo;
+        self
+    }
+
+    pub fn auto_resample(mut self, auto_resample: bool) -> Self {
+        self.auto_resample = auto_resample;
+        self
+    }
+
+    // a track with no placement starts at the beginning
+    pub fn track(mut self, sample: &dyn Sample) -> Self {
+        self.tracks.push(BuilderTrack {
+            sample: sample.box_clone(),
+            name: None,
+            starts: Vec::new(),
+            options: TrackOptions::default(),
+        });
+        self
+    }
+
+    pub fn at(self, start: usize) -> Self {
+        self.place(Start::Step(start))
+    }
+
+    pub fn at_sec(self, start: f32) -> Self {
+        self.place(Start::Seconds(start))
+    }
+
+    pub fn at_beat(self, bar: u32, beat: f32) -> Self {
+        self.place(Start::Beat(bar, beat))
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        match self.tracks.last_mut() {
+            Some(track) => track.name = Some(name.to_string()),
+            None => self.fail("Named a track before adding one"),
+        }
+        self
+    }
+
+    pub fn gain(mut self, gain: f32) -> Self {
+        match self.tracks.last_mut() {
+            Some(track) => track.options.gain = gain,
+            None => self.fail("Set the gain before adding a track"),
+        }
+        self
+    }
+
+    pub fn pan(mut self, pan: f32) -> Self {
+        match self.tracks.last_mut() {
+            Some(track) => track.options.pan = pan,
+            None => self.fail("Set the pan before adding a track"),
+        }
+        self
+    }
+
+    fn place(mut self, start: Start) -> Self {
+        match self.tracks.last_mut() {
+            Some(track) => track.starts.push(start),
+            None => self.fail("Placed a track before adding one"),
+        }
+        self
+    }
+
+    // only the first mistake is kept
+    fn fail(&mut self, message: &'static str) {
+        self.error.get_or_insert(message);
+    }
+
+    pub fn build(self) -> Result<Composition, Error> {
+        if let Some(message) = self.error {
+            return Err(Error::Invalid(message.to_string()));
+        }
+        let mut comp = Composition::new();
+        comp.set_tempo(self.tempo);
+        comp.set_auto_resample(self.auto_resample);
+        for track in &self.tracks {
+            // the first track sets the sample rate of the composition
+            let rate = if comp.tracks.is_empty() {
+                track.sample.sample_rate()
+            } else {
+                comp.sample_rate
+            };
+            let mut starts = track.starts.iter().map(|start| match *start {
+                Start::Step(step) => step,
+                Start::Seconds(seconds) => (seconds.max(0.0) * rate as f32) as usize,
+                Start::Beat(bar, beat) => comp.tempo.position(bar, beat, rate),
+            });
+            let first = starts.next().unwrap_or(0);
+            let rest: Vec<usize> = starts.collect();
+            let id = comp.add_track_with(track.sample.as_ref(), first, track.options)?;
+            for start in rest {
+                comp.add_track_id(id, start)?;
+            }
+            if let Some(name) = &track.name {
+                comp.set_track_name(id, Some(name))?;
+            }
+        }
+        Ok(comp)
+    }
+}
+
+impl Default for CompositionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct ScaledSample {
+    pub factor: f32,
+    pub sample: Box<dyn Sample>,
+}
+
+impl ScaledSample {
+    pub fn new(sample: Box<dyn Sample>, factor: f32) -> ScaledSample {
+        ScaledSample { factor, sample }
+    }
+
+    pub fn from(sample: &dyn Sample, factor: f32) -> ScaledSample {
+        ScaledSample::new(sample.box_clone(), factor)
+    }
+}
+
+impl Sample for ScaledSample {
+    fn sample_rate(&self) -> u32 {
+        self.sample.sample_rate()
+    }
+    fn length(&self) -> usize {
+        self.sample.length()
+    }
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        let mut wave = self.sample.waveform(channel)?;
+        simd::scale(&mut wave, self.factor);
+        Some(wave)
+    }
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        let filled = self.sample.fill(channel, offset, buffer)?;
+        simd::scale(&mut buffer[..filled], self.factor);
+        Some(filled)
+    }
+    fn channels(&self) -> u16 {
+        self.sample.channels()
+    }
+    fn metadata(&self) -> Option<&Metadata> {
+        self.sample.metadata()
+    }
+    fn set_metadata(&mut self, metadata: Metadata) -> Result<(), Error> {
+        self.sample.set_metadata(metadata)
+    }
+    fn box_clone(&self) -> Box<dyn Sample> {
+        Box::new(ScaledSample {
+            factor: self.factor,
+            sample: self.sample.box_clone(),
+        })
+    }
+}
+
+pub struct Reversed {
+    pub sample: Box<dyn Sample>,
+    // the sample's metadata with its markers and loops mirrored
+    metadata: Option<Metadata>,
+}
+
+impl Reversed {
+    pub fn new(sample: Box<dyn Sample>) -> Reversed {
+        Reversed {
+            metadata: sample.metadata().map(|m| m.reversed(sample.length())),
+            sample,
+        }
+    }
+
+    pub fn from(sample: &dyn Sample) -> Reversed {
+        Reversed::new(sample.box_clone())
+    }
+}
+
+impl Sample for Reversed {
+    fn sample_rate(&self) -> u32 {
+        self.sample.sample_rate()
+    }
+    fn length(&self) -> usize {
+        self.sample.length()
+    }
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        let mut wave = self.sample.waveform(channel)?;
+        // Vec<f32> is a Sample too so its own reverse has to be asked for
+        wave.as_mut_slice().reverse();
+        Some(wave)
+    }
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        if offset >= self.length() {
+            return (channel < self.channels()).then_some(0);
+        }
+        // the block comes from the mirrored position at the other end
+        let size = buffer.len().min(self.length().saturating_sub(offset));
+        let buffer = &mut buffer[..size];
+        let filled = self
+            .sample
+            .fill(channel, self.length() - offset - size, buffer)?;
+        buffer[filled..].iter_mut().for_each(|s| *s = 0.0);
+        buffer.reverse();
+        Some(size)
+    }
+    fn channels(&self) -> u16 {
+        self.sample.channels()
+    }
+    fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+    fn set_metadata(&mut self, metadata: Metadata) -> Result<(), Error> {
+        self.metadata = Some(metadata);
+        Ok(())
+    }
+    fn box_clone(&self) -> Box<dyn Sample> {
+        Box::new(Reversed {
+            sample: self.sample.box_clone(),
+            metadata: self.metadata.clone(),
+        })
+    }
+}
+
+pub struct Loop {
+    pub times: u16,
+    pub sample: Box<dyn Sample>,
+    // the end of each repeat fades into the start of the next over this many steps
+    pub crossfade: usize,
+    // loop as many times as needed and cut off at this length instead
+    pub length: Option<usize>,
+    // the sample's metadata for the first time through, cut off if the
+    // loop is shorter
+    metadata: Option<Metadata>,
+}
+
+impl Loop {
+    pub fn new(sample: Box<dyn Sample>, times: u16) -> Loop {
+        let mut looped = Loop {
+            times,
+            sample,
+            crossfade: 0,
+            length: None,
+            metadata: None,
+        };
+        looped.metadata = looped.first_metadata();
+        looped
+    }
+
+    pub fn from(sample: &dyn Sample, times: u16) -> Loop {
+        Loop::new(sample.box_clone(), times)
+    }
+
+    pub fn to_length(sample: &dyn Sample, length: usize) -> Loop {
+        Loop::new(sample.box_clone(), 0).with_length(length)
+    }
+
+    pub fn with_length(mut self, length: usize) -> Loop {
+        self.length = Some(length);
+        self.metadata = self.first_metadata();
+        self
+    }
+
+    pub fn with_crossfade(mut self, crossfade: usize) -> Loop {
+        self.crossfade = crossfade;
+        self
+    }
+
+    fn crossfade(&self) -> usize {
+        self.crossfade.min(self.sample.length().saturating_sub(1))
+    }
+
+    // number of steps between the start of each repeat
+    fn period(&self) -> usize {
+        self.sample.length() - self.crossfade()
+    }
+
+    fn first_metadata(&self) -> Option<Metadata> {
+        let end = self.length().min(self.sample.length());
+        self.sample.metadata().map(|m| m.slice(0, end))
+    }
+}
+
+impl Sample for Loop {
+    fn sample_rate(&self) -> u32 {
+        self.sample.sample_rate()
+    }
+    fn length(&self) -> usize {
+        match self.length {
+            Some(length) => length,
+            None if self.times == 0 || self.sample.length() == 0 => 0,
+            None => self.period() * self.times as usize + self.crossfade(),
+        }
+    }
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        let mut waveform = vec![0.0; self.length()];
+        self.fill(channel, 0, &mut waveform)?;
+        Some(waveform)
+    }
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        if channel >= self.sample.channels() {
+            return None;
+        }
+        if self.sample.length() == 0 {
+            return Some(0);
+        }
+
+        let size = buffer.len().min(self.length().saturating_sub(offset));
+        let crossfade = self.crossfade();
+        let period = self.period();
+        // whatever the sample comes up short by is silence
+        let read = |from: usize, buffer: &mut [f32]| {
+            let filled = self.sample.fill(channel, from, buffer)?;
+            buffer[filled..].iter_mut().for_each(|s| *s = 0.0);
+            Some(())
+        };
+        let mut tail = vec![0.0; crossfade.min(size)];
+        let mut written = 0;
+        while written < size {
+            let repeat = (offset + written) / period;
+            let position = (offset + written) % period;
+            let last = self.length.is_none() && repeat >= self.times as usize;
+            if position < crossfade && repeat > 0 {
+                // overlap the tail of the last repeat with the start of this one
+                let count = (crossfade - position).min(size - written);
+                let head = &mut buffer[written..written + count];
+                let tail = &mut tail[..count];
+                read(period + position, tail)?;
+                if last {
+                    head.copy_from_slice(tail);
+                } else {
+                    read(position, head)?;
+                    for (index, (value, faded)) in head.iter_mut().zip(tail.iter()).enumerate() {
+                        let t = (position + index) as f32 / crossfade as f32 * PI / 2.0;
+                        *value = *value * t.sin() + faded * t.cos();
+                    }
+                }
+                written += count;
+            } else {
+                let count = (period - position).min(size - written);
+                read(position, &mut buffer[written..written + count])?;
+                written += count;
+            }
+        }
+        Some(size)
+    }
+    fn channels(&self) -> u16 {
+        self.sample.channels()
+    }
+    fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+    fn set_metadata(&mut self, metadata: Metadata) -> Result<(), Error> {
+        self.metadata = Some(metadata);
+        Ok(())
+    }
+    fn box_clone(&self) -> Box<dyn Sample> {
+        Box::new(Loop {
+            times: self.times,
+            sample: self.sample.box_clone(),
+            crossfade: self.crossfade,
+            length: self.length,
+            metadata: self.metadata.clone(),
+        })
+    }
+}
+
+// a window into another sample that only reads the part it covers
+pub struct Slice {
+    pub start: usize,
+    pub end: usize,
+    pub sample: Box<dyn Sample>,
+    // the sample's metadata with its markers and loops moved to the slice
+    metadata: Option<Metadata>,
+}
+
+impl Slice {
+    // the bounds are clamped to the sample so the slice is never longer
+    // than what it points into
+    pub fn new(sample: Box<dyn Sample>, start: usize, end: usize) -> Slice {
+        let end = end.min(sample.length());
+        let start = start.min(end);
+        Slice {
+            start,
+            end,
+            metadata: sample.metadata().map(|m| m.slice(start, end)),
+            sample,
+        }
+    }
+
+    pub fn from(sample: &dyn Sample, start: usize, end: usize) -> Slice {
+        Slice::new(sample.box_clone(), start, end)
+    }
+}
+
+impl Sample for Slice {
+    fn sample_rate(&self) -> u32 {
+        self.sample.sample_rate()
+    }
+    fn length(&self) -> usize {
+        self.end - self.start
+    }
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        let mut waveform = vec![0.0; self.length()];
+        self.fill(channel, 0, &mut waveform)?;
+        Some(waveform)
+    }
+    fn waveform_ref(&self, channel: u16) -> Option<Cow<'_, [f32]>> {
+        Some(match self.sample.waveform_ref(channel)? {
+            Cow::Borrowed(wave) => Cow::Borrowed(&wave[self.start..self.end]),
+            Cow::Owned(wave) => Cow::Owned(wave[self.start..self.end].to_vec()),
+        })
+    }
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        let size = buffer.len().min(self.length().saturating_sub(offset));
+        self.sample
+            .fill(channel, self.start + offset, &mut buffer[..size])
+    }
+    fn channels(&self) -> u16 {
+        self.sample.channels()
+    }
+    fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+    fn set_metadata(&mut self, metadata: Metadata) -> Result<(), Error> {
+        self.metadata = Some(metadata);
+        Ok(())
+    }
+    fn box_clone(&self) -> Box<dyn Sample> {
+        Box::new(Slice {
+            start: self.start,
+            end: self.end,
+            sample: self.sample.box_clone(),
+            metadata: self.metadata.clone(),
+        })
+    }
+}
+
+// starts the sample after delay steps of silence
+pub struct Delayed {
+    pub delay: usize,
+    pub sample: Box<dyn Sample>,
+    // the sample's metadata with its markers and loops moved by the delay
+    metadata: Option<Metadata>,
+}
+
+impl Delayed {
+    pub fn new(sample: Box<dyn Sample>, delay: usize) -> Delayed {
+        Delayed {
+            delay,
+            metadata: sample.metadata().map(|m| m.shifted(delay)),
+            sample,
+        }
+    }
+
+    pub fn from(sample: &dyn Sample, delay: usize) -> Delayed {
+        Delayed::new(sample.box_clone(), delay)
+    }
+}
+
+impl Sample for Delayed {
+    fn sample_rate(&self) -> u32 {
+        self.sample.sample_rate()
+    }
+    fn length(&self) -> usize {
+        self.delay + self.sample.length()
+    }
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        let mut waveform = vec![0.0; self.length()];
+        self.fill(channel, 0, &mut waveform)?;
+        Some(waveform)
+    }
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        if channel >= self.channels() {
+            return None;
+        }
+        let size = buffer.len().min(self.length().saturating_sub(offset));
+        let silence = self.delay.saturating_sub(offset).min(size);
+        buffer[..silence].iter_mut().for_each(|s| *s = 0.0);
+        let from = (offset + silence).saturating_sub(self.delay);
+        let filled = self
+            .sample
+            .fill(channel, from, &mut buffer[silence..size])?;
+        Some(silence + filled)
+    }
+    fn channels(&self) -> u16 {
+        self.sample.channels()
+    }
+    fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+    fn set_metadata(&mut self, metadata: Metadata) -> Result<(), Error> {
+        self.metadata = Some(metadata);
+        Ok(())
+    }
+    fn box_clone(&self) -> Box<dyn Sample> {
+        Box::new(Delayed {
+            delay: self.delay,
+            sample: self.sample.box_clone(),
+            metadata: self.metadata.clone(),
+        })
+    }
+}
+
+// the sum of several samples, each with its own gain, as long as the
+// longest of them
+pub struct Mixed {
+    sample_rate: u32,
+    length: usize,
+    channels: u16,
+    samples: Vec<(Box<dyn Sample>, f32)>,
+}
+
+impl Mixed {
+    pub fn new() -> Mixed {
+        Mixed {
+            sample_rate: 0,
+            length: 0,
+            channels: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, sample: &dyn Sample, gain: f32) -> Result<(), Error> {
+        if self.samples.is_empty() {
+            self.sample_rate = sample.sample_rate();
+            self.channels = sample.channels();
+        } else if self.sample_rate != sample.sample_rate() {
+            return Err(Error::SampleRateMismatch {
+                expected: self.sample_rate,
+                found: sample.sample_rate(),
+            });
+        } else if self.channels != sample.channels() {
+            return Err(Error::ChannelMismatch {
+                expected: self.channels,
+                found: sample.channels(),
+            });
+        }
+        self.length = self.length.max(sample.length());
+        self.samples.push((sample.box_clone(), gain));
+        Ok(())
+    }
+}
+
+impl Default for Mixed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sample for Mixed {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+    fn length(&self) -> usize {
+        self.length
+    }
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        let mut waveform = vec![0.0; self.length()];
+        self.fill(channel, 0, &mut waveform)?;
+        Some(waveform)
+    }
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        if channel >= self.channels {
+            return None;
+        }
+        let size = buffer.len().min(self.length.saturating_sub(offset));
+        let buffer = &mut buffer[..size];
+        buffer.iter_mut().for_each(|s| *s = 0.0);
+        let mut scratch = vec![0.0; size];
+        for (sample, gain) in &self.samples {
+            let filled = sample.fill(channel, offset, &mut scratch)?;
+            simd::mix(buffer, &scratch[..filled], *gain);
+        }
+        Some(size)
+    }
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+    fn box_clone(&self) -> Box<dyn Sample> {
+        Box::new(Mixed {
+            sample_rate: self.sample_rate,
+            length: self.length,
+            channels: self.channels,
+            samples: self
+                .samples
+                .iter()
+                .map(|(sample, gain)| (sample.box_clone(), *gain))
+                .collect(),
+        })
+    }
+}
+
+// lets the boxes the sample methods hand back be mixed, scaled and passed
+// anywhere a sample is taken
+impl Sample for Box<dyn Sample> {
+    fn sample_rate(&self) -> u32 {
+        (**self).sample_rate()
+    }
+    fn length(&self) -> usize {
+        (**self).length()
+    }
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        (**self).waveform(channel)
+    }
+    fn waveform_ref(&self, channel: u16) -> Option<Cow<'_, [f32]>> {
+        (**self).waveform_ref(channel)
+    }
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        (**self).fill(channel, offset, buffer)
+    }
+    fn channels(&self) -> u16 {
+        (**self).channels()
+    }
+    fn metadata(&self) -> Option<&Metadata> {
+        (**self).metadata()
+    }
+    fn set_metadata(&mut self, metadata: Metadata) -> Result<(), Error> {
+        (**self).set_metadata(metadata)
+    }
+    fn box_clone(&self) -> Box<dyn Sample> {
+        (**self).box_clone()
+    }
+}
+
+// plain buffers are mono samples, slices and vecs at 44100 and a tuple at the
+// rate given first, clones are copied into a WaveForm. Sample methods win
+// over the ones a Vec gets from its slice, so vec.reverse() is the Sample
+// one and must_use flags it when the result is dropped
+trait Buffer {
+    fn parts(&self) -> (u32, &[f32]);
+}
+
+impl Buffer for &[f32] {
+    fn parts(&self) -> (u32, &[f32]) {
+        (RATE, self)
+    }
+}
+
+impl Buffer for Vec<f32> {
+    fn parts(&self) -> (u32, &[f32]) {
+        (RATE, self)
+    }
+}
+
+impl Buffer for (u32, Vec<f32>) {
+    fn parts(&self) -> (u32, &[f32]) {
+        (self.0, &self.1)
+    }
+}
+
+macro_rules! buffer_samples {
+    ($($buffer:ty),*) => {
+        $(
+            impl Sample for $buffer {
+                fn sample_rate(&self) -> u32 {
+                    self.parts().0
+                }
+                fn length(&self) -> usize {
+                    self.parts().1.len()
+                }
+                fn channels(&self) -> u16 {
+                    1
+                }
+                fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+                    self.waveform_ref(channel).map(Cow::into_owned)
+                }
+                fn waveform_ref(&self, channel: u16) -> Option<Cow<'_, [f32]>> {
+                    (channel == 0).then(|| Cow::Borrowed(self.parts().1))
+                }
+                fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+                    if channel > 0 {
+                        return None;
+                    }
+                    let wave = self.parts().1;
+                    let start = offset.min(wave.len());
+                    let end = (offset + buffer.len()).min(wave.len());
+                    buffer[..end - start].copy_from_slice(&wave[start..end]);
+                    Some(end - start)
+                }
+                fn box_clone(&self) -> Box<dyn Sample> {
+                    let (rate, wave) = self.parts();
+                    Box::new(WaveForm::with_rate(wave, rate))
+                }
+            }
+        )*
+    };
+}
+
+buffer_samples!(&[f32], Vec<f32>, (u32, Vec<f32>));
+
+// every channel of the sample folded down to mono and copied to each of
+// channels, a mono sample is only copied
+fn spread_channels(sample: &dyn Sample, channels: u16) -> Result<MultiChannel, Error> {
+    let mono = match sample.channels() {
+        1 => sample.waveform(0).ok_or(Error::MissingChannel(0))?,
+        _ => analysis::mono(sample),
+    };
+    let mono = WaveForm::from_vec(mono, sample.sample_rate());
+    let mut spread = MultiChannel::new();
+    for _ in 0..channels {
+        spread.add_channel(&mono)?;
+    }
+    Ok(spread)
+}
+
+// a + b mixes, a * gain scales and a >> steps delays, for quick sketches
+// like kick * 0.8 + snare.delayed(0.5), samples with a different channel
+// count are folded down to mono and spread over the channels of the first,
+// samples at other rates are only resampled when the composition on the
+// left has auto resample on
+//
+// # Panics
+//
+// when the sample can't be mixed in, like one at another sample rate or
+// one that doesn't give every channel it claims to have
+impl<S: Sample> Add<S> for Composition {
+    type Output = Composition;
+    fn add(mut self, other: S) -> Composition {
+        let added = if self.tracks.is_empty() || other.channels() == self.channels {
+            self.add_track(&other, 0)
+        } else {
+            spread_channels(&other, self.channels).and_then(|spread| self.add_track(&spread, 0))
+        };
+        if let Err(error) = added {
+            panic!("Can't mix the samples: {}", error);
+        }
+        self
+    }
+}
+
+macro_rules! mix_ops {
+    ($($sample:ty),*) => {
+        $(
+            impl<S: Sample> Add<S> for $sample {
+                type Output = Composition;
+                fn add(self, other: S) -> Composition {
+                    Composition::new() + self + other
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! gain_ops {
+    ($($sample:ty),*) => {
+        $(
+            impl Mul<f32> for $sample {
+                type Output = ScaledSample;
+                fn mul(self, factor: f32) -> ScaledSample {
+                    ScaledSample::new(Box::new(self), factor)
+                }
+            }
+
+            impl Shr<usize> for $sample {
+                type Output = Delayed;
+                fn shr(self, delay: usize) -> Delayed {
+                    Delayed::new(Box::new(self), delay)
+                }
+            }
+        )*
+    };
+}
+
+// Mixed is left out of the mixing since its own add would be hidden
+// behind the operator
+mix_ops!(
+    Box<dyn Sample>,
+    SineWave,
+    WaveForm,
+    MultiChannel,
+    ScaledSample,
+    Reversed,
+    Loop,
+    Slice,
+    Delayed
+);
+gain_ops!(
+    Box<dyn Sample>,
+    SineWave,
+    WaveForm,
+    MultiChannel,
+    Composition,
+    ScaledSample,
+    Reversed,
+    Loop,
+    Slice,
+    Delayed,
+    Mixed
+);
+
+#[cfg(feature = "async")]
+async fn blocking<T, F>(work: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| Error::Other(e.to_string()))?
+}
+
+// boxed errors can't cross threads so they're turned back into an Error,
+// keeping the kind when there is one
+#[cfg(all(feature = "async", feature = "wav"))]
+fn into_error(error: Box<dyn error::Error>) -> Error {
+    let error = match error.downcast::<Error>() {
+        Ok(error) => return *error,
+        Err(error) => error,
+    };
+    let error = match error.downcast::<hound::Error>() {
+        Ok(error) => return Error::from(*error),
+        Err(error) => error,
+    };
+    match error.downcast::<std::io::Error>() {
+        Ok(error) => Error::Io(*error),
+        Err(error) => Error::Other(error.to_string()),
+    }
+}
+
+// the file export paths of Sample
+#[cfg(feature = "wav")]
+fn export_file<S: Sample + ?Sized>(
+    sample: &S,
+    file: &str,
+    options: &ExportOptions,
+    progress: &dyn Progress,
+    cancel: Option<&CancelToken>,
+) -> Result<(), Box<dyn error::Error>> {
+    let result = write_wav(
+        sample,
+        &mut BufWriter::new(File::create(file)?),
+        options,
+        progress,
+        cancel,
+    );
+    if result.is_err() && cancel.is_some_and(CancelToken::is_cancelled) {
+        // a cut short file would look like a finished one
+        std::fs::remove_file(file)?;
+    }
+    result
+}
+
+// the WAV has to start at the beginning of the writer since its header is
+// patched at fixed offsets once the length is known, cancel is checked
+// between blocks
+#[cfg(feature = "wav")]
+fn write_wav<S: Sample + ?Sized, W: Write + Seek + ?Sized>(
+    sample: &S,
+    output: &mut W,
+    options: &ExportOptions,
+    progress: &dyn Progress,
+    cancel: Option<&CancelToken>,
+) -> Result<(), Box<dyn error::Error>> {
+    let gain = if options.normalize {
+        match sample.peak() {
+            peak if peak > 0.0 => 1.0 / peak,
+            _ => 1.0,
+        }
+    } else {
+        1.0
+    };
+    let release = 1.0 - (-1.0 / (LIMITER_RELEASE * sample.sample_rate() as f32)).exp();
+    let mut limit = 1.0;
+
+    // set up hound
+    let spec = hound::WavSpec {
+        channels: sample.channels(),
+        sample_rate: sample.sample_rate(),
+        bits_per_sample: BITS_PER_SAMPLE,
+        sample_format: hound::SampleFormat::Float,
+    };
+    let mut writer = hound::WavWriter::new(&mut *output, spec)?;
+
+    // stream the channels a block at a time and interleave them
+    let mut blocks = vec![vec![0.0; BLOCK_SIZE]; sample.channels() as usize];
+    let mut offset = 0;
+    while offset < sample.length() {
+        if cancel.is_some_and(CancelToken::is_cancelled) {
+            return Err(Error::Cancelled.into());
+        }
+        progress.update(offset as u64, sample.length() as u64);
+        let size = BLOCK_SIZE.min(sample.length() - offset);
+        for (channel, block) in blocks.iter_mut().enumerate() {
+            let block = &mut block[..size];
+            let filled = sample
+                .fill(channel as u16, offset, block)
+                .ok_or(Error::MissingChannel(channel as u16))?;
+            block[filled..].iter_mut().for_each(|s| *s = 0.0);
+        }
+        for index in 0..size {
+            if let Some(ceiling) = options.ceiling {
+                // instant attack and smooth release, linked across channels
+                let peak = blocks
+                    .iter()
+                    .fold(0.0f32, |max, block| max.max((block[index] * gain).abs()));
+                let target = if peak > ceiling { ceiling / peak } else { 1.0 };
+                limit = if target < limit {
+                    target
+                } else {
+                    limit + (target - limit) * release
+                };
+            }
+            for block in &blocks {
+                writer.write_sample(block[index] * gain * limit)?
+            }
+        }
+        offset += size;
+    }
+    writer.finalize()?;
+    progress.update(sample.length() as u64, sample.length() as u64);
+    if let Some(info) = sample.metadata().filter(|m| !m.is_empty()) {
+        metadata::append_chunks(output, info, sample.sample_rate())?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+// copy the metadata onto a sample made from the one it came from, samples
+// that can't hold it just go without. markers and loops past the end of a
+// shorter sample are cut off, and a sample that already has its own, like
+// a time stretch with its markers moved, keeps them
+pub(crate) fn keep_metadata(metadata: Option<&Metadata>, sample: &mut dyn Sample) {
+    if sample.metadata().is_some() {
+        return;
+    }
+    if let Some(metadata) = metadata {
+        sample.set_metadata(metadata.slice(0, sample.length())).ok();
+    }
+}
+
+// speaker azimuths in degrees (clockwise from front) in channel order,
+// None marks a channel that isn't panned into (the 5.1 LFE)
+fn speaker_layout(channels: u16) -> Option<Vec<Option<f32>>> {
+    match channels {
+        2 => Some(vec![Some(-30.0), Some(30.0)]),
+        4 => Some(vec![Some(-45.0), Some(45.0), Some(-135.0), Some(135.0)]),
+        6 => Some(vec![
+            Some(-30.0),
+            Some(30.0),
+            Some(0.0),
+            None,
+            Some(-110.0),
+            Some(110.0),
+        ]),
+        _ => None,
+    }
+}
+
+// constant power gains for each channel when placing a source at azimuth
+fn surround_gains(layout: &[Option<f32>], azimuth: f32) -> Vec<f32> {
+    let mut speakers: Vec<(usize, f32)> = layout
+        .iter()
+        .enumerate()
+        .filter_map(|(channel, angle)| angle.map(|a| (channel, a.rem_euclid(360.0))))
+        .collect();
+    speakers.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+
+    let azimuth = azimuth.rem_euclid(360.0);
+    let mut gains = vec![0.0; layout.len()];
+    for index in 0..speakers.len() {
+        let (left, start) = speakers[index];
+        let (right, mut end) = speakers[(index + 1) % speakers.len()];
+        if end <= start {
+            end += 360.0;
+        }
+        let mut angle = azimuth;
+        if angle < start {
+            angle += 360.0;
+        }
+        if angle <= end {
+            let pan = (angle - start) / (end - start) * PI / 2.0;
+            gains[left] = pan.cos();
+            gains[right] = pan.sin();
+            break;
+        }
+    }
+    gains
+}
+
+pub fn surround_mix(
+    sources: &[(&dyn Sample, f32)],
+    channels: u16,
+) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+    let layout = speaker_layout(channels).ok_or_else(|| {
+        Error::Unsupported(format!("No speaker layout for {} channels", channels))
+    })?;
+    if sources.is_empty() {
+        return Err(Box::new(Error::Invalid(
+            "Surround mix needs at least one source".to_string(),
+        )));
+    }
+
+    let sample_rate = sources[0].0.sample_rate();
+    let mut length = 0;
+    for (source, _) in sources {
+        if source.channels() != 1 {
+            return Err(Box::new(Error::ChannelMismatch {
+                expected: 1,
+                found: source.channels(),
+            }));
+        }
+        if source.sample_rate() != sample_rate {
+            return Err(Box::new(Error::SampleRateMismatch {
+                expected: sample_rate,
+                found: source.sample_rate(),
+            }));
+        }
+        length = length.max(source.length());
+    }
+
+    let mut wave_data: Vec<Vec<f32>> =
+        iter::repeat_n(vec![0.0; length], channels as usize).collect();
+    for (source, azimuth) in sources {
+        let wave = source.waveform(0).ok_or(Error::MissingChannel(0))?;
+        let gains = surround_gains(&layout, *azimuth);
+        for (channel, gain) in gains.iter().enumerate() {
+            if *gain == 0.0 {
+                continue;
+            }
+            for (t, val) in wave.iter().enumerate() {
+                wave_data[channel][t] += gain * val;
+            }
+        }
+    }
+
+    let mut mix = MultiChannel::new();
+    for waveform in wave_data {
+        mix.add_channel(&WaveForm {
+            sample_rate,
+            waveform: waveform.into(),
+        })?;
+    }
+    Ok(Box::new(mix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effect::fade::FadeOut;
+    use crate::tempo::TimeSignature;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn sine_440_to_wav() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(440.0, (RATE * 5) as usize, 0.5);
+        wave.export("./test_files/output/sine.wav")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn left_sine() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(440.0, (RATE * 5) as usize, 0.5);
+        let silence = SineWave::new(440.0, (RATE * 5) as usize, 0.0);
+        let left = MultiChannel::new_dual(&wave, &silence)?;
+        left.export("./test_files/output/left_sine.wav")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn right_sine() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(440.0, (RATE * 5) as usize, 0.5);
+        let silence = SineWave::new(440.0, (RATE * 5) as usize, 0.0);
+        let right = MultiChannel::new_dual(&silence, &wave)?;
+        right.export("./test_files/output/right_sine.wav")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    #[allow(clippy::identity_op)]
+    fn switch_lr_sine() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(440.0, (RATE * 1) as usize, 0.5);
+        let silence = SineWave::new(440.0, (RATE * 1) as usize, 0.0);
+        let left = MultiChannel::new_dual(&wave, &silence)?;
+        let right = MultiChannel::new_dual(&silence, &wave)?;
+        let mut comp = Composition::new();
+        let left = comp.add_track(&left, 0)?;
+        let right = comp.add_track_sec(&right, 1.0)?;
+        comp.add_track_id_sec(left, 2.0)?;
+        comp.add_track_id_sec(right, 3.0)?;
+        comp.add_track_id_sec(left, 4.0)?;
+
+        comp.export("./test_files/output/switch_lr_sine.wav")?;
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn from_mp3() -> Result<(), Box<dyn error::Error>> {
+        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        song.export("./test_files/output/from_mp3.wav")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "mp3")]
+    #[test]
+    fn from_mp3_gapless() -> Result<(), Box<dyn error::Error>> {
+        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        let gapless = MultiChannel::from_mp3_gapless("./test_files/songs/Chameleon_short.mp3")?;
+        let bytes = std::fs::read("./test_files/songs/Chameleon_short.mp3")?;
+        let info = Mp3Gapless::read(&bytes).unwrap();
+        assert_eq!(
+            (info.delay, info.padding, info.frame_samples),
+            (576, 991, 1152)
+        );
+        assert_eq!(gapless.length(), info.frames * 1152 - 576 - 991);
+
+        // the trimmed audio is the decoded audio with the ends cut off
+        let (start, _) = info.trim(song.length());
+        assert_eq!(
+            gapless.waveform(0).unwrap(),
+            song.waveform(0).unwrap()[start..start + gapless.length()]
+        );
+
+        // files without the header are left alone
+        assert!(Mp3Gapless::read(&bytes[..100]).is_none());
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "symphonia-backend", feature = "wav"))]
+    #[test]
+    fn from_symphonia() -> Result<(), Box<dyn error::Error>> {
+        let wav = MultiChannel::from_symphonia("./test_files/songs/switch_lr_sine.wav")?;
+        let expected = MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
+        assert_eq!(wav.sample_rate(), expected.sample_rate());
+        assert_eq!(wav.waveform(1), expected.waveform(1));
+
+        // decoders differ in how much start up delay they trim
+        let song = MultiChannel::from_symphonia("./test_files/songs/Chameleon_short.mp3")?;
+        let expected = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        assert_eq!(song.channels(), 2);
+        assert!((song.length() as i64 - expected.length() as i64).abs() < 4000);
+        song.export("./test_files/output/from_symphonia.wav")?;
+
+        assert!(MultiChannel::from_file("./test_files/songs/missing.m4a").is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn from_wav() -> Result<(), Box<dyn error::Error>> {
+        let song = MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
+        song.export("./test_files/output/from_wav.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn scale() -> Result<(), Box<dyn error::Error>> {
+        let wave = WaveForm::from(&[0.5, -0.25, 1.0]);
+        let louder = wave.scale(2.0);
+        assert_eq!(louder.waveform(0).unwrap(), vec![1.0, -0.5, 2.0]);
+
+        let mut block = [0.0; 2];
+        assert_eq!(louder.fill(0, 1, &mut block), Some(2));
+        assert_eq!(block, [-0.5, 2.0]);
+        assert!(louder.waveform(1).is_none());
+
+        let quieter = louder.scale(0.25);
+        assert_eq!(quieter.waveform(0).unwrap(), vec![0.25, -0.125, 0.5]);
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn from_file() -> Result<(), Box<dyn error::Error>> {
+        let song = MultiChannel::from_file("./test_files/songs/switch_lr_sine.wav")?;
+        assert_eq!(song.channels(), 2);
+        let song = MultiChannel::from_file("./test_files/songs/Chameleon_short.mp3")?;
+        assert_eq!(song.channels(), 2);
+        assert!(MultiChannel::from_file("./Cargo.toml").is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "ogg")]
+    #[test]
+    fn from_ogg() -> Result<(), Box<dyn error::Error>> {
+        // two channels of silence at 22050, 201 short blocks of which the
+        // first only primes the overlap
+        let song = MultiChannel::from_ogg("./test_files/fixtures/silence.ogg")?;
+        assert_eq!(song.channels(), 2);
+        assert_eq!(song.sample_rate(), 22050);
+        assert_eq!(song.length(), 200 * 128);
+        assert_eq!(song.peak(), 0.0);
+        assert_eq!(song.metadata().unwrap().name.as_deref(), Some("silence"));
+
+        let bytes = std::fs::read("./test_files/fixtures/silence.ogg")?;
+        let song = MultiChannel::from_bytes(&bytes, Format::Ogg)?;
+        assert_eq!((song.channels(), song.length()), (2, 200 * 128));
+        Ok(())
+    }
+
+    #[cfg(feature = "ogg")]
+    #[test]
+    fn from_ogg_rejects_other_formats() {
+        assert!(MultiChannel::from_ogg("./test_files/songs/switch_lr_sine.wav").is_err());
+        assert!(MultiChannel::from_ogg("./test_files/songs/missing.ogg").is_err());
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn pick_sample() -> Result<(), Box<dyn error::Error>> {
+        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?
+            .sample_sec(10.8, 15.9)?;
+        song.export("./test_files/output/sample.wav")?;
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn loop_sample() -> Result<(), Box<dyn error::Error>> {
+        let beat = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?
+            .sample_sec(11.1, 16.2)?;
+        let backing = Loop::from(&*beat, 8);
+        backing.export("./test_files/output/loop_sample.wav")?;
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn surround_quad_front() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(440.0, RATE as usize, 0.5);
+        let mix = surround_mix(&[(&wave, 0.0)], 4)?;
+        assert_eq!(mix.channels(), 4);
+        let front_left = mix.waveform(0).unwrap();
+        let front_right = mix.waveform(1).unwrap();
+        let rear_left = mix.waveform(2).unwrap();
+        let rear_right = mix.waveform(3).unwrap();
+        assert!(front_left.iter().any(|s| s.abs() > 0.1));
+        assert_eq!(front_left, front_right);
+        assert!(rear_left.iter().all(|s| s.abs() < 1e-6));
+        assert!(rear_right.iter().all(|s| s.abs() < 1e-6));
+        mix.export("./test_files/output/surround_quad.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn modulate_by_ramp() -> Result<(), Box<dyn error::Error>> {
+        let tone = WaveForm::from(&[0.5; 1000]);
+        let ramp: Vec<f32> = (0..100).map(|t| t as f32 / 99.0).collect();
+        let shaped = tone.modulate_amplitude_by(&WaveForm::from(&ramp))?;
+        let wave = shaped.waveform(0).unwrap();
+        assert_eq!(wave.len(), 1000);
+        assert!(wave[0].abs() < 1e-6);
+        assert!((wave[499] - 0.25).abs() < 0.01);
+        assert!((wave[999] - 0.5).abs() < 1e-6);
+        assert!(wave.windows(2).all(|w| w[1] >= w[0]));
+        Ok(())
+    }
+
+    #[test]
+    fn raw_i24_le() -> Result<(), Box<dyn error::Error>> {
+        let left = WaveForm::from(&[1.0, 0.0, -1.0, 2.0]);
+        let right = WaveForm::from(&[0.0, 0.5, 0.0, -2.0]);
+        let stereo = MultiChannel::new_dual(&left, &right)?;
+        let bytes = stereo.to_raw_i24_le()?;
+        assert_eq!(
+            bytes.len(),
+            stereo.length() * stereo.channels() as usize * 3
+        );
+        let first = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]);
+        assert_eq!(first, (1 << 23) - 1);
+        // clamped full scale negative on the right channel of the last frame
+        let last = &bytes[bytes.len() - 3..];
+        assert_eq!(last, &[0x01, 0x00, 0x80]);
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn fill_blocks_match_waveform() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(440.0, 10_000, 0.5);
+        let mut comp = Composition::new();
+        let id = comp.add_track(&wave, 0)?;
+        comp.add_track_id(id, 2_500)?;
+        let backing = Loop::from(&comp, 3);
+
+        let expected = backing.waveform(0).unwrap();
+        let mut streamed = Vec::new();
+        let mut block = [0.0; 1000];
+        let mut offset = 0;
+        while let Some(filled) = backing.fill(0, offset, &mut block) {
+            if filled == 0 {
+                break;
+            }
+            streamed.extend_from_slice(&block[..filled]);
+            offset += filled;
+        }
+        assert_eq!(streamed, expected);
+        backing.export("./test_files/output/fill_blocks.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn track_gain_and_pan() -> Result<(), Box<dyn error::Error>> {
+        let wave = WaveForm::from(&[0.5; 100]);
+        let stereo = MultiChannel::new_dual(&wave, &wave)?;
+        let mut comp = Composition::new();
+        comp.add_track_with(&stereo, 0, TrackOptions::new(0.5, -1.0))?;
+        let right = comp.add_track_with(&stereo, 50, TrackOptions::new(1.0, 0.5))?;
+        assert_eq!(comp.waveform(0).unwrap()[..50], [0.25; 50]);
+        assert_eq!(comp.waveform(1).unwrap()[..50], [0.0; 50]);
+        assert_eq!(comp.waveform(0).unwrap()[50], 0.25 + 0.25);
+        assert_eq!(comp.waveform(1).unwrap()[50], 0.5);
+
+        comp.set_track_options(right, TrackOptions::default())?;
+        assert_eq!(comp.waveform(0).unwrap()[50], 0.25 + 0.5);
+        assert!(comp.set_track_options(5, TrackOptions::default()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn builder() -> Result<(), Box<dyn error::Error>> {
+        let wave = WaveForm::with_rate(&[0.5; 100], 100);
+        let stereo = MultiChannel::new_dual(&wave, &wave)?;
+        let comp = Composition::builder()
+            .tempo(Tempo::new(60.0, TimeSignature::default()))
+            .track(&stereo)
+            .at_sec(0.0)
+            .at_beat(1, 0.0)
+            .track(&stereo)
+            .at_sec(1.0)
+            .gain(0.8)
+            .pan(-0.5)
+            .build()?;
+        // the second bar starts four beats in at 60 bpm
+        assert_eq!(comp.length(), 500);
+        let left = comp.waveform(0).unwrap();
+        let right = comp.waveform(1).unwrap();
+        assert_eq!(left[..100], [0.5; 100]);
+        assert_eq!(left[100..200], [0.4; 100]);
+        assert_eq!(right[100..200], [0.2; 100]);
+        assert_eq!(left[400..], [0.5; 100]);
+
+        assert!(Composition::builder()
+            .gain(0.5)
+            .track(&wave)
+            .build()
+            .is_err());
+        let mono = WaveForm::from(&[0.5; 10]);
+        let mismatched = Composition::builder().track(&wave).track(&mono).build();
+        assert!(mismatched.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn operators() -> Result<(), Box<dyn error::Error>> {
+        let kick = WaveForm::with_rate(&[1.0; 10], 10);
+        let snare = WaveForm::with_rate(&[0.5; 10], 10);
+        let mix = kick.clone() * 0.8 + snare.delayed(0.5);
+        assert_eq!(mix.length(), 15);
+        let wave = mix.waveform(0).unwrap();
+        assert_eq!(wave[..5], [0.8; 5]);
+        assert_eq!(wave[5..10], [0.8 + 0.5; 5]);
+        assert_eq!(wave[10..], [0.5; 5]);
+
+        let shifted = (kick.clone() >> 3) + snare.clone() + kick.reverse();
+        assert_eq!(shifted.length(), 13);
+        assert_eq!(shifted.waveform(0).unwrap()[..3], [1.5; 3]);
+        assert_eq!(shifted.waveform(0).unwrap()[3..10], [2.5; 7]);
+        assert_eq!(shifted.waveform(0).unwrap()[10..], [1.0; 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn operators_channel_counts() -> Result<(), Box<dyn error::Error>> {
+        let mono = WaveForm::from(&[0.5; 4]);
+        let stereo =
+            MultiChannel::new_dual(&WaveForm::from(&[1.0; 4]), &WaveForm::from(&[0.0; 4]))?;
+
+        // mono is copied to both sides
+        let up = stereo.box_clone() + mono.clone();
+        assert_eq!(up.channels(), 2);
+        assert_eq!(up.waveform(0).unwrap(), [1.5; 4]);
+        assert_eq!(up.waveform(1).unwrap(), [0.5; 4]);
+
+        // stereo is folded down into a mono mix
+        let down = mono + stereo;
+        assert_eq!(down.channels(), 1);
+        assert_eq!(down.waveform(0).unwrap(), [1.0; 4]);
+        Ok(())
+    }
+
+    #[test]
+    fn operators_sample_rates() -> Result<(), Box<dyn error::Error>> {
+        let slow = WaveForm::with_rate(&[0.5; 10], 10);
+        let fast = WaveForm::with_rate(&[1.0; 20], 20);
+        assert!(std::panic::catch_unwind(|| slow.clone() + fast.clone()).is_err());
+
+        let mut comp = Composition::new();
+        comp.set_auto_resample(true);
+        let mix = comp + fast + slow;
+        assert!(mix.auto_resample);
+        assert_eq!(mix.sample_rate(), 20);
+        assert_eq!(mix.length(), 20);
+        Ok(())
+    }
+
+    #[test]
+    fn plain_buffers() -> Result<(), Box<dyn error::Error>> {
+        let values = vec![0.25, 0.5, 0.75, 1.0];
+        let slice: &[f32] = &values;
+        assert_eq!(slice.sample_rate(), 44100);
+        assert_eq!(values.length(), 4);
+        assert_eq!(slice.waveform(0), Some(values.clone()));
+        assert_eq!(values.waveform(1), None);
+
+        let rated = (22050, values.clone());
+        assert_eq!(rated.sample_rate(), 22050);
+        assert_eq!(rated.sample(1, 3)?.waveform(0).unwrap(), [0.5, 0.75]);
+        let copy = rated.box_clone();
+        assert_eq!(copy.sample_rate(), 22050);
+        assert_eq!(copy.waveform(0), Some(values.clone()));
+
+        // no WaveForm needed to build up a composition
+        let mut comp = Composition::new();
+        comp.add_track(&values, 0)?;
+        comp.add_track(&slice, 2)?;
+        assert_eq!(comp.waveform(0).unwrap(), [0.25, 0.5, 1.0, 1.5, 0.75, 1.0]);
+        let mix = Composition::new() + values.clone() + vec![1.0; 2];
+        assert_eq!(mix.waveform(0).unwrap(), [1.25, 1.5, 0.75, 1.0]);
+        let reversed = values.reverse();
+        assert_eq!(reversed.waveform(0).unwrap(), [1.0, 0.75, 0.5, 0.25]);
+        Ok(())
+    }
+
+    #[test]
+    fn iterators() -> Result<(), Box<dyn error::Error>> {
+        let ramp: WaveForm = (0..4).map(|n| n as f32 / 4.0).collect();
+        assert_eq!(ramp.sample_rate(), 44100);
+        assert_eq!(ramp.waveform(0).unwrap(), [0.0, 0.25, 0.5, 0.75]);
+
+        let half = WaveForm::from_iter(8000, ramp.iter_frames().map(|frame| frame[0] * 0.5));
+        assert_eq!(half.sample_rate(), 8000);
+        assert_eq!(half.waveform(0).unwrap(), [0.0, 0.125, 0.25, 0.375]);
+
+        let stereo = MultiChannel::new_dual(&ramp, &ramp.scale(-1.0))?;
+        let frames: Vec<_> = stereo.iter_frames().collect();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[1], [0.25, -0.25]);
+        assert_eq!(Composition::new().iter_frames().count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn zero_crossings() -> Result<(), Box<dyn error::Error>> {
+        // 100 step cycles crossing zero every 50 steps
+        let sine = SineWave::new(441.0, 1000, 0.5);
+        let slice = sine.sample_zero_crossing(120, 380)?;
+        assert_eq!(slice.length(), 301);
+        assert_eq!(slice.waveform(0), sine.sample(100, 401)?.waveform(0));
+        let wave = slice.waveform(0).unwrap();
+        assert!(wave[0].abs() < 1e-3 && wave[300].abs() < 1e-3);
+
+        // a left channel that never crosses is ignored when looking at the right
+        let stereo = MultiChannel::new_dual(&WaveForm::from(&[0.6; 1000]), &sine)?;
+        let options = ZeroCrossingOptions {
+            channel: Some(1),
+            ..ZeroCrossingOptions::default()
+        };
+        let slice = stereo.sample_zero_crossing_with(120, 380, &options)?;
+        assert_eq!(slice.length(), 301);
+        // summed it never reaches zero so nothing moves
+        assert_eq!(stereo.sample_zero_crossing(120, 380)?.length(), 260);
+
+        let options = ZeroCrossingOptions {
+            fade: 10,
+            ..ZeroCrossingOptions::default()
+        };
+        let faded = stereo.sample_zero_crossing_with(120, 380, &options)?;
+        let left = faded.waveform(0).unwrap();
+        assert_eq!(left[0], 0.0);
+        assert_eq!(left[5], 0.3);
+        assert_eq!(left[130], 0.6);
+        assert_eq!(left[259], 0.0);
+
+        // boundaries at the ends of the sample only search inwards
+        assert_eq!(sine.sample_zero_crossing(0, 1000)?.length(), 901);
+        assert!(sine.sample_zero_crossing(10, 1001).is_err());
+        assert!(sine.sample_zero_crossing(20, 10).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn typed_errors() {
+        let mono = WaveForm::from(&[0.5; 10]);
+        let stereo = MultiChannel::new_dual(&mono, &mono).unwrap();
+        let mut comp = Composition::new();
+        comp.add_track(&stereo, 0).unwrap();
+        assert!(matches!(
+            comp.add_track(&mono, 0),
+            Err(Error::ChannelMismatch {
+                expected: 2,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            comp.add_track_id(3, 0),
+            Err(Error::OutOfRange {
+                what: "track",
+                index: 3,
+                len: 1
+            })
+        ));
+        let slow = WaveForm::with_rate(&[0.5; 10], 22050);
+        assert!(matches!(
+            MultiChannel::new_dual(&mono, &slow),
+            Err(Error::SampleRateMismatch {
+                expected: 44100,
+                found: 22050
+            })
+        ));
+        assert!(matches!(
+            MultiChannel::from_file("./test_files/songs/missing.wav"),
+            Err(Error::Io(_))
+        ));
+        // symphonia gets a go at anything the built in decoders can't read
+        let toml = MultiChannel::from_file("./Cargo.toml");
+        if cfg!(feature = "symphonia-backend") {
+            assert!(toml.is_err());
+        } else {
+            assert!(matches!(toml, Err(Error::Unsupported(_))));
+        }
+
+        // the boxed errors from effects and sample methods downcast back
+        let error = mono.append(&stereo, 0).err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::ChannelMismatch { .. })
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Expected 1 channels but the sample has 2"
+        );
+    }
+
+    #[test]
+    fn placements_past_the_end() -> Result<(), Box<dyn error::Error>> {
+        let wave = WaveForm::with_rate(&[1.0; 10], 10);
+        let mut comp = Composition::new();
+        // padding asked for before any track is kept
+        comp.extend_to(40);
+        let id = comp.add_track_sec(&wave, 0.5)?;
+        assert_eq!(comp.length(), 40);
+        comp.add_track_id(id, 8)?;
+        comp.add_track_id(id, 38)?;
+        assert_eq!(comp.length(), 48);
+        assert!(matches!(
+            comp.add_track_id(id, usize::MAX - 5),
+            Err(Error::Invalid(_))
+        ));
+        assert!(comp.add_track(&wave, usize::MAX).is_err());
+        assert_eq!(comp.length(), 48);
+
+        let out = comp.waveform(0).unwrap();
+        assert_eq!(out.len(), 48);
+        assert_eq!(out[..5], [0.0; 5]);
+        assert_eq!(out[5..8], [1.0; 3]);
+        assert_eq!(out[8..15], [2.0; 7]);
+        assert_eq!(out[15..18], [1.0; 3]);
+        assert_eq!(out[38..], [1.0; 10]);
+
+        // reads running off the end, both mixed and from the render
+        let mut block = [9.0; 20];
+        assert_eq!(comp.fill(0, 40, &mut block), Some(8));
+        assert_eq!(comp.fill(0, 100, &mut block), Some(0));
+        comp.add_track_id(id, 45)?;
+        assert_eq!(comp.fill(0, 40, &mut block), Some(15));
+        assert_eq!(block[..15], comp.waveform(0).unwrap()[40..]);
+        Ok(())
+    }
+
+    #[test]
+    fn remove_and_move() -> Result<(), Box<dyn error::Error>> {
+        let short = WaveForm::from(&[1.0; 10]);
+        let long = WaveForm::from(&[0.5; 30]);
+        let mut comp = Composition::new();
+        let a = comp.add_track(&short, 0)?;
+        comp.add_track_id(a, 50)?;
+        let b = comp.add_track(&long, 20)?;
+        let c = comp.add_track(&short, 5)?;
+        comp.sidechain(a, c, 0.5, 0.0, 0.1)?;
+        comp.sidechain(b, c, 0.5, 0.0, 0.1)?;
+        assert_eq!(comp.length(), 60);
+
+        assert_eq!(comp.remove_placement(a, 1)?, 50);
+        assert_eq!(comp.length(), 50);
+        assert!(matches!(
+            comp.remove_placement(a, 1),
+            Err(Error::OutOfRange {
+                what: "placement",
+                ..
+            })
+        ));
+        comp.move_placement(a, 0, 90)?;
+        assert_eq!(comp.length(), 100);
+        assert_eq!(comp.waveform(0).unwrap()[90..], [1.0; 10]);
+        assert!(comp.move_placement(a, 0, usize::MAX).is_err());
+
+        // the long track's sidechain now points at the short one's new id
+        comp.remove_track(a)?;
+        assert_eq!(comp.length(), 50);
+        assert_eq!(comp.sidechains.len(), 1);
+        assert_eq!(
+            (comp.sidechains[0].source, comp.sidechains[0].target),
+            (0, 1)
+        );
+        assert!(comp.remove_track(2).is_err());
+
+        comp.extend_to(70);
+        comp.remove_track(0)?;
+        assert_eq!(comp.length(), 70);
+        assert!(comp.sidechains.is_empty());
+        assert_eq!(comp.waveform(0).unwrap()[5..15], [1.0; 10]);
+
+        comp.clear();
+        assert_eq!(comp.length(), 0);
+        comp.add_track(&WaveForm::with_rate(&[1.0; 4], 8000), 0)?;
+        assert_eq!(comp.sample_rate(), 8000);
+        Ok(())
+    }
+
+    #[test]
+    fn mute_and_solo() -> Result<(), Box<dyn error::Error>> {
+        let mut comp = Composition::new();
+        let a = comp.add_track(&WaveForm::from(&[1.0; 10]), 0)?;
+        let b = comp.add_track(&WaveForm::from(&[2.0; 10]), 0)?;
+        let c = comp.add_track(&WaveForm::from(&[4.0; 10]), 0)?;
+        assert_eq!(comp.waveform(0).unwrap()[0], 7.0);
+
+        comp.set_mute(b, true)?;
+        assert_eq!(comp.waveform(0).unwrap()[0], 5.0);
+        comp.set_solo(a, true)?;
+        comp.set_solo(b, true)?;
+        // muting wins over solo
+        assert_eq!(comp.waveform(0).unwrap()[0], 1.0);
+        let mut block = [0.0; 4];
+        comp.fill(0, 2, &mut block);
+        assert_eq!(block, [1.0; 4]);
+
+        comp.set_mute(b, false)?;
+        comp.set_solo(a, false)?;
+        assert_eq!(comp.render().waveform(0).unwrap()[0], 2.0);
+        comp.set_solo(b, false)?;
+        assert_eq!(comp.waveform(0).unwrap()[0], 7.0);
+        assert!(comp.set_mute(c + 1, true).is_err());
+        assert!(comp.set_solo(c + 1, true).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn named_tracks() -> Result<(), Box<dyn error::Error>> {
+        let kick = WaveForm::from(&[1.0; 10]);
+        let snare = WaveForm::from(&[0.5; 10]);
+        let mut comp = Composition::new();
+        comp.add_named_track("kick", &kick, 0)?;
+        let id = comp.add_track(&snare, 5)?;
+        assert!(comp.add_named_track("kick", &snare, 0).is_err());
+        assert_eq!(comp.track_id("kick"), Some(0));
+        assert_eq!(comp.track_name(id), None);
+
+        comp.set_track_name(id, Some("snare"))?;
+        comp.add_track_id("kick", 20)?;
+        comp.add_track_id_sec("snare", 0.0)?;
+        comp.set_track_options("snare", TrackOptions::new(2.0, 0.0))?;
+        comp.automate_gain("kick", &[(0.0, 0.5)])?;
+        comp.sidechain("kick", "snare", 0.0, 0.0, 0.1)?;
+        assert_eq!(comp.waveform(0).unwrap()[..5], [1.5; 5]);
+        assert!(matches!(
+            comp.add_track_id("hat", 0),
+            Err(Error::UnknownTrack(name)) if name == "hat"
+        ));
+        assert!(comp.set_track_name("snare", Some("kick")).is_err());
+
+        comp.move_placement("kick", 1, 30)?;
+        comp.remove_track("kick")?;
+        assert_eq!(comp.track_id("snare"), Some(0));
+        assert_eq!(comp.track_id("kick"), None);
+
+        let built = Composition::builder()
+            .track(&kick)
+            .name("kick")
+            .track(&snare)
+            .build()?;
+        assert_eq!(built.track_id("kick"), Some(0));
+        assert_eq!(built.track_name(1), None);
+        Ok(())
+    }
+
+    // counts the blocks read from it
+    #[derive(Clone)]
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Sample for Counted {
+        fn sample_rate(&self) -> u32 {
+            100
+        }
+        fn length(&self) -> usize {
+            1000
+        }
+        fn channels(&self) -> u16 {
+            1
+        }
+        fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+            let mut waveform = vec![0.0; self.length()];
+            self.fill(channel, 0, &mut waveform)?;
+            Some(waveform)
+        }
+        fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+            if channel > 0 {
+                return None;
+            }
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let size = buffer.len().min(self.length().saturating_sub(offset));
+            buffer[..size].iter_mut().for_each(|s| *s = 1.0);
+            Some(size)
+        }
+        fn box_clone(&self) -> Box<dyn Sample> {
+            Box::new(self.clone())
+        }
     }
 
     #[test]
-    fn loop_sample() -> Result<(), Box<dyn error::Error>> {
-        let beat =
-            MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?.sample_sec(11.1, 16.2);
-        let backing = Loop::from(&*beat, 8);
-        backing.export("./test_files/output/loop_sample.wav")?;
+    fn section_effects_run_per_block() -> Result<(), Box<dyn error::Error>> {
+        use crate::effect::distortion::HardClip;
+
+        let reads = Arc::new(AtomicUsize::new(0));
+        let mut section = Composition::new();
+        section.add_track(&Counted(reads.clone()), 0)?;
+        let mut song = Composition::new();
+        let fade = FadeOut::new(1.0);
+        song.add_track_with_effects(&section, 0, &[&HardClip::new(0.5), &fade])?;
+        assert_eq!(song.length(), 1000);
+        assert_eq!(reads.load(Ordering::SeqCst), 0);
+
+        // only the block asked for is read and processed
+        let mut block = [0.0; 10];
+        assert_eq!(song.fill(0, 950, &mut block), Some(10));
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+        assert!((block[0] - 0.5 * 0.49).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn nested_sections() -> Result<(), Box<dyn error::Error>> {
+        use crate::effect::fade::FadeOut;
+
+        let mut verse = Composition::new();
+        verse.add_track(&WaveForm::with_rate(&[1.0; 100], 100), 0)?;
+        verse.add_track(&WaveForm::with_rate(&[0.5; 50], 100), 50)?;
+        let mut chorus = Composition::new();
+        chorus.add_track(&WaveForm::with_rate(&[0.25; 100], 100), 0)?;
+
+        let mut song = Composition::new();
+        song.add_named_track("verse", &verse, 0)?;
+        let fade = FadeOut::new(0.5);
+        let quiet = verse.scale(0.5);
+        song.add_track_with_effects(&chorus, 100, &[])?;
+        let last = song.add_track_with_effects(quiet.as_ref(), 200, &[&fade])?;
+        song.add_track_id("verse", 300)?;
+        song.add_track_id(last, 400)?;
+        assert_eq!(song.length(), 500);
+
+        let out = song.waveform(0).unwrap();
+        assert_eq!(out[..50], [1.0; 50]);
+        assert_eq!(out[50..100], [1.5; 50]);
+        assert_eq!(out[100..200], [0.25; 100]);
+        // the faded copy is untouched until the last half second
+        assert_eq!(out[200..250], [0.5; 50]);
+        assert!(out[295..300].iter().all(|s| *s < 0.1));
+        assert_eq!(out[300..400], verse.waveform(0).unwrap()[..]);
+        assert_eq!(out[400..], out[200..300]);
+        // the sections are copies so changing one later leaves the song alone
+        verse.add_track(&WaveForm::with_rate(&[1.0; 10], 100), 0)?;
+        assert_eq!(song.waveform(0).unwrap(), out);
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn progress() -> Result<(), Box<dyn error::Error>> {
+        use std::cell::RefCell;
+
+        let updates = RefCell::new(Vec::new());
+        let record = |done, total| updates.borrow_mut().push((done, total));
+        let finished = |updates: &[(u64, u64)]| {
+            assert!(updates.windows(2).all(|w| w[0].0 <= w[1].0));
+            let (done, total) = *updates.last().unwrap();
+            assert_eq!(done, total);
+            total
+        };
+
+        let song = "./test_files/songs/Chameleon_short.mp3";
+        let loaded = MultiChannel::from_file_with_progress(song, &record)?;
+        assert_eq!(finished(&updates.take()), std::fs::metadata(song)?.len());
+        assert_eq!(
+            loaded.waveform(0),
+            MultiChannel::from_mp3(song)?.waveform(0)
+        );
+
+        let comp = Composition::builder()
+            .track(&loaded)
+            .track(&loaded.sample(0, 10000)?)
+            .at(50000)
+            .build()?;
+        let rendered = comp.render_with_progress(&record);
+        let rendering = updates.take();
+        assert_eq!(finished(&rendering), 2 * comp.length() as u64);
+        assert!(rendering.len() > 2);
+        assert_eq!(rendered.waveform(1), comp.mix_channel(1));
+        // rendering again is only a copy and says it's already done
+        comp.render_with_progress(&record);
+        assert_eq!(updates.take().len(), 1);
+
+        rendered.export_with_progress(
+            "./test_files/output/progress.wav",
+            &ExportOptions::default(),
+            &record,
+        )?;
+        assert_eq!(finished(&updates.take()), comp.length() as u64);
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn cancel() -> Result<(), Box<dyn error::Error>> {
+        let comp = Composition::builder()
+            .track(&SineWave::new(440.0, 100000, 0.5))
+            .track(&SineWave::new(220.0, 50000, 0.5))
+            .at(20000)
+            .build()?;
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        assert!(matches!(
+            comp.render_cancellable(&cancel),
+            Err(Error::Cancelled)
+        ));
+        // a cancelled render leaves nothing cached
+        assert!(comp.cached(0).is_none());
+        let file = "./test_files/output/cancelled.wav";
+        assert!(comp
+            .export_cancellable(file, &ExportOptions::default(), &cancel)
+            .is_err());
+        assert!(!Path::new(file).exists());
+        let fade = FadeOut::new(0.1);
+        assert!(comp.apply_all_cancellable(&[&fade], &cancel).is_err());
+
+        // cancelling from the progress callback stops it part way
+        let cancel = CancelToken::new();
+        let calls = std::cell::Cell::new(0);
+        let stop = |done, _| {
+            calls.set(calls.get() + 1);
+            if done > 10000 {
+                cancel.cancel();
+            }
+        };
+        assert!(comp.render_from(Some(&stop), Some(&cancel)).is_none());
+        assert!(calls.get() < 10);
+
+        let rendered = comp.render_cancellable(&CancelToken::new())?;
+        assert_eq!(rendered.waveform(0), comp.mix_channel(0));
+        Ok(())
+    }
+
+    #[cfg(all(feature = "async", feature = "mp3", feature = "wav"))]
+    #[test]
+    fn async_io() -> Result<(), Box<dyn error::Error>> {
+        let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+        runtime.block_on(async {
+            let song = "./test_files/songs/Chameleon_short.mp3";
+            let loaded = MultiChannel::from_mp3_async(song).await?;
+            assert_eq!(
+                loaded.waveform(0),
+                MultiChannel::from_mp3(song)?.waveform(0)
+            );
+
+            let sine = SineWave::new(440.0, 1000, 0.5);
+            let file = "./test_files/output/async.wav";
+            sine.export_async(file, &ExportOptions::default()).await?;
+            let exported = MultiChannel::from_file_async(file).await?;
+            assert_eq!(exported.waveform(0), sine.waveform(0));
+
+            assert!(matches!(
+                MultiChannel::from_wav_async("./test_files/missing.wav").await,
+                Err(Error::Io(_))
+            ));
+            let missing =
+                sine.export_async("./test_files/missing/out.wav", &ExportOptions::default());
+            assert!(matches!(missing.await, Err(Error::Io(_))));
+            Ok(())
+        })
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn from_bytes_and_readers() -> Result<(), Box<dyn error::Error>> {
+        let song = "./test_files/songs/Chameleon_short.mp3";
+        let bytes = std::fs::read(song)?;
+        assert_eq!(Format::detect(&bytes), Some(Format::Mp3));
+        let loaded = MultiChannel::from_bytes(&bytes, Format::Mp3)?;
+        let expected = MultiChannel::from_mp3(song)?;
+        assert_eq!(loaded.waveform(1), expected.waveform(1));
+        assert!(loaded.metadata().is_none());
+
+        let mut sine = MultiChannel::new();
+        sine.add_channel(&WaveForm::with_rate(&[0.0, 0.5, -0.5, 0.25], 48000))?;
+        sine.set_metadata(
+            Metadata::default()
+                .with_name("sine")
+                .with_loop(1, 3)
+                .with_root_note(Note::A3),
+        )?;
+        sine.export("./test_files/output/from_bytes.wav")?;
+        let bytes = std::fs::read("./test_files/output/from_bytes.wav")?;
+        assert_eq!(Format::detect(&bytes[..12]), Some(Format::Wav));
+        let loaded = MultiChannel::from_wav_reader(std::io::Cursor::new(&bytes))?;
+        assert_eq!(loaded.sample_rate(), 48000);
+        assert_eq!(loaded.channels[0].sample_rate(), 48000);
+        assert_eq!(loaded.waveform(0), sine.waveform(0));
+        assert_eq!(loaded.metadata(), sine.metadata());
+
+        assert_eq!(Format::detect(b"not audio"), None);
+        assert!(MultiChannel::from_bytes(b"not audio", Format::Wav).is_err());
+        assert!(MultiChannel::from_bytes(b"not audio", Format::Mp3).is_err());
+        assert!(MultiChannel::from_bytes(b"not audio", Format::Ogg).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn export_to_writer() -> Result<(), Box<dyn error::Error>> {
+        let mut sine = MultiChannel::new();
+        sine.add_channel(&SineWave::new(440.0, 10000, 0.5))?;
+        sine.add_channel(&SineWave::new(220.0, 10000, 0.5))?;
+        sine.set_metadata(Metadata::default().with_name("sines"))?;
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        sine.export_to_writer(&mut buffer, &ExportOptions::default())?;
+
+        // the same bytes a file export writes
+        sine.export("./test_files/output/export_to_writer.wav")?;
+        let bytes = buffer.into_inner();
+        assert_eq!(sine.export_to_bytes(&ExportOptions::default())?, bytes);
+        assert_eq!(
+            bytes,
+            std::fs::read("./test_files/output/export_to_writer.wav")?
+        );
+        let loaded = MultiChannel::from_bytes(&bytes, Format::Wav)?;
+        assert_eq!(loaded.waveform(1), sine.waveform(1));
+        assert_eq!(loaded.metadata(), sine.metadata());
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn integer_wavs() -> Result<(), Box<dyn error::Error>> {
+        for bits in [8, 16, 24, 32] {
+            let file = format!("./test_files/output/int_{}.wav", bits);
+            let spec = hound::WavSpec {
+                channels: 2,
+                sample_rate: 44100,
+                bits_per_sample: bits,
+                sample_format: hound::SampleFormat::Int,
+            };
+            let full = 1i64 << (bits - 1);
+            let mut writer = hound::WavWriter::create(&file, spec)?;
+            for value in [0, full / 2, -full / 2, -full] {
+                writer.write_sample(value as i32)?;
+                writer.write_sample((value / 2) as i32)?;
+            }
+            writer.finalize()?;
+
+            let loaded = MultiChannel::from_wav(&file)?;
+            assert_eq!(loaded.channels(), 2);
+            assert_eq!(loaded.waveform(0).unwrap(), [0.0, 0.5, -0.5, -1.0]);
+            assert_eq!(loaded.waveform(1).unwrap(), [0.0, 0.25, -0.25, -0.5]);
+        }
+        Ok(())
+    }
+
+    // 400 steps at 100 a second with a point, a region and a loop
+    fn marked() -> Result<MultiChannel, Error> {
+        let wave = WaveForm::with_rate(&[0.5; 400], 100);
+        let mut stereo = MultiChannel::new_dual(&wave, &wave)?;
+        stereo.add_marker(Marker::new("hit", 100))?;
+        stereo.add_marker(Marker::region("verse", 200, 300))?;
+        stereo.add_loop(200, 400)?;
+        Ok(stereo)
+    }
+
+    #[test]
+    fn resampled_markers() -> Result<(), Box<dyn error::Error>> {
+        let double = marked()?.resample(200)?;
+        assert_eq!(double.length(), 800);
+        assert_eq!(double.marker("hit")?.position, 200);
+        assert_eq!(double.marker("verse")?, &Marker::region("verse", 400, 600));
+        assert_eq!(double.metadata().unwrap().loops, [(400, 800)]);
+        Ok(())
+    }
+
+    #[test]
+    fn reversed_fill() {
+        let reversed = WaveForm::from(&[0.25, 0.5, 0.75, 1.0]).reverse();
+        let mut block = [0.0; 4];
+        assert_eq!(reversed.fill(0, 1, &mut block), Some(3));
+        assert_eq!(block, [0.75, 0.5, 0.25, 0.0]);
+        assert_eq!(reversed.fill(0, 4, &mut block), Some(0));
+        assert_eq!(reversed.fill(0, 10, &mut block), Some(0));
+        assert_eq!(reversed.fill(1, 10, &mut block), None);
+    }
+
+    #[test]
+    fn reversed_markers() -> Result<(), Box<dyn error::Error>> {
+        let reversed = marked()?.reverse();
+        let names: Vec<&str> = reversed.markers().iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["verse", "hit"]);
+        assert_eq!(reversed.marker("hit")?.position, 300);
+        assert_eq!(
+            reversed.marker("verse")?,
+            &Marker::region("verse", 100, 200)
+        );
+        assert_eq!(reversed.metadata().unwrap().loops, [(0, 200)]);
+        Ok(())
+    }
+
+    #[test]
+    fn delayed_markers() -> Result<(), Box<dyn error::Error>> {
+        let delayed = marked()? >> 50;
+        assert_eq!(delayed.marker("hit")?.position, 150);
+        assert_eq!(delayed.marker("verse")?, &Marker::region("verse", 250, 350));
+        assert_eq!(delayed.metadata().unwrap().loops, [(250, 450)]);
+        assert_eq!(delayed.sample_region("verse")?.length(), 100);
+        Ok(())
+    }
+
+    #[test]
+    fn looped_markers() -> Result<(), Box<dyn error::Error>> {
+        // the first time through keeps its markers
+        let twice = marked()?.repeat(2);
+        assert_eq!(twice.metadata(), marked()?.metadata());
+
+        // and a loop cut short loses what is past its end
+        let short = marked()?.loop_to_length(250);
+        assert_eq!(short.marker("hit")?.position, 100);
+        assert_eq!(short.marker("verse")?, &Marker::region("verse", 200, 250));
+        assert!(short.metadata().unwrap().loops.is_empty());
+        Ok(())
+    }
+
+    // keeps the first half of every channel
+    struct Halve;
+
+    impl Effect for Halve {
+        fn apply(&self, sample: &dyn Sample) -> Result<Box<dyn Sample>, Box<dyn error::Error>> {
+            let mut channels = MultiChannel::new();
+            for channel in 0..sample.channels() {
+                let wave = sample.waveform(channel).unwrap();
+                channels.add_channel(&WaveForm::with_rate(
+                    &wave[..wave.len() / 2],
+                    sample.sample_rate(),
+                ))?;
+            }
+            Ok(Box::new(channels))
+        }
+    }
+
+    #[test]
+    fn effect_markers() -> Result<(), Box<dyn error::Error>> {
+        let half = marked()?.apply(&Halve)?;
+        assert_eq!(half.length(), 200);
+        assert_eq!(half.marker("hit")?.position, 100);
+        assert_eq!(half.marker("verse")?, &Marker::region("verse", 200, 200));
+        assert!(half.metadata().unwrap().loops.is_empty());
+
+        // a fade keeps the length and every marker where it was
+        let faded = marked()?.apply(&FadeOut::new(1.0))?;
+        assert_eq!(faded.metadata(), marked()?.metadata());
+        Ok(())
+    }
+
+    #[test]
+    fn zero_crossing_markers() -> Result<(), Box<dyn error::Error>> {
+        let options = ZeroCrossingOptions {
+            fade: 10,
+            ..ZeroCrossingOptions::default()
+        };
+        let faded = marked()?.sample_zero_crossing_with(50, 350, &options)?;
+        assert_eq!(faded.marker("hit")?.position, 50);
+        assert_eq!(faded.marker("verse")?, &Marker::region("verse", 150, 250));
+        assert_eq!(faded.metadata(), marked()?.sample(50, 350)?.metadata());
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn markers() -> Result<(), Box<dyn error::Error>> {
+        let wave: Vec<f32> = (0..400).map(|t| t as f32).collect();
+        let wave = WaveForm::with_rate(&wave, 100);
+        let mut stereo = MultiChannel::new_dual(&wave, &wave)?;
+        assert!(stereo.markers().is_empty());
+        stereo.add_marker(Marker::new("chorus", 300))?;
+        stereo.add_marker(Marker::new("verse 2", 100))?;
+        stereo.add_marker(Marker::region("drop", 150, 250))?;
+        assert!(stereo.add_marker(Marker::new("end", 401)).is_err());
+        assert!(wave.clone().add_marker(Marker::new("a", 0)).is_err());
+        let names: Vec<&str> = stereo.markers().iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(names, ["verse 2", "drop", "chorus"]);
+
+        let verse = stereo.sample_between_markers("verse 2", "chorus")?;
+        assert_eq!(verse.length(), 200);
+        assert_eq!(verse.waveform(1).unwrap()[0], 100.0);
+        let drop = stereo.sample_region("drop")?;
+        assert_eq!(drop.waveform(0).unwrap()[..2], [150.0, 151.0]);
+        assert!(stereo.sample_region("chorus").is_err());
+        assert!(matches!(
+            stereo.sample_between_markers("verse 2", "outro"),
+            Err(Error::UnknownMarker(_))
+        ));
+
+        // the slice keeps the markers inside it, moved to its own start
+        assert_eq!(verse.marker("drop")?.position, 50);
+        assert!(verse.marker("chorus").is_ok());
+        stereo.add_loop(100, 200)?;
+        assert!(stereo.add_loop(200, 100).is_err());
+        assert_eq!(
+            stereo.sample(50, 400)?.metadata().unwrap().loops,
+            [(50, 150)]
+        );
+
+        stereo.set_metadata(Metadata {
+            bpm: Some(120.0),
+            key: Some("A minor".to_string()),
+            root_note: Some(Note::A3),
+            tags: vec!["drums".to_string(), "loop".to_string()],
+            ..stereo.metadata().unwrap().clone().with_name("break")
+        })?;
+        stereo.export("./test_files/output/markers.wav")?;
+        let loaded = MultiChannel::from_wav("./test_files/output/markers.wav")?;
+        assert_eq!(
+            loaded.metadata(),
+            Some(&Metadata {
+                source: Some("./test_files/output/markers.wav".to_string()),
+                ..stereo.metadata().unwrap().clone()
+            })
+        );
+        let bytes = std::fs::read("./test_files/output/markers.wav")?;
+        assert!(bytes.windows(4).any(|w| w == b"cue "));
+        assert!(bytes.windows(8).any(|w| w == b"verse 2\0"));
+        assert!(bytes.windows(4).any(|w| w == b"smpl"));
+
+        let mut comp = Composition::new();
+        comp.add_track(&stereo, 0)?;
+        comp.set_tempo(Tempo::new(60.0, TimeSignature::default()));
+        comp.add_marker_beat("two", 0, 2.0)?;
+        assert_eq!(comp.marker("two")?.position, 200);
+        assert_eq!(comp.render().markers().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn render_is_cached() -> Result<(), Box<dyn error::Error>> {
+        let wave = WaveForm::from(&[0.5; 100]);
+        let stereo = MultiChannel::new_dual(&wave, &wave)?;
+        let mut comp = Composition::new();
+        let id = comp.add_track_with(&stereo, 0, TrackOptions::new(1.0, -1.0))?;
+        comp.set_metadata(Metadata::new().with_name("mix"))?;
+
+        let mix = comp.render();
+        assert_eq!(mix.channels(), 2);
+        assert_eq!(mix.length(), 100);
+        assert_eq!(mix.metadata().unwrap().name.as_deref(), Some("mix"));
+        assert_eq!(mix.waveform(0), comp.waveform(0));
+        assert_eq!(comp.waveform(1).unwrap(), vec![0.0; 100]);
+        let mut block = [1.0; 10];
+        assert_eq!(comp.fill(0, 95, &mut block), Some(5));
+        assert_eq!(block[..5], [0.5; 5]);
+
+        // every change to the arrangement mixes it again
+        comp.set_track_options(id, TrackOptions::default())?;
+        assert_eq!(comp.render().waveform(1).unwrap(), vec![0.5; 100]);
+        comp.add_track_id(id, 50)?;
+        assert_eq!(comp.render().waveform(0).unwrap()[50..100], [1.0; 50]);
+        comp.extend_to(200);
+        assert_eq!(comp.render().length(), 200);
+        assert_eq!(comp.box_clone().waveform(0), comp.waveform(0));
+        // the first render is left as it was
+        assert_eq!(mix.waveform(0).unwrap(), vec![0.5; 100]);
+        Ok(())
+    }
+
+    #[test]
+    fn channel_utilities() -> Result<(), Box<dyn error::Error>> {
+        let left = WaveForm::from(&[1.0, 0.5]);
+        let right = WaveForm::from(&[0.0, -0.5]);
+        let mut stereo = MultiChannel::new_dual(&left, &right)?;
+        let mono = stereo.to_mono();
+        assert_eq!(mono.channels(), 1);
+        assert_eq!(mono.waveform(0), Some(vec![0.5, 0.0]));
+
+        let parts = stereo.split();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[1].waveform(0), right.waveform(0));
+
+        stereo.swap_channels(0, 1)?;
+        assert_eq!(stereo.waveform(0), right.waveform(0));
+        assert_eq!(stereo.waveform(1), left.waveform(0));
+        assert!(stereo.swap_channels(0, 2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn automate_gain() -> Result<(), Box<dyn error::Error>> {
+        let wave = WaveForm::with_rate(&[1.0; 100], 100);
+        let mut comp = Composition::new();
+        let id = comp.add_track_with(&wave, 0, TrackOptions::new(0.5, 0.0))?;
+        comp.add_track_id(id, 100)?;
+        comp.automate_gain(id, &[(1.5, 0.0), (0.5, 1.0)])?;
+        let out = comp.waveform(0).unwrap();
+        // held before the first point, a ramp down, then held again
+        assert_eq!(out[..51], [0.5; 51]);
+        assert_eq!(out[100], 0.25);
+        assert_eq!(out[150..], [0.0; 50]);
+        assert!(comp.automate_gain(3, &[]).is_err());
+
+        // the same rendering streamed in blocks
+        let mut block = vec![0.0; 30];
+        comp.fill(0, 90, &mut block);
+        assert_eq!(block[..], out[90..120]);
+
+        comp.automate_gain(id, &[])?;
+        assert_eq!(comp.waveform(0).unwrap(), vec![0.5; 200]);
+
+        // seconds can't be turned into steps without a rate
+        let mut unrated = Composition::new();
+        let id = unrated.add_track(&WaveForm::with_rate(&[1.0; 10], 0), 0)?;
+        assert!(matches!(
+            unrated.automate_gain(id, &[(0.5, 0.0)]),
+            Err(Error::Invalid(_))
+        ));
+        unrated.automate_gain(id, &[])?;
+        Ok(())
+    }
+
+    #[test]
+    fn sidechain_ducking() -> Result<(), Box<dyn error::Error>> {
+        let bass = WaveForm::with_rate(&[0.5; 1000], 1000);
+        let kick = WaveForm::with_rate(&[1.0; 100], 1000);
+        let mut comp = Composition::new();
+        let bass_id = comp.add_track(&bass, 0)?;
+        let kick_id = comp.add_track(&kick, 500)?;
+        comp.sidechain(kick_id, bass_id, 0.8, 0.0, 0.05)?;
+        assert!(comp.sidechain(bass_id, bass_id, 0.8, 0.0, 0.05).is_err());
+        assert!(comp.sidechain(kick_id, 7, 0.8, 0.0, 0.05).is_err());
+
+        let out = comp.waveform(0).unwrap();
+        // untouched before the kick, ducked under it and recovering after
+        assert_eq!(out[..500], [0.5; 500]);
+        assert!((out[550] - (1.0 + 0.5 * 0.2)).abs() < 1e-6);
+        assert!(out[650] > 0.1 && out[650] < 0.5);
+        assert!((out[999] - 0.5).abs() < 1e-3);
+
+        // blocks render the same as the whole waveform
+        let mut block = vec![0.0; 64];
+        comp.fill(0, 620, &mut block);
+        for (a, b) in block.iter().zip(&out[620..684]) {
+            assert!((a - b).abs() < 1e-6);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn sidechain_envelope_cached() -> Result<(), Box<dyn error::Error>> {
+        let bass = WaveForm::with_rate(&[0.5; 1000], 1000);
+        let kick = WaveForm::with_rate(&[1.0; 100], 1000);
+        let stereo = |wave: &WaveForm| -> Result<MultiChannel, Error> {
+            let mut channels = MultiChannel::new();
+            channels.add_channel(wave)?;
+            channels.add_channel(wave)?;
+            Ok(channels)
+        };
+        let mut comp = Composition::new();
+        let bass_id = comp.add_track(&stereo(&bass)?, 0)?;
+        let kick_id = comp.add_track(&stereo(&kick)?, 500)?;
+        comp.sidechain(kick_id, bass_id, 0.8, 0.0, 0.05)?;
+
+        // worked out once and shared by both channels
+        let mut block = vec![0.0; 64];
+        comp.fill(0, 620, &mut block);
+        assert_eq!(comp.envelopes.lock().unwrap().len(), 1);
+        let envelope = comp.envelope(0);
+        comp.fill(1, 620, &mut block);
+        assert!(Arc::ptr_eq(&envelope, &comp.envelope(0)));
+        assert!((envelope[550] - 0.2).abs() < 1e-6);
+
+        // and worked out again once the kick moves
+        comp.move_placement(kick_id, 0, 200)?;
+        assert!(comp.envelopes.lock().unwrap().is_empty());
+        let out = comp.waveform(1).unwrap();
+        assert_eq!(out[150], 0.5);
+        assert!((out[250] - (1.0 + 0.5 * 0.2)).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn normalize() {
+        let wave = WaveForm::from(&[0.25, -0.5, 0.1]);
+        assert_eq!(wave.peak(), 0.5);
+        let normal = wave.normalize();
+        assert_eq!(normal.waveform(0).unwrap(), vec![0.5, -1.0, 0.2]);
+        let silence = WaveForm::from(&[0.0; 4]);
+        assert_eq!(silence.normalize().waveform(0).unwrap(), vec![0.0; 4]);
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn normalize_loudness() {
+        let quiet = SineWave::new(1000.0, 44100, 0.05);
+        let loud = MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav").unwrap();
+        for sample in [&quiet as &dyn Sample, &loud].iter() {
+            let matched = sample.normalize_loudness(-16.0);
+            assert!((analysis::integrated_loudness(matched.as_ref()) + 16.0).abs() < 0.01);
+        }
+        let silent = WaveForm::from(&[0.0; 100]);
+        assert_eq!(
+            silent.normalize_loudness(-16.0).waveform(0).unwrap(),
+            vec![0.0; 100]
+        );
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn export_with_limiter() -> Result<(), Box<dyn error::Error>> {
+        let wave = SineWave::new(440.0, RATE as usize, 0.8);
+        let mut comp = Composition::new();
+        let id = comp.add_track(&wave, 0)?;
+        comp.add_track_id(id, 0)?;
+        assert!(comp.peak() > 1.0);
+
+        let options = ExportOptions {
+            normalize: false,
+            ceiling: Some(1.0),
+        };
+        comp.export_with("./test_files/output/limited.wav", &options)?;
+        let limited = MultiChannel::from_wav("./test_files/output/limited.wav")?;
+        assert!(limited.peak() <= 1.0);
+        assert!(limited.peak() > 0.9);
+
+        let options = ExportOptions {
+            normalize: true,
+            ceiling: None,
+        };
+        comp.export_with("./test_files/output/normalized.wav", &options)?;
+        let normalized = MultiChannel::from_wav("./test_files/output/normalized.wav")?;
+        assert!((normalized.peak() - 1.0).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn append_crossfade() -> Result<(), Box<dyn error::Error>> {
+        let first = WaveForm::from(&[1.0; 100]);
+        let second = WaveForm::from(&[0.5; 100]);
+        let joined = first.append(&second, 0)?;
+        assert_eq!(joined.length(), 200);
+        assert_eq!(joined.waveform(0).unwrap()[99..101], [1.0, 0.5]);
+
+        let faded = first.append(&second, 50)?;
+        assert_eq!(faded.length(), 150);
+        let wave = faded.waveform(0).unwrap();
+        assert_eq!(wave[49], 1.0);
+        assert!((wave[50] - 1.0).abs() < 0.01);
+        assert!((wave[99] - 0.5).abs() < 0.02);
+        assert_eq!(wave[100], 0.5);
+
+        let stereo = MultiChannel::new_dual(&first, &second)?;
+        assert!(first.append(&stereo, 10).is_err());
+
+        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        let stitched = song
+            .sample_sec(10.8, 12.0)?
+            .append(&*song.sample_sec(14.0, 16.0)?, 2205)?;
+        stitched.export("./test_files/output/append_crossfade.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn repeat_and_loop_to_length() {
+        let beat = WaveForm::from(&[1.0, 2.0, 3.0]);
+        assert_eq!(
+            beat.repeat(3).waveform(0).unwrap(),
+            vec![1.0, 2.0, 3.0, 1.0, 2.0, 3.0, 1.0, 2.0, 3.0]
+        );
+        assert_eq!(beat.repeat(0).length(), 0);
+        let filled = beat.loop_to_length(7);
+        assert_eq!(filled.length(), 7);
+        assert_eq!(
+            filled.waveform(0).unwrap(),
+            vec![1.0, 2.0, 3.0, 1.0, 2.0, 3.0, 1.0]
+        );
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn loop_crossfade() -> Result<(), Box<dyn error::Error>> {
+        let beat = WaveForm::from(&[0.0, 0.0, 0.0, 0.0, 1.0, 1.0]);
+        let looped = Loop::from(&beat, 2).with_crossfade(2);
+        assert_eq!(looped.length(), 10);
+        let wave = looped.waveform(0).unwrap();
+        // the start of the second repeat is all tail then fades in
+        assert_eq!(wave[4], 1.0);
+        assert!((wave[5] - (PI / 4.0).cos()).abs() < 1e-6);
+        assert_eq!(wave[6..8], [0.0, 0.0]);
+        assert_eq!(wave[8..10], [1.0, 1.0]);
+
+        let mut block = [0.0; 3];
+        looped.fill(0, 4, &mut block);
+        assert_eq!(block, wave[4..7]);
+
+        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        let beat = song.sample_sec(11.1, 13.65)?;
+        Loop::to_length(&*beat, RATE as usize * 10)
+            .with_crossfade(441)
+            .export("./test_files/output/loop_crossfade.wav")?;
+        Ok(())
+    }
+
+    // a sample that claims more than it gives, only half of every block
+    #[derive(Clone)]
+    struct Short;
+
+    impl Sample for Short {
+        fn sample_rate(&self) -> u32 {
+            RATE
+        }
+        fn length(&self) -> usize {
+            4
+        }
+        fn channels(&self) -> u16 {
+            1
+        }
+        fn waveform(&self, _channel: u16) -> Option<Vec<f32>> {
+            Some(vec![1.0; 4])
+        }
+        fn fill(&self, _channel: u16, _offset: usize, buffer: &mut [f32]) -> Option<usize> {
+            let filled = buffer.len() / 2;
+            buffer[..filled].iter_mut().for_each(|s| *s = 1.0);
+            Some(filled)
+        }
+        fn box_clone(&self) -> Box<dyn Sample> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn short_fills() {
+        let mut block = [9.0; 8];
+        assert_eq!(Loop::from(&Short, 2).fill(0, 0, &mut block), Some(8));
+        assert_eq!(block, [1.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+
+        let mut block = [9.0; 4];
+        assert_eq!(Reversed::from(&Short).fill(0, 0, &mut block), Some(4));
+        assert_eq!(block, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    // claims two channels but only ever gives the first
+    #[derive(Clone)]
+    struct OneSided;
+
+    impl Sample for OneSided {
+        fn sample_rate(&self) -> u32 {
+            RATE
+        }
+        fn length(&self) -> usize {
+            4
+        }
+        fn channels(&self) -> u16 {
+            2
+        }
+        fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+            (channel == 0).then(|| vec![1.0; 4])
+        }
+        fn fill(&self, channel: u16, _offset: usize, buffer: &mut [f32]) -> Option<usize> {
+            buffer.iter_mut().for_each(|s| *s = 1.0);
+            (channel == 0).then_some(buffer.len().min(4))
+        }
+        fn box_clone(&self) -> Box<dyn Sample> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn failing_track() -> Result<(), Box<dyn error::Error>> {
+        let wave = WaveForm::from(&[0.5; 8]);
+        let mut comp = Composition::new();
+        comp.add_track(&MultiChannel::new_dual(&wave, &wave)?, 0)?;
+        comp.add_track(&OneSided, 2)?;
+
+        // only the one sided track drops out of the right channel
+        let mut block = [9.0; 8];
+        assert_eq!(comp.fill(1, 0, &mut block), Some(8));
+        assert_eq!(block, [0.5; 8]);
+        let rendered = comp.render();
+        assert_eq!(rendered.waveform(1), Some(vec![0.5; 8]));
+        assert_eq!(
+            rendered.waveform(0),
+            Some(vec![0.5, 0.5, 1.5, 1.5, 1.5, 1.5, 0.5, 0.5])
+        );
+        assert_eq!(comp.fill(2, 0, &mut block), None);
+        Ok(())
+    }
+
+    #[cfg(all(feature = "parallel", feature = "wav"))]
+    #[test]
+    fn render_parallel() -> Result<(), Box<dyn error::Error>> {
+        let song = MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
+        let mut comp = Composition::new();
+        let drums = comp.add_track(song.sample(0, RATE as usize)?.as_ref(), 0)?;
+        comp.add_track_id(drums, RATE as usize / 2)?;
+        let pad = comp.add_track_with(
+            song.sample(RATE as usize, 3 * RATE as usize)?.as_ref(),
+            1000,
+            TrackOptions::new(0.5, 0.3),
+        )?;
+        comp.automate_gain(pad, &[(0.0, 0.0), (1.0, 1.0)])?;
+        comp.sidechain(drums, pad, 0.6, 0.01, 0.1)?;
+
+        let channels = comp.render_parallel().unwrap();
+        assert_eq!(channels.len(), 2);
+        for (channel, wave) in channels.iter().enumerate() {
+            let mut serial = vec![0.0; comp.length()];
+            comp.fill(channel as u16, 0, &mut serial);
+            for (a, b) in wave.iter().zip(&serial) {
+                assert!((a - b).abs() < 1e-6);
+            }
+            assert_eq!(comp.waveform(channel as u16).as_ref(), Some(wave));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn shared_data() -> Result<(), Box<dyn error::Error>> {
+        let wave = WaveForm::from_vec(vec![0.5; 44100], RATE);
+        let copy = wave.clone();
+        assert!(Arc::ptr_eq(&wave.waveform, &copy.waveform));
+
+        let mut comp = Composition::new();
+        let id = comp.add_track(&wave, 0)?;
+        for start in 1..10 {
+            comp.add_track_id(id, start * 44100)?;
+        }
+        let stereo = MultiChannel::new_dual(&wave, &copy)?;
+        let _clones: Vec<_> = (0..10).map(|_| stereo.box_clone()).collect();
+        // the data is never copied, only the count goes up
+        assert_eq!(Arc::strong_count(&wave.waveform), 25);
+        assert_eq!(comp.waveform(0).unwrap().len(), 10 * 44100);
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn metadata() -> Result<(), Box<dyn error::Error>> {
+        let song = MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
+        let info = song.metadata().unwrap();
+        assert_eq!(info.name.as_deref(), Some("switch_lr_sine"));
+        assert_eq!(
+            info.source.as_deref(),
+            Some("./test_files/songs/switch_lr_sine.wav")
+        );
+
+        let mut hit = song.sample_sec(0.5, 1.0)?.scale(0.5).reverse();
+        let mut info = hit.metadata().unwrap().clone().with_tag("sweep");
+        info.bpm = Some(128.0);
+        info.key = Some("A minor".to_string());
+        hit.set_metadata(info.clone())?;
+        assert_eq!(hit.metadata(), Some(&info));
+        assert_eq!(hit.to_mono().metadata(), Some(&info));
+        assert_eq!(hit.resample(22050)?.metadata(), Some(&info));
+        let faded = hit.apply(&crate::effect::fade::FadeIn::new(0.1))?;
+        assert_eq!(faded.metadata(), Some(&info));
+        assert!(WaveForm::from(&[0.0]).set_metadata(info.clone()).is_err());
+
+        faded.export("./test_files/output/metadata.wav")?;
+        let bytes = std::fs::read("./test_files/output/metadata.wav")?;
+        let riff = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        assert_eq!(riff as usize, bytes.len() - 8);
+        let find = |needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+        assert!(find(b"LIST") && find(b"INFOINAM"));
+        assert!(find(b"switch_lr_sine\0") && find(b"sweep") && find(b"bpm=128; key=A minor"));
+        // the extra chunk doesn't get in the way of reading the audio
+        let read = MultiChannel::from_wav("./test_files/output/metadata.wav")?;
+        assert_eq!(read.length(), faded.length());
+        Ok(())
+    }
+
+    #[test]
+    fn borrowed_waveforms() -> Result<(), Box<dyn error::Error>> {
+        let left = WaveForm::from(&[1.0, 2.0, 3.0, 4.0]);
+        let right = WaveForm::from(&[5.0, 6.0, 7.0, 8.0]);
+        let stereo = MultiChannel::new_dual(&left, &right)?;
+        for sample in [&left as &dyn Sample, &stereo].iter() {
+            let wave = sample.waveform_ref(0).unwrap();
+            assert!(matches!(wave, Cow::Borrowed(_)));
+            assert_eq!(*wave, [1.0, 2.0, 3.0, 4.0]);
+        }
+        assert!(stereo.waveform_ref(2).is_none());
+
+        let slice = stereo.sample(1, 3)?;
+        assert!(matches!(
+            slice.waveform_ref(1),
+            Some(Cow::Borrowed(&[6.0, 7.0]))
+        ));
+        // computed samples hand out their own copy
+        let scaled = slice.scale(2.0);
+        assert!(matches!(scaled.waveform_ref(1), Some(Cow::Owned(_))));
+        assert_eq!(*scaled.waveform_ref(1).unwrap(), [12.0, 14.0]);
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn lazy_edits() -> Result<(), Box<dyn error::Error>> {
+        let wave = WaveForm::with_rate(&[1.0, 2.0, 3.0, 4.0, 5.0], 8000);
+        let slice = wave.sample(1, 4)?;
+        assert_eq!(slice.sample_rate(), 8000);
+        assert_eq!(slice.waveform(0).unwrap(), vec![2.0, 3.0, 4.0]);
+        let mut block = [9.0; 4];
+        assert_eq!(slice.fill(0, 2, &mut block), Some(1));
+        assert_eq!(block[0], 4.0);
+        assert_eq!(Slice::from(&wave, 3, 10).length(), 2);
+        assert_eq!(Slice::from(&wave, 7, 10).length(), 0);
+        assert!(matches!(
+            wave.sample(3, 10),
+            Err(Error::OutOfRange {
+                index: 10,
+                len: 5,
+                ..
+            })
+        ));
+        assert!(matches!(wave.sample(4, 2), Err(Error::Invalid(_))));
+        assert_eq!(wave.sample_clamped(3, 10).length(), 2);
+
+        // 5 steps at 8000 is 0.625 ms, the end in seconds reaches the last step
+        assert_eq!(wave.len_sec(), 0.000_625);
+        assert_eq!(wave.duration(), Duration::from_micros(625));
+        assert_eq!(wave.sample_sec(0.0, wave.len_sec())?.length(), 5);
+        assert!(wave.sample_sec(-1.0, 0.0).is_err());
+        assert_eq!(wave.sample_sec_clamped(0.000_25, 1.0).length(), 3);
+
+        let delayed = Delayed::from(slice.as_ref(), 2);
+        assert_eq!(delayed.waveform(0).unwrap(), vec![0.0, 0.0, 2.0, 3.0, 4.0]);
+        let mut block = [9.0; 2];
+        assert_eq!(delayed.fill(0, 1, &mut block), Some(2));
+        assert_eq!(block, [0.0, 2.0]);
+        assert!(delayed.waveform(1).is_none());
+
+        let mut mixed = Mixed::new();
+        mixed.add(&wave, 1.0)?;
+        mixed.add(&delayed, 0.5)?;
+        assert_eq!(mixed.waveform(0).unwrap(), vec![1.0, 2.0, 4.0, 5.5, 7.0]);
+        assert!(mixed.add(&WaveForm::from(&[1.0]), 1.0).is_err());
+
+        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        let mut edit = Mixed::new();
+        let hit = song.sample_sec(10.8, 11.3)?.scale(0.8);
+        edit.add(hit.as_ref(), 1.0)?;
+        edit.add(
+            &Delayed::from(hit.as_ref(), song.sample_rate() as usize / 4),
+            0.5,
+        )?;
+        assert_eq!(edit.channels(), song.channels());
+        edit.export("./test_files/output/lazy_edits.wav")?;
+        Ok(())
+    }
+
+    #[cfg(all(feature = "mp3", feature = "wav"))]
+    #[test]
+    fn reverse() -> Result<(), Box<dyn error::Error>> {
+        let left = WaveForm::from(&[1.0, 2.0, 3.0, 4.0]);
+        let right = WaveForm::from(&[5.0, 6.0, 7.0, 8.0]);
+        let stereo = MultiChannel::new_dual(&left, &right)?;
+        let reversed = stereo.reverse();
+        assert_eq!(reversed.waveform(0).unwrap(), vec![4.0, 3.0, 2.0, 1.0]);
+        assert_eq!(reversed.waveform(1).unwrap(), vec![8.0, 7.0, 6.0, 5.0]);
+
+        let mut block = [0.0; 3];
+        assert_eq!(reversed.fill(1, 2, &mut block), Some(2));
+        assert_eq!(block[..2], [6.0, 5.0]);
+        assert_eq!(
+            reversed.reverse().waveform(0).unwrap(),
+            vec![1.0, 2.0, 3.0, 4.0]
+        );
+
+        let song = MultiChannel::from_mp3("./test_files/songs/Chameleon_short.mp3")?;
+        song.sample_sec(10.8, 15.9)?
+            .reverse()
+            .export("./test_files/output/reverse.wav")?;
         Ok(())
     }
 }
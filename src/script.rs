@@ -0,0 +1,323 @@
+// a plain text arrangement that can be edited by hand and loaded with
+// Composition::from_script, one statement per line and # starts a comment
+//
+//     tempo 120 4/4
+//     length 16s
+//
+//     track kick "drums/kick.wav"
+//         at 0:0 0:2 1:0 1:2
+//         gain 0.9
+//
+//     track vox "vox.mp3" 10 15
+//         at 4s
+//         pan -0.3
+//         effect fade_out duration=0.5 curve=EqualPower
+//
+// a track line takes a name, a file and optionally the start and end in
+// seconds to cut it to, the lines after it up to the next track apply to it.
+// positions are steps, seconds with an s on the end or bar:beat counted from
+// 0:0. effects take the same names and fields as in a project file.
+use crate::project::{Source, TrackEffect};
+use crate::sample::{Composition, CompositionBuilder, Sample};
+use crate::tempo::{Tempo, TimeSignature};
+use crate::Error;
+
+use std::error;
+use std::fs;
+
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Position {
+    Step(usize),
+    Seconds(f32),
+    Beat(u32, f32),
+}
+
+// split a line on whitespace keeping quoted strings whole and dropping
+// anything after a # outside of quotes
+fn tokens(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '#' => break,
+            '"' => {
+                let mut token = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => token.push(c),
+                        None => return Err("Unclosed quote".to_string()),
+                    }
+                }
+                tokens.push(token);
+            }
+            c if c.is_whitespace() => (),
+            c => {
+                let mut token = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '#') {
+                    token.push(c);
+                }
+                tokens.push(token);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn number<T: std::str::FromStr>(token: &str) -> Result<T, String> {
+    token
+        .parse()
+        .map_err(|_| format!("Expected a number but found {}", token))
+}
+
+fn position(token: &str) -> Result<Position, String> {
+    if let Some(seconds) = token.strip_suffix('s') {
+        return Ok(Position::Seconds(number(seconds)?));
+    }
+    match token.split_once(':') {
+        Some((bar, beat)) => Ok(Position::Beat(number(bar)?, number(beat)?)),
+        None => Ok(Position::Step(number(token)?)),
+    }
+}
+
+// key=value pairs turned into the same tagged form a project file uses
+fn effect(name: &str, fields: &[String]) -> Result<TrackEffect, String> {
+    let mut object = Map::new();
+    object.insert("effect".to_string(), Value::String(name.to_string()));
+    for field in fields {
+        let (key, value) = field
+            .split_once('=')
+            .ok_or(format!("Expected key=value but found {}", field))?;
+        let value = match value {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            value => serde_json::from_str::<serde_json::Number>(value)
+                .map(Value::Number)
+                .unwrap_or_else(|_| Value::String(value.to_string())),
+        };
+        object.insert(key.to_string(), value);
+    }
+    serde_json::from_value(Value::Object(object)).map_err(|e| format!("{} effect: {}", name, e))
+}
+
+struct ScriptTrack {
+    name: String,
+    source: Source,
+    effects: Vec<TrackEffect>,
+    starts: Vec<Position>,
+    gain: Option<f32>,
+    pan: Option<f32>,
+}
+
+#[derive(Default)]
+struct Script {
+    tempo: Tempo,
+    length: Option<Position>,
+    auto_resample: bool,
+    tracks: Vec<ScriptTrack>,
+}
+
+impl Script {
+    fn statement(&mut self, tokens: &[String]) -> Result<(), String> {
+        let words: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        if let ["track", name, path, cut @ ..] = words.as_slice() {
+            let source = match cut {
+                [] => Source::file(path),
+                [start, end] => Source::File {
+                    path: path.to_string(),
+                    start: Some(number(start)?),
+                    end: Some(number(end)?),
+                },
+                _ => {
+                    return Err("A track takes a name, a file and an optional start and end".into())
+                }
+            };
+            self.tracks.push(ScriptTrack {
+                name: name.to_string(),
+                source,
+                effects: Vec::new(),
+                starts: Vec::new(),
+                gain: None,
+                pan: None,
+            });
+            return Ok(());
+        }
+
+        match words.as_slice() {
+            ["tempo", bpm] => self.tempo = Tempo::new(number(bpm)?, TimeSignature::default()),
+            ["tempo", bpm, signature] => {
+                let (beats, unit) = signature
+                    .split_once('/')
+                    .ok_or(format!("Expected a time signature but found {}", signature))?;
+                let signature = TimeSignature::new(number(beats)?, number(unit)?);
+                self.tempo = Tempo::new(number(bpm)?, signature);
+            }
+            ["length", length] => self.length = Some(position(length)?),
+            ["auto_resample"] => self.auto_resample = true,
+            [statement, args @ ..] => {
+                let track = self
+                    .tracks
+                    .last_mut()
+                    .ok_or(format!("{} has to come after a track", statement))?;
+                match (*statement, args) {
+                    ("at", starts) if !starts.is_empty() => {
+                        for start in starts {
+                            track.starts.push(position(start)?);
+                        }
+                    }
+                    ("gain", [gain]) => track.gain = Some(number(gain)?),
+                    ("pan", [pan]) => track.pan = Some(number(pan)?),
+                    ("effect", [name, ..]) => track.effects.push(effect(name, &tokens[2..])?),
+                    _ => return Err(format!("Can't understand {}", words.join(" "))),
+                }
+            }
+            [] => (),
+        }
+        Ok(())
+    }
+
+    fn parse(text: &str) -> Result<Script, Error> {
+        let mut script = Script::default();
+        for (index, line) in text.lines().enumerate() {
+            tokens(line)
+                .and_then(|tokens| script.statement(&tokens))
+                .map_err(|e| Error::Decode(format!("line {}: {}", index + 1, e)))?;
+        }
+        Ok(script)
+    }
+
+    fn to_composition(&self) -> Result<Composition, Box<dyn error::Error>> {
+        let mut builder = CompositionBuilder::new()
+            .tempo(self.tempo.clone())
+            .auto_resample(self.auto_resample);
+        for track in &self.tracks {
+            let mut sample = track.source.load()?;
+            for effect in &track.effects {
+                sample = sample.apply(effect)?;
+            }
+            builder = builder.track(sample.as_ref()).name(&track.name);
+            for start in &track.starts {
+                builder = match *start {
+                    Position::Step(step) => builder.at(step),
+                    Position::Seconds(seconds) => builder.at_sec(seconds),
+                    Position::Beat(bar, beat) => builder.at_beat(bar, beat),
+                };
+            }
+            if let Some(gain) = track.gain {
+                builder = builder.gain(gain);
+            }
+            if let Some(pan) = track.pan {
+                builder = builder.pan(pan);
+            }
+        }
+        let mut comp = builder.build()?;
+
+        if let Some(length) = self.length {
+            let rate = comp.sample_rate();
+            comp.extend_to(match length {
+                Position::Step(step) => step,
+                Position::Seconds(seconds) => (seconds * rate as f32).round() as usize,
+                Position::Beat(bar, beat) => self.tempo.position(bar, beat, rate),
+            });
+        }
+        Ok(comp)
+    }
+}
+
+impl Composition {
+    // see the top of this file for the format, file paths are relative to the
+    // working directory like in a project
+    pub fn from_script(path: &str) -> Result<Composition, Box<dyn error::Error>> {
+        Composition::from_script_str(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_script_str(script: &str) -> Result<Composition, Box<dyn error::Error>> {
+        Script::parse(script)?.to_composition()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SONG: &str = "./test_files/songs/switch_lr_sine.wav";
+
+    #[test]
+    fn tokens_and_positions() {
+        assert_eq!(
+            tokens(r#"track vox "my vox.wav" 1.5 # the lead"#).unwrap(),
+            ["track", "vox", "my vox.wav", "1.5"]
+        );
+        assert!(tokens(r#"track vox "my vox.wav"#).is_err());
+        assert_eq!(position("44100"), Ok(Position::Step(44100)));
+        assert_eq!(position("1.5s"), Ok(Position::Seconds(1.5)));
+        assert_eq!(position("2:1.5"), Ok(Position::Beat(2, 1.5)));
+        assert!(position("soon").is_err());
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn from_script() -> Result<(), Box<dyn error::Error>> {
+        use crate::effect::fade::{Curve, FadeOut};
+        use crate::sample::MultiChannel;
+
+        let text = format!(
+            "# two copies of the same song
+            tempo 120 4/4
+            length 10s
+
+            track first {song} 0 1
+                at 0 1s
+                gain 0.5
+                effect fade_out duration=0.2 curve=EqualPower
+
+            track second {song}
+                at 1:0
+                pan -0.5
+            ",
+            song = SONG
+        );
+        std::fs::write("./test_files/output/script.msm", &text)?;
+        let comp = Composition::from_script("./test_files/output/script.msm")?;
+        assert_eq!(comp.length(), 10 * 44100);
+        assert_eq!(comp.track_id("first"), Some(0));
+        assert_eq!(comp.track_id("second"), Some(1));
+
+        // the same arrangement put together by hand
+        let song = MultiChannel::from_wav(SONG)?;
+        let first = song
+            .sample(0, 44100)?
+            .apply(&FadeOut::new(0.2).with_curve(Curve::EqualPower))?;
+        let mut expected = Composition::builder()
+            .track(first.as_ref())
+            .at(0)
+            .at_sec(1.0)
+            .gain(0.5)
+            .track(&song)
+            .at_beat(1, 0.0)
+            .pan(-0.5)
+            .tempo(Tempo::new(120.0, TimeSignature::new(4, 4)))
+            .build()?;
+        expected.extend_to(10 * 44100);
+        assert_eq!(comp.waveform(0), expected.waveform(0));
+        assert_eq!(comp.waveform(1), expected.waveform(1));
+        comp.export("./test_files/output/script.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn script_errors() {
+        let error = |text: &str| {
+            Composition::from_script_str(text)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert!(error("gain 0.5").contains("line 1"));
+        assert!(error("tempo 120\nwobble").contains("line 2"));
+        assert!(error(&format!("track a {}\neffect fade_out", SONG)).contains("duration"));
+        assert!(Composition::from_script("./test_files/missing.msm").is_err());
+    }
+}
@@ -0,0 +1,290 @@
+use crate::kit::{Hit, SampleKit};
+use crate::sample::{Composition, Sample, TrackOptions};
+use crate::tempo::Tempo;
+use crate::Error;
+
+pub struct Row {
+    pub sample: Box<dyn Sample>,
+    // velocity of each step, None for steps that are not played
+    pub steps: Vec<Option<f32>>,
+    // the kit pad this row plays instead of its sample
+    pub pad: Option<usize>,
+}
+
+// a step grid like a drum machine, every row triggers one sample
+pub struct Sequencer {
+    pub steps_per_beat: u32,
+    // in [0, 1), how far every second step is pushed back as a fraction
+    // of a step
+    pub swing: f32,
+    pub tempo: Tempo,
+    steps: usize,
+    rows: Vec<Row>,
+    kit: Option<SampleKit>,
+}
+
+impl Sequencer {
+    pub fn new(steps: usize, steps_per_beat: u32, tempo: Tempo) -> Self {
+        Sequencer {
+            steps_per_beat,
+            swing: 0.0,
+            tempo,
+            steps,
+            rows: Vec::new(),
+            kit: None,
+        }
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    pub fn add_row(&mut self, sample: &dyn Sample) -> usize {
+        self.rows.push(Row {
+            sample: sample.box_clone(),
+            steps: vec![None; self.steps],
+            pad: None,
+        });
+        self.rows.len() - 1
+    }
+
+    pub fn set_kit(&mut self, kit: SampleKit) {
+        self.kit = Some(kit);
+    }
+
+    // a row that triggers the named pad of the kit, so the pad's gain,
+    // pitch and choke group apply
+    pub fn add_pad(&mut self, name: &str) -> Result<usize, Error> {
+        let kit = self
+            .kit
+            .as_ref()
+            .ok_or_else(|| Error::Invalid("The sequencer does not have a kit".to_string()))?;
+        let pad = kit
+            .index(name)
+            .ok_or_else(|| Error::Invalid(format!("The kit has no pad called {}", name)))?;
+        self.rows.push(Row {
+            sample: kit.pads()[pad].sample().box_clone(),
+            steps: vec![None; self.steps],
+            pad: Some(pad),
+        });
+        Ok(self.rows.len() - 1)
+    }
+
+    pub fn set_step(&mut self, row: usize, step: usize, velocity: f32) -> Result<(), Error> {
+        *self.step_mut(row, step)? = Some(velocity);
+        Ok(())
+    }
+
+    pub fn clear_step(&mut self, row: usize, step: usize) -> Result<(), Error> {
+        *self.step_mut(row, step)? = None;
+        Ok(())
+    }
+
+    // fill a row from a grid like "x...x...X...x...", X plays at full
+    // velocity, x at half and . or - is a rest, spaces and | are ignored
+    pub fn set_pattern(&mut self, row: usize, pattern: &str) -> Result<(), Error> {
+        let steps = pattern
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '|')
+            .map(|c| match c {
+                'X' => Ok(Some(1.0)),
+                'x' => Ok(Some(0.5)),
+                '.' | '-' => Ok(None),
+                _ => Err(Error::Invalid(format!("Unknown step \"{}\" in pattern", c))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if steps.len() != self.steps {
+            return Err(Error::LengthMismatch {
+                expected: self.steps,
+                found: steps.len(),
+            });
+        }
+        let rows = self.rows.len();
+        let row = self.rows.get_mut(row).ok_or(Error::OutOfRange {
+            what: "row",
+            index: row,
+            len: rows,
+        })?;
+        row.steps = steps;
+        Ok(())
+    }
+
+    fn step_mut(&mut self, row: usize, step: usize) -> Result<&mut Option<f32>, Error> {
+        let (rows, steps) = (self.rows.len(), self.steps);
+        self.rows
+            .get_mut(row)
+            .ok_or(Error::OutOfRange {
+                what: "row",
+                index: row,
+                len: rows,
+            })?
+            .steps
+            .get_mut(step)
+            .ok_or(Error::OutOfRange {
+                what: "step",
+                index: step,
+                len: steps,
+            })
+    }
+
+    // in beats from the start of the first repeat
+    pub fn step_position(&self, repeat: u32, step: usize) -> f64 {
+        let index = repeat as f64 * self.steps as f64 + step as f64;
+        let swing = if step % 2 == 1 {
+            self.swing as f64
+        } else {
+            0.0
+        };
+        (index + swing) / self.steps_per_beat as f64
+    }
+
+    // play the pattern the given number of times back to back
+    pub fn render(&self, repeats: u32) -> Result<Composition, Error> {
+        let mut comp = Composition::new();
+        comp.set_tempo(self.tempo.clone());
+        let rate = match self.rows.first() {
+            Some(row) => row.sample.sample_rate(),
+            None => return Ok(comp),
+        };
+        let position = |beats: f64| (self.tempo.beats_to_seconds(beats) * rate as f64).round();
+
+        let mut hits = Vec::new();
+        for row in &self.rows {
+            // placements with the same velocity share a track
+            let mut ids: Vec<(f32, usize)> = Vec::new();
+            for repeat in 0..repeats {
+                for (step, velocity) in row.steps.iter().enumerate() {
+                    let velocity = match velocity {
+                        Some(velocity) => *velocity,
+                        None => continue,
+                    };
+                    let start = position(self.step_position(repeat, step)) as usize;
+                    if let Some(pad) = row.pad {
+                        hits.push(Hit::new(pad, start, velocity));
+                        continue;
+                    }
+                    match ids.iter().find(|(v, _)| *v == velocity) {
+                        Some((_, id)) => comp.add_track_id(*id, start)?,
+                        None => {
+                            let options = TrackOptions::new(velocity, 0.0);
+                            let id = comp.add_track_with(&*row.sample, start, options)?;
+                            ids.push((velocity, id));
+                        }
+                    }
+                }
+            }
+        }
+
+        if let Some(kit) = &self.kit {
+            kit.place(&mut comp, &hits)?;
+        }
+
+        let end = repeats as f64 * self.steps as f64 / self.steps_per_beat as f64;
+        comp.extend_to(position(end) as usize);
+        Ok(comp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kit::Pad;
+    use crate::oscillator::WhiteNoise;
+    use crate::sample::{SineWave, WaveForm};
+    use std::error;
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn drum_grid() -> Result<(), Box<dyn error::Error>> {
+        let kick = SineWave::new(60.0, 8000, 0.9);
+        let hat = WhiteNoise::new(2000, 0.3);
+        let mut seq = Sequencer::new(16, 4, Tempo::default());
+        let kick_row = seq.add_row(&kick);
+        let hat_row = seq.add_row(&hat);
+        seq.set_pattern(kick_row, "X... x... X... x...")?;
+        seq.set_pattern(hat_row, "..x. ..x. ..x. ..X.")?;
+        seq.set_step(hat_row, 15, 0.25)?;
+        assert!(matches!(
+            seq.set_pattern(hat_row, "x.x"),
+            Err(Error::LengthMismatch {
+                expected: 16,
+                found: 3
+            })
+        ));
+        assert!(matches!(
+            seq.set_pattern(hat_row, "..o...........x."),
+            Err(Error::Invalid(_))
+        ));
+        assert!(matches!(
+            seq.set_step(2, 0, 1.0),
+            Err(Error::OutOfRange {
+                what: "row",
+                index: 2,
+                len: 2
+            })
+        ));
+        assert!(matches!(
+            seq.set_step(0, 16, 1.0),
+            Err(Error::OutOfRange { what: "step", .. })
+        ));
+        #[allow(deprecated)]
+        let message = seq
+            .set_pattern(hat_row, "..o...........x.")
+            .unwrap_err()
+            .message()
+            .into_owned();
+        assert_eq!(message, "Unknown step \"o\" in pattern");
+
+        // 16 sixteenth notes at 120 bpm is two seconds
+        let comp = seq.render(2)?;
+        assert_eq!(comp.length(), 4 * 44100);
+        comp.export("./test_files/output/sequencer.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn kit_rows() -> Result<(), Box<dyn error::Error>> {
+        let mut kit = SampleKit::new();
+        kit.add_pad(Pad::new("kick", &SineWave::new(60.0, 8000, 0.9)))?;
+        kit.add_pad(Pad::new("open", &WhiteNoise::new(20000, 0.3)).with_choke(0))?;
+        kit.add_pad(Pad::new("closed", &WhiteNoise::new(2000, 0.3)).with_choke(0))?;
+        let mut seq = Sequencer::new(8, 4, Tempo::default());
+        assert!(seq.add_pad("kick").is_err());
+        seq.set_kit(kit);
+        let kick = seq.add_pad("kick")?;
+        let open = seq.add_pad("open")?;
+        let closed = seq.add_pad("closed")?;
+        assert!(seq.add_pad("snare").is_err());
+        seq.set_pattern(kick, "X...X...")?;
+        seq.set_pattern(open, "X.......")?;
+        seq.set_pattern(closed, "..X.....")?;
+
+        let wave = seq.render(1)?.waveform(0).unwrap();
+        assert_eq!(wave.len(), 44100);
+        // the closed hat chokes the open one two sixteenths in
+        assert!(wave[10000].abs() > 0.0);
+        assert!(wave[13025..22050].iter().all(|s| *s == 0.0));
+        Ok(())
+    }
+
+    #[test]
+    fn swing_and_velocity() -> Result<(), Box<dyn error::Error>> {
+        let click = WaveForm::from(&[1.0]);
+        let mut seq = Sequencer::new(4, 4, Tempo::default());
+        let row = seq.add_row(&click);
+        seq.set_pattern(row, "Xxx.")?;
+        seq.swing = 0.5;
+        let wave = seq.render(1)?.waveform(0).unwrap();
+        // a sixteenth at 120 bpm is 5512.5 steps
+        assert_eq!(wave[0], 1.0);
+        assert_eq!(wave[8269], 0.5);
+        assert_eq!(wave[11025], 0.5);
+        assert_eq!(wave.iter().filter(|s| **s != 0.0).count(), 3);
+        assert_eq!(wave.len(), 22050);
+        Ok(())
+    }
+}
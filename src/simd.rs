@@ -0,0 +1,124 @@
+// the mixing hot paths worked through in fixed size chunks so the compiler
+// turns each chunk into vector instructions, the leftover steps at the end
+// go through the plain scalar loop
+
+use std::convert::TryInto;
+
+const LANES: usize = 8;
+
+// out += gain * input
+pub(crate) fn mix(out: &mut [f32], input: &[f32], gain: f32) {
+    let size = out.len().min(input.len());
+    let mut outs = out[..size].chunks_exact_mut(LANES);
+    let mut ins = input[..size].chunks_exact(LANES);
+    for (out, input) in (&mut outs).zip(&mut ins) {
+        let out: &mut [f32; LANES] = out.try_into().unwrap();
+        let input: &[f32; LANES] = input.try_into().unwrap();
+        for lane in 0..LANES {
+            out[lane] += gain * input[lane];
+        }
+    }
+    for (out, input) in outs.into_remainder().iter_mut().zip(ins.remainder()) {
+        *out += gain * input;
+    }
+}
+
+// out += input * gains, for gains that change every step
+pub(crate) fn mix_product(out: &mut [f32], input: &[f32], gains: &[f32]) {
+    let size = out.len().min(input.len()).min(gains.len());
+    let mut outs = out[..size].chunks_exact_mut(LANES);
+    let mut ins = input[..size].chunks_exact(LANES);
+    let mut gain_chunks = gains[..size].chunks_exact(LANES);
+    for ((out, input), gains) in (&mut outs).zip(&mut ins).zip(&mut gain_chunks) {
+        let out: &mut [f32; LANES] = out.try_into().unwrap();
+        let input: &[f32; LANES] = input.try_into().unwrap();
+        let gains: &[f32; LANES] = gains.try_into().unwrap();
+        for lane in 0..LANES {
+            out[lane] += input[lane] * gains[lane];
+        }
+    }
+    for ((out, input), gain) in outs
+        .into_remainder()
+        .iter_mut()
+        .zip(ins.remainder())
+        .zip(gain_chunks.remainder())
+    {
+        *out += input * gain;
+    }
+}
+
+pub(crate) fn scale(buffer: &mut [f32], gain: f32) {
+    let mut chunks = buffer.chunks_exact_mut(LANES);
+    for chunk in &mut chunks {
+        let chunk: &mut [f32; LANES] = chunk.try_into().unwrap();
+        for value in chunk.iter_mut() {
+            *value *= gain;
+        }
+    }
+    for value in chunks.into_remainder() {
+        *value *= gain;
+    }
+}
+
+// buffer *= a gain going from start by step every value, for fades
+pub(crate) fn ramp(buffer: &mut [f32], start: f32, step: f32) {
+    let mut chunks = buffer.chunks_exact_mut(LANES);
+    let mut index = 0;
+    for chunk in &mut chunks {
+        let chunk: &mut [f32; LANES] = chunk.try_into().unwrap();
+        for (lane, value) in chunk.iter_mut().enumerate() {
+            *value *= start + step * (index + lane) as f32;
+        }
+        index += LANES;
+    }
+    for (lane, value) in chunks.into_remainder().iter_mut().enumerate() {
+        *value *= start + step * (index + lane) as f32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal(length: usize) -> Vec<f32> {
+        (0..length).map(|t| (t as f32 * 0.37).sin()).collect()
+    }
+
+    #[test]
+    fn matches_scalar() {
+        // lengths around the chunk size so both paths are covered
+        for length in [0, 1, 7, 8, 9, 31, 1000].iter() {
+            let input = signal(*length);
+            let gains: Vec<f32> = (0..*length).map(|t| t as f32 / 100.0).collect();
+
+            let mut out = vec![0.5; *length];
+            mix(&mut out, &input, 0.3);
+            for (out, input) in out.iter().zip(&input) {
+                assert_eq!(*out, 0.5 + 0.3 * input);
+            }
+
+            let mut out = vec![0.5; *length];
+            mix_product(&mut out, &input, &gains);
+            for ((out, input), gain) in out.iter().zip(&input).zip(&gains) {
+                assert_eq!(*out, 0.5 + input * gain);
+            }
+
+            let mut scaled = input.clone();
+            scale(&mut scaled, -2.0);
+            for (scaled, input) in scaled.iter().zip(&input) {
+                assert_eq!(*scaled, input * -2.0);
+            }
+
+            let mut faded = input.clone();
+            ramp(&mut faded, 1.0, -0.001);
+            for (t, (faded, input)) in faded.iter().zip(&input).enumerate() {
+                assert_eq!(*faded, input * (1.0 - 0.001 * t as f32));
+            }
+        }
+
+        // a shorter input only mixes into the start
+        let mut out = vec![1.0; 10];
+        mix(&mut out, &[1.0; 3], 1.0);
+        assert_eq!(out[..4], [2.0, 2.0, 2.0, 1.0]);
+    }
+}
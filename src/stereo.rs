@@ -0,0 +1,174 @@
+use crate::effect::MultiChannelEffect;
+use crate::sample::{MultiChannel, Sample, WaveForm};
+use crate::Error;
+
+use std::error;
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+// constant power gains for a position from -1 (left) to 1 (right)
+pub fn pan_gains(position: f32) -> (f32, f32) {
+    let angle = (position.clamp(-1.0, 1.0) + 1.0) * PI / 4.0;
+    (angle.cos(), angle.sin())
+}
+
+pub fn pan(sample: &dyn Sample, position: f32) -> Result<MultiChannel, Error> {
+    if sample.channels() != 1 {
+        return Err(Error::ChannelMismatch {
+            expected: 1,
+            found: sample.channels(),
+        });
+    }
+    let (left, right) = pan_gains(position);
+    MultiChannel::new_dual(&*sample.scale(left), &*sample.scale(right))
+}
+
+fn stereo_pair(sample: &dyn Sample) -> Result<(Vec<f32>, Vec<f32>), Error> {
+    if sample.channels() != 2 {
+        return Err(Error::ChannelMismatch {
+            expected: 2,
+            found: sample.channels(),
+        });
+    }
+    let first = sample.waveform(0).ok_or(Error::MissingChannel(0))?;
+    let second = sample.waveform(1).ok_or(Error::MissingChannel(1))?;
+    Ok((first, second))
+}
+
+// left and right become the mid (sum) and side (difference) channels
+pub fn to_mid_side(sample: &dyn Sample) -> Result<MultiChannel, Error> {
+    let (left, right) = stereo_pair(sample)?;
+    let (mid, side): (Vec<f32>, Vec<f32>) = left
+        .iter()
+        .zip(&right)
+        .map(|(l, r)| ((l + r) / 2.0, (l - r) / 2.0))
+        .unzip();
+    MultiChannel::new_dual(
+        &WaveForm::with_rate(&mid, sample.sample_rate()),
+        &WaveForm::with_rate(&side, sample.sample_rate()),
+    )
+}
+
+pub fn from_mid_side(sample: &dyn Sample) -> Result<MultiChannel, Error> {
+    let (mid, side) = stereo_pair(sample)?;
+    let (left, right): (Vec<f32>, Vec<f32>) =
+        mid.iter().zip(&side).map(|(m, s)| (m + s, m - s)).unzip();
+    MultiChannel::new_dual(
+        &WaveForm::with_rate(&left, sample.sample_rate()),
+        &WaveForm::with_rate(&right, sample.sample_rate()),
+    )
+}
+
+// scales the side channel, 0 folds to mono, 1 leaves the sample alone
+// and above 1 widens it
+#[derive(Serialize, Deserialize)]
+pub struct StereoWidth {
+    pub width: f32,
+}
+
+impl StereoWidth {
+    pub fn new(width: f32) -> Self {
+        StereoWidth { width }
+    }
+}
+
+impl MultiChannelEffect for StereoWidth {
+    fn process_channels(
+        &self,
+        channels: &[Vec<f32>],
+        _sample_rate: u32,
+    ) -> Result<Vec<Vec<f32>>, Box<dyn error::Error>> {
+        let (left, right) = match channels {
+            [left, right] => (left, right),
+            _ => {
+                return Err(Box::new(Error::ChannelMismatch {
+                    expected: 2,
+                    found: channels.len() as u16,
+                }))
+            }
+        };
+        let width = self.width.max(0.0);
+        let (left, right) = left
+            .iter()
+            .zip(right)
+            .map(|(l, r)| {
+                let mid = (l + r) / 2.0;
+                let side = (l - r) / 2.0 * width;
+                (mid + side, mid - side)
+            })
+            .unzip();
+        Ok(vec![left, right])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sample::WaveForm;
+    use std::error;
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn pan_positions() -> Result<(), Box<dyn error::Error>> {
+        use crate::sample::SineWave;
+
+        let wave = WaveForm::from(&[1.0; 4]);
+        let hard_left = pan(&wave, -1.0)?;
+        assert_eq!(hard_left.waveform(0), Some(vec![1.0; 4]));
+        assert!(hard_left
+            .waveform(1)
+            .unwrap()
+            .iter()
+            .all(|s| s.abs() < 1e-6));
+
+        // equal power in the middle
+        let center = pan(&wave, 0.0)?;
+        let (left, right) = (center.waveform(0).unwrap(), center.waveform(1).unwrap());
+        assert!((left[0] - right[0]).abs() < 1e-6);
+        assert!((left[0].powi(2) + right[0].powi(2) - 1.0).abs() < 1e-6);
+
+        let stereo = MultiChannel::new_dual(&wave, &wave)?;
+        assert!(pan(&stereo, 0.0).is_err());
+
+        pan(&SineWave::new(440.0, 44100, 0.5), 0.7)?.export("./test_files/output/pan.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn mid_side_round_trip() -> Result<(), Box<dyn error::Error>> {
+        let left = WaveForm::from(&[1.0, 0.5, 0.0]);
+        let right = WaveForm::from(&[0.0, 0.5, -1.0]);
+        let stereo = MultiChannel::new_dual(&left, &right)?;
+        let encoded = to_mid_side(&stereo)?;
+        assert_eq!(encoded.waveform(0), Some(vec![0.5, 0.5, -0.5]));
+        assert_eq!(encoded.waveform(1), Some(vec![0.5, 0.0, 0.5]));
+        let decoded = from_mid_side(&encoded)?;
+        assert_eq!(decoded.waveform(0), left.waveform(0));
+        assert_eq!(decoded.waveform(1), right.waveform(0));
+        assert!(to_mid_side(&left).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn stereo_width() -> Result<(), Box<dyn error::Error>> {
+        let left = WaveForm::from(&[1.0, 0.5]);
+        let right = WaveForm::from(&[0.0, 0.5]);
+        let stereo = MultiChannel::new_dual(&left, &right)?;
+        let mono = stereo.apply(&StereoWidth::new(0.0))?;
+        assert_eq!(mono.waveform(0), mono.waveform(1));
+        assert_eq!(mono.waveform(0), Some(vec![0.5, 0.5]));
+        let same = stereo.apply(&StereoWidth::new(1.0))?;
+        assert_eq!(same.waveform(0), left.waveform(0));
+        let wide = stereo.apply(&StereoWidth::new(2.0))?;
+        assert_eq!(wide.waveform(0), Some(vec![1.5, 0.5]));
+        assert_eq!(wide.waveform(1), Some(vec![-0.5, 0.5]));
+        assert!(left.apply(&StereoWidth::new(1.0)).is_err());
+
+        let song = MultiChannel::from_wav("./test_files/songs/switch_lr_sine.wav")?;
+        song.apply(&StereoWidth::new(0.3))?
+            .export("./test_files/output/stereo_width.wav")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+use crate::metadata::Metadata;
+use crate::sample::Sample;
+use crate::Error;
+
+use std::error;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use minimp3::ffi;
+
+// frames decoded and thrown away before the first one asked for, layer 3
+// frames lean on the bit reservoir and filter state of the ones before
+const PREROLL: usize = 3;
+// frames decoded at once so small reads in a row don't seek every time
+const CHUNK_FRAMES: usize = 64;
+
+const BITRATES: [[u32; 14]; 5] = [
+    [
+        32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    [
+        32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    [
+        32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+    [
+        32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Header {
+    bytes: usize,
+    // steps per channel
+    steps: usize,
+    sample_rate: u32,
+    channels: u16,
+}
+
+fn parse_header(header: &[u8; 4]) -> Option<Header> {
+    if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    // 0 is mpeg 2.5, 2 mpeg 2 and 3 mpeg 1
+    let version = (header[1] >> 3) & 3;
+    // 1 is layer 3, 2 layer 2 and 3 layer 1
+    let layer = (header[1] >> 1) & 3;
+    let bitrate = (header[2] >> 4) as usize;
+    let rate = ((header[2] >> 2) & 3) as usize;
+    if version == 1 || layer == 0 || bitrate == 0 || bitrate == 15 || rate == 3 {
+        return None;
+    }
+    let padding = ((header[2] >> 1) & 1) as usize;
+    let mpeg1 = version == 3;
+
+    let table = match (mpeg1, layer) {
+        (true, 3) => 0,
+        (true, 2) => 1,
+        (true, _) => 2,
+        (false, 3) => 3,
+        (false, _) => 4,
+    };
+    let bitrate = BITRATES[table][bitrate - 1] as usize * 1000;
+    let sample_rate = [44100, 48000, 32000][rate]
+        >> match version {
+            3 => 0,
+            2 => 1,
+            _ => 2,
+        };
+    let (bytes, steps) = match layer {
+        3 => ((12 * bitrate / sample_rate as usize + padding) * 4, 384),
+        2 => (144 * bitrate / sample_rate as usize + padding, 1152),
+        _ if mpeg1 => (144 * bitrate / sample_rate as usize + padding, 1152),
+        _ => (72 * bitrate / sample_rate as usize + padding, 576),
+    };
+    Some(Header {
+        bytes,
+        steps,
+        sample_rate,
+        channels: if header[3] >> 6 == 3 { 1 } else { 2 },
+    })
+}
+
+impl Header {
+    fn matches(&self, other: &Header) -> bool {
+        self.sample_rate == other.sample_rate && self.channels == other.channels
+    }
+}
+
+fn read_header<R: Read + Seek>(file: &mut R, position: u64) -> io::Result<Option<Header>> {
+    let mut header = [0; 4];
+    file.seek(SeekFrom::Start(position))?;
+    file.read_exact(&mut header)?;
+    Ok(parse_header(&header))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    offset: u64,
+    bytes: usize,
+    // the first step of the frame in the whole stream
+    start: usize,
+    steps: usize,
+}
+
+// channels decoded for the steps from start
+struct Decoded {
+    start: usize,
+    channels: Vec<Vec<f32>>,
+}
+
+// an mp3 file decoded a piece at a time as it is read, opening only scans
+// the frame headers so slices of long recordings don't decode the rest
+pub struct Mp3Stream {
+    path: Arc<str>,
+    frames: Arc<[Frame]>,
+    sample_rate: u32,
+    channels: u16,
+    length: usize,
+    metadata: Option<Metadata>,
+    decoded: Mutex<Option<Decoded>>,
+}
+
+impl Mp3Stream {
+    pub fn open(filename: &str) -> Result<Mp3Stream, Box<dyn error::Error>> {
+        let mut file = BufReader::new(File::open(filename)?);
+        let end = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+
+        // skip an id3v2 tag at the start
+        let mut position = 0;
+        let mut tag = [0; 10];
+        if file.read_exact(&mut tag).is_ok() && &tag[..3] == b"ID3" {
+            let size = tag[6..10]
+                .iter()
+                .fold(0u64, |size, byte| (size << 7) | (*byte & 0x7F) as u64);
+            let footer = if tag[5] & 0x10 != 0 { 10 } else { 0 };
+            position = 10 + size + footer;
+        }
+
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut first: Option<Header> = None;
+        let mut start = 0;
+        let mut synced = false;
+        while position + 4 <= end {
+            let frame = read_header(&mut file, position)?.filter(|frame| {
+                position + frame.bytes as u64 <= end
+                    && first.is_none_or(|first| first.matches(frame))
+            });
+            // after a gap a frame only counts when another like it follows,
+            // so stray bytes that look like a header are skipped a byte at a
+            // time until the stream lines up again
+            let frame = match frame {
+                Some(frame) if !synced => {
+                    let next = position + frame.bytes as u64;
+                    let follows = next + 4 > end
+                        || read_header(&mut file, next)?.is_some_and(|after| after.matches(&frame));
+                    Some(frame).filter(|_| follows)
+                }
+                frame => frame,
+            };
+            match frame {
+                Some(frame) => {
+                    first.get_or_insert(frame);
+                    frames.push(Frame {
+                        offset: position,
+                        bytes: frame.bytes,
+                        start,
+                        steps: frame.steps,
+                    });
+                    start += frame.steps;
+                    position += frame.bytes as u64;
+                    synced = true;
+                }
+                None => {
+                    position += 1;
+                    synced = false;
+                }
+            }
+        }
+
+        let first =
+            first.ok_or_else(|| Error::Decode("No mp3 frames found in file".to_string()))?;
+        Ok(Mp3Stream {
+            path: filename.into(),
+            frames: frames.into(),
+            sample_rate: first.sample_rate,
+            channels: first.channels,
+            length: start,
+            metadata: Some(Metadata::from_file(filename)),
+            decoded: Mutex::new(None),
+        })
+    }
+
+    // decode the frames from first up to and not including last
+    fn decode(&self, first: usize, last: usize) -> io::Result<Decoded> {
+        let from = first.saturating_sub(PREROLL);
+        // one more frame so the decoder can check it has found a frame
+        let to = (last + 1).min(self.frames.len());
+        let offset = self.frames[from].offset;
+        let end = self.frames[to - 1].offset + self.frames[to - 1].bytes as u64;
+        let mut bytes = vec![0; (end - offset) as usize];
+        let mut file = File::open(&*self.path)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut bytes)?;
+
+        let mut decoder: Box<ffi::mp3dec_t> = Box::new(unsafe { mem::zeroed() });
+        unsafe { ffi::mp3dec_init(&mut *decoder) };
+        let mut pcm = vec![0i16; minimp3::MAX_SAMPLES_PER_FRAME];
+        let mut channels = vec![Vec::new(); self.channels as usize];
+        for frame in &self.frames[from..last] {
+            let data = &bytes[(frame.offset - offset) as usize..];
+            let mut info: ffi::mp3dec_frame_info_t = unsafe { mem::zeroed() };
+            let steps = unsafe {
+                ffi::mp3dec_decode_frame(
+                    &mut *decoder,
+                    data.as_ptr(),
+                    data.len() as _,
+                    pcm.as_mut_ptr(),
+                    &mut info,
+                )
+            } as usize;
+            if frame.start < self.frames[first].start {
+                continue;
+            }
+
+            // a frame the decoder can't make sense of is left silent so
+            // every later frame stays in place
+            let decoded = if info.channels as u16 == self.channels {
+                steps.min(frame.steps)
+            } else {
+                0
+            };
+            for (channel, wave) in channels.iter_mut().enumerate() {
+                wave.extend(
+                    pcm.iter()
+                        .skip(channel)
+                        .step_by(self.channels as usize)
+                        .take(decoded)
+                        .map(|value| *value as f32 / i16::MAX as f32),
+                );
+                wave.resize(wave.len() + frame.steps - decoded, 0.0);
+            }
+        }
+        Ok(Decoded {
+            start: self.frames[first].start,
+            channels,
+        })
+    }
+}
+
+impl Clone for Mp3Stream {
+    fn clone(&self) -> Self {
+        Mp3Stream {
+            path: self.path.clone(),
+            frames: self.frames.clone(),
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            length: self.length,
+            metadata: self.metadata.clone(),
+            decoded: Mutex::new(None),
+        }
+    }
+}
+
+impl Sample for Mp3Stream {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn length(&self) -> usize {
+        self.length
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        let mut waveform = vec![0.0; self.length];
+        self.fill(channel, 0, &mut waveform)?;
+        Some(waveform)
+    }
+
+    fn fill(&self, channel: u16, offset: usize, buffer: &mut [f32]) -> Option<usize> {
+        if channel >= self.channels {
+            return None;
+        }
+
+        let size = buffer.len().min(self.length.saturating_sub(offset));
+        let mut decoded = self.decoded.lock().ok()?;
+        let mut filled = 0;
+        while filled < size {
+            let step = offset + filled;
+            let cached = decoded.as_ref().is_some_and(|decoded| {
+                step >= decoded.start && step < decoded.start + decoded.channels[0].len()
+            });
+            if !cached {
+                let first = self.frames.partition_point(|frame| frame.start <= step) - 1;
+                let last = (first + CHUNK_FRAMES).min(self.frames.len());
+                *decoded = Some(self.decode(first, last).ok()?);
+            }
+            let chunk = decoded.as_ref()?;
+            let wave = &chunk.channels[channel as usize][step - chunk.start..];
+            let count = wave.len().min(size - filled);
+            buffer[filled..filled + count].copy_from_slice(&wave[..count]);
+            filled += count;
+        }
+        Some(size)
+    }
+
+    fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    fn set_metadata(&mut self, metadata: Metadata) -> Result<(), Error> {
+        self.metadata = Some(metadata);
+        Ok(())
+    }
+
+    fn box_clone(&self) -> Box<dyn Sample> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(all(test, feature = "wav"))]
+mod tests {
+    use super::*;
+    use crate::sample::MultiChannel;
+
+    #[test]
+    fn matches_full_decode() -> Result<(), Box<dyn error::Error>> {
+        let path = "./test_files/songs/Chameleon_short.mp3";
+        let stream = Mp3Stream::open(path)?;
+        let song = MultiChannel::from_mp3(path)?;
+        assert_eq!(stream.sample_rate(), song.sample_rate());
+        assert_eq!(stream.channels(), song.channels());
+        assert_eq!(stream.length(), song.length());
+        assert_eq!(
+            stream.metadata().unwrap().name.as_deref(),
+            Some("Chameleon_short")
+        );
+
+        // reads landing on and across chunk edges and right at the end
+        let frames = stream.frames.len();
+        for start in [0, 100_000, 1152 * CHUNK_FRAMES - 300, stream.length() - 500].iter() {
+            let end = (*start + 5000).min(stream.length());
+            let part = stream.sample(*start, end)?;
+            let expected = song.sample(*start, end)?;
+            for channel in 0..2 {
+                let wave = part.waveform(channel).unwrap();
+                let full = expected.waveform(channel).unwrap();
+                assert_eq!(wave.len(), full.len());
+                for (a, b) in wave.iter().zip(&full) {
+                    assert!((a - b).abs() < 1e-3);
+                }
+            }
+        }
+        assert_eq!(stream.frames.len(), frames);
+
+        let clip = stream.sample_sec(11.1, 16.2)?;
+        assert_eq!(clip.length(), song.sample_sec(11.1, 16.2)?.length());
+        clip.export("./test_files/output/mp3_stream.wav")?;
+
+        assert!(Mp3Stream::open("./test_files/songs/switch_lr_sine.wav").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,484 @@
+use crate::envelope::Envelope;
+use crate::note::Note;
+use crate::oscillator::{generated_sample, random, WhiteNoise};
+use crate::sample::Sample;
+
+use std::borrow::Cow;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+const RATE: u32 = 44100;
+// ln(1000), decays below are the time to fall by 60 dB
+const DECAY_60DB: f64 = 6.907_755;
+
+fn decay_gain(step: usize, decay: f32, sample_rate: u32) -> f64 {
+    if decay <= 0.0 {
+        return 0.0;
+    }
+    (-DECAY_60DB * step as f64 / (decay as f64 * sample_rate as f64)).exp()
+}
+
+// two operator FM, a sine modulator at ratio times the carrier frequency
+// bends the phase of a sine carrier by up to index radians
+#[derive(Debug, Clone)]
+pub struct FmSynth {
+    pub frequency: f32,
+    pub amplitude: f32,
+    pub ratio: f32,
+    pub index: f32,
+    // the volume over the note
+    pub envelope: Envelope,
+    // the index over the note, a falling index makes the tone go from
+    // bright to pure like a struck bell
+    pub index_envelope: Envelope,
+    sample_rate: u32,
+    length: usize,
+}
+
+impl FmSynth {
+    pub fn new(frequency: f32, length: usize, amplitude: f32) -> Self {
+        FmSynth {
+            frequency,
+            amplitude,
+            ratio: 1.0,
+            index: 1.0,
+            envelope: Envelope::new(0.0, 0.0, 1.0, 0.0),
+            index_envelope: Envelope::new(0.0, 0.0, 1.0, 0.0),
+            sample_rate: RATE,
+            length,
+        }
+    }
+
+    pub fn note(note: Note, duration: f32, amplitude: f32) -> Self {
+        let length = (duration * RATE as f32) as usize;
+        FmSynth::new(note.to_frequency(), length, amplitude)
+    }
+
+    // a plucked bass with the modulator at the carrier frequency
+    pub fn bass(note: Note, duration: f32, amplitude: f32) -> Self {
+        FmSynth::note(note, duration, amplitude)
+            .with_ratio(1.0)
+            .with_index(3.0)
+            .with_envelope(Envelope::new(0.005, 0.3, 0.6, 0.05))
+            .with_index_envelope(Envelope::new(0.0, 0.2, 0.3, 0.0))
+    }
+
+    // an inharmonic ratio and a long decay
+    pub fn bell(note: Note, duration: f32, amplitude: f32) -> Self {
+        FmSynth::note(note, duration, amplitude)
+            .with_ratio(3.5)
+            .with_index(5.0)
+            .with_envelope(Envelope::new(0.001, duration, 0.0, 0.0))
+            .with_index_envelope(Envelope::new(0.0, duration, 0.0, 0.0))
+    }
+
+    pub fn with_ratio(mut self, ratio: f32) -> Self {
+        self.ratio = ratio;
+        self
+    }
+
+    pub fn with_index(mut self, index: f32) -> Self {
+        self.index = index;
+        self
+    }
+
+    pub fn with_envelope(mut self, envelope: Envelope) -> Self {
+        self.envelope = envelope;
+        self
+    }
+
+    pub fn with_index_envelope(mut self, envelope: Envelope) -> Self {
+        self.index_envelope = envelope;
+        self
+    }
+
+    fn value_at(&self, step: usize) -> f32 {
+        let time = step as f64 / self.sample_rate as f64;
+        let carrier = self.frequency as f64 * time;
+        let modulator = (2.0 * PI * carrier * self.ratio as f64).sin();
+        let index = self.index
+            * self
+                .index_envelope
+                .gain(step, self.length, self.sample_rate);
+        let value = (2.0 * PI * carrier + index as f64 * modulator).sin() as f32;
+        self.amplitude * self.envelope.gain(step, self.length, self.sample_rate) * value
+    }
+}
+
+generated_sample!(FmSynth);
+
+// a plucked string, a burst of noise one period long fed back through an
+// averaging filter so the high harmonics die out first, the string is
+// rendered once up front since every step depends on the ones before it
+#[derive(Debug, Clone)]
+pub struct KarplusStrong {
+    frequency: f32,
+    damping: f32,
+    seed: u64,
+    sample_rate: u32,
+    waveform: Arc<[f32]>,
+}
+
+impl KarplusStrong {
+    // damping in [0, 1] is the energy lost on every pass, 0 rings for the
+    // whole duration and higher values die out faster
+    pub fn new(frequency: f32, duration: f32, damping: f32) -> Self {
+        KarplusStrong::with_seed(frequency, duration, damping, 0)
+    }
+
+    pub fn note(note: Note, duration: f32, damping: f32) -> Self {
+        KarplusStrong::new(note.to_frequency(), duration, damping)
+    }
+
+    // a different seed plucks the string with a different burst of noise
+    pub fn with_seed(frequency: f32, duration: f32, damping: f32, seed: u64) -> Self {
+        let length = (duration * RATE as f32) as usize;
+        let period = ((RATE as f32 / frequency).round() as usize).max(1);
+        let feedback = 0.5 * (1.0 - damping.clamp(0.0, 1.0));
+
+        let mut waveform = vec![0.0; length];
+        let size = period.min(length);
+        WhiteNoise::new(size, 1.0)
+            .with_seed(seed)
+            .fill(0, 0, &mut waveform[..size]);
+        for step in period..length {
+            let back = step - period;
+            waveform[step] = feedback * (waveform[back] + waveform[back + 1]);
+        }
+
+        KarplusStrong {
+            frequency,
+            damping,
+            seed,
+            sample_rate: RATE,
+            waveform: waveform.into(),
+        }
+    }
+
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    pub fn damping(&self) -> f32 {
+        self.damping
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Sample for KarplusStrong {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn length(&self) -> usize {
+        self.waveform.len()
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn waveform(&self, channel: u16) -> Option<Vec<f32>> {
+        self.waveform_ref(channel).map(|wave| wave.into_owned())
+    }
+
+    fn waveform_ref(&self, channel: u16) -> Option<Cow<'_, [f32]>> {
+        if channel > 0 {
+            return None;
+        }
+        Some(Cow::Borrowed(&self.waveform))
+    }
+
+    fn box_clone(&self) -> Box<dyn Sample> {
+        Box::new(self.clone())
+    }
+}
+
+// a sine swept down from start to end Hz, most of the sweep happens in
+// the first sweep seconds
+#[derive(Debug, Clone)]
+pub struct Kick {
+    pub start: f32,
+    pub end: f32,
+    pub sweep: f32,
+    pub decay: f32,
+    pub amplitude: f32,
+    sample_rate: u32,
+    length: usize,
+}
+
+impl Kick {
+    pub fn new(duration: f32, amplitude: f32) -> Self {
+        Kick {
+            start: 150.0,
+            end: 50.0,
+            sweep: 0.03,
+            decay: 0.5,
+            amplitude,
+            sample_rate: RATE,
+            length: (duration * RATE as f32) as usize,
+        }
+    }
+
+    pub fn with_pitch(mut self, start: f32, end: f32) -> Self {
+        self.start = start;
+        self.end = end;
+        self
+    }
+
+    pub fn with_sweep(mut self, sweep: f32) -> Self {
+        self.sweep = sweep;
+        self
+    }
+
+    pub fn with_decay(mut self, decay: f32) -> Self {
+        self.decay = decay;
+        self
+    }
+
+    fn value_at(&self, step: usize) -> f32 {
+        let time = step as f64 / self.sample_rate as f64;
+        let sweep = (self.sweep as f64).max(1e-6);
+        // the integral of the frequency falling exponentially to end
+        let cycles = self.end as f64 * time
+            + (self.start - self.end) as f64 * sweep * (1.0 - (-time / sweep).exp());
+        let gain = decay_gain(step, self.decay, self.sample_rate);
+        self.amplitude * (gain * (2.0 * PI * cycles).sin()) as f32
+    }
+}
+
+generated_sample!(Kick);
+
+// a short sine body under a burst of noise, noise is the part of the mix
+// that is noise
+#[derive(Debug, Clone)]
+pub struct Snare {
+    pub tone: f32,
+    pub noise: f32,
+    pub decay: f32,
+    pub amplitude: f32,
+    pub seed: u64,
+    sample_rate: u32,
+    length: usize,
+}
+
+impl Snare {
+    pub fn new(duration: f32, amplitude: f32) -> Self {
+        Snare {
+            tone: 180.0,
+            noise: 0.7,
+            decay: 0.2,
+            amplitude,
+            seed: 0,
+            sample_rate: RATE,
+            length: (duration * RATE as f32) as usize,
+        }
+    }
+
+    pub fn with_tone(mut self, tone: f32) -> Self {
+        self.tone = tone;
+        self
+    }
+
+    pub fn with_noise(mut self, noise: f32) -> Self {
+        self.noise = noise;
+        self
+    }
+
+    pub fn with_decay(mut self, decay: f32) -> Self {
+        self.decay = decay;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn value_at(&self, step: usize) -> f32 {
+        let time = step as f64 / self.sample_rate as f64;
+        let noise = self.noise.clamp(0.0, 1.0);
+        // the body rings for half as long as the noise
+        let body = (2.0 * PI * self.tone as f64 * time).sin()
+            * decay_gain(step, self.decay / 2.0, self.sample_rate);
+        let rattle =
+            random(self.seed, step as u64) as f64 * decay_gain(step, self.decay, self.sample_rate);
+        self.amplitude * ((1.0 - noise) as f64 * body + noise as f64 * rattle) as f32
+    }
+}
+
+generated_sample!(Snare);
+
+// noise with the lows taken out by differencing neighbouring steps, a short
+// decay for a closed hat and a long one for an open hat
+#[derive(Debug, Clone)]
+pub struct HiHat {
+    pub decay: f32,
+    pub amplitude: f32,
+    pub seed: u64,
+    sample_rate: u32,
+    length: usize,
+}
+
+impl HiHat {
+    pub fn new(duration: f32, amplitude: f32) -> Self {
+        HiHat {
+            decay: 0.05,
+            amplitude,
+            seed: 0,
+            sample_rate: RATE,
+            length: (duration * RATE as f32) as usize,
+        }
+    }
+
+    pub fn closed(amplitude: f32) -> Self {
+        HiHat::new(0.1, amplitude)
+    }
+
+    pub fn open(amplitude: f32) -> Self {
+        HiHat::new(0.5, amplitude).with_decay(0.4)
+    }
+
+    pub fn with_decay(mut self, decay: f32) -> Self {
+        self.decay = decay;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    fn value_at(&self, step: usize) -> f32 {
+        let previous = match step {
+            0 => 0.0,
+            _ => random(self.seed, step as u64 - 1),
+        };
+        let noise = 0.5 * (random(self.seed, step as u64) - previous);
+        self.amplitude * noise * decay_gain(step, self.decay, self.sample_rate) as f32
+    }
+}
+
+generated_sample!(HiHat);
+
+#[cfg(all(test, feature = "wav"))]
+mod tests {
+    use super::*;
+    use crate::sample::SineWave;
+    use std::error;
+
+    #[test]
+    fn fm_synth() -> Result<(), Box<dyn error::Error>> {
+        // no modulation is a plain sine
+        let pure = FmSynth::new(440.0, 1000, 0.5).with_index(0.0);
+        let sine = SineWave::new(440.0, 1000, 0.5).waveform(0).unwrap();
+        for (a, b) in pure.waveform(0).unwrap().iter().zip(sine.iter()) {
+            assert!((a - b).abs() < 1e-4);
+        }
+        let modulated = FmSynth::new(440.0, 1000, 0.5).with_index(2.0);
+        let wave = modulated.waveform(0).unwrap();
+        assert!(wave
+            .iter()
+            .zip(sine.iter())
+            .any(|(a, b)| (a - b).abs() > 0.1));
+        assert!(wave.iter().all(|s| s.abs() <= 0.5));
+
+        let mut block = [0.0; 10];
+        modulated.fill(0, 500, &mut block);
+        assert_eq!(block, wave[500..510]);
+
+        let bell = FmSynth::bell(Note::A4, 2.0, 0.5);
+        let wave = bell.waveform(0).unwrap();
+        let start = wave[..4410].iter().fold(0f32, |a, b| a.max(b.abs()));
+        let end = wave[wave.len() - 4410..]
+            .iter()
+            .fold(0f32, |a, b| a.max(b.abs()));
+        assert!(end < start / 10.0);
+
+        bell.export("./test_files/output/fm_bell.wav")?;
+        FmSynth::bass(Note::A1, 1.0, 0.5).export("./test_files/output/fm_bass.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn plucked_string() -> Result<(), Box<dyn error::Error>> {
+        let string = KarplusStrong::new(441.0, 1.0, 0.01);
+        let wave = string.waveform(0).unwrap();
+        assert_eq!(wave.len(), 44100);
+        // every step averages two steps one period of 100 back
+        let expected = 0.5 * 0.99 * (wave[100] + wave[101]);
+        assert!((wave[200] - expected).abs() < 1e-6);
+        assert!(wave[..100].iter().any(|s| s.abs() > 0.5));
+
+        let level = |wave: &[f32]| wave.iter().fold(0f32, |a, b| a.max(b.abs()));
+        assert!(level(&wave[40000..]) < level(&wave[..1000]) / 10.0);
+        let muted = KarplusStrong::new(441.0, 1.0, 0.2).waveform(0).unwrap();
+        assert!(level(&muted[10000..]) < level(&wave[10000..]));
+
+        let mut block = [0.0; 10];
+        string.fill(0, 500, &mut block);
+        assert_eq!(block, wave[500..510]);
+        assert_eq!(
+            KarplusStrong::new(441.0, 1.0, 0.01).waveform(0).unwrap(),
+            wave
+        );
+        assert_ne!(
+            KarplusStrong::with_seed(441.0, 1.0, 0.01, 1)
+                .waveform(0)
+                .unwrap(),
+            wave
+        );
+        assert_eq!(KarplusStrong::new(441.0, 0.0, 0.5).length(), 0);
+
+        KarplusStrong::note(Note::E2, 2.0, 0.005)
+            .scale(0.5)
+            .export("./test_files/output/karplus_strong.wav")?;
+        Ok(())
+    }
+
+    #[test]
+    fn drums() -> Result<(), Box<dyn error::Error>> {
+        let level = |wave: &[f32]| wave.iter().fold(0f32, |a, b| a.max(b.abs()));
+        let kick = Kick::new(0.5, 0.8);
+        let wave = kick.waveform(0).unwrap();
+        assert_eq!(wave.len(), 22050);
+        assert_eq!(wave[0], 0.0);
+        assert!(level(&wave) <= 0.8);
+        assert!(level(&wave[20000..]) < level(&wave[..2000]) / 10.0);
+        // the first half cycle at close to 150 Hz is shorter than one at 50
+        let crossing = wave[1..].iter().position(|s| *s < 0.0).unwrap();
+        assert!(crossing < 200);
+
+        let snare = Snare::new(0.3, 0.8);
+        let wave = snare.waveform(0).unwrap();
+        assert!(level(&wave) <= 0.8);
+        assert!(level(&wave[11000..]) < level(&wave[..1000]) / 10.0);
+        assert_ne!(snare.clone().with_seed(3).waveform(0).unwrap(), wave);
+        let tone = Snare::new(0.3, 0.8).with_noise(0.0).waveform(0).unwrap();
+        assert_eq!(
+            tone,
+            Snare::new(0.3, 0.8)
+                .with_seed(3)
+                .with_noise(0.0)
+                .waveform(0)
+                .unwrap()
+        );
+
+        let open = HiHat::open(0.5);
+        let closed = HiHat::closed(0.5);
+        let open_wave = open.waveform(0).unwrap();
+        assert!(level(&open_wave) <= 0.5);
+        assert!(level(&closed.waveform(0).unwrap()[3000..]) < level(&open_wave[3000..4000]));
+        let mut block = [0.0; 10];
+        open.fill(0, 500, &mut block);
+        assert_eq!(block, open_wave[500..510]);
+
+        kick.export("./test_files/output/synth_kick.wav")?;
+        snare.export("./test_files/output/synth_snare.wav")?;
+        open.export("./test_files/output/synth_open_hat.wav")?;
+        Ok(())
+    }
+}
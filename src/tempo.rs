@@ -0,0 +1,217 @@
+use crate::Error;
+
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+const DEFAULT_BPM: f32 = 120.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimeSignature {
+    pub beats_per_bar: u32,
+    pub beat_unit: u32,
+}
+
+impl TimeSignature {
+    pub fn new(beats_per_bar: u32, beat_unit: u32) -> Self {
+        TimeSignature {
+            beats_per_bar,
+            beat_unit,
+        }
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        TimeSignature::new(4, 4)
+    }
+}
+
+// bars and beats count from zero, so bar 1 beat 0 is the start of the
+// second bar
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "TempoMap")]
+pub struct Tempo {
+    pub signature: TimeSignature,
+    // (beat, bpm) pairs sorted by the beat the tempo starts on
+    changes: Vec<(f64, f32)>,
+}
+
+// a tempo as it was saved, only turned into a Tempo once it checks out
+#[derive(Deserialize)]
+struct TempoMap {
+    signature: TimeSignature,
+    changes: Vec<(f64, f32)>,
+}
+
+impl TryFrom<TempoMap> for Tempo {
+    type Error = Error;
+
+    fn try_from(map: TempoMap) -> Result<Self, Error> {
+        let tempo = Tempo {
+            signature: map.signature,
+            changes: map.changes,
+        };
+        tempo.validate()?;
+        Ok(tempo)
+    }
+}
+
+impl Tempo {
+    pub fn new(bpm: f32, signature: TimeSignature) -> Self {
+        Tempo {
+            signature,
+            changes: vec![(0.0, bpm)],
+        }
+    }
+
+    pub fn add_change(&mut self, bar: u32, beat: f32, bpm: f32) {
+        let at = self.beats(bar, beat);
+        self.changes.retain(|(start, _)| *start != at);
+        let index = self
+            .changes
+            .iter()
+            .position(|(start, _)| *start > at)
+            .unwrap_or(self.changes.len());
+        self.changes.insert(index, (at, bpm));
+    }
+
+    // the tempo map needs at least one change, sorted by beat, and every
+    // bpm above zero, a Tempo read from a file is checked already
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.changes.is_empty() {
+            return Err(Error::Invalid("A tempo needs at least one bpm".to_string()));
+        }
+        if self.changes.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err(Error::Invalid(
+                "Tempo changes must be sorted by beat".to_string(),
+            ));
+        }
+        if let Some((_, bpm)) = self
+            .changes
+            .iter()
+            .find(|(_, bpm)| !bpm.is_finite() || *bpm <= 0.0)
+        {
+            return Err(Error::Invalid(format!("{} is not a valid bpm", bpm)));
+        }
+        Ok(())
+    }
+
+    pub fn bpm_at(&self, bar: u32, beat: f32) -> f32 {
+        let at = self.beats(bar, beat);
+        self.changes
+            .iter()
+            .take_while(|(start, _)| *start <= at)
+            .last()
+            .or_else(|| self.changes.first())
+            .map_or(DEFAULT_BPM, |(_, bpm)| *bpm)
+    }
+
+    pub fn beats(&self, bar: u32, beat: f32) -> f64 {
+        bar as f64 * self.signature.beats_per_bar as f64 + beat as f64
+    }
+
+    // walk the tempo map adding up the time spent in each section
+    pub fn beats_to_seconds(&self, beats: f64) -> f64 {
+        let mut seconds = 0.0;
+        for (index, (start, bpm)) in self.changes.iter().enumerate() {
+            if beats <= *start {
+                break;
+            }
+            let end = match self.changes.get(index + 1) {
+                Some((next, _)) => beats.min(*next),
+                None => beats,
+            };
+            seconds += (end - start) * 60.0 / *bpm as f64;
+        }
+        seconds
+    }
+
+    pub fn seconds(&self, bar: u32, beat: f32) -> f64 {
+        self.beats_to_seconds(self.beats(bar, beat))
+    }
+
+    pub fn position(&self, bar: u32, beat: f32, sample_rate: u32) -> usize {
+        (self.seconds(bar, beat) * sample_rate as f64).round() as usize
+    }
+}
+
+impl Default for Tempo {
+    fn default() -> Self {
+        Tempo::new(DEFAULT_BPM, TimeSignature::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "wav")]
+    use std::error;
+
+    #[test]
+    fn tempo_changes() {
+        let mut tempo = Tempo::new(120.0, TimeSignature::new(4, 4));
+        assert_eq!(tempo.seconds(1, 0.0), 2.0);
+        assert_eq!(tempo.seconds(0, 2.5), 1.25);
+
+        tempo.add_change(2, 0.0, 60.0);
+        assert_eq!(tempo.bpm_at(1, 3.0), 120.0);
+        assert_eq!(tempo.bpm_at(2, 0.0), 60.0);
+        assert_eq!(tempo.seconds(2, 0.0), 4.0);
+        assert_eq!(tempo.seconds(3, 0.0), 8.0);
+        assert_eq!(tempo.position(2, 1.0, 44100), 5 * 44100);
+
+        let waltz = Tempo::new(90.0, TimeSignature::new(3, 4));
+        assert_eq!(waltz.seconds(2, 0.0), 4.0);
+    }
+
+    #[test]
+    fn validate() {
+        let read = |json: &str| serde_json::from_str::<Tempo>(json);
+        let tempo = read(
+            r#"{"signature":{"beats_per_bar":4,"beat_unit":4},"changes":[[0.0,90.0],[4.0,120.0]]}"#,
+        );
+        assert_eq!(tempo.unwrap().bpm_at(1, 0.0), 120.0);
+        for changes in [
+            "[]",
+            "[[4.0,120.0],[0.0,90.0]]",
+            "[[0.0,0.0]]",
+            "[[0.0,-60.0]]",
+        ] {
+            let json = format!(
+                r#"{{"signature":{{"beats_per_bar":4,"beat_unit":4}},"changes":{}}}"#,
+                changes
+            );
+            assert!(read(&json).is_err());
+        }
+
+        let mut tempo = Tempo::new(0.0, TimeSignature::default());
+        assert!(tempo.validate().is_err());
+        tempo.add_change(0, 0.0, 60.0);
+        assert!(tempo.validate().is_ok());
+        assert!(Tempo::default().validate().is_ok());
+    }
+
+    #[cfg(feature = "wav")]
+    #[test]
+    fn add_track_beat() -> Result<(), Box<dyn error::Error>> {
+        use crate::sample::{Composition, Sample, SineWave};
+
+        let click = SineWave::new(880.0, 2205, 0.5);
+        let mut comp = Composition::new();
+        let mut tempo = Tempo::new(120.0, TimeSignature::default());
+        tempo.add_change(1, 0.0, 240.0);
+        comp.set_tempo(tempo);
+
+        let id = comp.add_track_beat(&click, 0, 0.0)?;
+        for beat in 1..4 {
+            comp.add_track_id_beat(id, 0, beat as f32)?;
+        }
+        for beat in 0..4 {
+            comp.add_track_id_beat(id, 1, beat as f32)?;
+        }
+        // four beats at 120 then four at 240
+        assert_eq!(comp.length(), 44100 * 3 - 11025 + 2205);
+        comp.export("./test_files/output/tempo_clicks.wav")?;
+        Ok(())
+    }
+}